@@ -0,0 +1,587 @@
+//! Command buffers use typestates to track which state of the
+//! [command buffer lifecycle](https://registry.khronos.org/vulkan/specs/1.3-extensions/html/vkspec.html#commandbuffers-lifecycle)
+//! they are in. Recording commands before [CommandBuffer::begin], ending a command buffer twice
+//! or submitting a command buffer which is still recording will not compile.
+//!
+//! ```compile_fail
+//! # use dagal::abstraction::prelude as abstraction;
+//! # fn record(command_buffer: abstraction::CommandBuffer<abstraction::Initial>) {
+//! // A command buffer in the initial state cannot be ended
+//! let command_buffer = command_buffer.end();
+//! # }
+//! ```
+//!
+//! ```compile_fail
+//! # use dagal::abstraction::prelude as abstraction;
+//! # fn record(command_buffer: abstraction::CommandBuffer<abstraction::Recording>, queue: &abstraction::Queue) {
+//! // A command buffer which is still recording cannot be submitted
+//! let command_buffer = command_buffer.submit(queue, &[], &[], ash::vk::Fence::null());
+//! # }
+//! ```
+use crate::abstraction::prelude as abstraction;
+use ash::vk;
+use ash::vk::TaggedStructure;
+use std::marker::PhantomData;
+
+/// Marker trait for every state a [CommandBuffer] can be in
+pub trait CommandBufferState {}
+
+/// Command buffer has been allocated or its pool has been reset
+pub struct Initial;
+/// Command buffer is currently recording commands
+pub struct Recording;
+/// Command buffer has finished recording and can be submitted
+pub struct Executable;
+/// Command buffer has been submitted to a queue
+pub struct Pending;
+
+impl CommandBufferState for Initial {}
+impl CommandBufferState for Recording {}
+impl CommandBufferState for Executable {}
+impl CommandBufferState for Pending {}
+
+/// An abstraction for [vk::CommandBuffer]
+///
+/// Command buffers are owned by their [abstraction::CommandPool] and are only ever freed by it.
+pub struct CommandBuffer<S: CommandBufferState> {
+    handle: vk::CommandBuffer,
+    device: abstraction::Device,
+    /// Queue family of the pool the command buffer was allocated from
+    queue_family_index: u32,
+    level: vk::CommandBufferLevel,
+    state: PhantomData<S>,
+}
+
+/// Describes the dynamic rendering pass a secondary [CommandBuffer] will be executed within
+///
+/// Everything must match the [abstraction::RenderingInfo] passed to [CommandBuffer::begin_rendering]
+/// of the primary command buffer, except for [vk::RenderingFlags::CONTENTS_SECONDARY_COMMAND_BUFFERS].
+/// Use [abstraction::PipelineRenderingInfo::get_inheritance] to derive it from the pass.
+#[derive(Clone, Default)]
+pub struct RenderingInheritance {
+    pub flags: vk::RenderingFlags,
+    pub view_mask: u32,
+    pub color_attachment_formats: Vec<vk::Format>,
+    pub depth_attachment_format: vk::Format,
+    pub stencil_attachment_format: vk::Format,
+    pub rasterization_samples: vk::SampleCountFlags,
+}
+
+impl<S: CommandBufferState> CommandBuffer<S> {
+    /// Moves the command buffer into the next state
+    fn into_state<N: CommandBufferState>(self) -> CommandBuffer<N> {
+        CommandBuffer {
+            handle: self.handle,
+            device: self.device,
+            queue_family_index: self.queue_family_index,
+            level: self.level,
+            state: PhantomData,
+        }
+    }
+
+    pub fn get_handle(&self) -> vk::CommandBuffer {
+        self.handle
+    }
+
+    pub fn get_queue_family_index(&self) -> u32 {
+        self.queue_family_index
+    }
+
+    pub fn get_level(&self) -> vk::CommandBufferLevel {
+        self.level
+    }
+
+    pub fn get_device(&self) -> abstraction::Device {
+        self.device.clone()
+    }
+}
+
+impl CommandBuffer<Initial> {
+    pub(crate) fn from_vk(
+        handle: vk::CommandBuffer,
+        device: abstraction::Device,
+        queue_family_index: u32,
+        level: vk::CommandBufferLevel,
+    ) -> Self {
+        Self {
+            handle,
+            device,
+            queue_family_index,
+            level,
+            state: PhantomData,
+        }
+    }
+
+    /// Begin recording commands into a primary command buffer
+    pub fn begin(
+        self,
+        flags: vk::CommandBufferUsageFlags,
+    ) -> Result<CommandBuffer<Recording>, vk::Result> {
+        let begin_info = vk::CommandBufferBeginInfo {
+            s_type: vk::CommandBufferBeginInfo::STRUCTURE_TYPE,
+            flags,
+            ..Default::default()
+        };
+        unsafe {
+            self.device
+                .handle_as_ref()
+                .begin_command_buffer(self.handle, &begin_info)?;
+        }
+        Ok(self.into_state())
+    }
+
+    /// Begin recording commands into a secondary command buffer which will be executed inside
+    /// of a dynamic rendering pass
+    pub fn begin_secondary(
+        self,
+        flags: vk::CommandBufferUsageFlags,
+        rendering: &RenderingInheritance,
+    ) -> Result<CommandBuffer<Recording>, vk::Result> {
+        debug_assert_eq!(self.level, vk::CommandBufferLevel::SECONDARY);
+        let rendering_inheritance = vk::CommandBufferInheritanceRenderingInfo {
+            s_type: vk::CommandBufferInheritanceRenderingInfo::STRUCTURE_TYPE,
+            flags: rendering.flags,
+            view_mask: rendering.view_mask,
+            color_attachment_count: rendering.color_attachment_formats.len() as u32,
+            p_color_attachment_formats: rendering.color_attachment_formats.as_ptr(),
+            depth_attachment_format: rendering.depth_attachment_format,
+            stencil_attachment_format: rendering.stencil_attachment_format,
+            rasterization_samples: rendering.rasterization_samples,
+            ..Default::default()
+        };
+        let inheritance_info = vk::CommandBufferInheritanceInfo {
+            s_type: vk::CommandBufferInheritanceInfo::STRUCTURE_TYPE,
+            p_next: abstraction::utility::p_next(&rendering_inheritance),
+            ..Default::default()
+        };
+        let begin_info = vk::CommandBufferBeginInfo {
+            s_type: vk::CommandBufferBeginInfo::STRUCTURE_TYPE,
+            flags: flags | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE,
+            p_inheritance_info: &inheritance_info,
+            ..Default::default()
+        };
+        unsafe {
+            self.device
+                .handle_as_ref()
+                .begin_command_buffer(self.handle, &begin_info)?;
+        }
+        Ok(self.into_state())
+    }
+}
+
+impl CommandBuffer<Recording> {
+    /// Finish recording commands
+    pub fn end(self) -> Result<CommandBuffer<Executable>, vk::Result> {
+        unsafe {
+            self.device.handle_as_ref().end_command_buffer(self.handle)?;
+        }
+        Ok(self.into_state())
+    }
+
+    pub fn copy_buffer(
+        &self,
+        src: &abstraction::Buffer,
+        dst: &abstraction::Buffer,
+        regions: &[vk::BufferCopy],
+    ) {
+        unsafe {
+            self.device.handle_as_ref().cmd_copy_buffer(
+                self.handle,
+                src.get_handle(),
+                dst.get_handle(),
+                regions,
+            );
+        }
+    }
+
+    pub fn copy_buffer_to_image(
+        &self,
+        src: &abstraction::Buffer,
+        dst: &abstraction::Image,
+        dst_layout: vk::ImageLayout,
+        regions: &[vk::BufferImageCopy],
+    ) {
+        unsafe {
+            self.device.handle_as_ref().cmd_copy_buffer_to_image(
+                self.handle,
+                src.get_handle(),
+                dst.get_handle(),
+                dst_layout,
+                regions,
+            );
+        }
+    }
+
+    pub fn copy_image_to_buffer(
+        &self,
+        src: &abstraction::Image,
+        src_layout: vk::ImageLayout,
+        dst: &abstraction::Buffer,
+        regions: &[vk::BufferImageCopy],
+    ) {
+        unsafe {
+            self.device.handle_as_ref().cmd_copy_image_to_buffer(
+                self.handle,
+                src.get_handle(),
+                src_layout,
+                dst.get_handle(),
+                regions,
+            );
+        }
+    }
+
+    pub fn copy_image(
+        &self,
+        src: &abstraction::Image,
+        src_layout: vk::ImageLayout,
+        dst: &abstraction::Image,
+        dst_layout: vk::ImageLayout,
+        regions: &[vk::ImageCopy],
+    ) {
+        unsafe {
+            self.device.handle_as_ref().cmd_copy_image(
+                self.handle,
+                src.get_handle(),
+                src_layout,
+                dst.get_handle(),
+                dst_layout,
+                regions,
+            );
+        }
+    }
+
+    pub fn bind_pipeline(&self, bind_point: vk::PipelineBindPoint, pipeline: vk::Pipeline) {
+        unsafe {
+            self.device
+                .handle_as_ref()
+                .cmd_bind_pipeline(self.handle, bind_point, pipeline);
+        }
+    }
+
+    pub fn set_viewport(&self, viewport: vk::Viewport) {
+        unsafe {
+            self.device
+                .handle_as_ref()
+                .cmd_set_viewport(self.handle, 0, &[viewport]);
+        }
+    }
+
+    pub fn set_scissor(&self, scissor: vk::Rect2D) {
+        unsafe {
+            self.device
+                .handle_as_ref()
+                .cmd_set_scissor(self.handle, 0, &[scissor]);
+        }
+    }
+
+    pub fn bind_descriptor_sets(
+        &self,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+        first_set: u32,
+        descriptor_sets: &[vk::DescriptorSet],
+        dynamic_offsets: &[u32],
+    ) {
+        unsafe {
+            self.device.handle_as_ref().cmd_bind_descriptor_sets(
+                self.handle,
+                bind_point,
+                layout,
+                first_set,
+                descriptor_sets,
+                dynamic_offsets,
+            );
+        }
+    }
+
+    /// Bind descriptor buffers, which are referred to by their index in `descriptor_buffers`
+    /// by [CommandBuffer::set_descriptor_buffer_offsets]
+    ///
+    /// Returns [vk::Result::ERROR_EXTENSION_NOT_PRESENT] unless the device uses
+    /// [abstraction::DescriptorBackend::Buffer].
+    pub fn bind_descriptor_buffers(
+        &self,
+        descriptor_buffers: &[&abstraction::DescriptorBuffer],
+    ) -> Result<(), vk::Result> {
+        let binding_infos: Vec<vk::DescriptorBufferBindingInfoEXT> = descriptor_buffers
+            .iter()
+            .map(|descriptor_buffer| descriptor_buffer.get_binding_info())
+            .collect();
+        let loader = self
+            .device
+            .get_descriptor_buffer_loader()
+            .ok_or(vk::Result::ERROR_EXTENSION_NOT_PRESENT)?;
+        unsafe { loader.cmd_bind_descriptor_buffers(self.handle, &binding_infos) };
+        Ok(())
+    }
+
+    /// Bind sets starting at `first_set`, each found at an offset into one of the buffers bound
+    /// by [CommandBuffer::bind_descriptor_buffers]
+    ///
+    /// Returns [vk::Result::ERROR_EXTENSION_NOT_PRESENT] unless the device uses
+    /// [abstraction::DescriptorBackend::Buffer].
+    pub fn set_descriptor_buffer_offsets(
+        &self,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+        first_set: u32,
+        buffer_indices: &[u32],
+        offsets: &[vk::DeviceSize],
+    ) -> Result<(), vk::Result> {
+        let loader = self
+            .device
+            .get_descriptor_buffer_loader()
+            .ok_or(vk::Result::ERROR_EXTENSION_NOT_PRESENT)?;
+        unsafe {
+            loader.cmd_set_descriptor_buffer_offsets(
+                self.handle,
+                bind_point,
+                layout,
+                first_set,
+                buffer_indices,
+                offsets,
+            );
+        }
+        Ok(())
+    }
+
+    /// Push every field of `data` to `set` of `layout` without allocating a set
+    ///
+    /// The set's layout has to be created with
+    /// [abstraction::DescriptorSetLayoutBuilder::push_descriptor].
+    ///
+    /// Returns [vk::Result::ERROR_EXTENSION_NOT_PRESENT] unless the device supports
+    /// `VK_KHR_push_descriptor`.
+    pub fn push_descriptor_set<T: abstraction::DescriptorTemplate>(
+        &self,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+        set: u32,
+        data: &T,
+    ) -> Result<(), vk::Result> {
+        let loader = self
+            .device
+            .get_push_descriptor_loader()
+            .ok_or(vk::Result::ERROR_EXTENSION_NOT_PRESENT)?;
+        let descriptors = crate::abstraction::descriptors::template::get_descriptors(data);
+        let writes = crate::abstraction::descriptors::template::get_writes(vk::DescriptorSet::null(), &descriptors);
+        unsafe { loader.cmd_push_descriptor_set(self.handle, bind_point, layout, set, &writes) };
+        Ok(())
+    }
+
+    /// Push every field of `data` through a template created with
+    /// [abstraction::DescriptorUpdateTemplate::new_push]
+    ///
+    /// Returns [vk::Result::ERROR_EXTENSION_NOT_PRESENT] unless the device supports
+    /// `VK_KHR_push_descriptor`.
+    pub fn push_descriptor_set_with_template<T: abstraction::DescriptorTemplate>(
+        &self,
+        template: &abstraction::DescriptorUpdateTemplate<T>,
+        data: &T,
+    ) -> Result<(), vk::Result> {
+        template.push(self.handle, data)
+    }
+
+    pub fn push_constants(
+        &self,
+        layout: vk::PipelineLayout,
+        stage_flags: vk::ShaderStageFlags,
+        offset: u32,
+        constants: &[u8],
+    ) {
+        unsafe {
+            self.device.handle_as_ref().cmd_push_constants(
+                self.handle,
+                layout,
+                stage_flags,
+                offset,
+                constants,
+            );
+        }
+    }
+
+    pub fn dispatch(&self, group_count_x: u32, group_count_y: u32, group_count_z: u32) {
+        unsafe {
+            self.device.handle_as_ref().cmd_dispatch(
+                self.handle,
+                group_count_x,
+                group_count_y,
+                group_count_z,
+            );
+        }
+    }
+
+    /// Dispatch enough workgroups of `pipeline` to run at least `total_threads` invocations
+    ///
+    /// The pipeline is not bound, see [abstraction::ComputePipeline::bind].
+    pub fn dispatch_for(&self, pipeline: &abstraction::ComputePipeline, total_threads: [u32; 3]) {
+        let [x, y, z] = pipeline.get_group_count(total_threads);
+        self.dispatch(x, y, z);
+    }
+
+    /// Dispatch with the group counts read from a [vk::DispatchIndirectCommand] at `offset` of
+    /// `buffer`
+    pub fn dispatch_indirect(&self, buffer: &abstraction::Buffer, offset: vk::DeviceSize) {
+        unsafe {
+            self.device
+                .handle_as_ref()
+                .cmd_dispatch_indirect(self.handle, buffer.get_handle(), offset);
+        }
+    }
+
+    pub fn draw(
+        &self,
+        vertex_count: u32,
+        instance_count: u32,
+        first_vertex: u32,
+        first_instance: u32,
+    ) {
+        unsafe {
+            self.device.handle_as_ref().cmd_draw(
+                self.handle,
+                vertex_count,
+                instance_count,
+                first_vertex,
+                first_instance,
+            );
+        }
+    }
+
+    pub fn draw_indexed(
+        &self,
+        index_count: u32,
+        instance_count: u32,
+        first_index: u32,
+        vertex_offset: i32,
+        first_instance: u32,
+    ) {
+        unsafe {
+            self.device.handle_as_ref().cmd_draw_indexed(
+                self.handle,
+                index_count,
+                instance_count,
+                first_index,
+                vertex_offset,
+                first_instance,
+            );
+        }
+    }
+
+    /// Record a synchronization2 pipeline barrier
+    pub fn pipeline_barrier2(&self, dependency_info: &vk::DependencyInfo) {
+        unsafe {
+            self.device
+                .handle_as_ref()
+                .cmd_pipeline_barrier2(self.handle, dependency_info);
+        }
+    }
+
+    /// Move `resource` to `access`, recording only the barriers needed since its last access
+    pub fn transition<T: abstraction::TrackedResource + ?Sized>(
+        &self,
+        resource: &T,
+        access: abstraction::ResourceAccess,
+    ) {
+        let mut batch = abstraction::BarrierBatch::new();
+        resource.transition(access, &mut batch);
+        self.flush_barriers(&batch);
+    }
+
+    /// Move several resources at once, recording all of their barriers in a single
+    /// `vkCmdPipelineBarrier2`
+    pub fn transition_all(
+        &self,
+        transitions: &[(&dyn abstraction::TrackedResource, abstraction::ResourceAccess)],
+    ) {
+        let mut batch = abstraction::BarrierBatch::new();
+        for (resource, access) in transitions {
+            resource.transition(*access, &mut batch);
+        }
+        self.flush_barriers(&batch);
+    }
+
+    /// Record every barrier in `batch`
+    pub fn flush_barriers(&self, batch: &abstraction::BarrierBatch) {
+        if batch.is_empty() {
+            return;
+        }
+        self.pipeline_barrier2(&batch.get_dependency_info());
+    }
+
+    /// Begin a dynamic rendering pass
+    pub fn begin_rendering(&self, rendering_info: &abstraction::RenderingInfo) {
+        rendering_info.with_vk_rendering_info(|rendering_info| unsafe {
+            self.device
+                .handle_as_ref()
+                .cmd_begin_rendering(self.handle, rendering_info);
+        });
+    }
+
+    pub fn end_rendering(&self) {
+        unsafe {
+            self.device.handle_as_ref().cmd_end_rendering(self.handle);
+        }
+    }
+
+    /// Execute secondary command buffers
+    ///
+    /// If executed inside of a rendering pass, the pass must have been started with
+    /// [vk::RenderingFlags::CONTENTS_SECONDARY_COMMAND_BUFFERS].
+    pub fn execute_commands(&self, command_buffers: &[CommandBuffer<Executable>]) {
+        let handles: Vec<vk::CommandBuffer> = command_buffers
+            .iter()
+            .map(|command_buffer| {
+                debug_assert_eq!(command_buffer.level, vk::CommandBufferLevel::SECONDARY);
+                command_buffer.handle
+            })
+            .collect();
+        unsafe {
+            self.device
+                .handle_as_ref()
+                .cmd_execute_commands(self.handle, handles.as_slice());
+        }
+    }
+}
+
+impl CommandBuffer<Executable> {
+    /// Get the [vk::CommandBufferSubmitInfo] to submit the command buffer in a batch by hand
+    pub fn submit_info(&self) -> vk::CommandBufferSubmitInfo {
+        vk::CommandBufferSubmitInfo {
+            s_type: vk::CommandBufferSubmitInfo::STRUCTURE_TYPE,
+            command_buffer: self.handle,
+            device_mask: 0,
+            ..Default::default()
+        }
+    }
+
+    /// Submit the command buffer to a queue
+    ///
+    /// The queue must belong to the same queue family the command buffer's pool was created for.
+    pub fn submit(
+        self,
+        queue: &abstraction::Queue,
+        wait_semaphores: &[vk::SemaphoreSubmitInfo],
+        signal_semaphores: &[vk::SemaphoreSubmitInfo],
+        fence: vk::Fence,
+    ) -> Result<CommandBuffer<Pending>, vk::Result> {
+        assert_eq!(queue.get_family_index(), self.queue_family_index);
+        debug_assert_eq!(self.level, vk::CommandBufferLevel::PRIMARY);
+        let command_buffer_info = self.submit_info();
+        let submit_info = vk::SubmitInfo2 {
+            s_type: vk::SubmitInfo2::STRUCTURE_TYPE,
+            wait_semaphore_info_count: wait_semaphores.len() as u32,
+            p_wait_semaphore_infos: wait_semaphores.as_ptr(),
+            command_buffer_info_count: 1,
+            p_command_buffer_infos: &command_buffer_info,
+            signal_semaphore_info_count: signal_semaphores.len() as u32,
+            p_signal_semaphore_infos: signal_semaphores.as_ptr(),
+            ..Default::default()
+        };
+        unsafe {
+            self.device
+                .handle_as_ref()
+                .queue_submit2(queue.get_handle(), &[submit_info], fence)?;
+        }
+        Ok(self.into_state())
+    }
+}
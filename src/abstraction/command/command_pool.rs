@@ -0,0 +1,144 @@
+use crate::abstraction::prelude as abstraction;
+use ash::vk;
+use ash::vk::TaggedStructure;
+
+use super::command_buffer::{CommandBuffer, Initial};
+
+/// An abstraction for [vk::CommandPool]
+///
+/// Command buffers are never freed individually. Instead, the entire pool is reset once all of
+/// its command buffers have finished executing (usually once per frame) and previously allocated
+/// command buffers are handed out again by [CommandPool::allocate].
+pub struct CommandPool {
+    handle: vk::CommandPool,
+    device: abstraction::Device,
+    /// Queue family all command buffers allocated from the pool can be submitted to
+    queue_family_index: u32,
+    /// Every primary command buffer allocated from the pool so far
+    primary: CommandBufferList,
+    /// Every secondary command buffer allocated from the pool so far
+    secondary: CommandBufferList,
+}
+
+/// Command buffers of a single [vk::CommandBufferLevel] allocated from a [CommandPool]
+#[derive(Default)]
+struct CommandBufferList {
+    handles: Vec<vk::CommandBuffer>,
+    /// Number of command buffers in `handles` handed out since the last reset
+    used: usize,
+}
+
+impl CommandPool {
+    pub fn new(
+        device: abstraction::Device,
+        queue_family_index: u32,
+        flags: vk::CommandPoolCreateFlags,
+    ) -> Result<Self, vk::Result> {
+        let command_pool_ci = vk::CommandPoolCreateInfo {
+            s_type: vk::CommandPoolCreateInfo::STRUCTURE_TYPE,
+            flags,
+            queue_family_index,
+            ..Default::default()
+        };
+        let handle = unsafe {
+            device
+                .handle_as_ref()
+                .create_command_pool(&command_pool_ci, None)?
+        };
+        Ok(Self {
+            handle,
+            device,
+            queue_family_index,
+            primary: CommandBufferList::default(),
+            secondary: CommandBufferList::default(),
+        })
+    }
+
+    /// Get a primary [CommandBuffer] in the [Initial] state
+    ///
+    /// Command buffers which were allocated before the last [CommandPool::reset] are reused
+    /// before any new command buffers are allocated.
+    pub fn allocate(&mut self) -> Result<CommandBuffer<Initial>, vk::Result> {
+        self.allocate_level(vk::CommandBufferLevel::PRIMARY)
+    }
+
+    /// Get a secondary [CommandBuffer] in the [Initial] state
+    ///
+    /// Secondary command buffers must be started with [CommandBuffer::begin_secondary] and can
+    /// only be executed from within a primary command buffer.
+    pub fn allocate_secondary(&mut self) -> Result<CommandBuffer<Initial>, vk::Result> {
+        self.allocate_level(vk::CommandBufferLevel::SECONDARY)
+    }
+
+    fn allocate_level(
+        &mut self,
+        level: vk::CommandBufferLevel,
+    ) -> Result<CommandBuffer<Initial>, vk::Result> {
+        let list = if level == vk::CommandBufferLevel::PRIMARY {
+            &mut self.primary
+        } else {
+            &mut self.secondary
+        };
+        if list.used == list.handles.len() {
+            let command_buffer_ai = vk::CommandBufferAllocateInfo {
+                s_type: vk::CommandBufferAllocateInfo::STRUCTURE_TYPE,
+                command_pool: self.handle,
+                level,
+                command_buffer_count: 1,
+                ..Default::default()
+            };
+            let mut handles = unsafe {
+                self.device
+                    .handle_as_ref()
+                    .allocate_command_buffers(&command_buffer_ai)?
+            };
+            list.handles.append(&mut handles);
+        }
+        let handle = list.handles[list.used];
+        list.used += 1;
+        Ok(CommandBuffer::from_vk(
+            handle,
+            self.device.clone(),
+            self.queue_family_index,
+            level,
+        ))
+    }
+
+    /// Resets the pool returning every command buffer allocated from it back to the [Initial]
+    /// state
+    ///
+    /// # Safety
+    /// None of the command buffers allocated from the pool may still be pending execution on the
+    /// GPU. Any [CommandBuffer] previously handed out must no longer be used.
+    pub unsafe fn reset(&mut self) -> Result<(), vk::Result> {
+        self.device
+            .handle_as_ref()
+            .reset_command_pool(self.handle, vk::CommandPoolResetFlags::empty())?;
+        self.primary.used = 0;
+        self.secondary.used = 0;
+        Ok(())
+    }
+
+    pub fn get_handle(&self) -> vk::CommandPool {
+        self.handle
+    }
+
+    pub fn get_queue_family_index(&self) -> u32 {
+        self.queue_family_index
+    }
+
+    pub fn get_device(&self) -> abstraction::Device {
+        self.device.clone()
+    }
+}
+
+impl Drop for CommandPool {
+    fn drop(&mut self) {
+        unsafe {
+            // Destroying the pool frees all command buffers allocated from it
+            self.device
+                .handle_as_ref()
+                .destroy_command_pool(self.handle, None);
+        }
+    }
+}
@@ -0,0 +1,8 @@
+//! Contains everything relating to recording and submitting commands
+pub mod command_buffer;
+pub mod command_pool;
+pub mod rendering;
+
+pub use command_buffer::*;
+pub use command_pool::*;
+pub use rendering::*;
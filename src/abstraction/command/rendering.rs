@@ -0,0 +1,439 @@
+//! Helpers for [dynamic rendering](https://registry.khronos.org/vulkan/specs/1.3-extensions/man/html/vkCmdBeginRendering.html)
+//!
+//! A single [RenderingInfo] describes every attachment of a rendering pass. Both the
+//! [vk::RenderingInfo] used to begin the pass and the [vk::PipelineRenderingCreateInfo] used to
+//! create pipelines for it are derived from it, so attachment formats cannot drift out of sync.
+use crate::abstraction::prelude as abstraction;
+use ash::vk;
+use ash::vk::TaggedStructure;
+
+/// The image view an attachment renders to along with the information needed to describe it
+#[derive(Copy, Clone)]
+pub struct AttachmentView {
+    pub handle: vk::ImageView,
+    pub format: vk::Format,
+    pub extent: vk::Extent3D,
+}
+
+impl From<&abstraction::Image> for AttachmentView {
+    fn from(image: &abstraction::Image) -> Self {
+        Self {
+            handle: image.get_image_view(),
+            format: image.get_format(),
+            extent: image.get_extent(),
+        }
+    }
+}
+
+impl From<&abstraction::ImageView> for AttachmentView {
+    fn from(image_view: &abstraction::ImageView) -> Self {
+        Self {
+            handle: image_view.get_handle(),
+            format: image_view.get_format(),
+            extent: image_view.get_extent(),
+        }
+    }
+}
+
+/// Describes a multisampled attachment being resolved into another image
+#[derive(Copy, Clone)]
+pub struct AttachmentResolve {
+    pub view: AttachmentView,
+    pub mode: vk::ResolveModeFlags,
+    pub layout: vk::ImageLayout,
+}
+
+/// A single attachment of a [RenderingInfo]
+#[derive(Copy, Clone)]
+pub struct RenderingAttachment {
+    view: AttachmentView,
+    layout: vk::ImageLayout,
+    load_op: vk::AttachmentLoadOp,
+    store_op: vk::AttachmentStoreOp,
+    clear_value: vk::ClearValue,
+    resolve: Option<AttachmentResolve>,
+}
+
+impl RenderingAttachment {
+    /// Creates an attachment which loads and stores its previous contents
+    ///
+    /// The layout defaults to [vk::ImageLayout::ATTACHMENT_OPTIMAL].
+    pub fn new<T: Into<AttachmentView>>(view: T) -> Self {
+        Self {
+            view: view.into(),
+            layout: vk::ImageLayout::ATTACHMENT_OPTIMAL,
+            load_op: vk::AttachmentLoadOp::LOAD,
+            store_op: vk::AttachmentStoreOp::STORE,
+            clear_value: vk::ClearValue::default(),
+            resolve: None,
+        }
+    }
+
+    pub fn layout(mut self, layout: vk::ImageLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn load_op(mut self, load_op: vk::AttachmentLoadOp) -> Self {
+        self.load_op = load_op;
+        self
+    }
+
+    pub fn store_op(mut self, store_op: vk::AttachmentStoreOp) -> Self {
+        self.store_op = store_op;
+        self
+    }
+
+    /// Clear a color attachment to `color` when the pass begins
+    pub fn clear_color(mut self, color: [f32; 4]) -> Self {
+        self.load_op = vk::AttachmentLoadOp::CLEAR;
+        self.clear_value = vk::ClearValue {
+            color: vk::ClearColorValue { float32: color },
+        };
+        self
+    }
+
+    /// Clear a depth and/or stencil attachment when the pass begins
+    pub fn clear_depth_stencil(mut self, depth: f32, stencil: u32) -> Self {
+        self.load_op = vk::AttachmentLoadOp::CLEAR;
+        self.clear_value = vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue { depth, stencil },
+        };
+        self
+    }
+
+    /// Resolve the attachment into `view` at the end of the pass
+    ///
+    /// The resolve target uses the same layout as the attachment.
+    pub fn resolve<T: Into<AttachmentView>>(mut self, view: T, mode: vk::ResolveModeFlags) -> Self {
+        self.resolve = Some(AttachmentResolve {
+            view: view.into(),
+            mode,
+            layout: self.layout,
+        });
+        self
+    }
+
+    pub fn get_view(&self) -> AttachmentView {
+        self.view
+    }
+
+    pub fn get_format(&self) -> vk::Format {
+        self.view.format
+    }
+
+    fn get_vk_attachment_info(&self) -> vk::RenderingAttachmentInfo {
+        let (resolve_mode, resolve_image_view, resolve_image_layout) = match self.resolve {
+            Some(resolve) => (resolve.mode, resolve.view.handle, resolve.layout),
+            None => (
+                vk::ResolveModeFlags::NONE,
+                vk::ImageView::null(),
+                vk::ImageLayout::UNDEFINED,
+            ),
+        };
+        vk::RenderingAttachmentInfo {
+            s_type: vk::RenderingAttachmentInfo::STRUCTURE_TYPE,
+            image_view: self.view.handle,
+            image_layout: self.layout,
+            resolve_mode,
+            resolve_image_view,
+            resolve_image_layout,
+            load_op: self.load_op,
+            store_op: self.store_op,
+            clear_value: self.clear_value,
+            ..Default::default()
+        }
+    }
+}
+
+/// Describes every attachment of a dynamic rendering pass
+#[derive(Clone)]
+pub struct RenderingInfo {
+    flags: vk::RenderingFlags,
+    render_area: Option<vk::Rect2D>,
+    layer_count: u32,
+    view_mask: u32,
+    color_attachments: Vec<RenderingAttachment>,
+    depth_attachment: Option<RenderingAttachment>,
+    stencil_attachment: Option<RenderingAttachment>,
+}
+
+impl Default for RenderingInfo {
+    fn default() -> Self {
+        Self {
+            flags: vk::RenderingFlags::empty(),
+            render_area: None,
+            layer_count: 1,
+            view_mask: 0,
+            color_attachments: Vec::new(),
+            depth_attachment: None,
+            stencil_attachment: None,
+        }
+    }
+}
+
+impl RenderingInfo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn flags(mut self, flags: vk::RenderingFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Set the area rendered to
+    ///
+    /// If not set, the render area covers the smallest extent of all attachments.
+    pub fn render_area(mut self, render_area: vk::Rect2D) -> Self {
+        self.render_area = Some(render_area);
+        self
+    }
+
+    pub fn layer_count(mut self, layer_count: u32) -> Self {
+        self.layer_count = layer_count;
+        self
+    }
+
+    pub fn view_mask(mut self, view_mask: u32) -> Self {
+        self.view_mask = view_mask;
+        self
+    }
+
+    /// Add a color attachment, color attachments are bound in the order they are added
+    pub fn color_attachment(mut self, attachment: RenderingAttachment) -> Self {
+        self.color_attachments.push(attachment);
+        self
+    }
+
+    pub fn depth_attachment(mut self, attachment: RenderingAttachment) -> Self {
+        self.depth_attachment = Some(attachment);
+        self
+    }
+
+    /// Set the stencil attachment
+    ///
+    /// If both a depth and stencil attachment are used, both must refer to the same image view.
+    pub fn stencil_attachment(mut self, attachment: RenderingAttachment) -> Self {
+        self.stencil_attachment = Some(attachment);
+        self
+    }
+
+    /// Use the same depth/stencil image as both the depth and stencil attachment
+    pub fn depth_stencil_attachment(self, attachment: RenderingAttachment) -> Self {
+        self.depth_attachment(attachment)
+            .stencil_attachment(attachment)
+    }
+
+    pub fn get_color_attachments(&self) -> &[RenderingAttachment] {
+        self.color_attachments.as_slice()
+    }
+
+    pub fn get_depth_attachment(&self) -> Option<&RenderingAttachment> {
+        self.depth_attachment.as_ref()
+    }
+
+    pub fn get_stencil_attachment(&self) -> Option<&RenderingAttachment> {
+        self.stencil_attachment.as_ref()
+    }
+
+    /// Get the render area of the pass
+    pub fn get_render_area(&self) -> vk::Rect2D {
+        if let Some(render_area) = self.render_area {
+            return render_area;
+        }
+        let extent = self
+            .color_attachments
+            .iter()
+            .chain(self.depth_attachment.iter())
+            .chain(self.stencil_attachment.iter())
+            .map(|attachment| attachment.view.extent)
+            .reduce(|a, b| vk::Extent3D {
+                width: a.width.min(b.width),
+                height: a.height.min(b.height),
+                depth: a.depth.min(b.depth),
+            })
+            .unwrap_or_default();
+        vk::Rect2D {
+            offset: vk::Offset2D::default(),
+            extent: vk::Extent2D {
+                width: extent.width,
+                height: extent.height,
+            },
+        }
+    }
+
+    /// Get the attachment formats pipelines used within the pass must be created with
+    pub fn get_pipeline_rendering_info(&self) -> PipelineRenderingInfo {
+        PipelineRenderingInfo {
+            view_mask: self.view_mask,
+            color_attachment_formats: self
+                .color_attachments
+                .iter()
+                .map(|attachment| attachment.get_format())
+                .collect(),
+            depth_attachment_format: self
+                .depth_attachment
+                .map(|attachment| attachment.get_format())
+                .unwrap_or(vk::Format::UNDEFINED),
+            stencil_attachment_format: self
+                .stencil_attachment
+                .map(|attachment| attachment.get_format())
+                .unwrap_or(vk::Format::UNDEFINED),
+        }
+    }
+
+    /// Build the [vk::RenderingInfo] and pass it to `f`
+    ///
+    /// [vk::RenderingInfo] points into temporaries, so it is only valid for the duration of `f`.
+    pub(crate) fn with_vk_rendering_info<R>(&self, f: impl FnOnce(&vk::RenderingInfo) -> R) -> R {
+        debug_assert!(
+            match (self.depth_attachment, self.stencil_attachment) {
+                (Some(depth), Some(stencil)) => depth.view.handle == stencil.view.handle,
+                _ => true,
+            },
+            "Depth and stencil attachments must use the same image view"
+        );
+        let color_attachments: Vec<vk::RenderingAttachmentInfo> = self
+            .color_attachments
+            .iter()
+            .map(|attachment| attachment.get_vk_attachment_info())
+            .collect();
+        let depth_attachment = self
+            .depth_attachment
+            .map(|attachment| attachment.get_vk_attachment_info());
+        let stencil_attachment = self
+            .stencil_attachment
+            .map(|attachment| attachment.get_vk_attachment_info());
+        let rendering_info = vk::RenderingInfo {
+            s_type: vk::RenderingInfo::STRUCTURE_TYPE,
+            flags: self.flags,
+            render_area: self.get_render_area(),
+            layer_count: self.layer_count,
+            view_mask: self.view_mask,
+            color_attachment_count: color_attachments.len() as u32,
+            p_color_attachments: color_attachments.as_ptr(),
+            p_depth_attachment: depth_attachment
+                .as_ref()
+                .map_or(std::ptr::null(), |attachment| attachment as *const _),
+            p_stencil_attachment: stencil_attachment
+                .as_ref()
+                .map_or(std::ptr::null(), |attachment| attachment as *const _),
+            ..Default::default()
+        };
+        f(&rendering_info)
+    }
+}
+
+/// Attachment formats of a dynamic rendering pass used to create pipelines
+///
+/// Usually derived from a [RenderingInfo] using [RenderingInfo::get_pipeline_rendering_info].
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct PipelineRenderingInfo {
+    pub view_mask: u32,
+    pub color_attachment_formats: Vec<vk::Format>,
+    pub depth_attachment_format: vk::Format,
+    pub stencil_attachment_format: vk::Format,
+}
+
+impl PipelineRenderingInfo {
+    /// Get the [vk::PipelineRenderingCreateInfo] to chain into a pipeline's create info
+    ///
+    /// The returned struct points into `self` and must not outlive it.
+    pub fn get_vk_create_info(&self) -> vk::PipelineRenderingCreateInfo {
+        vk::PipelineRenderingCreateInfo {
+            s_type: vk::PipelineRenderingCreateInfo::STRUCTURE_TYPE,
+            view_mask: self.view_mask,
+            color_attachment_count: self.color_attachment_formats.len() as u32,
+            p_color_attachment_formats: self.color_attachment_formats.as_ptr(),
+            depth_attachment_format: self.depth_attachment_format,
+            stencil_attachment_format: self.stencil_attachment_format,
+            ..Default::default()
+        }
+    }
+
+    /// Get the [abstraction::RenderingInheritance] for secondary command buffers recorded for
+    /// the pass
+    pub fn get_inheritance(
+        &self,
+        flags: vk::RenderingFlags,
+        rasterization_samples: vk::SampleCountFlags,
+    ) -> abstraction::RenderingInheritance {
+        abstraction::RenderingInheritance {
+            flags: flags & !vk::RenderingFlags::CONTENTS_SECONDARY_COMMAND_BUFFERS,
+            view_mask: self.view_mask,
+            color_attachment_formats: self.color_attachment_formats.clone(),
+            depth_attachment_format: self.depth_attachment_format,
+            stencil_attachment_format: self.stencil_attachment_format,
+            rasterization_samples,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attachment_view(format: vk::Format, width: u32, height: u32) -> AttachmentView {
+        AttachmentView {
+            handle: vk::ImageView::null(),
+            format,
+            extent: vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            },
+        }
+    }
+
+    #[test]
+    /// Formats handed to pipelines must match the attachments of the pass
+    fn test_pipeline_rendering_info() {
+        let rendering_info = RenderingInfo::new()
+            .color_attachment(RenderingAttachment::new(attachment_view(
+                vk::Format::R8G8B8A8_UNORM,
+                1920,
+                1080,
+            )))
+            .color_attachment(RenderingAttachment::new(attachment_view(
+                vk::Format::R16G16B16A16_SFLOAT,
+                1920,
+                1080,
+            )))
+            .depth_attachment(
+                RenderingAttachment::new(attachment_view(vk::Format::D32_SFLOAT, 1920, 1080))
+                    .clear_depth_stencil(0.0, 0),
+            );
+        let pipeline_rendering_info = rendering_info.get_pipeline_rendering_info();
+        assert_eq!(
+            pipeline_rendering_info.color_attachment_formats,
+            vec![vk::Format::R8G8B8A8_UNORM, vk::Format::R16G16B16A16_SFLOAT]
+        );
+        assert_eq!(pipeline_rendering_info.depth_attachment_format, vk::Format::D32_SFLOAT);
+        assert_eq!(pipeline_rendering_info.stencil_attachment_format, vk::Format::UNDEFINED);
+        rendering_info.with_vk_rendering_info(|vk_rendering_info| {
+            assert_eq!(vk_rendering_info.color_attachment_count, 2);
+            assert!(!vk_rendering_info.p_depth_attachment.is_null());
+            assert!(vk_rendering_info.p_stencil_attachment.is_null());
+        });
+    }
+
+    #[test]
+    /// Without an explicit render area, the smallest attachment is covered
+    fn test_default_render_area() {
+        let rendering_info = RenderingInfo::new()
+            .color_attachment(RenderingAttachment::new(attachment_view(
+                vk::Format::R8G8B8A8_UNORM,
+                1920,
+                1080,
+            )))
+            .color_attachment(RenderingAttachment::new(attachment_view(
+                vk::Format::R8G8B8A8_UNORM,
+                960,
+                1200,
+            )));
+        let render_area = rendering_info.get_render_area();
+        assert_eq!(render_area.extent.width, 960);
+        assert_eq!(render_area.extent.height, 1080);
+    }
+}
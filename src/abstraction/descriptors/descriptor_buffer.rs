@@ -0,0 +1,402 @@
+use super::enums::DescriptorInfo;
+use super::layout::DescriptorSetLayout;
+use super::set::{get_image_layout, DescriptorWriter};
+use crate::abstraction::prelude as abstraction;
+use ash::vk;
+use ash::vk::TaggedStructure;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Get the size of a single descriptor of `descriptor_type` in a descriptor buffer
+fn get_descriptor_size(
+    properties: &vk::PhysicalDeviceDescriptorBufferPropertiesEXT,
+    descriptor_type: vk::DescriptorType,
+) -> usize {
+    match descriptor_type {
+        vk::DescriptorType::SAMPLER => properties.sampler_descriptor_size,
+        vk::DescriptorType::COMBINED_IMAGE_SAMPLER => properties.combined_image_sampler_descriptor_size,
+        vk::DescriptorType::SAMPLED_IMAGE => properties.sampled_image_descriptor_size,
+        vk::DescriptorType::STORAGE_IMAGE => properties.storage_image_descriptor_size,
+        vk::DescriptorType::INPUT_ATTACHMENT => properties.input_attachment_descriptor_size,
+        vk::DescriptorType::UNIFORM_TEXEL_BUFFER => properties.uniform_texel_buffer_descriptor_size,
+        vk::DescriptorType::STORAGE_TEXEL_BUFFER => properties.storage_texel_buffer_descriptor_size,
+        vk::DescriptorType::UNIFORM_BUFFER => properties.uniform_buffer_descriptor_size,
+        _ => properties.storage_buffer_descriptor_size,
+    }
+}
+
+/// A host-visible buffer descriptors are written into using `VK_EXT_descriptor_buffer`
+///
+/// Only available if [abstraction::Device::get_descriptor_backend] is
+/// [super::DescriptorBackend::Buffer]. Sets are sub-allocated linearly and are only freed all
+/// at once through [DescriptorBuffer::reset]. Layouts of the sets have to be created with
+/// [super::DescriptorSetLayoutBuilder::descriptor_buffer].
+pub struct DescriptorBuffer {
+    handle: vk::Buffer,
+    device: abstraction::Device,
+    loader: ash::extensions::ext::DescriptorBuffer,
+    memory: vk::DeviceMemory,
+    /// Persistently mapped memory of the whole buffer
+    mapped: *mut u8,
+    size: vk::DeviceSize,
+    address: vk::DeviceAddress,
+    usage: vk::BufferUsageFlags,
+    /// Bytes handed out to sets since the last reset
+    allocated: AtomicU64,
+}
+
+// Sets only ever write to their own part of the mapped memory
+unsafe impl Send for DescriptorBuffer {}
+unsafe impl Sync for DescriptorBuffer {}
+
+impl DescriptorBuffer {
+    /// Create a descriptor buffer of `size` bytes
+    ///
+    /// `usage` is either [vk::BufferUsageFlags::RESOURCE_DESCRIPTOR_BUFFER_EXT],
+    /// [vk::BufferUsageFlags::SAMPLER_DESCRIPTOR_BUFFER_EXT] or both. Returns
+    /// [vk::Result::ERROR_EXTENSION_NOT_PRESENT] unless the device uses
+    /// [super::DescriptorBackend::Buffer].
+    pub fn new(
+        device: abstraction::Device,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+    ) -> Result<Self, vk::Result> {
+        let loader = device
+            .get_descriptor_buffer_loader()
+            .cloned()
+            .ok_or(vk::Result::ERROR_EXTENSION_NOT_PRESENT)?;
+        let usage = usage | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS;
+        let buffer_ci = vk::BufferCreateInfo {
+            s_type: vk::BufferCreateInfo::STRUCTURE_TYPE,
+            size,
+            usage,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            ..Default::default()
+        };
+        let handle = unsafe { device.handle_as_ref().create_buffer(&buffer_ci, None)? };
+        let memory = Self::allocate_memory(&device, handle).inspect_err(|_| {
+            unsafe { device.handle_as_ref().destroy_buffer(handle, None) };
+        })?;
+        let mapped = unsafe {
+            device
+                .handle_as_ref()
+                .map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+        };
+        let mapped = match mapped {
+            Ok(mapped) => mapped as *mut u8,
+            Err(error) => {
+                unsafe {
+                    device.handle_as_ref().destroy_buffer(handle, None);
+                    device.handle_as_ref().free_memory(memory, None);
+                }
+                return Err(error);
+            }
+        };
+        let address = get_buffer_address(&device, handle);
+        Ok(Self {
+            handle,
+            device,
+            loader,
+            memory,
+            mapped,
+            size,
+            address,
+            usage,
+            allocated: AtomicU64::new(0),
+        })
+    }
+
+    /// Allocate and bind host-visible memory with device addresses enabled to `handle`
+    fn allocate_memory(
+        device: &abstraction::Device,
+        handle: vk::Buffer,
+    ) -> Result<vk::DeviceMemory, vk::Result> {
+        let memory_requirements = unsafe { device.handle_as_ref().get_buffer_memory_requirements(handle) };
+        let memory_type_index = device
+            .find_memory_type_index(
+                memory_requirements.memory_type_bits,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            )
+            .ok_or(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY)?;
+        let allocate_flags_info = vk::MemoryAllocateFlagsInfo {
+            s_type: vk::MemoryAllocateFlagsInfo::STRUCTURE_TYPE,
+            flags: vk::MemoryAllocateFlags::DEVICE_ADDRESS,
+            ..Default::default()
+        };
+        let allocate_info = vk::MemoryAllocateInfo {
+            s_type: vk::MemoryAllocateInfo::STRUCTURE_TYPE,
+            p_next: abstraction::utility::p_next(&allocate_flags_info),
+            allocation_size: memory_requirements.size,
+            memory_type_index,
+        };
+        let memory = unsafe { device.handle_as_ref().allocate_memory(&allocate_info, None)? };
+        if let Err(error) = unsafe { device.handle_as_ref().bind_buffer_memory(handle, memory, 0) } {
+            unsafe { device.handle_as_ref().free_memory(memory, None) };
+            return Err(error);
+        }
+        Ok(memory)
+    }
+
+    /// Allocate room for a set of `layout`
+    ///
+    /// Returns [vk::Result::ERROR_OUT_OF_POOL_MEMORY] once the buffer is full.
+    pub fn allocate(&self, layout: Arc<DescriptorSetLayout>) -> Result<DescriptorBufferSet<'_>, vk::Result> {
+        let size = unsafe { self.loader.get_descriptor_set_layout_size(layout.get_handle()) };
+        let alignment = self
+            .device
+            .get_descriptor_buffer_properties()
+            .descriptor_buffer_offset_alignment
+            .max(1);
+        let mut offset = 0;
+        self.allocated
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |allocated| {
+                offset = allocated.div_ceil(alignment) * alignment;
+                if offset + size <= self.size {
+                    Some(offset + size)
+                } else {
+                    None
+                }
+            })
+            .map_err(|_| vk::Result::ERROR_OUT_OF_POOL_MEMORY)?;
+        Ok(DescriptorBufferSet {
+            device: self.device.clone(),
+            loader: &self.loader,
+            layout,
+            offset,
+            size,
+            mapped: unsafe { self.mapped.add(offset as usize) },
+        })
+    }
+
+    /// Free every set allocated from the buffer
+    ///
+    /// # Safety
+    /// None of the sets may be used by command buffers which have not finished executing.
+    pub unsafe fn reset(&mut self) {
+        *self.allocated.get_mut() = 0;
+    }
+
+    /// Get the [vk::DescriptorBufferBindingInfoEXT] used to bind the buffer
+    pub fn get_binding_info(&self) -> vk::DescriptorBufferBindingInfoEXT {
+        vk::DescriptorBufferBindingInfoEXT {
+            s_type: vk::DescriptorBufferBindingInfoEXT::STRUCTURE_TYPE,
+            address: self.address,
+            usage: self.usage,
+            ..Default::default()
+        }
+    }
+
+    pub fn get_handle(&self) -> vk::Buffer {
+        self.handle
+    }
+
+    pub fn get_address(&self) -> vk::DeviceAddress {
+        self.address
+    }
+
+    pub fn get_size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    pub fn get_device(&self) -> abstraction::Device {
+        self.device.clone()
+    }
+}
+
+impl Drop for DescriptorBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.handle_as_ref().unmap_memory(self.memory);
+            self.device.handle_as_ref().destroy_buffer(self.handle, None);
+            self.device.handle_as_ref().free_memory(self.memory, None);
+        }
+    }
+}
+
+/// Get the device address of `buffer`, which has to be created with
+/// [vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS]
+fn get_buffer_address(device: &abstraction::Device, buffer: vk::Buffer) -> vk::DeviceAddress {
+    let address_info = vk::BufferDeviceAddressInfo {
+        s_type: vk::BufferDeviceAddressInfo::STRUCTURE_TYPE,
+        buffer,
+        ..Default::default()
+    };
+    unsafe { device.handle_as_ref().get_buffer_device_address(&address_info) }
+}
+
+/// A set of descriptors within a [DescriptorBuffer]
+///
+/// Bound using [abstraction::CommandBuffer::set_descriptor_buffer_offsets] with
+/// [DescriptorBufferSet::get_offset].
+pub struct DescriptorBufferSet<'b> {
+    device: abstraction::Device,
+    loader: &'b ash::extensions::ext::DescriptorBuffer,
+    layout: Arc<DescriptorSetLayout>,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+    /// Start of the set in the mapped memory of the buffer
+    mapped: *mut u8,
+}
+
+impl DescriptorBufferSet<'_> {
+    /// Offset of the set from the start of its [DescriptorBuffer]
+    pub fn get_offset(&self) -> vk::DeviceSize {
+        self.offset
+    }
+
+    pub fn get_layout(&self) -> &Arc<DescriptorSetLayout> {
+        &self.layout
+    }
+
+    /// Write the descriptor `data` of `descriptor_type` into `array_element` of `binding`
+    fn write_data(
+        &self,
+        binding: u32,
+        array_element: u32,
+        descriptor_type: vk::DescriptorType,
+        data: vk::DescriptorDataEXT,
+    ) {
+        debug_assert_eq!(
+            self.layout
+                .get_binding(binding)
+                .map(|layout_binding| layout_binding.descriptor_type),
+            Some(descriptor_type),
+            "Binding {} does not exist or has a different descriptor type",
+            binding
+        );
+        let descriptor_size = get_descriptor_size(self.device.get_descriptor_buffer_properties(), descriptor_type);
+        let binding_offset = unsafe {
+            self.loader
+                .get_descriptor_set_layout_binding_offset(self.layout.get_handle(), binding)
+        };
+        let offset = binding_offset as usize + array_element as usize * descriptor_size;
+        debug_assert!(offset + descriptor_size <= self.size as usize);
+
+        let get_info = vk::DescriptorGetInfoEXT {
+            s_type: vk::DescriptorGetInfoEXT::STRUCTURE_TYPE,
+            ty: descriptor_type,
+            data,
+            ..Default::default()
+        };
+        unsafe {
+            let descriptor = std::slice::from_raw_parts_mut(self.mapped.add(offset), descriptor_size);
+            self.loader.get_descriptor(&get_info, descriptor);
+        }
+    }
+}
+
+/// Resolve [vk::WHOLE_SIZE] into the number of bytes left after `offset`, [None] if the range
+/// does not fit into the buffer
+fn get_buffer_range(
+    size: vk::DeviceSize,
+    offset: vk::DeviceSize,
+    range: vk::DeviceSize,
+) -> Option<vk::DeviceSize> {
+    let available = size.checked_sub(offset)?;
+    match range {
+        vk::WHOLE_SIZE => Some(available),
+        range if range <= available => Some(range),
+        _ => None,
+    }
+}
+
+impl DescriptorWriter for DescriptorBufferSet<'_> {
+    /// Raw buffer descriptors return [vk::Result::ERROR_INVALID_DEVICE_ADDRESS_EXT], as their
+    /// usage can not be checked before querying their address, write them through
+    /// [DescriptorWriter::write_buffer] instead
+    fn write_descriptor(
+        &self,
+        binding: u32,
+        array_element: u32,
+        descriptor_type: vk::DescriptorType,
+        descriptor: DescriptorInfo,
+    ) -> Result<(), vk::Result> {
+        // Pointed to by `data` and have to outlive the call
+        let sampler;
+        let image_info;
+        let data = match descriptor {
+            DescriptorInfo::Image(info) if descriptor_type == vk::DescriptorType::SAMPLER => {
+                sampler = info.sampler;
+                vk::DescriptorDataEXT { p_sampler: &sampler }
+            }
+            DescriptorInfo::Image(info) => {
+                image_info = vk::DescriptorImageInfo {
+                    image_layout: get_image_layout(descriptor_type, info.image_layout),
+                    ..info
+                };
+                match descriptor_type {
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER => vk::DescriptorDataEXT {
+                        p_combined_image_sampler: &image_info,
+                    },
+                    vk::DescriptorType::INPUT_ATTACHMENT => vk::DescriptorDataEXT {
+                        p_input_attachment_image: &image_info,
+                    },
+                    vk::DescriptorType::STORAGE_IMAGE => vk::DescriptorDataEXT {
+                        p_storage_image: &image_info,
+                    },
+                    _ => vk::DescriptorDataEXT {
+                        p_sampled_image: &image_info,
+                    },
+                }
+            }
+            DescriptorInfo::Buffer(_) => return Err(vk::Result::ERROR_INVALID_DEVICE_ADDRESS_EXT),
+        };
+        self.write_data(binding, array_element, descriptor_type, data);
+        Ok(())
+    }
+
+    /// Descriptor buffers refer to buffers by their device address, so `buffer` has to be
+    /// created with [vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS], otherwise
+    /// [vk::Result::ERROR_INVALID_DEVICE_ADDRESS_EXT] is returned.
+    fn write_buffer(
+        &self,
+        binding: u32,
+        array_element: u32,
+        descriptor_type: vk::DescriptorType,
+        buffer: &abstraction::Buffer,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+    ) -> Result<(), vk::Result> {
+        if !buffer
+            .get_usage()
+            .contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS)
+        {
+            return Err(vk::Result::ERROR_INVALID_DEVICE_ADDRESS_EXT);
+        }
+        // Addresses have no size to resolve whole size ranges against
+        let range = get_buffer_range(buffer.get_size(), offset, range)
+            .ok_or(vk::Result::ERROR_UNKNOWN)?;
+        let address_info = vk::DescriptorAddressInfoEXT {
+            s_type: vk::DescriptorAddressInfoEXT::STRUCTURE_TYPE,
+            address: get_buffer_address(&self.device, buffer.get_handle()) + offset,
+            range,
+            format: vk::Format::UNDEFINED,
+            ..Default::default()
+        };
+        let data = if descriptor_type == vk::DescriptorType::UNIFORM_BUFFER {
+            vk::DescriptorDataEXT {
+                p_uniform_buffer: &address_info,
+            }
+        } else {
+            vk::DescriptorDataEXT {
+                p_storage_buffer: &address_info,
+            }
+        };
+        self.write_data(binding, array_element, descriptor_type, data);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Whole size ranges cover the rest of the buffer and ranges past its end are rejected
+    fn test_buffer_range() {
+        assert_eq!(get_buffer_range(256, 64, vk::WHOLE_SIZE), Some(192));
+        assert_eq!(get_buffer_range(256, 64, 128), Some(128));
+        assert_eq!(get_buffer_range(256, 64, 256), None);
+        assert_eq!(get_buffer_range(256, 512, vk::WHOLE_SIZE), None);
+    }
+}
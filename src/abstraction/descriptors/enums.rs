@@ -1,6 +1,22 @@
 use ash::vk;
 
+#[derive(Copy, Clone, Debug)]
 pub enum DescriptorInfo {
     Image(vk::DescriptorImageInfo),
     Buffer(vk::DescriptorBufferInfo),
 }
+
+/// How descriptors are stored on the device, chosen when the [crate::abstraction::Device] is
+/// created
+///
+/// [DescriptorBackend::Buffer] is used whenever `VK_EXT_descriptor_buffer` and its feature are
+/// supported, unless [crate::abstraction::PhysicalDeviceRequirements::prefer_descriptor_pools]
+/// is set.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum DescriptorBackend {
+    /// Descriptor sets allocated from [super::DescriptorPool]
+    Pool,
+    /// Descriptors written straight into [super::DescriptorBuffer] using
+    /// `VK_EXT_descriptor_buffer`
+    Buffer,
+}
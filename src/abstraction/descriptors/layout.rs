@@ -0,0 +1,327 @@
+use crate::abstraction::prelude as abstraction;
+use ash::vk;
+use ash::vk::TaggedStructure;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// A single binding of a [DescriptorSetLayout]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct DescriptorSetLayoutBinding {
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    pub descriptor_count: u32,
+    pub stage_flags: vk::ShaderStageFlags,
+    pub binding_flags: vk::DescriptorBindingFlags,
+}
+
+/// Describes a [DescriptorSetLayout]
+///
+/// Bindings are kept sorted, so builders describing the same layout compare and hash equal
+/// no matter the order bindings were added in.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct DescriptorSetLayoutBuilder {
+    bindings: Vec<DescriptorSetLayoutBinding>,
+    flags: vk::DescriptorSetLayoutCreateFlags,
+}
+
+/// Whether bindings of `descriptor_type` may be updated after being bound
+fn supports_update_after_bind(descriptor_type: vk::DescriptorType) -> bool {
+    !matches!(
+        descriptor_type,
+        vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC | vk::DescriptorType::STORAGE_BUFFER_DYNAMIC
+    )
+}
+
+impl DescriptorSetLayoutBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a binding, replacing any previous binding with the same index
+    pub fn add_binding(
+        mut self,
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        descriptor_count: u32,
+        stage_flags: vk::ShaderStageFlags,
+    ) -> Self {
+        self.bindings.retain(|existing| existing.binding != binding);
+        let index = self
+            .bindings
+            .partition_point(|existing| existing.binding < binding);
+        self.bindings.insert(
+            index,
+            DescriptorSetLayoutBinding {
+                binding,
+                descriptor_type,
+                descriptor_count,
+                stage_flags,
+                binding_flags: if self
+                    .flags
+                    .contains(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+                    && supports_update_after_bind(descriptor_type)
+                {
+                    vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+                } else {
+                    vk::DescriptorBindingFlags::empty()
+                },
+            },
+        );
+        self
+    }
+
+    /// Set the [vk::DescriptorBindingFlags] of `binding`, which has to have been added already
+    pub fn binding_flags(mut self, binding: u32, binding_flags: vk::DescriptorBindingFlags) -> Self {
+        let existing = self
+            .bindings
+            .iter_mut()
+            .find(|existing| existing.binding == binding);
+        debug_assert!(existing.is_some(), "Binding {} has not been added", binding);
+        if let Some(existing) = existing {
+            existing.binding_flags = binding_flags;
+        }
+        self
+    }
+
+    /// Allow every binding to be updated after the set has been bound, except for dynamic
+    /// buffers which cannot be
+    ///
+    /// Sets of the layout have to be allocated from a pool created with
+    /// [vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND].
+    pub fn update_after_bind(mut self) -> Self {
+        self.flags |= vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL;
+        for binding in self.bindings.iter_mut() {
+            if supports_update_after_bind(binding.descriptor_type) {
+                binding.binding_flags |= vk::DescriptorBindingFlags::UPDATE_AFTER_BIND;
+            }
+        }
+        self
+    }
+
+    /// Allow sets of the layout to be written into a [super::DescriptorBuffer]
+    pub fn descriptor_buffer(mut self) -> Self {
+        self.flags |= vk::DescriptorSetLayoutCreateFlags::DESCRIPTOR_BUFFER_EXT;
+        self
+    }
+
+    /// Allow sets of the layout to be written the way `backend` stores descriptors, which only
+    /// changes anything for [super::DescriptorBackend::Buffer]
+    pub fn backend(self, backend: super::DescriptorBackend) -> Self {
+        match backend {
+            super::DescriptorBackend::Pool => self,
+            super::DescriptorBackend::Buffer => self.descriptor_buffer(),
+        }
+    }
+
+    /// Allow the layout to be pushed straight into command buffers instead of allocating sets
+    pub fn push_descriptor(mut self) -> Self {
+        self.flags |= vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR;
+        self
+    }
+
+    pub fn flags(mut self, flags: vk::DescriptorSetLayoutCreateFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn get_bindings(&self) -> &[DescriptorSetLayoutBinding] {
+        &self.bindings
+    }
+
+    pub fn build(self, device: abstraction::Device) -> Result<DescriptorSetLayout, vk::Result> {
+        DescriptorSetLayout::from_builder(device, self)
+    }
+}
+
+/// An abstraction for [vk::DescriptorSetLayout]
+pub struct DescriptorSetLayout {
+    handle: vk::DescriptorSetLayout,
+    device: abstraction::Device,
+    bindings: Vec<DescriptorSetLayoutBinding>,
+    flags: vk::DescriptorSetLayoutCreateFlags,
+}
+
+impl DescriptorSetLayout {
+    pub fn from_builder(
+        device: abstraction::Device,
+        builder: DescriptorSetLayoutBuilder,
+    ) -> Result<Self, vk::Result> {
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = builder
+            .bindings
+            .iter()
+            .map(|binding| vk::DescriptorSetLayoutBinding {
+                binding: binding.binding,
+                descriptor_type: binding.descriptor_type,
+                descriptor_count: binding.descriptor_count,
+                stage_flags: binding.stage_flags,
+                ..Default::default()
+            })
+            .collect();
+        let binding_flags: Vec<vk::DescriptorBindingFlags> = builder
+            .bindings
+            .iter()
+            .map(|binding| binding.binding_flags)
+            .collect();
+        let binding_flags_ci = vk::DescriptorSetLayoutBindingFlagsCreateInfo {
+            s_type: vk::DescriptorSetLayoutBindingFlagsCreateInfo::STRUCTURE_TYPE,
+            binding_count: binding_flags.len() as u32,
+            p_binding_flags: binding_flags.as_ptr(),
+            ..Default::default()
+        };
+        let has_binding_flags = binding_flags.iter().any(|flags| !flags.is_empty());
+        let layout_ci = vk::DescriptorSetLayoutCreateInfo {
+            s_type: vk::DescriptorSetLayoutCreateInfo::STRUCTURE_TYPE,
+            p_next: if has_binding_flags {
+                abstraction::utility::p_next(&binding_flags_ci)
+            } else {
+                std::ptr::null()
+            },
+            flags: builder.flags,
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+        };
+        let handle = unsafe {
+            device
+                .handle_as_ref()
+                .create_descriptor_set_layout(&layout_ci, None)?
+        };
+        Ok(Self {
+            handle,
+            device,
+            bindings: builder.bindings,
+            flags: builder.flags,
+        })
+    }
+
+    pub fn get_handle(&self) -> vk::DescriptorSetLayout {
+        self.handle
+    }
+
+    pub fn get_bindings(&self) -> &[DescriptorSetLayoutBinding] {
+        &self.bindings
+    }
+
+    pub fn get_binding(&self, binding: u32) -> Option<&DescriptorSetLayoutBinding> {
+        self.bindings
+            .iter()
+            .find(|existing| existing.binding == binding)
+    }
+
+    pub fn get_flags(&self) -> vk::DescriptorSetLayoutCreateFlags {
+        self.flags
+    }
+
+    pub fn is_update_after_bind(&self) -> bool {
+        self.flags
+            .contains(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+    }
+
+    pub fn get_device(&self) -> abstraction::Device {
+        self.device.clone()
+    }
+}
+
+impl Drop for DescriptorSetLayout {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .handle_as_ref()
+                .destroy_descriptor_set_layout(self.handle, None);
+        }
+    }
+}
+
+/// Deduplicates [DescriptorSetLayout], identical builders share the same layout
+pub struct DescriptorSetLayoutCache {
+    device: abstraction::Device,
+    layouts: Mutex<HashMap<DescriptorSetLayoutBuilder, Arc<DescriptorSetLayout>>>,
+}
+
+impl DescriptorSetLayoutCache {
+    pub fn new(device: abstraction::Device) -> Self {
+        Self {
+            device,
+            layouts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get the layout described by `builder`, creating it if no identical layout exists yet
+    pub fn get_or_create(
+        &self,
+        builder: DescriptorSetLayoutBuilder,
+    ) -> Result<Arc<DescriptorSetLayout>, vk::Result> {
+        let mut layouts = self.layouts.lock().unwrap();
+        if let Some(layout) = layouts.get(&builder) {
+            return Ok(layout.clone());
+        }
+        let layout = Arc::new(builder.clone().build(self.device.clone())?);
+        layouts.insert(builder, layout.clone());
+        Ok(layout)
+    }
+
+    pub fn get_device(&self) -> abstraction::Device {
+        self.device.clone()
+    }
+
+    /// Number of unique layouts created so far
+    pub fn len(&self) -> usize {
+        self.layouts.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Builders describing the same layout are equal regardless of binding order
+    fn test_builder_ordering() {
+        let stages = vk::ShaderStageFlags::FRAGMENT;
+        let first = DescriptorSetLayoutBuilder::new()
+            .add_binding(1, vk::DescriptorType::SAMPLED_IMAGE, 4, stages)
+            .add_binding(0, vk::DescriptorType::UNIFORM_BUFFER, 1, stages)
+            .update_after_bind();
+        let second = DescriptorSetLayoutBuilder::new()
+            .add_binding(0, vk::DescriptorType::UNIFORM_BUFFER, 1, stages)
+            .add_binding(1, vk::DescriptorType::SAMPLED_IMAGE, 4, stages)
+            .update_after_bind();
+        assert_eq!(first, second);
+        assert_eq!(first.get_bindings()[0].binding, 0);
+        assert_eq!(
+            first.get_bindings()[1].binding_flags,
+            vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+        );
+
+        let replaced = second.add_binding(1, vk::DescriptorType::STORAGE_IMAGE, 4, stages);
+        assert_eq!(replaced.get_bindings().len(), 2);
+        assert_ne!(first, replaced);
+    }
+
+    #[test]
+    /// Dynamic buffers are left out when allowing updates after binding
+    fn test_update_after_bind_dynamic() {
+        let stages = vk::ShaderStageFlags::VERTEX;
+        let builder = DescriptorSetLayoutBuilder::new()
+            .add_binding(0, vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1, stages)
+            .update_after_bind()
+            .add_binding(1, vk::DescriptorType::STORAGE_BUFFER_DYNAMIC, 1, stages)
+            .add_binding(2, vk::DescriptorType::STORAGE_BUFFER, 1, stages);
+        let flags: Vec<_> = builder
+            .get_bindings()
+            .iter()
+            .map(|binding| binding.binding_flags)
+            .collect();
+        assert_eq!(
+            flags,
+            [
+                vk::DescriptorBindingFlags::empty(),
+                vk::DescriptorBindingFlags::empty(),
+                vk::DescriptorBindingFlags::UPDATE_AFTER_BIND,
+            ]
+        );
+    }
+}
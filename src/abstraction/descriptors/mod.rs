@@ -1,3 +1,13 @@
+pub mod descriptor_buffer;
 pub mod enums;
+pub mod layout;
+pub mod pool;
+pub mod set;
+pub mod template;
 
+pub use descriptor_buffer::*;
 pub use enums::*;
+pub use layout::*;
+pub use pool::*;
+pub use set::*;
+pub use template::*;
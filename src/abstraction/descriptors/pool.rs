@@ -0,0 +1,200 @@
+use super::layout::DescriptorSetLayout;
+use super::set::DescriptorSet;
+use crate::abstraction::prelude as abstraction;
+use ash::vk;
+use ash::vk::TaggedStructure;
+use std::sync::Arc;
+
+/// Upper limit of sets a single pool created by [DescriptorPool] grows to
+const MAX_SETS_PER_POOL: u32 = 4096;
+
+/// Share of descriptors of a single type per set allocated from a [DescriptorPool]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PoolSizeRatio {
+    pub descriptor_type: vk::DescriptorType,
+    pub ratio: f32,
+}
+
+/// Get the pool sizes of a pool with room for `max_sets` sets
+fn get_pool_sizes(ratios: &[PoolSizeRatio], max_sets: u32) -> Vec<vk::DescriptorPoolSize> {
+    ratios
+        .iter()
+        .map(|ratio| vk::DescriptorPoolSize {
+            ty: ratio.descriptor_type,
+            descriptor_count: ((ratio.ratio * max_sets as f32).ceil() as u32).max(1),
+        })
+        .collect()
+}
+
+/// A growable collection of [vk::DescriptorPool]
+///
+/// Once a pool runs out of memory a new, larger, pool is created instead of failing the
+/// allocation. Sets are never freed individually, every pool is reset at once instead.
+pub struct DescriptorPool {
+    device: abstraction::Device,
+    flags: vk::DescriptorPoolCreateFlags,
+    ratios: Vec<PoolSizeRatio>,
+    /// Sets the next created pool has room for
+    sets_per_pool: u32,
+    /// Pools which may still have room for more sets, the last one is allocated from
+    ready_pools: Vec<vk::DescriptorPool>,
+    /// Pools which ran out of memory
+    full_pools: Vec<vk::DescriptorPool>,
+}
+
+impl DescriptorPool {
+    pub fn new(
+        device: abstraction::Device,
+        flags: vk::DescriptorPoolCreateFlags,
+        ratios: &[PoolSizeRatio],
+        initial_sets: u32,
+    ) -> Result<Self, vk::Result> {
+        let mut pool = Self {
+            device,
+            flags,
+            ratios: ratios.to_vec(),
+            sets_per_pool: initial_sets.max(1),
+            ready_pools: Vec::new(),
+            full_pools: Vec::new(),
+        };
+        let handle = pool.create_pool()?;
+        pool.ready_pools.push(handle);
+        Ok(pool)
+    }
+
+    /// Create a pool with room for [Self::sets_per_pool] sets and grow the size of the next one
+    fn create_pool(&mut self) -> Result<vk::DescriptorPool, vk::Result> {
+        let pool_sizes = get_pool_sizes(&self.ratios, self.sets_per_pool);
+        let pool_ci = vk::DescriptorPoolCreateInfo {
+            s_type: vk::DescriptorPoolCreateInfo::STRUCTURE_TYPE,
+            flags: self.flags,
+            max_sets: self.sets_per_pool,
+            pool_size_count: pool_sizes.len() as u32,
+            p_pool_sizes: pool_sizes.as_ptr(),
+            ..Default::default()
+        };
+        let handle = unsafe {
+            self.device
+                .handle_as_ref()
+                .create_descriptor_pool(&pool_ci, None)?
+        };
+        self.sets_per_pool = (self.sets_per_pool + self.sets_per_pool / 2).min(MAX_SETS_PER_POOL);
+        Ok(handle)
+    }
+
+    fn allocate_from(
+        &self,
+        pool: vk::DescriptorPool,
+        layout: &DescriptorSetLayout,
+    ) -> Result<vk::DescriptorSet, vk::Result> {
+        let layout_handle = layout.get_handle();
+        let set_ai = vk::DescriptorSetAllocateInfo {
+            s_type: vk::DescriptorSetAllocateInfo::STRUCTURE_TYPE,
+            descriptor_pool: pool,
+            descriptor_set_count: 1,
+            p_set_layouts: &layout_handle,
+            ..Default::default()
+        };
+        unsafe {
+            self.device
+                .handle_as_ref()
+                .allocate_descriptor_sets(&set_ai)
+                .map(|sets| sets[0])
+        }
+    }
+
+    /// Allocate a set of `layout`, creating a new pool if the current one is out of memory
+    pub fn allocate(&mut self, layout: Arc<DescriptorSetLayout>) -> Result<DescriptorSet, vk::Result> {
+        debug_assert!(
+            !layout.is_update_after_bind()
+                || self
+                    .flags
+                    .contains(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND),
+            "Update after bind layouts need an update after bind pool"
+        );
+        let mut pool = match self.ready_pools.last() {
+            Some(pool) => *pool,
+            None => {
+                let pool = self.create_pool()?;
+                self.ready_pools.push(pool);
+                pool
+            }
+        };
+        let handle = match self.allocate_from(pool, &layout) {
+            Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY) | Err(vk::Result::ERROR_FRAGMENTED_POOL) => {
+                self.full_pools.push(self.ready_pools.pop().unwrap());
+                pool = match self.ready_pools.last() {
+                    Some(pool) => *pool,
+                    None => {
+                        let pool = self.create_pool()?;
+                        self.ready_pools.push(pool);
+                        pool
+                    }
+                };
+                self.allocate_from(pool, &layout)?
+            }
+            result => result?,
+        };
+        Ok(DescriptorSet::from_vk(handle, self.device.clone(), layout))
+    }
+
+    /// Reset every pool, making their memory available for new sets
+    ///
+    /// # Safety
+    /// Sets allocated from the pool become invalid, none of them may be used by command
+    /// buffers which have not finished executing.
+    pub unsafe fn reset(&mut self) -> Result<(), vk::Result> {
+        self.ready_pools.append(&mut self.full_pools);
+        for pool in self.ready_pools.iter() {
+            self.device
+                .handle_as_ref()
+                .reset_descriptor_pool(*pool, vk::DescriptorPoolResetFlags::empty())?;
+        }
+        Ok(())
+    }
+
+    pub fn get_flags(&self) -> vk::DescriptorPoolCreateFlags {
+        self.flags
+    }
+
+    pub fn get_device(&self) -> abstraction::Device {
+        self.device.clone()
+    }
+}
+
+impl Drop for DescriptorPool {
+    fn drop(&mut self) {
+        for pool in self.ready_pools.drain(..).chain(self.full_pools.drain(..)) {
+            unsafe { self.device.handle_as_ref().destroy_descriptor_pool(pool, None) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Every descriptor type gets its share of the sets, rounded up
+    fn test_pool_sizes() {
+        let pool_sizes = get_pool_sizes(
+            &[
+                PoolSizeRatio {
+                    descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                    ratio: 1.0,
+                },
+                PoolSizeRatio {
+                    descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    ratio: 2.5,
+                },
+                PoolSizeRatio {
+                    descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+                    ratio: 0.01,
+                },
+            ],
+            3,
+        );
+        let counts: Vec<u32> = pool_sizes.iter().map(|size| size.descriptor_count).collect();
+        assert_eq!(counts, vec![3, 8, 1]);
+    }
+}
@@ -0,0 +1,245 @@
+use super::enums::DescriptorInfo;
+use super::layout::DescriptorSetLayout;
+use crate::abstraction::prelude as abstraction;
+use abstraction::Resource;
+use ash::vk;
+use ash::vk::TaggedStructure;
+use std::sync::Arc;
+
+/// An abstraction for [vk::DescriptorSet]
+///
+/// Sets are freed along with the [super::DescriptorPool] they were allocated from.
+pub struct DescriptorSet {
+    handle: vk::DescriptorSet,
+    device: abstraction::Device,
+    layout: Arc<DescriptorSetLayout>,
+}
+
+impl DescriptorSet {
+    /// Wrap an existing [vk::DescriptorSet] allocated with `layout`
+    pub fn from_vk(
+        handle: vk::DescriptorSet,
+        device: abstraction::Device,
+        layout: Arc<DescriptorSetLayout>,
+    ) -> Self {
+        Self {
+            handle,
+            device,
+            layout,
+        }
+    }
+
+    pub fn get_handle(&self) -> vk::DescriptorSet {
+        self.handle
+    }
+
+    pub fn get_layout(&self) -> &Arc<DescriptorSetLayout> {
+        &self.layout
+    }
+}
+
+impl DescriptorWriter for DescriptorSet {
+    fn write_descriptor(
+        &self,
+        binding: u32,
+        array_element: u32,
+        descriptor_type: vk::DescriptorType,
+        descriptor: DescriptorInfo,
+    ) -> Result<(), vk::Result> {
+        debug_assert_eq!(
+            self.layout
+                .get_binding(binding)
+                .map(|layout_binding| layout_binding.descriptor_type),
+            Some(descriptor_type),
+            "Binding {} does not exist or has a different descriptor type",
+            binding
+        );
+        let mut write = vk::WriteDescriptorSet {
+            s_type: vk::WriteDescriptorSet::STRUCTURE_TYPE,
+            dst_set: self.handle,
+            dst_binding: binding,
+            dst_array_element: array_element,
+            descriptor_count: 1,
+            descriptor_type,
+            ..Default::default()
+        };
+        let descriptor = match descriptor {
+            DescriptorInfo::Image(image_info) => DescriptorInfo::Image(vk::DescriptorImageInfo {
+                image_layout: get_image_layout(descriptor_type, image_info.image_layout),
+                ..image_info
+            }),
+            descriptor => descriptor,
+        };
+        match &descriptor {
+            DescriptorInfo::Image(image_info) => write.p_image_info = image_info,
+            DescriptorInfo::Buffer(buffer_info) => write.p_buffer_info = buffer_info,
+        }
+        unsafe { self.device.handle_as_ref().update_descriptor_sets(&[write], &[]) };
+        Ok(())
+    }
+}
+
+/// Writes descriptors into a set, implemented by every descriptor backend
+pub trait DescriptorWriter {
+    /// Write `descriptor` into `array_element` of `binding`
+    ///
+    /// Image descriptors are written in the layout they were given, or the layout expected by
+    /// `descriptor_type` if that is [vk::ImageLayout::UNDEFINED].
+    fn write_descriptor(
+        &self,
+        binding: u32,
+        array_element: u32,
+        descriptor_type: vk::DescriptorType,
+        descriptor: DescriptorInfo,
+    ) -> Result<(), vk::Result>;
+
+    /// Write `range` bytes of `buffer` starting at `offset` into `array_element` of `binding`
+    ///
+    /// A `range` of [vk::WHOLE_SIZE] covers the rest of the buffer.
+    fn write_buffer(
+        &self,
+        binding: u32,
+        array_element: u32,
+        descriptor_type: vk::DescriptorType,
+        buffer: &abstraction::Buffer,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+    ) -> Result<(), vk::Result> {
+        self.write_descriptor(
+            binding,
+            array_element,
+            descriptor_type,
+            DescriptorInfo::Buffer(vk::DescriptorBufferInfo {
+                buffer: buffer.get_handle(),
+                offset,
+                range,
+            }),
+        )
+    }
+
+    fn write_uniform_buffer(
+        &self,
+        binding: u32,
+        array_element: u32,
+        buffer: &abstraction::Buffer,
+    ) -> Result<(), vk::Result> {
+        self.write_buffer(
+            binding,
+            array_element,
+            vk::DescriptorType::UNIFORM_BUFFER,
+            buffer,
+            0,
+            vk::WHOLE_SIZE,
+        )
+    }
+
+    fn write_storage_buffer(
+        &self,
+        binding: u32,
+        array_element: u32,
+        buffer: &abstraction::Buffer,
+    ) -> Result<(), vk::Result> {
+        self.write_buffer(
+            binding,
+            array_element,
+            vk::DescriptorType::STORAGE_BUFFER,
+            buffer,
+            0,
+            vk::WHOLE_SIZE,
+        )
+    }
+
+    /// Write `resource` in the layout expected by the descriptor type, use
+    /// [DescriptorWriter::write_descriptor] to write it in another layout
+    fn write_sampled_image(
+        &self,
+        binding: u32,
+        array_element: u32,
+        resource: &dyn Resource,
+    ) -> Result<(), vk::Result> {
+        self.write_descriptor(
+            binding,
+            array_element,
+            vk::DescriptorType::SAMPLED_IMAGE,
+            get_untracked_descriptor(resource),
+        )
+    }
+
+    /// Write `resource` in the layout expected by the descriptor type, use
+    /// [DescriptorWriter::write_descriptor] to write it in another layout
+    fn write_storage_image(
+        &self,
+        binding: u32,
+        array_element: u32,
+        resource: &dyn Resource,
+    ) -> Result<(), vk::Result> {
+        self.write_descriptor(
+            binding,
+            array_element,
+            vk::DescriptorType::STORAGE_IMAGE,
+            get_untracked_descriptor(resource),
+        )
+    }
+
+    /// Write an image along with its own sampler, in the layout expected by the descriptor type
+    fn write_combined_image_sampler(
+        &self,
+        binding: u32,
+        array_element: u32,
+        resource: &dyn Resource,
+    ) -> Result<(), vk::Result> {
+        self.write_descriptor(
+            binding,
+            array_element,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            get_untracked_descriptor(resource),
+        )
+    }
+
+    fn write_sampler(
+        &self,
+        binding: u32,
+        array_element: u32,
+        sampler: vk::Sampler,
+    ) -> Result<(), vk::Result> {
+        self.write_descriptor(
+            binding,
+            array_element,
+            vk::DescriptorType::SAMPLER,
+            DescriptorInfo::Image(vk::DescriptorImageInfo {
+                sampler,
+                ..Default::default()
+            }),
+        )
+    }
+}
+
+/// Get the descriptor of `resource` without the layout it was last used in, so images are
+/// written in the layout expected by the descriptor type
+fn get_untracked_descriptor(resource: &dyn Resource) -> DescriptorInfo {
+    match resource.get_descriptor() {
+        DescriptorInfo::Image(image_info) => DescriptorInfo::Image(vk::DescriptorImageInfo {
+            image_layout: vk::ImageLayout::UNDEFINED,
+            ..image_info
+        }),
+        descriptor => descriptor,
+    }
+}
+
+/// Get the layout images are accessed in through descriptors of `descriptor_type`, unless the
+/// descriptor already has a layout other than [vk::ImageLayout::UNDEFINED]
+pub(crate) fn get_image_layout(
+    descriptor_type: vk::DescriptorType,
+    image_layout: vk::ImageLayout,
+) -> vk::ImageLayout {
+    if image_layout != vk::ImageLayout::UNDEFINED {
+        return image_layout;
+    }
+    match descriptor_type {
+        vk::DescriptorType::SAMPLED_IMAGE
+        | vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+        | vk::DescriptorType::INPUT_ATTACHMENT => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        vk::DescriptorType::STORAGE_IMAGE => vk::ImageLayout::GENERAL,
+        _ => image_layout,
+    }
+}
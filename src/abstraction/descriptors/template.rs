@@ -0,0 +1,354 @@
+use super::enums::DescriptorInfo;
+use super::layout::{DescriptorSetLayout, DescriptorSetLayoutBuilder};
+use super::set::{get_image_layout, DescriptorSet};
+use crate::abstraction::prelude as abstraction;
+use ash::vk;
+use ash::vk::TaggedStructure;
+use std::marker::PhantomData;
+
+/// A binding written from a single field of a [DescriptorTemplate]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct TemplateBinding {
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+}
+
+/// A struct whose fields map to the bindings of a set, usually implemented through
+/// [crate::descriptor_template]
+pub trait DescriptorTemplate {
+    /// Get the binding of every field, in the same order as [Self::get_descriptors]
+    fn get_bindings() -> Vec<TemplateBinding>;
+
+    /// Get the descriptor of every field
+    fn get_descriptors(&self) -> Vec<DescriptorInfo>;
+
+    /// Get a layout containing every binding of the struct, usable with `backend`
+    fn get_layout_builder(
+        backend: abstraction::DescriptorBackend,
+        stage_flags: vk::ShaderStageFlags,
+    ) -> DescriptorSetLayoutBuilder {
+        Self::get_bindings()
+            .into_iter()
+            .fold(DescriptorSetLayoutBuilder::new(), |builder, binding| {
+                builder.add_binding(binding.binding, binding.descriptor_type, 1, stage_flags)
+            })
+            .backend(backend)
+    }
+}
+
+/// Declare a struct of [DescriptorInfo] fields which implements [DescriptorTemplate]
+///
+/// Every field is annotated with the binding and descriptor type it is written to.
+/// ```
+/// use ash::vk;
+/// use dagal::abstraction::prelude::{DescriptorInfo, DescriptorTemplate};
+///
+/// dagal::descriptor_template! {
+///     pub struct MaterialDescriptors {
+///         #[descriptor(binding = 0, ty = vk::DescriptorType::UNIFORM_BUFFER)]
+///         pub parameters: DescriptorInfo,
+///         #[descriptor(binding = 1, ty = vk::DescriptorType::COMBINED_IMAGE_SAMPLER)]
+///         pub albedo: DescriptorInfo,
+///     }
+/// }
+///
+/// let bindings = MaterialDescriptors::get_bindings();
+/// assert_eq!(bindings[1].binding, 1);
+/// assert_eq!(bindings[1].descriptor_type, vk::DescriptorType::COMBINED_IMAGE_SAMPLER);
+/// ```
+#[macro_export]
+macro_rules! descriptor_template {
+    (
+        $(#[$struct_meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                #[descriptor(binding = $binding:expr, ty = $descriptor_type:expr)]
+                $field_vis:vis $field:ident: $field_type:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$struct_meta])*
+        $vis struct $name {
+            $($field_vis $field: $field_type),*
+        }
+
+        impl $crate::abstraction::descriptors::DescriptorTemplate for $name {
+            fn get_bindings() -> Vec<$crate::abstraction::descriptors::TemplateBinding> {
+                vec![$($crate::abstraction::descriptors::TemplateBinding {
+                    binding: $binding,
+                    descriptor_type: $descriptor_type,
+                }),*]
+            }
+
+            fn get_descriptors(&self) -> Vec<$crate::abstraction::descriptors::DescriptorInfo> {
+                vec![$(self.$field),*]
+            }
+        }
+    };
+}
+
+/// A single descriptor as read by [vk::DescriptorUpdateTemplate]
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) union RawDescriptor {
+    image: vk::DescriptorImageInfo,
+    buffer: vk::DescriptorBufferInfo,
+}
+
+/// Get the descriptors of `data`, with images in the layout their descriptor type expects
+pub(crate) fn get_descriptors<T: DescriptorTemplate>(data: &T) -> Vec<(TemplateBinding, DescriptorInfo)> {
+    let bindings = T::get_bindings();
+    let descriptors = data.get_descriptors();
+    debug_assert_eq!(bindings.len(), descriptors.len());
+    bindings
+        .into_iter()
+        .zip(descriptors)
+        .map(|(binding, descriptor)| {
+            let descriptor = match descriptor {
+                DescriptorInfo::Image(image_info) => DescriptorInfo::Image(vk::DescriptorImageInfo {
+                    image_layout: get_image_layout(binding.descriptor_type, image_info.image_layout),
+                    ..image_info
+                }),
+                descriptor => descriptor,
+            };
+            (binding, descriptor)
+        })
+        .collect()
+}
+
+/// Flatten the descriptors of `data` into the memory layout described by the template entries
+fn get_template_data<T: DescriptorTemplate>(data: &T) -> Vec<RawDescriptor> {
+    get_descriptors(data)
+        .into_iter()
+        .map(|(_, descriptor)| match descriptor {
+            DescriptorInfo::Image(image) => RawDescriptor { image },
+            DescriptorInfo::Buffer(buffer) => RawDescriptor { buffer },
+        })
+        .collect()
+}
+
+/// Get the [vk::WriteDescriptorSet] writing every descriptor of `descriptors` into `set`
+///
+/// Writes point into `descriptors`, which has to outlive them.
+pub(crate) fn get_writes(
+    set: vk::DescriptorSet,
+    descriptors: &[(TemplateBinding, DescriptorInfo)],
+) -> Vec<vk::WriteDescriptorSet> {
+    descriptors
+        .iter()
+        .map(|(binding, descriptor)| {
+            let mut write = vk::WriteDescriptorSet {
+                s_type: vk::WriteDescriptorSet::STRUCTURE_TYPE,
+                dst_set: set,
+                dst_binding: binding.binding,
+                descriptor_count: 1,
+                descriptor_type: binding.descriptor_type,
+                ..Default::default()
+            };
+            match descriptor {
+                DescriptorInfo::Image(image_info) => write.p_image_info = image_info,
+                DescriptorInfo::Buffer(buffer_info) => write.p_buffer_info = buffer_info,
+            }
+            write
+        })
+        .collect()
+}
+
+/// An abstraction for [vk::DescriptorUpdateTemplate] writing every field of `T` at once
+pub struct DescriptorUpdateTemplate<T: DescriptorTemplate> {
+    handle: vk::DescriptorUpdateTemplate,
+    device: abstraction::Device,
+    template_type: vk::DescriptorUpdateTemplateType,
+    /// Layout the set is pushed to, only used by push descriptor templates
+    pipeline_layout: vk::PipelineLayout,
+    set: u32,
+    phantom_marker: PhantomData<fn(&T)>,
+}
+
+impl<T: DescriptorTemplate> DescriptorUpdateTemplate<T> {
+    /// Create a template updating sets allocated with `layout`
+    pub fn new(device: abstraction::Device, layout: &DescriptorSetLayout) -> Result<Self, vk::Result> {
+        Self::create(
+            device,
+            layout,
+            vk::DescriptorUpdateTemplateType::DESCRIPTOR_SET,
+            vk::PipelineBindPoint::GRAPHICS,
+            vk::PipelineLayout::null(),
+            0,
+        )
+    }
+
+    /// Create a template pushing descriptors to `set` of `pipeline_layout`, see
+    /// [abstraction::CommandBuffer::push_descriptor_set_with_template]
+    pub fn new_push(
+        device: abstraction::Device,
+        layout: &DescriptorSetLayout,
+        bind_point: vk::PipelineBindPoint,
+        pipeline_layout: vk::PipelineLayout,
+        set: u32,
+    ) -> Result<Self, vk::Result> {
+        Self::create(
+            device,
+            layout,
+            vk::DescriptorUpdateTemplateType::PUSH_DESCRIPTORS_KHR,
+            bind_point,
+            pipeline_layout,
+            set,
+        )
+    }
+
+    fn create(
+        device: abstraction::Device,
+        layout: &DescriptorSetLayout,
+        template_type: vk::DescriptorUpdateTemplateType,
+        bind_point: vk::PipelineBindPoint,
+        pipeline_layout: vk::PipelineLayout,
+        set: u32,
+    ) -> Result<Self, vk::Result> {
+        let stride = std::mem::size_of::<RawDescriptor>();
+        let entries: Vec<vk::DescriptorUpdateTemplateEntry> = T::get_bindings()
+            .iter()
+            .enumerate()
+            .map(|(index, binding)| vk::DescriptorUpdateTemplateEntry {
+                dst_binding: binding.binding,
+                dst_array_element: 0,
+                descriptor_count: 1,
+                descriptor_type: binding.descriptor_type,
+                offset: index * stride,
+                stride,
+            })
+            .collect();
+        let template_ci = vk::DescriptorUpdateTemplateCreateInfo {
+            s_type: vk::DescriptorUpdateTemplateCreateInfo::STRUCTURE_TYPE,
+            descriptor_update_entry_count: entries.len() as u32,
+            p_descriptor_update_entries: entries.as_ptr(),
+            template_type,
+            descriptor_set_layout: layout.get_handle(),
+            pipeline_bind_point: bind_point,
+            pipeline_layout,
+            set,
+            ..Default::default()
+        };
+        let handle = unsafe {
+            device
+                .handle_as_ref()
+                .create_descriptor_update_template(&template_ci, None)?
+        };
+        Ok(Self {
+            handle,
+            device,
+            template_type,
+            pipeline_layout,
+            set,
+            phantom_marker: PhantomData,
+        })
+    }
+
+    /// Write every field of `data` into `set`
+    pub fn update(&self, set: &DescriptorSet, data: &T) {
+        debug_assert_eq!(self.template_type, vk::DescriptorUpdateTemplateType::DESCRIPTOR_SET);
+        let template_data = get_template_data(data);
+        unsafe {
+            self.device.handle_as_ref().update_descriptor_set_with_template(
+                set.get_handle(),
+                self.handle,
+                template_data.as_ptr() as *const _,
+            );
+        }
+    }
+
+    /// Push every field of `data` into `command_buffer`
+    pub(crate) fn push(&self, command_buffer: vk::CommandBuffer, data: &T) -> Result<(), vk::Result> {
+        debug_assert_eq!(
+            self.template_type,
+            vk::DescriptorUpdateTemplateType::PUSH_DESCRIPTORS_KHR
+        );
+        let loader = self
+            .device
+            .get_push_descriptor_loader()
+            .ok_or(vk::Result::ERROR_EXTENSION_NOT_PRESENT)?;
+        let template_data = get_template_data(data);
+        unsafe {
+            loader.cmd_push_descriptor_set_with_template(
+                command_buffer,
+                self.handle,
+                self.pipeline_layout,
+                self.set,
+                template_data.as_ptr() as *const _,
+            );
+        }
+        Ok(())
+    }
+
+    pub fn get_handle(&self) -> vk::DescriptorUpdateTemplate {
+        self.handle
+    }
+}
+
+impl<T: DescriptorTemplate> Drop for DescriptorUpdateTemplate<T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .handle_as_ref()
+                .destroy_descriptor_update_template(self.handle, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::descriptor_template! {
+        struct TestDescriptors {
+            #[descriptor(binding = 2, ty = vk::DescriptorType::STORAGE_IMAGE)]
+            output: DescriptorInfo,
+            #[descriptor(binding = 0, ty = vk::DescriptorType::UNIFORM_BUFFER)]
+            parameters: DescriptorInfo,
+        }
+    }
+
+    #[test]
+    /// Fields are flattened in declaration order with images in the layout of their binding
+    /// unless they have one
+    fn test_template_data() {
+        let data = TestDescriptors {
+            output: DescriptorInfo::Image(vk::DescriptorImageInfo {
+                image_layout: vk::ImageLayout::UNDEFINED,
+                ..Default::default()
+            }),
+            parameters: DescriptorInfo::Buffer(vk::DescriptorBufferInfo {
+                offset: 64,
+                range: 256,
+                ..Default::default()
+            }),
+        };
+        let template_data = get_template_data(&data);
+        assert_eq!(template_data.len(), 2);
+        unsafe {
+            assert_eq!(template_data[0].image.image_layout, vk::ImageLayout::GENERAL);
+            assert_eq!(template_data[1].buffer.range, 256);
+        }
+
+        // A layout given by the caller is kept
+        let data = TestDescriptors {
+            output: DescriptorInfo::Image(vk::DescriptorImageInfo {
+                image_layout: vk::ImageLayout::SHARED_PRESENT_KHR,
+                ..Default::default()
+            }),
+            ..data
+        };
+        let template_data = get_template_data(&data);
+        unsafe {
+            assert_eq!(template_data[0].image.image_layout, vk::ImageLayout::SHARED_PRESENT_KHR);
+        }
+
+        let layout = TestDescriptors::get_layout_builder(abstraction::DescriptorBackend::Pool, vk::ShaderStageFlags::COMPUTE);
+        let bindings: Vec<u32> = layout.get_bindings().iter().map(|binding| binding.binding).collect();
+        assert_eq!(bindings, vec![0, 2]);
+        assert_eq!(
+            TestDescriptors::get_layout_builder(abstraction::DescriptorBackend::Buffer, vk::ShaderStageFlags::COMPUTE),
+            layout.descriptor_buffer()
+        );
+    }
+}
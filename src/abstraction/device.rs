@@ -2,14 +2,31 @@ use crate::abstraction::prelude as abstraction;
 use ash::vk;
 use ash::vk::TaggedStructure;
 use std::ffi::{c_char, c_void, CString};
+use std::path::Path;
 use std::ptr;
 use std::sync::Arc;
-use crate::abstraction::utility::deferred_deletion_queue::DeferredDeletionQueue;
 
 /// Provides information on queue family on the device
+#[derive(Copy, Clone, Debug)]
 pub struct DeviceFamilyQueue {
     flags: vk::QueueFlags,
     index: u32,
+    /// Amount of queues created in the family
+    count: u32,
+}
+
+impl DeviceFamilyQueue {
+    pub fn get_flags(&self) -> vk::QueueFlags {
+        self.flags
+    }
+
+    pub fn get_index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn get_count(&self) -> u32 {
+        self.count
+    }
 }
 
 // Thanks phobos-rs :)
@@ -18,15 +35,45 @@ pub struct DeviceFamilyQueue {
 /// lifetime management.
 pub struct DeviceInner {
     handle: ash::Device,
+    /// The [vk::PhysicalDevice] the device was created from
+    physical_device: vk::PhysicalDevice,
     /// Features of the underlying [vk::PhysicalDevice]
     physical_device_features: vk::PhysicalDeviceFeatures2,
     /// All queue families
     queue_families: Vec<DeviceFamilyQueue>,
+    /// Vulkan version supported by the underlying [vk::PhysicalDevice]
+    api_version: u32,
+    /// Memory heaps and types of the underlying [vk::PhysicalDevice]
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    descriptor_backend: abstraction::DescriptorBackend,
+    /// Loader of `VK_EXT_descriptor_buffer`, only loaded for [abstraction::DescriptorBackend::Buffer]
+    descriptor_buffer_loader: Option<ash::extensions::ext::DescriptorBuffer>,
+    descriptor_buffer_properties: vk::PhysicalDeviceDescriptorBufferPropertiesEXT,
+    /// Loader of `VK_KHR_push_descriptor`, only loaded if supported
+    push_descriptor_loader: Option<ash::extensions::khr::PushDescriptor>,
+    /// Loader of `VK_KHR_swapchain`, only loaded if supported
+    swapchain_loader: Option<abstraction::SwapchainLoader>,
+    /// Loader of `VK_EXT_extended_dynamic_state3`, only loaded if supported
+    extended_dynamic_state_3_loader: Option<ash::extensions::ext::ExtendedDynamicState3>,
+    /// Features of `VK_EXT_extended_dynamic_state3` which were enabled
+    extended_dynamic_state_3_features: vk::PhysicalDeviceExtendedDynamicState3FeaturesEXT,
+    /// Properties of `VK_EXT_graphics_pipeline_library`, only queried if supported
+    graphics_pipeline_library_properties: Option<vk::PhysicalDeviceGraphicsPipelineLibraryPropertiesEXT>,
+    /// Cache every pipeline is created with
+    pipeline_cache: vk::PipelineCache,
+    /// Identifies this device and driver in saved pipeline caches
+    pipeline_cache_header: abstraction::PipelineCacheHeader,
 }
 
+// The only raw pointers held are the `p_next` of the stored features and properties which are
+// never dereferenced as they are nulled out once queried.
+unsafe impl Send for DeviceInner {}
+unsafe impl Sync for DeviceInner {}
+
 impl Drop for DeviceInner {
     fn drop(&mut self) {
         unsafe {
+            self.handle.destroy_pipeline_cache(self.pipeline_cache, None);
             self.handle.destroy_device(None);
         }
     }
@@ -54,7 +101,7 @@ impl Device {
         // TODO: allow user to manually pick and choose with queues are used
         let gpu_requirements = physical_device.get_gpu_requirements();
         // keeps tracks of all queue requirements that have been met and as such can be ignored by future iterations
-        let queue_requirements_met: Vec<bool> = vec![false; gpu_requirements.queues.len()];
+        let mut queue_requirements_met: Vec<bool> = vec![false; gpu_requirements.queues.len()];
         let queue_cis: Vec<vk::DeviceQueueCreateInfo> = physical_device
             .get_queue_families()
             .iter()
@@ -82,12 +129,13 @@ impl Device {
                         if (queue_requirements.dedicated
                             && queue_requirements.queue_flags
                                 == queue_family.queue_family_properties.queue_flags)
-                            && (!queue_requirements.dedicated
+                            || (!queue_requirements.dedicated
                                 && queue_family
                                     .queue_family_properties
                                     .queue_flags
                                     .contains(queue_requirements.queue_flags))
                         {
+                            queue_requirements_met[queue_requirements_index] = true;
                             queue_family_used_slots += queue_requirements.count;
                             return queue_requirements.count;
                         }
@@ -97,9 +145,6 @@ impl Device {
                 // If queue_count is zero, we don't need to make a queue at all
                 if queue_count == 0 {
                     return None;
-                } else if queue_requirements_met.iter().all(|x| *x) == false {
-                    // queue requirements were not met and as such, we can ignore this device
-                    return None;
                 }
                 Some(vk::DeviceQueueCreateInfo {
                     s_type: vk::DeviceQueueCreateInfo::STRUCTURE_TYPE,
@@ -111,8 +156,104 @@ impl Device {
                 })
             })
             .collect();
-        let c_strs: Vec<CString> = gpu_requirements
-            .extensions
+        // Pipelines cannot mix descriptor buffers with sets allocated from pools, so descriptor
+        // buffers are used for everything whenever they are supported
+        let descriptor_buffer_name = ash::extensions::ext::DescriptorBuffer::name()
+            .to_str()
+            .unwrap();
+        let mut supported_descriptor_buffer_features = vk::PhysicalDeviceDescriptorBufferFeaturesEXT::default();
+        if !gpu_requirements.prefer_descriptor_pools
+            && physical_device.has_extensions(&[descriptor_buffer_name])
+        {
+            let mut features_2 = vk::PhysicalDeviceFeatures2 {
+                s_type: vk::PhysicalDeviceFeatures2::STRUCTURE_TYPE,
+                p_next: abstraction::utility::p_next_mut(&mut supported_descriptor_buffer_features),
+                ..Default::default()
+            };
+            unsafe {
+                instance
+                    .get_vk_instance()
+                    .get_physical_device_features2(*physical_device.get_handle(), &mut features_2)
+            };
+        }
+        let descriptor_backend = if supported_descriptor_buffer_features.descriptor_buffer == vk::TRUE {
+            abstraction::DescriptorBackend::Buffer
+        } else {
+            abstraction::DescriptorBackend::Pool
+        };
+        let push_descriptor_name = ash::extensions::khr::PushDescriptor::name()
+            .to_str()
+            .unwrap();
+        let supports_push_descriptor = physical_device.has_extensions(&[push_descriptor_name]);
+        // Every supported state of extended dynamic state 3 is enabled
+        let extended_dynamic_state_3_name = ash::extensions::ext::ExtendedDynamicState3::name()
+            .to_str()
+            .unwrap();
+        let supports_extended_dynamic_state_3 =
+            physical_device.has_extensions(&[extended_dynamic_state_3_name]);
+        let mut extended_dynamic_state_3_features =
+            vk::PhysicalDeviceExtendedDynamicState3FeaturesEXT::default();
+        if supports_extended_dynamic_state_3 {
+            let mut features_2 = vk::PhysicalDeviceFeatures2 {
+                s_type: vk::PhysicalDeviceFeatures2::STRUCTURE_TYPE,
+                p_next: abstraction::utility::p_next_mut(&mut extended_dynamic_state_3_features),
+                ..Default::default()
+            };
+            unsafe {
+                instance
+                    .get_vk_instance()
+                    .get_physical_device_features2(*physical_device.get_handle(), &mut features_2)
+            };
+            extended_dynamic_state_3_features.p_next = ptr::null_mut();
+        }
+        // Graphics pipeline libraries are only used if the feature is supported as well
+        let pipeline_library_name = vk::KhrPipelineLibraryFn::name().to_str().unwrap();
+        let graphics_pipeline_library_name = vk::ExtGraphicsPipelineLibraryFn::name()
+            .to_str()
+            .unwrap();
+        let mut graphics_pipeline_library_features =
+            vk::PhysicalDeviceGraphicsPipelineLibraryFeaturesEXT::default();
+        if physical_device.has_extensions(&[pipeline_library_name, graphics_pipeline_library_name]) {
+            let mut features_2 = vk::PhysicalDeviceFeatures2 {
+                s_type: vk::PhysicalDeviceFeatures2::STRUCTURE_TYPE,
+                p_next: abstraction::utility::p_next_mut(&mut graphics_pipeline_library_features),
+                ..Default::default()
+            };
+            unsafe {
+                instance
+                    .get_vk_instance()
+                    .get_physical_device_features2(*physical_device.get_handle(), &mut features_2)
+            };
+        }
+        let supports_graphics_pipeline_library =
+            graphics_pipeline_library_features.graphics_pipeline_library == vk::TRUE;
+        let swapchain_name = ash::extensions::khr::Swapchain::name().to_str().unwrap();
+        let supports_swapchain = physical_device.has_extensions(&[swapchain_name]);
+        // Optional extensions are enabled whenever they are supported
+        let mut optional_extensions = Vec::new();
+        if descriptor_backend == abstraction::DescriptorBackend::Buffer {
+            optional_extensions.push(descriptor_buffer_name);
+        }
+        if supports_push_descriptor {
+            optional_extensions.push(push_descriptor_name);
+        }
+        if supports_swapchain {
+            optional_extensions.push(swapchain_name);
+        }
+        if supports_extended_dynamic_state_3 {
+            optional_extensions.push(extended_dynamic_state_3_name);
+        }
+        if supports_graphics_pipeline_library {
+            optional_extensions.push(pipeline_library_name);
+            optional_extensions.push(graphics_pipeline_library_name);
+        }
+        let mut extensions = gpu_requirements.extensions.clone();
+        for name in optional_extensions {
+            if !extensions.iter().any(|ext| ext.as_str() == name) {
+                extensions.push(name.to_string());
+            }
+        }
+        let c_strs: Vec<CString> = extensions
             .iter()
             .map(|ext| CString::new(ext.as_str()).unwrap())
             .collect();
@@ -121,7 +262,33 @@ impl Device {
             .map(|ext| ext.as_ptr() as *const c_char)
             .collect();
         // rebind pointers
+        let mut descriptor_buffer_features = vk::PhysicalDeviceDescriptorBufferFeaturesEXT {
+            s_type: vk::PhysicalDeviceDescriptorBufferFeaturesEXT::STRUCTURE_TYPE,
+            descriptor_buffer: vk::TRUE,
+            ..Default::default()
+        };
+        let mut enabled_extended_dynamic_state_3_features = extended_dynamic_state_3_features;
+        let mut enabled_graphics_pipeline_library_features =
+            vk::PhysicalDeviceGraphicsPipelineLibraryFeaturesEXT {
+                s_type: vk::PhysicalDeviceGraphicsPipelineLibraryFeaturesEXT::STRUCTURE_TYPE,
+                graphics_pipeline_library: vk::TRUE,
+                ..Default::default()
+            };
         let (mut features, mut features_1_1, mut features_1_2, mut features_1_3) = physical_device.get_features();
+        let mut extension_features: *mut c_void = ptr::null_mut();
+        if supports_extended_dynamic_state_3 {
+            extension_features = abstraction::utility::p_next_mut(&mut enabled_extended_dynamic_state_3_features);
+        }
+        if supports_graphics_pipeline_library {
+            enabled_graphics_pipeline_library_features.p_next = extension_features;
+            extension_features =
+                abstraction::utility::p_next_mut(&mut enabled_graphics_pipeline_library_features);
+        }
+        if descriptor_backend == abstraction::DescriptorBackend::Buffer {
+            descriptor_buffer_features.p_next = extension_features;
+            extension_features = abstraction::utility::p_next_mut(&mut descriptor_buffer_features);
+        }
+        features_1_3.p_next = extension_features;
         features.p_next = abstraction::utility::p_next_mut(&mut features_1_1);
         features_1_1.p_next = abstraction::utility::p_next_mut(&mut features_1_2);
         features_1_2.p_next = abstraction::utility::p_next_mut(&mut features_1_3);
@@ -131,7 +298,7 @@ impl Device {
             p_next: &features as *const _ as *const c_void,
             queue_create_info_count: queue_cis.len() as u32,
             p_queue_create_infos: queue_cis.as_ptr(),
-            enabled_extension_count: gpu_requirements.extensions.len() as u32,
+            enabled_extension_count: c_ptrs.len() as u32,
             pp_enabled_extension_names: c_ptrs.as_ptr(),
             ..Default::default()
         };
@@ -142,11 +309,117 @@ impl Device {
             return Err(handle.err().unwrap());
         }
         let handle = handle.unwrap();
+        let queue_families: Vec<DeviceFamilyQueue> = queue_cis
+            .iter()
+            .map(|queue_ci| {
+                let queue_family = &physical_device.get_queue_families()[queue_ci.queue_family_index as usize];
+                DeviceFamilyQueue {
+                    flags: queue_family.queue_family_properties.queue_flags,
+                    index: queue_ci.queue_family_index,
+                    count: queue_ci.queue_count,
+                }
+            })
+            .collect();
+        let properties = unsafe {
+            instance
+                .get_vk_instance()
+                .get_physical_device_properties(*physical_device.get_handle())
+        };
+        let api_version = properties.api_version;
+        let pipeline_cache_header = abstraction::PipelineCacheHeader::from_properties(&properties);
+        let memory_properties = unsafe {
+            instance
+                .get_vk_instance()
+                .get_physical_device_memory_properties(*physical_device.get_handle())
+        };
+        let mut descriptor_buffer_properties = vk::PhysicalDeviceDescriptorBufferPropertiesEXT::default();
+        let descriptor_buffer_loader = if descriptor_backend == abstraction::DescriptorBackend::Buffer {
+            let mut properties_2 = vk::PhysicalDeviceProperties2 {
+                s_type: vk::PhysicalDeviceProperties2::STRUCTURE_TYPE,
+                p_next: abstraction::utility::p_next_mut(&mut descriptor_buffer_properties),
+                ..Default::default()
+            };
+            unsafe {
+                instance
+                    .get_vk_instance()
+                    .get_physical_device_properties2(*physical_device.get_handle(), &mut properties_2)
+            };
+            descriptor_buffer_properties.p_next = ptr::null_mut();
+            Some(ash::extensions::ext::DescriptorBuffer::new(
+                instance.get_vk_instance(),
+                &handle,
+            ))
+        } else {
+            None
+        };
+        let graphics_pipeline_library_properties = if supports_graphics_pipeline_library {
+            let mut graphics_pipeline_library_properties =
+                vk::PhysicalDeviceGraphicsPipelineLibraryPropertiesEXT::default();
+            let mut properties_2 = vk::PhysicalDeviceProperties2 {
+                s_type: vk::PhysicalDeviceProperties2::STRUCTURE_TYPE,
+                p_next: abstraction::utility::p_next_mut(&mut graphics_pipeline_library_properties),
+                ..Default::default()
+            };
+            unsafe {
+                instance
+                    .get_vk_instance()
+                    .get_physical_device_properties2(*physical_device.get_handle(), &mut properties_2)
+            };
+            graphics_pipeline_library_properties.p_next = ptr::null_mut();
+            Some(graphics_pipeline_library_properties)
+        } else {
+            None
+        };
+        let push_descriptor_loader = if supports_push_descriptor {
+            Some(ash::extensions::khr::PushDescriptor::new(
+                instance.get_vk_instance(),
+                &handle,
+            ))
+        } else {
+            None
+        };
+        let swapchain_loader = if supports_swapchain {
+            Some(abstraction::SwapchainLoader::new(instance, &handle))
+        } else {
+            None
+        };
+        let extended_dynamic_state_3_loader = if supports_extended_dynamic_state_3 {
+            Some(ash::extensions::ext::ExtendedDynamicState3::new(
+                instance.get_vk_instance(),
+                &handle,
+            ))
+        } else {
+            None
+        };
+        let pipeline_cache_ci = vk::PipelineCacheCreateInfo {
+            s_type: vk::PipelineCacheCreateInfo::STRUCTURE_TYPE,
+            ..Default::default()
+        };
+        let pipeline_cache = match unsafe { handle.create_pipeline_cache(&pipeline_cache_ci, None) } {
+            Ok(pipeline_cache) => pipeline_cache,
+            Err(error) => {
+                unsafe { handle.destroy_device(None) };
+                return Err(error);
+            }
+        };
         Ok(Self {
             handle: Arc::new(DeviceInner {
                 handle,
+                physical_device: unsafe { *physical_device.get_handle() },
                 physical_device_features: physical_device.get_features().0,
-                queue_families: vec![],
+                queue_families,
+                api_version,
+                memory_properties,
+                descriptor_backend,
+                descriptor_buffer_loader,
+                descriptor_buffer_properties,
+                push_descriptor_loader,
+                swapchain_loader,
+                extended_dynamic_state_3_loader,
+                extended_dynamic_state_3_features,
+                graphics_pipeline_library_properties,
+                pipeline_cache,
+                pipeline_cache_header,
             }),
         })
     }
@@ -154,4 +427,157 @@ impl Device {
     pub fn handle_as_ref(&self) -> &ash::Device {
         &self.handle.handle
     }
+
+    pub fn get_physical_device(&self) -> vk::PhysicalDevice {
+        self.handle.physical_device
+    }
+
+    /// Get every queue family queues were created in
+    pub fn get_queue_families(&self) -> &[DeviceFamilyQueue] {
+        self.handle.queue_families.as_slice()
+    }
+
+    /// Get the Vulkan version supported by the device, see [vk::api_version_major]
+    pub fn get_api_version(&self) -> u32 {
+        self.handle.api_version
+    }
+
+    pub fn get_memory_properties(&self) -> &vk::PhysicalDeviceMemoryProperties {
+        &self.handle.memory_properties
+    }
+
+    /// Find the first memory type allowed by `memory_type_bits` which has every flag of `flags`
+    pub fn find_memory_type_index(
+        &self,
+        memory_type_bits: u32,
+        flags: vk::MemoryPropertyFlags,
+    ) -> Option<u32> {
+        let memory_properties = &self.handle.memory_properties;
+        (0..memory_properties.memory_type_count).find(|index| {
+            memory_type_bits & (1 << index) != 0
+                && memory_properties.memory_types[*index as usize]
+                    .property_flags
+                    .contains(flags)
+        })
+    }
+
+    /// Get the backend descriptors should be stored with on this device
+    pub fn get_descriptor_backend(&self) -> abstraction::DescriptorBackend {
+        self.handle.descriptor_backend
+    }
+
+    /// Get the loader of `VK_EXT_descriptor_buffer`, [None] unless the descriptor backend is
+    /// [abstraction::DescriptorBackend::Buffer]
+    pub fn get_descriptor_buffer_loader(&self) -> Option<&ash::extensions::ext::DescriptorBuffer> {
+        self.handle.descriptor_buffer_loader.as_ref()
+    }
+
+    /// Get the descriptor sizes and alignments of `VK_EXT_descriptor_buffer`, zeroed unless the
+    /// descriptor backend is [abstraction::DescriptorBackend::Buffer]
+    pub fn get_descriptor_buffer_properties(&self) -> &vk::PhysicalDeviceDescriptorBufferPropertiesEXT {
+        &self.handle.descriptor_buffer_properties
+    }
+
+    /// Get the loader of `VK_KHR_push_descriptor`, [None] if the device does not support it
+    pub fn get_push_descriptor_loader(&self) -> Option<&ash::extensions::khr::PushDescriptor> {
+        self.handle.push_descriptor_loader.as_ref()
+    }
+
+    /// Get the loader of `VK_KHR_swapchain`, [None] if the device does not support it
+    pub fn get_swapchain_loader(&self) -> Option<&abstraction::SwapchainLoader> {
+        self.handle.swapchain_loader.as_ref()
+    }
+
+    /// Get the loader of `VK_EXT_extended_dynamic_state3`, [None] if the device does not support it
+    pub fn get_extended_dynamic_state_3_loader(
+        &self,
+    ) -> Option<&ash::extensions::ext::ExtendedDynamicState3> {
+        self.handle.extended_dynamic_state_3_loader.as_ref()
+    }
+
+    /// Get the states of `VK_EXT_extended_dynamic_state3` which were enabled, every state is
+    /// disabled if the device does not support the extension
+    pub fn get_extended_dynamic_state_3_features(
+        &self,
+    ) -> &vk::PhysicalDeviceExtendedDynamicState3FeaturesEXT {
+        &self.handle.extended_dynamic_state_3_features
+    }
+
+    /// Get the properties of `VK_EXT_graphics_pipeline_library`, [None] if the device does not
+    /// support graphics pipeline libraries
+    pub fn get_graphics_pipeline_library_properties(
+        &self,
+    ) -> Option<&vk::PhysicalDeviceGraphicsPipelineLibraryPropertiesEXT> {
+        self.handle.graphics_pipeline_library_properties.as_ref()
+    }
+
+    /// Get the cache every pipeline should be created with
+    pub fn get_pipeline_cache(&self) -> vk::PipelineCache {
+        self.handle.pipeline_cache
+    }
+
+    pub fn get_pipeline_cache_header(&self) -> &abstraction::PipelineCacheHeader {
+        &self.handle.pipeline_cache_header
+    }
+
+    /// Merge the pipeline cache saved at `path` into [Self::get_pipeline_cache]
+    ///
+    /// Files which are corrupt or were saved by another device or driver are ignored.
+    ///
+    /// # Safety
+    /// Merging writes to the externally synchronized pipeline cache, so no pipeline may be
+    /// created from this device on another thread while the cache is loaded.
+    pub unsafe fn load_pipeline_cache<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<abstraction::PipelineCacheLoad, abstraction::PipelineCacheError> {
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(abstraction::PipelineCacheLoad::NotFound)
+            }
+            Err(error) => return Err(error.into()),
+        };
+        let data = match self.handle.pipeline_cache_header.decode(&bytes) {
+            Ok(data) => data,
+            Err(load) => return Ok(load),
+        };
+        let pipeline_cache_ci = vk::PipelineCacheCreateInfo {
+            s_type: vk::PipelineCacheCreateInfo::STRUCTURE_TYPE,
+            initial_data_size: data.len(),
+            p_initial_data: data.as_ptr() as *const c_void,
+            ..Default::default()
+        };
+        unsafe {
+            let loaded = self.handle.handle.create_pipeline_cache(&pipeline_cache_ci, None)?;
+            let result = self
+                .handle
+                .handle
+                .merge_pipeline_caches(self.handle.pipeline_cache, &[loaded]);
+            self.handle.handle.destroy_pipeline_cache(loaded, None);
+            result?;
+        }
+        Ok(abstraction::PipelineCacheLoad::Loaded)
+    }
+
+    /// Save [Self::get_pipeline_cache] to `path`, replacing the file atomically
+    pub fn save_pipeline_cache<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<(), abstraction::PipelineCacheError> {
+        let data = unsafe {
+            self.handle
+                .handle
+                .get_pipeline_cache_data(self.handle.pipeline_cache)?
+        };
+        let bytes = self.handle.pipeline_cache_header.encode(&data);
+        abstraction::write_atomic(path.as_ref(), &bytes)?;
+        Ok(())
+    }
+
+    /// Retrieve a queue which was created alongside the device
+    pub fn get_queue(&self, family_index: u32, queue_index: u32) -> abstraction::Queue {
+        let handle = unsafe { self.handle.handle.get_device_queue(family_index, queue_index) };
+        abstraction::Queue::new(self.clone(), handle, family_index)
+    }
 }
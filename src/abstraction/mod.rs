@@ -6,13 +6,16 @@
 //!
 //! It is important to note that both CAN be used, **but at a significant performance overhead**.
 
+pub mod command;
 mod debugging;
 pub mod descriptors;
 pub mod device;
 pub mod instance;
 pub mod physical_device;
-mod pipeline;
+pub mod pipeline;
 pub mod prelude;
 pub mod queue;
 pub mod resource;
+pub mod sync;
 pub mod utility;
+pub mod wsi;
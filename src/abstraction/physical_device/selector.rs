@@ -18,6 +18,8 @@ pub struct QueueRequirements {
 pub struct PhysicalDeviceRequirements {
     pub extensions: Vec<String>,
     pub queues: Vec<QueueRequirements>,
+    /// Allocate descriptor sets from pools even if `VK_EXT_descriptor_buffer` is supported
+    pub prefer_descriptor_pools: bool,
 }
 
 // These are minimum requirements we expect any GPU should have.
@@ -32,6 +34,7 @@ pub fn get_minimum_viable_requirements() -> PhysicalDeviceRequirements {
             .map(|ext_name| ext_name.to_string_lossy().into_owned())
             .collect(),
         queues: Vec::new(),
+        prefer_descriptor_pools: false,
     }
 }
 
@@ -0,0 +1,287 @@
+use super::*;
+use crate::abstraction::prelude as abstraction;
+use ash::vk;
+use crate::abstraction::utility::shader::ShaderModule;
+use ash::vk::TaggedStructure;
+use std::sync::Arc;
+
+/// Get the number of workgroups of `local_size` needed to cover `total_threads`
+pub(crate) fn get_group_count(local_size: [u32; 3], total_threads: [u32; 3]) -> [u32; 3] {
+    [0, 1, 2].map(|axis| total_threads[axis].div_ceil(local_size[axis].max(1)))
+}
+
+/// Replace every component of `local_size` given by a specialization constant with the value
+/// `specialization` sets it to, if any
+pub(crate) fn get_specialized_local_size(
+    local_size: [u32; 3],
+    constant_ids: [Option<u32>; 3],
+    specialization: &SpecializationConstants,
+) -> [u32; 3] {
+    [0, 1, 2].map(|axis| {
+        constant_ids[axis]
+            .and_then(|constant_id| specialization.get_u32(constant_id))
+            .unwrap_or(local_size[axis])
+    })
+}
+
+/// Describes a [ComputePipeline]
+#[derive(Clone)]
+pub struct ComputePipelineBuilder {
+    device: abstraction::Device,
+    deletion_queue: PipelineDeletionQueue,
+    stage: Option<ShaderStage>,
+    /// Workgroup size declared by the shader, before specialization
+    local_size: [u32; 3],
+    /// Specialization constant id of every component of `local_size` given by one
+    local_size_constant_ids: [Option<u32>; 3],
+    set_layouts: Vec<Arc<abstraction::DescriptorSetLayout>>,
+    push_constant_ranges: Vec<vk::PushConstantRange>,
+}
+
+impl PipelineBuilder for ComputePipelineBuilder {}
+
+impl ComputePipelineBuilder {
+    /// Start describing a pipeline which is handed over to `deletion_queue` once dropped
+    pub fn new(device: abstraction::Device, deletion_queue: PipelineDeletionQueue) -> Self {
+        Self {
+            device,
+            deletion_queue,
+            stage: None,
+            local_size: [1, 1, 1],
+            local_size_constant_ids: [None; 3],
+            set_layouts: Vec::new(),
+            push_constant_ranges: Vec::new(),
+        }
+    }
+
+    /// Set the compute shader, `local_size` has to match the workgroup size it declares
+    pub fn shader(mut self, module: vk::ShaderModule, entry_point: &str, local_size: [u32; 3]) -> Self {
+        self.stage = Some(ShaderStage::new(
+            vk::ShaderStageFlags::COMPUTE,
+            module,
+            entry_point,
+        ));
+        self.local_size = local_size;
+        self.local_size_constant_ids = [None; 3];
+        self
+    }
+
+    /// Set the compute shader to `entry_point` of `module`, using the workgroup size it declares
+    ///
+    /// Components of the workgroup size given by specialization constants follow the values
+    /// passed to [ComputePipelineBuilder::specialization].
+    pub fn shader_module(
+        self,
+        module: &ShaderModule,
+        entry_point: &str,
+    ) -> Result<Self, ReflectionError> {
+        let declared = module
+            .get_entry_point(entry_point)
+            .filter(|declared| declared.stage == vk::ShaderStageFlags::COMPUTE)
+            .ok_or_else(|| ReflectionError::EntryPointNotFound(entry_point.to_string()))?;
+        let local_size = declared
+            .local_size
+            .ok_or_else(|| ReflectionError::MissingLocalSize(entry_point.to_string()))?;
+        let mut builder = self.shader(module.get_handle(), entry_point, local_size);
+        builder.local_size_constant_ids = declared.local_size_constant_ids;
+        Ok(builder)
+    }
+
+    /// Set the specialization constants of the shader, which has to have been set already
+    pub fn specialization(mut self, specialization: SpecializationConstants) -> Self {
+        debug_assert!(self.stage.is_some(), "Shader has not been set");
+        self.stage = self
+            .stage
+            .map(|stage| stage.specialization(specialization));
+        self
+    }
+
+    /// Add a set layout after every previously added one
+    pub fn set_layout(mut self, set_layout: Arc<abstraction::DescriptorSetLayout>) -> Self {
+        self.set_layouts.push(set_layout);
+        self
+    }
+
+    pub fn push_constant_range(mut self, offset: u32, size: u32) -> Self {
+        self.push_constant_ranges.push(vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            offset,
+            size,
+        });
+        self
+    }
+
+    /// Replace the set layouts and push constant ranges with the ones reflected from the
+    /// shaders, set layouts are shared through `cache`
+    pub fn reflected_layout(
+        mut self,
+        reflection: &PipelineReflection,
+        cache: &abstraction::DescriptorSetLayoutCache,
+    ) -> Result<Self, vk::Result> {
+        self.set_layouts = reflection.create_set_layouts(cache)?;
+        self.push_constant_ranges = reflection.get_push_constant_ranges();
+        Ok(self)
+    }
+
+    pub fn get_stage(&self) -> Option<&ShaderStage> {
+        self.stage.as_ref()
+    }
+
+    pub fn get_set_layouts(&self) -> &[Arc<abstraction::DescriptorSetLayout>] {
+        &self.set_layouts
+    }
+
+    pub fn get_push_constant_ranges(&self) -> &[vk::PushConstantRange] {
+        &self.push_constant_ranges
+    }
+
+    /// Get the workgroup size of the shader with its specialization constants applied
+    pub fn get_local_size(&self) -> [u32; 3] {
+        match self.stage.as_ref() {
+            Some(stage) => get_specialized_local_size(
+                self.local_size,
+                self.local_size_constant_ids,
+                stage.get_specialization(),
+            ),
+            None => self.local_size,
+        }
+    }
+
+    pub fn build(self) -> Result<ComputePipeline, vk::Result> {
+        ComputePipeline::from_builder(self)
+    }
+}
+
+/// An abstraction for compute [vk::Pipeline]
+///
+/// Owns its [vk::PipelineLayout], both are destroyed through the [PipelineDeletionQueue] the
+/// pipeline was built with.
+pub struct ComputePipeline {
+    inner: Option<PipelineInner>,
+    deletion_queue: PipelineDeletionQueue,
+    builder: ComputePipelineBuilder,
+}
+
+impl Pipeline for ComputePipeline {
+    type PipelineBuilderType = ComputePipelineBuilder;
+
+    fn from_builder(builder: ComputePipelineBuilder) -> Result<Self, vk::Result> {
+        let stage = builder
+            .stage
+            .as_ref()
+            .ok_or(vk::Result::ERROR_INITIALIZATION_FAILED)?;
+        let device = builder.device.clone();
+        let layout =
+            PipelineInner::create_layout(&device, &builder.set_layouts, &builder.push_constant_ranges)?;
+        let map_entries = stage.get_specialization().get_vk_map_entries();
+        let specialization_info = stage.get_vk_specialization_info(&map_entries);
+        let pipeline_ci = vk::ComputePipelineCreateInfo {
+            s_type: vk::ComputePipelineCreateInfo::STRUCTURE_TYPE,
+            flags: PipelineInner::get_create_flags(&builder.set_layouts),
+            stage: stage.get_vk_create_info(&specialization_info),
+            layout,
+            base_pipeline_index: -1,
+            ..Default::default()
+        };
+        let handle = unsafe {
+            device
+                .handle_as_ref()
+                .create_compute_pipelines(device.get_pipeline_cache(), &[pipeline_ci], None)
+        };
+        let handle = match handle {
+            Ok(pipelines) => pipelines[0],
+            Err((_, error)) => {
+                unsafe { device.handle_as_ref().destroy_pipeline_layout(layout, None) };
+                return Err(error);
+            }
+        };
+        Ok(Self {
+            inner: Some(PipelineInner::new(
+                handle,
+                layout,
+                device,
+                builder.set_layouts.clone(),
+            )),
+            deletion_queue: builder.deletion_queue.clone(),
+            builder,
+        })
+    }
+
+    fn get_handle(&self) -> vk::Pipeline {
+        self.inner.as_ref().unwrap().get_handle()
+    }
+
+    fn get_layout(&self) -> vk::PipelineLayout {
+        self.inner.as_ref().unwrap().get_layout()
+    }
+
+    fn get_bind_point(&self) -> vk::PipelineBindPoint {
+        vk::PipelineBindPoint::COMPUTE
+    }
+}
+
+impl ComputePipeline {
+    /// Get the builder the pipeline was created from
+    pub fn get_builder(&self) -> &ComputePipelineBuilder {
+        &self.builder
+    }
+
+    /// Workgroup size of the shader with its specialization constants applied
+    pub fn get_local_size(&self) -> [u32; 3] {
+        self.builder.get_local_size()
+    }
+
+    /// Get the number of workgroups needed to run at least `total_threads` invocations
+    pub fn get_group_count(&self, total_threads: [u32; 3]) -> [u32; 3] {
+        get_group_count(self.get_local_size(), total_threads)
+    }
+
+    /// Get the arguments of an indirect dispatch running at least `total_threads` invocations,
+    /// to be written into the buffer passed to [abstraction::CommandBuffer::dispatch_indirect]
+    pub fn get_indirect_command(&self, total_threads: [u32; 3]) -> vk::DispatchIndirectCommand {
+        let [x, y, z] = self.get_group_count(total_threads);
+        vk::DispatchIndirectCommand { x, y, z }
+    }
+
+    pub fn bind(&self, command_buffer: &abstraction::CommandBuffer<abstraction::Recording>) {
+        command_buffer.bind_pipeline(vk::PipelineBindPoint::COMPUTE, self.get_handle());
+    }
+}
+
+impl Drop for ComputePipeline {
+    fn drop(&mut self) {
+        if let Some(inner) = self.inner.take() {
+            self.deletion_queue.enqueue_deletion(inner, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Group counts are rounded up so every thread is covered
+    fn test_group_count() {
+        assert_eq!(get_group_count([64, 1, 1], [1000, 1, 1]), [16, 1, 1]);
+        assert_eq!(get_group_count([8, 8, 1], [1920, 1080, 1]), [240, 135, 1]);
+        assert_eq!(get_group_count([8, 8, 1], [0, 4, 1]), [0, 1, 1]);
+    }
+
+    #[test]
+    /// Specialization values replace the components given by their constant, others keep the
+    /// declared size
+    fn test_specialized_local_size() {
+        let specialization = SpecializationConstants::new()
+            .add_u32(3, 128)
+            .add_u32(4, 2);
+        assert_eq!(
+            get_specialized_local_size([64, 1, 1], [Some(3), None, Some(5)], &specialization),
+            [128, 1, 1]
+        );
+        assert_eq!(
+            get_specialized_local_size([64, 1, 1], [None; 3], &specialization),
+            [64, 1, 1]
+        );
+    }
+}
@@ -0,0 +1,1285 @@
+//! Pipelines described in RON or JSON files
+//!
+//! A [GraphicsPipelineDescription] or [ComputePipelineDescription] maps field for field onto
+//! [GraphicsPipelineBuilder] or [ComputePipelineBuilder]. Descriptions are validated against the
+//! reflection of their shaders before anything is handed to Vulkan, while set layouts and push
+//! constant ranges always come from reflection. The [PipelineLibraryCache] of pipelines linked
+//! from libraries is given when creating the builder.
+//!
+//! ```text
+//! (
+//!     shaders: [
+//!         (path: "mesh.vert.spv"),
+//!         (path: "mesh.frag.spv", specialization: [(id: 0, value: Bool(true))]),
+//!     ],
+//!     vertex_bindings: [(binding: 0, stride: 32)],
+//!     vertex_attributes: [(location: 0, binding: 0, format: "R32G32B32_SFLOAT", offset: 0)],
+//!     cull_mode: Back,
+//!     depth_test: Some((write: true, compare_op: GreaterOrEqual)),
+//!     color_attachments: [
+//!         (format: "R16G16B16A16_SFLOAT", blend: Alpha),
+//!         (
+//!             format: "R8G8B8A8_UNORM",
+//!             blend: Custom((
+//!                 src_color_blend_factor: DstColor,
+//!                 dst_color_blend_factor: Zero,
+//!                 color_blend_op: Add,
+//!                 src_alpha_blend_factor: One,
+//!                 dst_alpha_blend_factor: Zero,
+//!                 alpha_blend_op: Add,
+//!             )),
+//!             write_mask: [R, G, B],
+//!         ),
+//!     ],
+//!     depth_attachment_format: Some("D32_SFLOAT"),
+//!     dynamic_states: Some(["VIEWPORT", "SCISSOR", "CULL_MODE"]),
+//! )
+//! ```
+use super::*;
+use crate::abstraction::prelude as abstraction;
+use crate::abstraction::utility::shader::{self, ShaderError, ShaderModule};
+use ash::vk;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Values of [vk::Format] looked up by name, the formats of Vulkan 1.0 followed by those added
+/// by later versions and extensions
+const FORMAT_RANGES: &[(i32, i32)] = &[
+    (0, 184),
+    (1_000_054_000, 1_000_054_007),
+    (1_000_066_000, 1_000_066_013),
+    (1_000_156_000, 1_000_156_033),
+    (1_000_288_000, 1_000_288_029),
+    (1_000_330_000, 1_000_330_003),
+    (1_000_340_000, 1_000_340_001),
+    (1_000_464_000, 1_000_464_000),
+];
+
+/// Values of [vk::DynamicState] looked up by name
+const DYNAMIC_STATE_RANGES: &[(i32, i32)] = &[
+    (0, 8),
+    (1_000_087_000, 1_000_087_000),
+    (1_000_099_000, 1_000_099_002),
+    (1_000_143_000, 1_000_143_000),
+    (1_000_164_004, 1_000_164_006),
+    (1_000_205_000, 1_000_205_001),
+    (1_000_226_000, 1_000_226_000),
+    (1_000_259_000, 1_000_259_000),
+    (1_000_267_000, 1_000_267_011),
+    (1_000_347_000, 1_000_347_000),
+    (1_000_352_000, 1_000_352_000),
+    (1_000_377_000, 1_000_377_004),
+    (1_000_381_000, 1_000_381_000),
+    (1_000_455_002, 1_000_455_032),
+    (1_000_524_000, 1_000_524_000),
+];
+
+#[derive(Debug)]
+pub enum PipelineDescriptionError {
+    Io(std::io::Error),
+    /// The description is not valid RON or JSON
+    Parse(String),
+    /// A shader could not be loaded
+    Shader {
+        path: PathBuf,
+        error: ShaderError,
+    },
+    Reflection(ReflectionError),
+    /// The description does not match the reflection of its shaders
+    Invalid(String),
+    Vulkan(vk::Result),
+}
+
+impl fmt::Display for PipelineDescriptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineDescriptionError::Io(error) => write!(f, "{}", error),
+            PipelineDescriptionError::Parse(message) => write!(f, "{}", message),
+            PipelineDescriptionError::Shader { path, error } => {
+                write!(f, "{}: {}", path.display(), error)
+            }
+            PipelineDescriptionError::Reflection(error) => write!(f, "{}", error),
+            PipelineDescriptionError::Invalid(message) => write!(f, "{}", message),
+            PipelineDescriptionError::Vulkan(result) => write!(f, "{}", result),
+        }
+    }
+}
+
+impl std::error::Error for PipelineDescriptionError {}
+
+impl From<std::io::Error> for PipelineDescriptionError {
+    fn from(error: std::io::Error) -> Self {
+        PipelineDescriptionError::Io(error)
+    }
+}
+
+impl From<ReflectionError> for PipelineDescriptionError {
+    fn from(error: ReflectionError) -> Self {
+        PipelineDescriptionError::Reflection(error)
+    }
+}
+
+impl From<vk::Result> for PipelineDescriptionError {
+    fn from(result: vk::Result) -> Self {
+        PipelineDescriptionError::Vulkan(result)
+    }
+}
+
+/// Find the value in `ranges` whose name is `name`
+fn find_by_name<T: fmt::Debug>(
+    ranges: &[(i32, i32)],
+    from_raw: fn(i32) -> T,
+    name: &str,
+) -> Option<T> {
+    ranges
+        .iter()
+        .flat_map(|(first, last)| *first..=*last)
+        .map(from_raw)
+        .find(|value| format!("{:?}", value) == name)
+}
+
+/// Look up a [vk::Format] by its name without the `VK_FORMAT_` prefix, such as `R8G8B8A8_UNORM`
+pub fn get_format(name: &str) -> Option<vk::Format> {
+    find_by_name(FORMAT_RANGES, vk::Format::from_raw, name)
+}
+
+/// Look up a [vk::DynamicState] by its name without the `VK_DYNAMIC_STATE_` prefix, such as
+/// `CULL_MODE`
+pub fn get_dynamic_state(name: &str) -> Option<vk::DynamicState> {
+    find_by_name(DYNAMIC_STATE_RANGES, vk::DynamicState::from_raw, name)
+}
+
+fn parse_format(name: &str) -> Result<vk::Format, PipelineDescriptionError> {
+    get_format(name)
+        .ok_or_else(|| PipelineDescriptionError::Invalid(format!("unknown format {}", name)))
+}
+
+fn parse_dynamic_state(name: &str) -> Result<vk::DynamicState, PipelineDescriptionError> {
+    get_dynamic_state(name)
+        .ok_or_else(|| PipelineDescriptionError::Invalid(format!("unknown dynamic state {}", name)))
+}
+
+fn default_entry_point() -> String {
+    String::from("main")
+}
+
+fn default_samples() -> u32 {
+    1
+}
+
+/// Value of a specialization constant
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum SpecializationValue {
+    Bool(bool),
+    U32(u32),
+    I32(i32),
+    F32(f32),
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpecializationConstantDescription {
+    pub id: u32,
+    pub value: SpecializationValue,
+}
+
+/// A SPIR-V file along with the entry point used, the stage is taken from the entry point
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShaderDescription {
+    pub path: PathBuf,
+    #[serde(default = "default_entry_point")]
+    pub entry_point: String,
+    #[serde(default)]
+    pub specialization: Vec<SpecializationConstantDescription>,
+}
+
+impl SpecializationValue {
+    /// Whether the value can be given to a constant of `scalar_type`
+    pub fn matches(&self, scalar_type: ReflectedScalarType) -> bool {
+        matches!(
+            (self, scalar_type),
+            (SpecializationValue::Bool(_), ReflectedScalarType::Bool)
+                | (
+                    SpecializationValue::U32(_),
+                    ReflectedScalarType::Int {
+                        width: 32,
+                        signed: false
+                    }
+                )
+                | (
+                    SpecializationValue::I32(_),
+                    ReflectedScalarType::Int {
+                        width: 32,
+                        signed: true
+                    }
+                )
+                | (
+                    SpecializationValue::F32(_),
+                    ReflectedScalarType::Float { width: 32 }
+                )
+        )
+    }
+}
+
+impl ShaderDescription {
+    pub fn get_specialization(&self) -> SpecializationConstants {
+        self.specialization
+            .iter()
+            .fold(
+                SpecializationConstants::new(),
+                |constants, constant| match constant.value {
+                    SpecializationValue::Bool(value) => constants.add_bool(constant.id, value),
+                    SpecializationValue::U32(value) => constants.add_u32(constant.id, value),
+                    SpecializationValue::I32(value) => constants.add_i32(constant.id, value),
+                    SpecializationValue::F32(value) => constants.add_f32(constant.id, value),
+                },
+            )
+    }
+
+    /// Read the SPIR-V of the shader
+    fn read(&self) -> Result<Vec<u32>, PipelineDescriptionError> {
+        let shader_error = |error: ShaderError| PipelineDescriptionError::Shader {
+            path: self.path.clone(),
+            error,
+        };
+        let bytes = std::fs::read(&self.path).map_err(|error| shader_error(error.into()))?;
+        shader::spirv_from_bytes(&bytes).map_err(shader_error)
+    }
+
+    /// Reflect `code` and check every specialization constant exists and has the type of its
+    /// value
+    fn reflect(&self, code: &[u32]) -> Result<ShaderReflection, PipelineDescriptionError> {
+        let reflection =
+            ShaderReflection::new(code, &self.entry_point).map_err(|error| match error {
+                ReflectionError::Shader(error) => PipelineDescriptionError::Shader {
+                    path: self.path.clone(),
+                    error,
+                },
+                error => PipelineDescriptionError::Reflection(error),
+            })?;
+        for constant in self.specialization.iter() {
+            let reflected = reflection
+                .get_specialization_constants()
+                .iter()
+                .find(|reflected| reflected.constant_id == constant.id)
+                .ok_or_else(|| {
+                    PipelineDescriptionError::Invalid(format!(
+                        "{} has no specialization constant {}",
+                        self.path.display(),
+                        constant.id
+                    ))
+                })?;
+            if !constant.value.matches(reflected.scalar_type) {
+                return Err(PipelineDescriptionError::Invalid(format!(
+                    "{} specialization constant {} ({}) is a {:?} but is given {:?}",
+                    self.path.display(),
+                    constant.id,
+                    reflected.name,
+                    reflected.scalar_type,
+                    constant.value
+                )));
+            }
+        }
+        Ok(reflection)
+    }
+
+    fn resolve(&mut self, directory: &Path) {
+        self.path = directory.join(&self.path);
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Topology {
+    PointList,
+    LineList,
+    LineStrip,
+    TriangleList,
+    TriangleStrip,
+    TriangleFan,
+}
+
+impl Topology {
+    pub fn get_vk_topology(&self) -> vk::PrimitiveTopology {
+        match self {
+            Topology::PointList => vk::PrimitiveTopology::POINT_LIST,
+            Topology::LineList => vk::PrimitiveTopology::LINE_LIST,
+            Topology::LineStrip => vk::PrimitiveTopology::LINE_STRIP,
+            Topology::TriangleList => vk::PrimitiveTopology::TRIANGLE_LIST,
+            Topology::TriangleStrip => vk::PrimitiveTopology::TRIANGLE_STRIP,
+            Topology::TriangleFan => vk::PrimitiveTopology::TRIANGLE_FAN,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum PolygonMode {
+    Fill,
+    Line,
+    Point,
+}
+
+impl PolygonMode {
+    pub fn get_vk_polygon_mode(&self) -> vk::PolygonMode {
+        match self {
+            PolygonMode::Fill => vk::PolygonMode::FILL,
+            PolygonMode::Line => vk::PolygonMode::LINE,
+            PolygonMode::Point => vk::PolygonMode::POINT,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum CullMode {
+    None,
+    Front,
+    Back,
+    FrontAndBack,
+}
+
+impl CullMode {
+    pub fn get_vk_cull_mode(&self) -> vk::CullModeFlags {
+        match self {
+            CullMode::None => vk::CullModeFlags::NONE,
+            CullMode::Front => vk::CullModeFlags::FRONT,
+            CullMode::Back => vk::CullModeFlags::BACK,
+            CullMode::FrontAndBack => vk::CullModeFlags::FRONT_AND_BACK,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum FrontFace {
+    CounterClockwise,
+    Clockwise,
+}
+
+impl FrontFace {
+    pub fn get_vk_front_face(&self) -> vk::FrontFace {
+        match self {
+            FrontFace::CounterClockwise => vk::FrontFace::COUNTER_CLOCKWISE,
+            FrontFace::Clockwise => vk::FrontFace::CLOCKWISE,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum CompareOp {
+    Never,
+    Less,
+    Equal,
+    LessOrEqual,
+    Greater,
+    NotEqual,
+    GreaterOrEqual,
+    Always,
+}
+
+impl CompareOp {
+    pub fn get_vk_compare_op(&self) -> vk::CompareOp {
+        match self {
+            CompareOp::Never => vk::CompareOp::NEVER,
+            CompareOp::Less => vk::CompareOp::LESS,
+            CompareOp::Equal => vk::CompareOp::EQUAL,
+            CompareOp::LessOrEqual => vk::CompareOp::LESS_OR_EQUAL,
+            CompareOp::Greater => vk::CompareOp::GREATER,
+            CompareOp::NotEqual => vk::CompareOp::NOT_EQUAL,
+            CompareOp::GreaterOrEqual => vk::CompareOp::GREATER_OR_EQUAL,
+            CompareOp::Always => vk::CompareOp::ALWAYS,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum StencilOp {
+    Keep,
+    Zero,
+    Replace,
+    IncrementAndClamp,
+    DecrementAndClamp,
+    Invert,
+    IncrementAndWrap,
+    DecrementAndWrap,
+}
+
+impl StencilOp {
+    pub fn get_vk_stencil_op(&self) -> vk::StencilOp {
+        match self {
+            StencilOp::Keep => vk::StencilOp::KEEP,
+            StencilOp::Zero => vk::StencilOp::ZERO,
+            StencilOp::Replace => vk::StencilOp::REPLACE,
+            StencilOp::IncrementAndClamp => vk::StencilOp::INCREMENT_AND_CLAMP,
+            StencilOp::DecrementAndClamp => vk::StencilOp::DECREMENT_AND_CLAMP,
+            StencilOp::Invert => vk::StencilOp::INVERT,
+            StencilOp::IncrementAndWrap => vk::StencilOp::INCREMENT_AND_WRAP,
+            StencilOp::DecrementAndWrap => vk::StencilOp::DECREMENT_AND_WRAP,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum BlendFactor {
+    Zero,
+    One,
+    SrcColor,
+    OneMinusSrcColor,
+    DstColor,
+    OneMinusDstColor,
+    SrcAlpha,
+    OneMinusSrcAlpha,
+    DstAlpha,
+    OneMinusDstAlpha,
+    ConstantColor,
+    OneMinusConstantColor,
+    ConstantAlpha,
+    OneMinusConstantAlpha,
+    SrcAlphaSaturate,
+    Src1Color,
+    OneMinusSrc1Color,
+    Src1Alpha,
+    OneMinusSrc1Alpha,
+}
+
+impl BlendFactor {
+    pub fn get_vk_blend_factor(&self) -> vk::BlendFactor {
+        match self {
+            BlendFactor::Zero => vk::BlendFactor::ZERO,
+            BlendFactor::One => vk::BlendFactor::ONE,
+            BlendFactor::SrcColor => vk::BlendFactor::SRC_COLOR,
+            BlendFactor::OneMinusSrcColor => vk::BlendFactor::ONE_MINUS_SRC_COLOR,
+            BlendFactor::DstColor => vk::BlendFactor::DST_COLOR,
+            BlendFactor::OneMinusDstColor => vk::BlendFactor::ONE_MINUS_DST_COLOR,
+            BlendFactor::SrcAlpha => vk::BlendFactor::SRC_ALPHA,
+            BlendFactor::OneMinusSrcAlpha => vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            BlendFactor::DstAlpha => vk::BlendFactor::DST_ALPHA,
+            BlendFactor::OneMinusDstAlpha => vk::BlendFactor::ONE_MINUS_DST_ALPHA,
+            BlendFactor::ConstantColor => vk::BlendFactor::CONSTANT_COLOR,
+            BlendFactor::OneMinusConstantColor => vk::BlendFactor::ONE_MINUS_CONSTANT_COLOR,
+            BlendFactor::ConstantAlpha => vk::BlendFactor::CONSTANT_ALPHA,
+            BlendFactor::OneMinusConstantAlpha => vk::BlendFactor::ONE_MINUS_CONSTANT_ALPHA,
+            BlendFactor::SrcAlphaSaturate => vk::BlendFactor::SRC_ALPHA_SATURATE,
+            BlendFactor::Src1Color => vk::BlendFactor::SRC1_COLOR,
+            BlendFactor::OneMinusSrc1Color => vk::BlendFactor::ONE_MINUS_SRC1_COLOR,
+            BlendFactor::Src1Alpha => vk::BlendFactor::SRC1_ALPHA,
+            BlendFactor::OneMinusSrc1Alpha => vk::BlendFactor::ONE_MINUS_SRC1_ALPHA,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum BlendOp {
+    Add,
+    Subtract,
+    ReverseSubtract,
+    Min,
+    Max,
+}
+
+impl BlendOp {
+    pub fn get_vk_blend_op(&self) -> vk::BlendOp {
+        match self {
+            BlendOp::Add => vk::BlendOp::ADD,
+            BlendOp::Subtract => vk::BlendOp::SUBTRACT,
+            BlendOp::ReverseSubtract => vk::BlendOp::REVERSE_SUBTRACT,
+            BlendOp::Min => vk::BlendOp::MIN,
+            BlendOp::Max => vk::BlendOp::MAX,
+        }
+    }
+}
+
+/// Factors and operations of a [ColorBlendAttachment] with blending enabled
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BlendEquationDescription {
+    pub src_color_blend_factor: BlendFactor,
+    pub dst_color_blend_factor: BlendFactor,
+    pub color_blend_op: BlendOp,
+    pub src_alpha_blend_factor: BlendFactor,
+    pub dst_alpha_blend_factor: BlendFactor,
+    pub alpha_blend_op: BlendOp,
+}
+
+/// One of the blend modes of [ColorBlendAttachment], or an explicit equation
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum BlendMode {
+    Disabled,
+    Alpha,
+    Additive,
+    Custom(BlendEquationDescription),
+}
+
+impl BlendMode {
+    pub fn get_color_blend(&self) -> ColorBlendAttachment {
+        match self {
+            BlendMode::Disabled => ColorBlendAttachment::DISABLED,
+            BlendMode::Alpha => ColorBlendAttachment::ALPHA,
+            BlendMode::Additive => ColorBlendAttachment::ADDITIVE,
+            BlendMode::Custom(equation) => ColorBlendAttachment {
+                blend_enable: true,
+                src_color_blend_factor: equation.src_color_blend_factor.get_vk_blend_factor(),
+                dst_color_blend_factor: equation.dst_color_blend_factor.get_vk_blend_factor(),
+                color_blend_op: equation.color_blend_op.get_vk_blend_op(),
+                src_alpha_blend_factor: equation.src_alpha_blend_factor.get_vk_blend_factor(),
+                dst_alpha_blend_factor: equation.dst_alpha_blend_factor.get_vk_blend_factor(),
+                alpha_blend_op: equation.alpha_blend_op.get_vk_blend_op(),
+                color_write_mask: vk::ColorComponentFlags::RGBA,
+            },
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum ColorComponent {
+    R,
+    G,
+    B,
+    A,
+}
+
+impl ColorComponent {
+    pub fn get_vk_color_component(&self) -> vk::ColorComponentFlags {
+        match self {
+            ColorComponent::R => vk::ColorComponentFlags::R,
+            ColorComponent::G => vk::ColorComponentFlags::G,
+            ColorComponent::B => vk::ColorComponentFlags::B,
+            ColorComponent::A => vk::ColorComponentFlags::A,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VertexBindingDescription {
+    pub binding: u32,
+    pub stride: u32,
+    /// Advance per instance instead of per vertex
+    #[serde(default)]
+    pub per_instance: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VertexAttributeDescription {
+    pub location: u32,
+    pub binding: u32,
+    pub format: String,
+    pub offset: u32,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DepthBiasDescription {
+    pub constant_factor: f32,
+    #[serde(default)]
+    pub clamp: f32,
+    pub slope_factor: f32,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DepthTestDescription {
+    pub write: bool,
+    pub compare_op: CompareOp,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StencilFaceDescription {
+    pub fail_op: StencilOp,
+    pub pass_op: StencilOp,
+    pub depth_fail_op: StencilOp,
+    pub compare_op: CompareOp,
+    pub compare_mask: u32,
+    pub write_mask: u32,
+    pub reference: u32,
+}
+
+impl StencilFaceDescription {
+    pub fn get_vk_state(&self) -> vk::StencilOpState {
+        vk::StencilOpState {
+            fail_op: self.fail_op.get_vk_stencil_op(),
+            pass_op: self.pass_op.get_vk_stencil_op(),
+            depth_fail_op: self.depth_fail_op.get_vk_stencil_op(),
+            compare_op: self.compare_op.get_vk_compare_op(),
+            compare_mask: self.compare_mask,
+            write_mask: self.write_mask,
+            reference: self.reference,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StencilTestDescription {
+    pub front: StencilFaceDescription,
+    pub back: StencilFaceDescription,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColorAttachmentDescription {
+    pub format: String,
+    #[serde(default = "default_blend_mode")]
+    pub blend: BlendMode,
+    /// Components written to the attachment
+    #[serde(default = "default_write_mask")]
+    pub write_mask: Vec<ColorComponent>,
+}
+
+impl ColorAttachmentDescription {
+    pub fn get_color_blend(&self) -> ColorBlendAttachment {
+        ColorBlendAttachment {
+            color_write_mask: self
+                .write_mask
+                .iter()
+                .fold(vk::ColorComponentFlags::empty(), |mask, component| {
+                    mask | component.get_vk_color_component()
+                }),
+            ..self.blend.get_color_blend()
+        }
+    }
+}
+
+fn default_blend_mode() -> BlendMode {
+    BlendMode::Disabled
+}
+
+fn default_write_mask() -> Vec<ColorComponent> {
+    vec![
+        ColorComponent::R,
+        ColorComponent::G,
+        ColorComponent::B,
+        ColorComponent::A,
+    ]
+}
+
+/// Describes a [GraphicsPipeline], see [GraphicsPipelineBuilder] for what each field does
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GraphicsPipelineDescription {
+    pub shaders: Vec<ShaderDescription>,
+    #[serde(default)]
+    pub vertex_bindings: Vec<VertexBindingDescription>,
+    #[serde(default)]
+    pub vertex_attributes: Vec<VertexAttributeDescription>,
+    #[serde(default = "default_topology")]
+    pub topology: Topology,
+    #[serde(default)]
+    pub primitive_restart: bool,
+    #[serde(default)]
+    pub rasterizer_discard: bool,
+    #[serde(default = "default_polygon_mode")]
+    pub polygon_mode: PolygonMode,
+    #[serde(default = "default_cull_mode")]
+    pub cull_mode: CullMode,
+    #[serde(default = "default_front_face")]
+    pub front_face: FrontFace,
+    #[serde(default)]
+    pub depth_clamp: bool,
+    #[serde(default)]
+    pub depth_bias: Option<DepthBiasDescription>,
+    /// Number of samples per pixel
+    #[serde(default = "default_samples")]
+    pub samples: u32,
+    #[serde(default)]
+    pub depth_test: Option<DepthTestDescription>,
+    /// Minimum and maximum depth of the depth bounds test
+    #[serde(default)]
+    pub depth_bounds: Option<(f32, f32)>,
+    #[serde(default)]
+    pub stencil_test: Option<StencilTestDescription>,
+    #[serde(default)]
+    pub color_attachments: Vec<ColorAttachmentDescription>,
+    #[serde(default)]
+    pub depth_attachment_format: Option<String>,
+    #[serde(default)]
+    pub stencil_attachment_format: Option<String>,
+    #[serde(default)]
+    pub view_mask: u32,
+    /// Names of the dynamic states, such as `CULL_MODE`, [None] keeps the dynamic states of the
+    /// builder
+    #[serde(default)]
+    pub dynamic_states: Option<Vec<String>>,
+    /// Link the pipeline from parts cached in the [PipelineLibraryCache] given when creating
+    /// the builder
+    #[serde(default)]
+    pub libraries: bool,
+    #[serde(default)]
+    pub link_time_optimization: bool,
+}
+
+fn default_topology() -> Topology {
+    Topology::TriangleList
+}
+
+fn default_polygon_mode() -> PolygonMode {
+    PolygonMode::Fill
+}
+
+fn default_cull_mode() -> CullMode {
+    CullMode::None
+}
+
+fn default_front_face() -> FrontFace {
+    FrontFace::CounterClockwise
+}
+
+/// Parse a description from RON or JSON depending on the extension of `path`, shader paths are
+/// relative to the directory of `path`
+fn parse_file<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, PipelineDescriptionError> {
+    let source = std::fs::read_to_string(path)?;
+    let is_json = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+    let result = if is_json {
+        serde_json::from_str(&source).map_err(|error| error.to_string())
+    } else {
+        ron::from_str(&source).map_err(|error| error.to_string())
+    };
+    result.map_err(|message| {
+        PipelineDescriptionError::Parse(format!("{}: {}", path.display(), message))
+    })
+}
+
+fn get_directory(path: &Path) -> &Path {
+    path.parent().unwrap_or_else(|| Path::new(""))
+}
+
+impl GraphicsPipelineDescription {
+    pub fn from_ron(source: &str) -> Result<Self, PipelineDescriptionError> {
+        ron::from_str(source).map_err(|error| PipelineDescriptionError::Parse(error.to_string()))
+    }
+
+    pub fn from_json(source: &str) -> Result<Self, PipelineDescriptionError> {
+        serde_json::from_str(source)
+            .map_err(|error| PipelineDescriptionError::Parse(error.to_string()))
+    }
+
+    /// Load a `.json` or otherwise `.ron` file, shader paths are relative to the file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, PipelineDescriptionError> {
+        let mut description: Self = parse_file(path.as_ref())?;
+        for shader in description.shaders.iter_mut() {
+            shader.resolve(get_directory(path.as_ref()));
+        }
+        Ok(description)
+    }
+
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).unwrap()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Check the description against the reflection of its shaders, without calling Vulkan
+    ///
+    /// Every entry point and specialization constant has to exist, stages may only appear once,
+    /// every vertex input needs an attribute from a described binding and every fragment output
+    /// needs a color attachment.
+    pub fn validate(&self) -> Result<PipelineReflection, PipelineDescriptionError> {
+        let codes = self
+            .shaders
+            .iter()
+            .map(|shader| shader.read())
+            .collect::<Result<Vec<Vec<u32>>, _>>()?;
+        self.validate_code(&codes)
+    }
+
+    fn validate_code(
+        &self,
+        codes: &[Vec<u32>],
+    ) -> Result<PipelineReflection, PipelineDescriptionError> {
+        let invalid = |message: String| Err(PipelineDescriptionError::Invalid(message));
+        let mut stages: Vec<ShaderReflection> = Vec::new();
+        for (shader, code) in self.shaders.iter().zip(codes.iter()) {
+            let reflection = shader.reflect(code)?;
+            if stages
+                .iter()
+                .any(|stage| stage.get_stage() == reflection.get_stage())
+            {
+                return invalid(format!(
+                    "{:?} stage is described twice",
+                    reflection.get_stage()
+                ));
+            }
+            if reflection.get_stage() == vk::ShaderStageFlags::COMPUTE {
+                return invalid(format!("{} is a compute shader", shader.path.display()));
+            }
+            stages.push(reflection);
+        }
+        let reflection = PipelineReflection::new(stages)?;
+        for attribute in self.vertex_attributes.iter() {
+            parse_format(&attribute.format)?;
+            if !self
+                .vertex_bindings
+                .iter()
+                .any(|binding| binding.binding == attribute.binding)
+            {
+                return invalid(format!(
+                    "vertex attribute {} uses undescribed binding {}",
+                    attribute.location, attribute.binding
+                ));
+            }
+        }
+        if let Some(vertex) = reflection.get_stage(vk::ShaderStageFlags::VERTEX) {
+            for input in vertex.get_vertex_inputs() {
+                if !self
+                    .vertex_attributes
+                    .iter()
+                    .any(|attribute| attribute.location == input.location)
+                {
+                    return invalid(format!(
+                        "vertex input {} ({}) has no vertex attribute",
+                        input.location, input.name
+                    ));
+                }
+            }
+        }
+        for color_attachment in self.color_attachments.iter() {
+            parse_format(&color_attachment.format)?;
+        }
+        if let Some(fragment) = reflection.get_stage(vk::ShaderStageFlags::FRAGMENT) {
+            for output in fragment.get_fragment_outputs() {
+                if output.location as usize >= self.color_attachments.len() {
+                    return invalid(format!(
+                        "fragment output {} ({}) has no color attachment",
+                        output.location, output.name
+                    ));
+                }
+            }
+        }
+        for format in self
+            .depth_attachment_format
+            .iter()
+            .chain(self.stencil_attachment_format.iter())
+        {
+            parse_format(format)?;
+        }
+        if !self.samples.is_power_of_two() || self.samples > 64 {
+            return invalid(format!("{} is not a valid sample count", self.samples));
+        }
+        for dynamic_state in self.dynamic_states.iter().flatten() {
+            parse_dynamic_state(dynamic_state)?;
+        }
+        Ok(reflection)
+    }
+
+    /// Validate the description then create its shader modules and a builder using them
+    ///
+    /// The modules have to outlive every pipeline built from the builder, including rebuilds.
+    /// Descriptions using [Self::libraries] need a `library_cache`.
+    pub fn create_builder(
+        &self,
+        device: abstraction::Device,
+        deletion_queue: PipelineDeletionQueue,
+        set_layout_cache: &abstraction::DescriptorSetLayoutCache,
+        library_cache: Option<&PipelineLibraryCache>,
+    ) -> Result<(GraphicsPipelineBuilder, Vec<ShaderModule>), PipelineDescriptionError> {
+        if self.libraries && library_cache.is_none() {
+            return Err(PipelineDescriptionError::Invalid(String::from(
+                "pipeline is linked from libraries but no library cache was given",
+            )));
+        }
+        let codes = self
+            .shaders
+            .iter()
+            .map(|shader| shader.read())
+            .collect::<Result<Vec<Vec<u32>>, _>>()?;
+        let reflection = self.validate_code(&codes)?;
+        let modules = self
+            .shaders
+            .iter()
+            .zip(codes.iter())
+            .map(|(shader, code)| {
+                ShaderModule::from_spirv(device.clone(), code).map_err(|error| {
+                    PipelineDescriptionError::Shader {
+                        path: shader.path.clone(),
+                        error,
+                    }
+                })
+            })
+            .collect::<Result<Vec<ShaderModule>, _>>()?;
+        let mut builder = GraphicsPipelineBuilder::new(device, deletion_queue);
+        for ((shader, module), stage) in self
+            .shaders
+            .iter()
+            .zip(modules.iter())
+            .zip(reflection.get_stages().iter())
+        {
+            builder = builder
+                .shader_module(module, &shader.entry_point)?
+                .specialization(stage.get_stage(), shader.get_specialization());
+        }
+        for binding in self.vertex_bindings.iter() {
+            let input_rate = if binding.per_instance {
+                vk::VertexInputRate::INSTANCE
+            } else {
+                vk::VertexInputRate::VERTEX
+            };
+            builder = builder.vertex_binding(binding.binding, binding.stride, input_rate);
+        }
+        for attribute in self.vertex_attributes.iter() {
+            builder = builder.vertex_attribute(
+                attribute.location,
+                attribute.binding,
+                parse_format(&attribute.format)?,
+                attribute.offset,
+            );
+        }
+        builder = builder
+            .topology(self.topology.get_vk_topology())
+            .primitive_restart(self.primitive_restart)
+            .rasterizer_discard(self.rasterizer_discard)
+            .polygon_mode(self.polygon_mode.get_vk_polygon_mode())
+            .cull_mode(
+                self.cull_mode.get_vk_cull_mode(),
+                self.front_face.get_vk_front_face(),
+            )
+            .depth_clamp(self.depth_clamp)
+            .samples(vk::SampleCountFlags::from_raw(self.samples));
+        if let Some(depth_bias) = self.depth_bias {
+            builder = builder.depth_bias(DepthBias {
+                constant_factor: depth_bias.constant_factor,
+                clamp: depth_bias.clamp,
+                slope_factor: depth_bias.slope_factor,
+            });
+        }
+        if let Some(depth_test) = self.depth_test {
+            builder =
+                builder.depth_test(depth_test.write, depth_test.compare_op.get_vk_compare_op());
+        }
+        if let Some((min_depth_bounds, max_depth_bounds)) = self.depth_bounds {
+            builder = builder.depth_bounds(min_depth_bounds, max_depth_bounds);
+        }
+        if let Some(stencil_test) = self.stencil_test {
+            builder = builder.stencil_test(
+                stencil_test.front.get_vk_state(),
+                stencil_test.back.get_vk_state(),
+            );
+        }
+        builder = builder.rendering(abstraction::PipelineRenderingInfo {
+            view_mask: self.view_mask,
+            color_attachment_formats: self
+                .color_attachments
+                .iter()
+                .map(|color_attachment| parse_format(&color_attachment.format))
+                .collect::<Result<Vec<vk::Format>, _>>()?,
+            depth_attachment_format: match self.depth_attachment_format.as_ref() {
+                Some(format) => parse_format(format)?,
+                None => vk::Format::UNDEFINED,
+            },
+            stencil_attachment_format: match self.stencil_attachment_format.as_ref() {
+                Some(format) => parse_format(format)?,
+                None => vk::Format::UNDEFINED,
+            },
+        });
+        for (index, color_attachment) in self.color_attachments.iter().enumerate() {
+            builder = builder.color_blend(index, color_attachment.get_color_blend());
+        }
+        if let Some(dynamic_states) = self.dynamic_states.as_ref() {
+            builder = builder.dynamic_states(
+                dynamic_states
+                    .iter()
+                    .map(|dynamic_state| parse_dynamic_state(dynamic_state))
+                    .collect::<Result<Vec<vk::DynamicState>, _>>()?,
+            );
+        }
+        if let (true, Some(library_cache)) = (self.libraries, library_cache) {
+            builder = builder.library_cache(library_cache.clone());
+        }
+        builder = builder.link_time_optimization(self.link_time_optimization);
+        let builder = builder.reflected_layout(&reflection, set_layout_cache)?;
+        Ok((builder, modules))
+    }
+
+    /// Validate the description and build it, the shader modules are destroyed once built
+    pub fn build(
+        &self,
+        device: abstraction::Device,
+        deletion_queue: PipelineDeletionQueue,
+        set_layout_cache: &abstraction::DescriptorSetLayoutCache,
+        library_cache: Option<&PipelineLibraryCache>,
+    ) -> Result<GraphicsPipeline, PipelineDescriptionError> {
+        let (builder, _modules) =
+            self.create_builder(device, deletion_queue, set_layout_cache, library_cache)?;
+        Ok(builder.build()?)
+    }
+}
+
+/// Describes a [ComputePipeline], the local size is taken from the shader
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ComputePipelineDescription {
+    pub shader: ShaderDescription,
+}
+
+impl ComputePipelineDescription {
+    pub fn from_ron(source: &str) -> Result<Self, PipelineDescriptionError> {
+        ron::from_str(source).map_err(|error| PipelineDescriptionError::Parse(error.to_string()))
+    }
+
+    pub fn from_json(source: &str) -> Result<Self, PipelineDescriptionError> {
+        serde_json::from_str(source)
+            .map_err(|error| PipelineDescriptionError::Parse(error.to_string()))
+    }
+
+    /// Load a `.json` or otherwise `.ron` file, the shader path is relative to the file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, PipelineDescriptionError> {
+        let mut description: Self = parse_file(path.as_ref())?;
+        description.shader.resolve(get_directory(path.as_ref()));
+        Ok(description)
+    }
+
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).unwrap()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Check the shader is a compute shader and its specialization constants exist, without
+    /// calling Vulkan
+    pub fn validate(&self) -> Result<PipelineReflection, PipelineDescriptionError> {
+        self.validate_code(&self.shader.read()?)
+    }
+
+    fn validate_code(&self, code: &[u32]) -> Result<PipelineReflection, PipelineDescriptionError> {
+        let reflection = self.shader.reflect(code)?;
+        if reflection.get_stage() != vk::ShaderStageFlags::COMPUTE {
+            return Err(PipelineDescriptionError::Invalid(format!(
+                "{} is not a compute shader",
+                self.shader.path.display()
+            )));
+        }
+        Ok(PipelineReflection::new(vec![reflection])?)
+    }
+
+    /// Validate the description then create its shader module and a builder using it
+    ///
+    /// The module has to outlive every pipeline built from the builder, including rebuilds.
+    pub fn create_builder(
+        &self,
+        device: abstraction::Device,
+        deletion_queue: PipelineDeletionQueue,
+        set_layout_cache: &abstraction::DescriptorSetLayoutCache,
+    ) -> Result<(ComputePipelineBuilder, ShaderModule), PipelineDescriptionError> {
+        let code = self.shader.read()?;
+        let reflection = self.validate_code(&code)?;
+        let module = ShaderModule::from_spirv(device.clone(), &code).map_err(|error| {
+            PipelineDescriptionError::Shader {
+                path: self.shader.path.clone(),
+                error,
+            }
+        })?;
+        let builder = ComputePipelineBuilder::new(device, deletion_queue)
+            .shader_module(&module, &self.shader.entry_point)?
+            .specialization(self.shader.get_specialization())
+            .reflected_layout(&reflection, set_layout_cache)?;
+        Ok((builder, module))
+    }
+
+    /// Validate the description and build it, the shader module is destroyed once built
+    pub fn build(
+        &self,
+        device: abstraction::Device,
+        deletion_queue: PipelineDeletionQueue,
+        set_layout_cache: &abstraction::DescriptorSetLayoutCache,
+    ) -> Result<ComputePipeline, PipelineDescriptionError> {
+        let (builder, _module) = self.create_builder(device, deletion_queue, set_layout_cache)?;
+        Ok(builder.build()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// The same description loads from RON and JSON, filling in defaults
+    fn test_parse() {
+        let ron = r#"(
+            shaders: [
+                (path: "mesh.vert.spv"),
+                (path: "mesh.frag.spv", specialization: [(id: 0, value: Bool(true))]),
+            ],
+            cull_mode: Back,
+            depth_test: Some((write: true, compare_op: GreaterOrEqual)),
+            color_attachments: [(format: "R16G16B16A16_SFLOAT", blend: Alpha)],
+        )"#;
+        let json = r#"{
+            "shaders": [
+                {"path": "mesh.vert.spv"},
+                {"path": "mesh.frag.spv", "specialization": [{"id": 0, "value": {"Bool": true}}]}
+            ],
+            "cull_mode": "Back",
+            "depth_test": {"write": true, "compare_op": "GreaterOrEqual"},
+            "color_attachments": [{"format": "R16G16B16A16_SFLOAT", "blend": "Alpha"}]
+        }"#;
+        let description = GraphicsPipelineDescription::from_ron(ron).unwrap();
+        assert_eq!(
+            GraphicsPipelineDescription::from_json(json).unwrap(),
+            description
+        );
+        assert_eq!(description.shaders[0].entry_point, "main");
+        assert_eq!(description.samples, 1);
+        assert_eq!(description.topology, Topology::TriangleList);
+        assert_eq!(
+            GraphicsPipelineDescription::from_ron(&description.to_ron()).unwrap(),
+            description
+        );
+        assert!(GraphicsPipelineDescription::from_ron("(shaders: [], blend: Alpha)").is_err());
+    }
+
+    #[test]
+    /// Custom blend equations and write masks map onto the color blend of the builder
+    fn test_color_blend() {
+        let description = GraphicsPipelineDescription::from_ron(
+            r#"(
+                shaders: [],
+                color_attachments: [
+                    (format: "R8G8B8A8_UNORM", blend: Alpha),
+                    (
+                        format: "R8G8B8A8_UNORM",
+                        blend: Custom((
+                            src_color_blend_factor: DstColor,
+                            dst_color_blend_factor: Zero,
+                            color_blend_op: Add,
+                            src_alpha_blend_factor: One,
+                            dst_alpha_blend_factor: Zero,
+                            alpha_blend_op: Max,
+                        )),
+                        write_mask: [R, G, B],
+                    ),
+                ],
+                dynamic_states: Some(["VIEWPORT", "POLYGON_MODE_EXT"]),
+            )"#,
+        )
+        .unwrap();
+        assert_eq!(
+            description.color_attachments[0].get_color_blend(),
+            ColorBlendAttachment::ALPHA
+        );
+        let multiply = description.color_attachments[1].get_color_blend();
+        assert!(multiply.blend_enable);
+        assert_eq!(multiply.src_color_blend_factor, vk::BlendFactor::DST_COLOR);
+        assert_eq!(multiply.alpha_blend_op, vk::BlendOp::MAX);
+        assert_eq!(
+            multiply.color_write_mask,
+            vk::ColorComponentFlags::R | vk::ColorComponentFlags::G | vk::ColorComponentFlags::B
+        );
+        assert_eq!(
+            GraphicsPipelineDescription::from_json(&description.to_json()).unwrap(),
+            description
+        );
+    }
+
+    #[test]
+    /// Formats are looked up by their Vulkan name
+    fn test_get_format() {
+        assert_eq!(
+            get_format("R8G8B8A8_UNORM"),
+            Some(vk::Format::R8G8B8A8_UNORM)
+        );
+        assert_eq!(get_format("D32_SFLOAT"), Some(vk::Format::D32_SFLOAT));
+        assert_eq!(
+            get_format("G8_B8R8_2PLANE_420_UNORM"),
+            Some(vk::Format::G8_B8R8_2PLANE_420_UNORM)
+        );
+        assert_eq!(
+            get_format("A4B4G4R4_UNORM_PACK16"),
+            Some(vk::Format::A4B4G4R4_UNORM_PACK16)
+        );
+        assert_eq!(get_format("R8G8B8A8"), None);
+        assert_eq!(
+            get_dynamic_state("CULL_MODE"),
+            Some(vk::DynamicState::CULL_MODE)
+        );
+        assert_eq!(
+            get_dynamic_state("COLOR_BLEND_EQUATION_EXT"),
+            Some(vk::DynamicState::COLOR_BLEND_EQUATION_EXT)
+        );
+        assert_eq!(get_dynamic_state("CULL"), None);
+    }
+
+    fn instruction(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![((operands.len() as u32 + 1) << 16) | opcode];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    /// A shader of `execution_model` with a single vector at location 0 in `storage_class` and
+    /// a float specialization constant 0
+    fn interface_code(execution_model: u32, storage_class: u32) -> Vec<u32> {
+        let mut code = vec![shader::SPIRV_MAGIC, 0x0001_0300, 0, 7, 0];
+        let parts = [
+            // OpCapability Shader
+            instruction(17, &[1]),
+            // OpEntryPoint %1 "main" %5
+            instruction(15, &[execution_model, 1, u32::from_le_bytes(*b"main"), 0, 5]),
+            // OpDecorate %5 Location 0
+            instruction(71, &[5, 30, 0]),
+            // OpDecorate %6 SpecId 0
+            instruction(71, &[6, 1, 0]),
+            // %2 = OpTypeFloat 32
+            instruction(22, &[2, 32]),
+            // %3 = OpTypeVector %2 4
+            instruction(23, &[3, 2, 4]),
+            // %4 = OpTypePointer storage_class %3
+            instruction(32, &[4, storage_class, 3]),
+            // %5 = OpVariable %4 storage_class
+            instruction(59, &[4, 5, storage_class]),
+            // %6 = OpSpecConstant %2 1.0
+            instruction(50, &[2, 6, 1.0f32.to_bits()]),
+        ];
+        for part in parts {
+            code.extend(part);
+        }
+        code
+    }
+
+    #[test]
+    /// Interfaces and specialization constants of the shaders are checked against the
+    /// description
+    fn test_validate_code() {
+        // Vertex input and fragment output
+        let codes = [interface_code(0, 1), interface_code(4, 3)];
+        let description = |fragment_specialization: &str, attributes: &str, attachments: &str| {
+            GraphicsPipelineDescription::from_ron(&format!(
+                r#"(
+                    shaders: [
+                        (path: "mesh.vert.spv"),
+                        (path: "mesh.frag.spv", specialization: [{}]),
+                    ],
+                    vertex_bindings: [(binding: 0, stride: 16)],
+                    vertex_attributes: [{}],
+                    color_attachments: [{}],
+                )"#,
+                fragment_specialization, attributes, attachments
+            ))
+            .unwrap()
+        };
+        let specialization = "(id: 0, value: F32(0.5))";
+        let attribute = r#"(location: 0, binding: 0, format: "R32G32B32A32_SFLOAT", offset: 0)"#;
+        let attachment = r#"(format: "R8G8B8A8_UNORM")"#;
+        let reflection = description(specialization, attribute, attachment)
+            .validate_code(&codes)
+            .unwrap();
+        assert_eq!(reflection.get_stages().len(), 2);
+
+        let invalid = [
+            description("(id: 0, value: U32(1))", attribute, attachment),
+            description("(id: 1, value: F32(0.5))", attribute, attachment),
+            description(specialization, "", attachment),
+            description(specialization, attribute, ""),
+        ];
+        for description in invalid {
+            assert!(matches!(
+                description.validate_code(&codes),
+                Err(PipelineDescriptionError::Invalid(_))
+            ));
+        }
+    }
+}
@@ -1,14 +1,1123 @@
 use super::*;
+use crate::abstraction::prelude as abstraction;
+use ash::vk;
+use crate::abstraction::utility::shader::ShaderModule;
+use ash::vk::TaggedStructure;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 
-pub struct GraphicsPipelineBuilder {}
+/// Blending of a single color attachment
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ColorBlendAttachment {
+    pub blend_enable: bool,
+    pub src_color_blend_factor: vk::BlendFactor,
+    pub dst_color_blend_factor: vk::BlendFactor,
+    pub color_blend_op: vk::BlendOp,
+    pub src_alpha_blend_factor: vk::BlendFactor,
+    pub dst_alpha_blend_factor: vk::BlendFactor,
+    pub alpha_blend_op: vk::BlendOp,
+    pub color_write_mask: vk::ColorComponentFlags,
+}
+
+impl ColorBlendAttachment {
+    /// Overwrite the attachment
+    pub const DISABLED: Self = Self {
+        blend_enable: false,
+        src_color_blend_factor: vk::BlendFactor::ONE,
+        dst_color_blend_factor: vk::BlendFactor::ZERO,
+        color_blend_op: vk::BlendOp::ADD,
+        src_alpha_blend_factor: vk::BlendFactor::ONE,
+        dst_alpha_blend_factor: vk::BlendFactor::ZERO,
+        alpha_blend_op: vk::BlendOp::ADD,
+        color_write_mask: vk::ColorComponentFlags::RGBA,
+    };
+
+    /// Blend using the alpha of the fragment
+    pub const ALPHA: Self = Self {
+        blend_enable: true,
+        src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+        dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+        color_blend_op: vk::BlendOp::ADD,
+        src_alpha_blend_factor: vk::BlendFactor::ONE,
+        dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+        alpha_blend_op: vk::BlendOp::ADD,
+        color_write_mask: vk::ColorComponentFlags::RGBA,
+    };
+
+    /// Add the fragment onto the attachment
+    pub const ADDITIVE: Self = Self {
+        blend_enable: true,
+        src_color_blend_factor: vk::BlendFactor::ONE,
+        dst_color_blend_factor: vk::BlendFactor::ONE,
+        color_blend_op: vk::BlendOp::ADD,
+        src_alpha_blend_factor: vk::BlendFactor::ONE,
+        dst_alpha_blend_factor: vk::BlendFactor::ONE,
+        alpha_blend_op: vk::BlendOp::ADD,
+        color_write_mask: vk::ColorComponentFlags::RGBA,
+    };
+
+    pub fn get_vk_state(&self) -> vk::PipelineColorBlendAttachmentState {
+        vk::PipelineColorBlendAttachmentState {
+            blend_enable: self.blend_enable as vk::Bool32,
+            src_color_blend_factor: self.src_color_blend_factor,
+            dst_color_blend_factor: self.dst_color_blend_factor,
+            color_blend_op: self.color_blend_op,
+            src_alpha_blend_factor: self.src_alpha_blend_factor,
+            dst_alpha_blend_factor: self.dst_alpha_blend_factor,
+            alpha_blend_op: self.alpha_blend_op,
+            color_write_mask: self.color_write_mask,
+        }
+    }
+
+    pub fn get_vk_equation(&self) -> vk::ColorBlendEquationEXT {
+        vk::ColorBlendEquationEXT {
+            src_color_blend_factor: self.src_color_blend_factor,
+            dst_color_blend_factor: self.dst_color_blend_factor,
+            color_blend_op: self.color_blend_op,
+            src_alpha_blend_factor: self.src_alpha_blend_factor,
+            dst_alpha_blend_factor: self.dst_alpha_blend_factor,
+            alpha_blend_op: self.alpha_blend_op,
+        }
+    }
+}
+
+impl Default for ColorBlendAttachment {
+    fn default() -> Self {
+        Self::DISABLED
+    }
+}
+
+/// Constant and slope scaled bias added to the depth of fragments
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    pub clamp: f32,
+    pub slope_factor: f32,
+}
+
+/// Get the states a graphics pipeline leaves dynamic by default
+///
+/// Viewport and scissor are always dynamic, so are extended dynamic state 1 and 2 which are
+/// core in Vulkan 1.3. States of extended dynamic state 3 are only dynamic if enabled in
+/// `features`.
+/// Whether `dynamic_state` can only be set through extended dynamic state 3
+fn is_extended_dynamic_state_3(dynamic_state: vk::DynamicState) -> bool {
+    matches!(
+        dynamic_state,
+        vk::DynamicState::POLYGON_MODE_EXT
+            | vk::DynamicState::RASTERIZATION_SAMPLES_EXT
+            | vk::DynamicState::DEPTH_CLAMP_ENABLE_EXT
+            | vk::DynamicState::COLOR_BLEND_ENABLE_EXT
+            | vk::DynamicState::COLOR_BLEND_EQUATION_EXT
+            | vk::DynamicState::COLOR_WRITE_MASK_EXT
+    )
+}
+
+pub(crate) fn get_dynamic_states(
+    features: &vk::PhysicalDeviceExtendedDynamicState3FeaturesEXT,
+) -> Vec<vk::DynamicState> {
+    let mut dynamic_states = vec![
+        vk::DynamicState::VIEWPORT,
+        vk::DynamicState::SCISSOR,
+        vk::DynamicState::DEPTH_BIAS,
+        vk::DynamicState::DEPTH_BOUNDS,
+        vk::DynamicState::STENCIL_COMPARE_MASK,
+        vk::DynamicState::STENCIL_WRITE_MASK,
+        vk::DynamicState::STENCIL_REFERENCE,
+        // Extended dynamic state
+        vk::DynamicState::CULL_MODE,
+        vk::DynamicState::FRONT_FACE,
+        vk::DynamicState::PRIMITIVE_TOPOLOGY,
+        vk::DynamicState::DEPTH_TEST_ENABLE,
+        vk::DynamicState::DEPTH_WRITE_ENABLE,
+        vk::DynamicState::DEPTH_COMPARE_OP,
+        vk::DynamicState::DEPTH_BOUNDS_TEST_ENABLE,
+        vk::DynamicState::STENCIL_TEST_ENABLE,
+        vk::DynamicState::STENCIL_OP,
+        // Extended dynamic state 2
+        vk::DynamicState::RASTERIZER_DISCARD_ENABLE,
+        vk::DynamicState::DEPTH_BIAS_ENABLE,
+        vk::DynamicState::PRIMITIVE_RESTART_ENABLE,
+    ];
+    // Extended dynamic state 3
+    let optional_states = [
+        (
+            features.extended_dynamic_state3_polygon_mode,
+            vk::DynamicState::POLYGON_MODE_EXT,
+        ),
+        (
+            features.extended_dynamic_state3_rasterization_samples,
+            vk::DynamicState::RASTERIZATION_SAMPLES_EXT,
+        ),
+        (
+            features.extended_dynamic_state3_depth_clamp_enable,
+            vk::DynamicState::DEPTH_CLAMP_ENABLE_EXT,
+        ),
+        (
+            features.extended_dynamic_state3_color_blend_enable,
+            vk::DynamicState::COLOR_BLEND_ENABLE_EXT,
+        ),
+        (
+            features.extended_dynamic_state3_color_blend_equation,
+            vk::DynamicState::COLOR_BLEND_EQUATION_EXT,
+        ),
+        (
+            features.extended_dynamic_state3_color_write_mask,
+            vk::DynamicState::COLOR_WRITE_MASK_EXT,
+        ),
+    ];
+    dynamic_states.extend(
+        optional_states
+            .into_iter()
+            .filter(|(supported, _)| *supported == vk::TRUE)
+            .map(|(_, dynamic_state)| dynamic_state),
+    );
+    dynamic_states
+}
+
+/// Hash every stage of `stages` which belongs to the fragment shader part if `fragment` is set,
+/// or to the pre-rasterization part otherwise
+///
+/// Stages are hashed through the hash of their code rather than their module handle.
+fn hash_stages<H: Hasher>(stages: &[ShaderStage], fragment: bool, hasher: &mut H) {
+    for stage in stages.iter() {
+        if (stage.get_stage() == vk::ShaderStageFlags::FRAGMENT) == fragment {
+            (
+                stage.get_stage(),
+                stage.get_code_hash(),
+                stage.get_entry_point(),
+                stage.get_specialization(),
+            )
+                .hash(hasher);
+        }
+    }
+}
+
+/// Describes a [GraphicsPipeline] rendering into a dynamic rendering pass
+///
+/// Every state which is dynamic is also stored by the pipeline and set by
+/// [GraphicsPipeline::bind], except for the viewport and scissor.
+#[derive(Clone)]
+pub struct GraphicsPipelineBuilder {
+    device: abstraction::Device,
+    deletion_queue: PipelineDeletionQueue,
+    stages: Vec<ShaderStage>,
+    vertex_bindings: Vec<vk::VertexInputBindingDescription>,
+    vertex_attributes: Vec<vk::VertexInputAttributeDescription>,
+    topology: vk::PrimitiveTopology,
+    primitive_restart: bool,
+    rasterizer_discard: bool,
+    polygon_mode: vk::PolygonMode,
+    cull_mode: vk::CullModeFlags,
+    front_face: vk::FrontFace,
+    depth_clamp: bool,
+    depth_bias: Option<DepthBias>,
+    samples: vk::SampleCountFlags,
+    depth_test: bool,
+    depth_write: bool,
+    depth_compare_op: vk::CompareOp,
+    /// Minimum and maximum depth, [None] if the depth bounds test is disabled
+    depth_bounds: Option<(f32, f32)>,
+    /// Front and back stencil state, [None] if the stencil test is disabled
+    stencil: Option<(vk::StencilOpState, vk::StencilOpState)>,
+    color_blend: Vec<ColorBlendAttachment>,
+    rendering: abstraction::PipelineRenderingInfo,
+    set_layouts: Vec<Arc<abstraction::DescriptorSetLayout>>,
+    push_constant_ranges: Vec<vk::PushConstantRange>,
+    dynamic_states: Vec<vk::DynamicState>,
+    /// Cache parts are taken from, [None] to build monolithic pipelines
+    library_cache: Option<PipelineLibraryCache>,
+    link_time_optimization: bool,
+}
 
 impl PipelineBuilder for GraphicsPipelineBuilder {}
 
-pub struct GraphicsPipeline {}
+impl GraphicsPipelineBuilder {
+    /// Start describing a pipeline which is handed over to `deletion_queue` once dropped
+    pub fn new(device: abstraction::Device, deletion_queue: PipelineDeletionQueue) -> Self {
+        let dynamic_states = get_dynamic_states(device.get_extended_dynamic_state_3_features());
+        Self {
+            device,
+            deletion_queue,
+            stages: Vec::new(),
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            primitive_restart: false,
+            rasterizer_discard: false,
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            depth_clamp: false,
+            depth_bias: None,
+            samples: vk::SampleCountFlags::TYPE_1,
+            depth_test: false,
+            depth_write: false,
+            depth_compare_op: vk::CompareOp::ALWAYS,
+            depth_bounds: None,
+            stencil: None,
+            color_blend: Vec::new(),
+            rendering: abstraction::PipelineRenderingInfo::default(),
+            set_layouts: Vec::new(),
+            push_constant_ranges: Vec::new(),
+            dynamic_states,
+            library_cache: None,
+            link_time_optimization: false,
+        }
+    }
+
+    /// Add a shader stage, replacing any previous stage of the same type
+    pub fn shader_stage(
+        mut self,
+        stage: vk::ShaderStageFlags,
+        module: vk::ShaderModule,
+        entry_point: &str,
+    ) -> Self {
+        self.stages.retain(|existing| existing.get_stage() != stage);
+        self.stages.push(ShaderStage::new(stage, module, entry_point));
+        self
+    }
+
+    /// Add the stage of `entry_point` in `module`, replacing any previous stage of the same type
+    pub fn shader_module(
+        self,
+        module: &ShaderModule,
+        entry_point: &str,
+    ) -> Result<Self, ReflectionError> {
+        let stage = module
+            .get_entry_point(entry_point)
+            .ok_or_else(|| ReflectionError::EntryPointNotFound(entry_point.to_string()))?
+            .stage;
+        let mut builder = self.shader_stage(stage, module.get_handle(), entry_point);
+        let added = builder.stages.pop().unwrap();
+        builder.stages.push(added.code_hash(module.get_code_hash()));
+        Ok(builder)
+    }
+
+    /// Set the specialization constants of the stage of type `stage`, which has to have been
+    /// added already
+    pub fn specialization(
+        mut self,
+        stage: vk::ShaderStageFlags,
+        specialization: SpecializationConstants,
+    ) -> Self {
+        let index = self
+            .stages
+            .iter()
+            .position(|existing| existing.get_stage() == stage);
+        debug_assert!(index.is_some(), "Stage {:?} has not been added", stage);
+        if let Some(index) = index {
+            self.stages[index] = self.stages[index].clone().specialization(specialization);
+        }
+        self
+    }
+
+    pub fn vertex_binding(mut self, binding: u32, stride: u32, input_rate: vk::VertexInputRate) -> Self {
+        self.vertex_bindings.push(vk::VertexInputBindingDescription {
+            binding,
+            stride,
+            input_rate,
+        });
+        self
+    }
+
+    pub fn vertex_attribute(mut self, location: u32, binding: u32, format: vk::Format, offset: u32) -> Self {
+        self.vertex_attributes.push(vk::VertexInputAttributeDescription {
+            location,
+            binding,
+            format,
+            offset,
+        });
+        self
+    }
+
+    pub fn topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn primitive_restart(mut self, primitive_restart: bool) -> Self {
+        self.primitive_restart = primitive_restart;
+        self
+    }
+
+    pub fn rasterizer_discard(mut self, rasterizer_discard: bool) -> Self {
+        self.rasterizer_discard = rasterizer_discard;
+        self
+    }
+
+    pub fn polygon_mode(mut self, polygon_mode: vk::PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: vk::CullModeFlags, front_face: vk::FrontFace) -> Self {
+        self.cull_mode = cull_mode;
+        self.front_face = front_face;
+        self
+    }
+
+    pub fn depth_clamp(mut self, depth_clamp: bool) -> Self {
+        self.depth_clamp = depth_clamp;
+        self
+    }
+
+    pub fn depth_bias(mut self, depth_bias: DepthBias) -> Self {
+        self.depth_bias = Some(depth_bias);
+        self
+    }
+
+    pub fn samples(mut self, samples: vk::SampleCountFlags) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// Enable the depth test, writing depth if `depth_write` is set
+    pub fn depth_test(mut self, depth_write: bool, compare_op: vk::CompareOp) -> Self {
+        self.depth_test = true;
+        self.depth_write = depth_write;
+        self.depth_compare_op = compare_op;
+        self
+    }
+
+    pub fn depth_bounds(mut self, min_depth_bounds: f32, max_depth_bounds: f32) -> Self {
+        self.depth_bounds = Some((min_depth_bounds, max_depth_bounds));
+        self
+    }
+
+    pub fn stencil_test(mut self, front: vk::StencilOpState, back: vk::StencilOpState) -> Self {
+        self.stencil = Some((front, back));
+        self
+    }
+
+    /// Set the blending of the color attachment at `index`, attachments without blending set
+    /// use [ColorBlendAttachment::DISABLED]
+    pub fn color_blend(mut self, index: usize, color_blend: ColorBlendAttachment) -> Self {
+        if self.color_blend.len() <= index {
+            self.color_blend.resize(index + 1, ColorBlendAttachment::DISABLED);
+        }
+        self.color_blend[index] = color_blend;
+        self
+    }
+
+    /// Set every attachment format from the pass the pipeline renders in
+    pub fn rendering(mut self, rendering: abstraction::PipelineRenderingInfo) -> Self {
+        self.rendering = rendering;
+        self
+    }
+
+    /// Add a color attachment after every previously added one
+    pub fn color_attachment_format(mut self, format: vk::Format) -> Self {
+        self.rendering.color_attachment_formats.push(format);
+        self
+    }
+
+    pub fn depth_attachment_format(mut self, format: vk::Format) -> Self {
+        self.rendering.depth_attachment_format = format;
+        self
+    }
+
+    pub fn stencil_attachment_format(mut self, format: vk::Format) -> Self {
+        self.rendering.stencil_attachment_format = format;
+        self
+    }
+
+    /// Add a set layout after every previously added one
+    pub fn set_layout(mut self, set_layout: Arc<abstraction::DescriptorSetLayout>) -> Self {
+        self.set_layouts.push(set_layout);
+        self
+    }
+
+    pub fn push_constant_range(mut self, stage_flags: vk::ShaderStageFlags, offset: u32, size: u32) -> Self {
+        self.push_constant_ranges.push(vk::PushConstantRange {
+            stage_flags,
+            offset,
+            size,
+        });
+        self
+    }
+
+    /// Replace the set layouts and push constant ranges with the ones reflected from the
+    /// shaders, set layouts are shared through `cache`
+    pub fn reflected_layout(
+        mut self,
+        reflection: &PipelineReflection,
+        cache: &abstraction::DescriptorSetLayoutCache,
+    ) -> Result<Self, vk::Result> {
+        self.set_layouts = reflection.create_set_layouts(cache)?;
+        self.push_constant_ranges = reflection.get_push_constant_ranges();
+        Ok(self)
+    }
+
+    /// Override which states are dynamic
+    ///
+    /// By default the viewport, scissor and every state of extended dynamic state 1 and 2 are
+    /// dynamic, along with every state of extended dynamic state 3 the device supports.
+    /// Building fails with [vk::Result::ERROR_EXTENSION_NOT_PRESENT] if the override contains an
+    /// extended dynamic state 3 state the device does not support.
+    pub fn dynamic_states(mut self, dynamic_states: Vec<vk::DynamicState>) -> Self {
+        self.dynamic_states = dynamic_states;
+        self
+    }
+
+    /// Build the pipeline from parts cached in `cache` if the device supports graphics pipeline
+    /// libraries, otherwise the pipeline is built as a whole
+    pub fn library_cache(mut self, cache: PipelineLibraryCache) -> Self {
+        self.library_cache = Some(cache);
+        self
+    }
+
+    /// Optimize across parts when linking, which is slower but produces faster pipelines
+    pub fn link_time_optimization(mut self, link_time_optimization: bool) -> Self {
+        self.link_time_optimization = link_time_optimization;
+        self
+    }
+
+    /// Whether [Self::build] links the pipeline from parts
+    ///
+    /// Parts are only used if the device links them fast and every stage was added through
+    /// [Self::shader_module], as parts are keyed on the code of their shaders.
+    pub fn uses_libraries(&self) -> bool {
+        let fast_linking = self
+            .device
+            .get_graphics_pipeline_library_properties()
+            .is_some_and(|properties| {
+                properties.graphics_pipeline_library_fast_linking == vk::TRUE
+            });
+        self.library_cache.is_some()
+            && fast_linking
+            && self
+                .stages
+                .iter()
+                .all(|stage| stage.get_code_hash().is_some())
+    }
+
+    /// Get a key identifying every state which goes into `part`
+    ///
+    /// Shaders are identified by the hash of their code and set layouts by their bindings, so
+    /// handles destroyed and reused for something else never match a cached part.
+    pub fn get_part_key(&self, part: GraphicsPipelinePart) -> GraphicsPipelinePartKey {
+        let mut hasher = GraphicsPipelinePartKey::new(part);
+        self.dynamic_states.hash(&mut hasher);
+        let hash_layout = |hasher: &mut GraphicsPipelinePartKey| {
+            for set_layout in self.set_layouts.iter() {
+                (set_layout.get_flags(), set_layout.get_bindings()).hash(hasher);
+            }
+            for range in self.push_constant_ranges.iter() {
+                (range.stage_flags, range.offset, range.size).hash(hasher);
+            }
+        };
+        match part {
+            GraphicsPipelinePart::VertexInput => {
+                for binding in self.vertex_bindings.iter() {
+                    (binding.binding, binding.stride, binding.input_rate).hash(&mut hasher);
+                }
+                for attribute in self.vertex_attributes.iter() {
+                    (attribute.location, attribute.binding, attribute.format, attribute.offset)
+                        .hash(&mut hasher);
+                }
+                (self.topology, self.primitive_restart).hash(&mut hasher);
+            }
+            GraphicsPipelinePart::PreRasterization => {
+                hash_stages(&self.stages, false, &mut hasher);
+                hash_layout(&mut hasher);
+                (
+                    self.rasterizer_discard,
+                    self.polygon_mode,
+                    self.cull_mode,
+                    self.front_face,
+                    self.depth_clamp,
+                    self.rendering.view_mask,
+                )
+                    .hash(&mut hasher);
+                self.depth_bias
+                    .map(|bias| {
+                        [bias.constant_factor, bias.clamp, bias.slope_factor].map(f32::to_bits)
+                    })
+                    .hash(&mut hasher);
+            }
+            GraphicsPipelinePart::FragmentShader => {
+                hash_stages(&self.stages, true, &mut hasher);
+                hash_layout(&mut hasher);
+                (
+                    self.samples,
+                    self.depth_test,
+                    self.depth_write,
+                    self.depth_compare_op,
+                    &self.rendering,
+                )
+                    .hash(&mut hasher);
+                self.depth_bounds
+                    .map(|(min, max)| (min.to_bits(), max.to_bits()))
+                    .hash(&mut hasher);
+                for state in self.stencil.iter().flat_map(|(front, back)| [front, back]) {
+                    (
+                        state.fail_op,
+                        state.pass_op,
+                        state.depth_fail_op,
+                        state.compare_op,
+                        state.compare_mask,
+                        state.write_mask,
+                        state.reference,
+                    )
+                        .hash(&mut hasher);
+                }
+            }
+            GraphicsPipelinePart::FragmentOutput => {
+                (self.get_color_blend(), self.samples, &self.rendering).hash(&mut hasher);
+            }
+        }
+        hasher
+    }
+
+    pub fn get_stages(&self) -> &[ShaderStage] {
+        &self.stages
+    }
+
+    pub fn get_set_layouts(&self) -> &[Arc<abstraction::DescriptorSetLayout>] {
+        &self.set_layouts
+    }
+
+    pub fn get_push_constant_ranges(&self) -> &[vk::PushConstantRange] {
+        &self.push_constant_ranges
+    }
+
+    pub fn get_rendering(&self) -> &abstraction::PipelineRenderingInfo {
+        &self.rendering
+    }
+
+    pub fn get_dynamic_states(&self) -> &[vk::DynamicState] {
+        &self.dynamic_states
+    }
+
+    /// Get the blending of every color attachment of [Self::get_rendering]
+    pub fn get_color_blend(&self) -> Vec<ColorBlendAttachment> {
+        (0..self.rendering.color_attachment_formats.len())
+            .map(|index| {
+                self.color_blend
+                    .get(index)
+                    .copied()
+                    .unwrap_or(ColorBlendAttachment::DISABLED)
+            })
+            .collect()
+    }
+
+    pub fn build(self) -> Result<GraphicsPipeline, vk::Result> {
+        GraphicsPipeline::from_builder(self)
+    }
+}
+
+/// An abstraction for graphics [vk::Pipeline] using dynamic rendering
+///
+/// Owns its [vk::PipelineLayout], both are destroyed through the [PipelineDeletionQueue] the
+/// pipeline was built with.
+pub struct GraphicsPipeline {
+    inner: Option<PipelineInner>,
+    deletion_queue: PipelineDeletionQueue,
+    builder: GraphicsPipelineBuilder,
+}
 
 impl Pipeline for GraphicsPipeline {
     type PipelineBuilderType = GraphicsPipelineBuilder;
-    fn from_builder(builder: GraphicsPipelineBuilder) -> Self {
-        todo!()
+
+    fn from_builder(builder: GraphicsPipelineBuilder) -> Result<Self, vk::Result> {
+        let device = builder.device.clone();
+        // Extended dynamic state 3 states can only be set through the extension
+        let supported = get_dynamic_states(device.get_extended_dynamic_state_3_features());
+        let unsupported = builder.dynamic_states.iter().any(|dynamic_state| {
+            is_extended_dynamic_state_3(*dynamic_state)
+                && (!supported.contains(dynamic_state)
+                    || device.get_extended_dynamic_state_3_loader().is_none())
+        });
+        if unsupported {
+            return Err(vk::Result::ERROR_EXTENSION_NOT_PRESENT);
+        }
+        let layout =
+            PipelineInner::create_layout(&device, &builder.set_layouts, &builder.push_constant_ranges)?;
+        let handle = match builder.library_cache.as_ref() {
+            Some(cache) if builder.uses_libraries() => {
+                let libraries: Result<Vec<Arc<PipelineInner>>, vk::Result> = GraphicsPipelinePart::ALL
+                    .iter()
+                    .map(|part| {
+                        cache.get_or_create(builder.get_part_key(*part), || {
+                            Self::create_library(&builder, *part)
+                        })
+                    })
+                    .collect();
+                libraries.and_then(|libraries| {
+                    let handles: Vec<vk::Pipeline> =
+                        libraries.iter().map(|library| library.get_handle()).collect();
+                    Self::create_handle(&builder, layout, CreateMode::Link(&handles))
+                })
+            }
+            _ => Self::create_handle(&builder, layout, CreateMode::Monolithic),
+        };
+        let handle = match handle {
+            Ok(handle) => handle,
+            Err(error) => {
+                unsafe { device.handle_as_ref().destroy_pipeline_layout(layout, None) };
+                return Err(error);
+            }
+        };
+        Ok(Self {
+            inner: Some(PipelineInner::new(
+                handle,
+                layout,
+                device,
+                builder.set_layouts.clone(),
+            )),
+            deletion_queue: builder.deletion_queue.clone(),
+            builder,
+        })
+    }
+
+    fn get_handle(&self) -> vk::Pipeline {
+        self.inner.as_ref().unwrap().get_handle()
+    }
+
+    fn get_layout(&self) -> vk::PipelineLayout {
+        self.inner.as_ref().unwrap().get_layout()
+    }
+
+    fn get_bind_point(&self) -> vk::PipelineBindPoint {
+        vk::PipelineBindPoint::GRAPHICS
+    }
+}
+
+/// How [GraphicsPipeline::create_handle] creates a pipeline
+#[derive(Copy, Clone)]
+enum CreateMode<'a> {
+    Monolithic,
+    Part(GraphicsPipelinePart),
+    Link(&'a [vk::Pipeline]),
+}
+
+impl GraphicsPipeline {
+    /// Create a single part of `builder` to be cached as a library
+    fn create_library(
+        builder: &GraphicsPipelineBuilder,
+        part: GraphicsPipelinePart,
+    ) -> Result<PipelineInner, vk::Result> {
+        let device = builder.device.clone();
+        let (layout, set_layouts) = if part.uses_layout() {
+            let layout =
+                PipelineInner::create_layout(&device, &builder.set_layouts, &builder.push_constant_ranges)?;
+            (layout, builder.set_layouts.clone())
+        } else {
+            (vk::PipelineLayout::null(), Vec::new())
+        };
+        match Self::create_handle(builder, layout, CreateMode::Part(part)) {
+            Ok(handle) => Ok(PipelineInner::new(handle, layout, device, set_layouts)),
+            Err(error) => {
+                unsafe { device.handle_as_ref().destroy_pipeline_layout(layout, None) };
+                Err(error)
+            }
+        }
+    }
+
+    /// Create the [vk::Pipeline] of `builder` as a whole, as a single part or by linking parts
+    fn create_handle(
+        builder: &GraphicsPipelineBuilder,
+        layout: vk::PipelineLayout,
+        mode: CreateMode,
+    ) -> Result<vk::Pipeline, vk::Result> {
+        let parts = match mode {
+            CreateMode::Monolithic => GraphicsPipelinePart::ALL
+                .iter()
+                .fold(vk::GraphicsPipelineLibraryFlagsEXT::empty(), |parts, part| {
+                    parts | part.get_vk_flags()
+                }),
+            CreateMode::Part(part) => part.get_vk_flags(),
+            CreateMode::Link(_) => vk::GraphicsPipelineLibraryFlagsEXT::empty(),
+        };
+        let has_part = |part: GraphicsPipelinePart| parts.contains(part.get_vk_flags());
+        // Each stage belongs to either the pre-rasterization or the fragment shader part
+        let builder_stages: Vec<&ShaderStage> = builder
+            .stages
+            .iter()
+            .filter(|stage| {
+                if stage.get_stage() == vk::ShaderStageFlags::FRAGMENT {
+                    has_part(GraphicsPipelinePart::FragmentShader)
+                } else {
+                    has_part(GraphicsPipelinePart::PreRasterization)
+                }
+            })
+            .collect();
+        let map_entries: Vec<Vec<vk::SpecializationMapEntry>> = builder_stages
+            .iter()
+            .map(|stage| stage.get_specialization().get_vk_map_entries())
+            .collect();
+        let specialization_infos: Vec<vk::SpecializationInfo> = builder_stages
+            .iter()
+            .zip(map_entries.iter())
+            .map(|(stage, map_entries)| stage.get_vk_specialization_info(map_entries))
+            .collect();
+        let stages: Vec<vk::PipelineShaderStageCreateInfo> = builder_stages
+            .iter()
+            .zip(specialization_infos.iter())
+            .map(|(stage, specialization_info)| stage.get_vk_create_info(specialization_info))
+            .collect();
+        let vertex_input_state = vk::PipelineVertexInputStateCreateInfo {
+            s_type: vk::PipelineVertexInputStateCreateInfo::STRUCTURE_TYPE,
+            vertex_binding_description_count: builder.vertex_bindings.len() as u32,
+            p_vertex_binding_descriptions: builder.vertex_bindings.as_ptr(),
+            vertex_attribute_description_count: builder.vertex_attributes.len() as u32,
+            p_vertex_attribute_descriptions: builder.vertex_attributes.as_ptr(),
+            ..Default::default()
+        };
+        let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo {
+            s_type: vk::PipelineInputAssemblyStateCreateInfo::STRUCTURE_TYPE,
+            topology: builder.topology,
+            primitive_restart_enable: builder.primitive_restart as vk::Bool32,
+            ..Default::default()
+        };
+        let viewport_state = vk::PipelineViewportStateCreateInfo {
+            s_type: vk::PipelineViewportStateCreateInfo::STRUCTURE_TYPE,
+            viewport_count: 1,
+            scissor_count: 1,
+            ..Default::default()
+        };
+        let depth_bias = builder.depth_bias.unwrap_or_default();
+        let rasterization_state = vk::PipelineRasterizationStateCreateInfo {
+            s_type: vk::PipelineRasterizationStateCreateInfo::STRUCTURE_TYPE,
+            depth_clamp_enable: builder.depth_clamp as vk::Bool32,
+            rasterizer_discard_enable: builder.rasterizer_discard as vk::Bool32,
+            polygon_mode: builder.polygon_mode,
+            cull_mode: builder.cull_mode,
+            front_face: builder.front_face,
+            depth_bias_enable: builder.depth_bias.is_some() as vk::Bool32,
+            depth_bias_constant_factor: depth_bias.constant_factor,
+            depth_bias_clamp: depth_bias.clamp,
+            depth_bias_slope_factor: depth_bias.slope_factor,
+            line_width: 1.0,
+            ..Default::default()
+        };
+        let multisample_state = vk::PipelineMultisampleStateCreateInfo {
+            s_type: vk::PipelineMultisampleStateCreateInfo::STRUCTURE_TYPE,
+            rasterization_samples: builder.samples,
+            min_sample_shading: 1.0,
+            ..Default::default()
+        };
+        let (min_depth_bounds, max_depth_bounds) = builder.depth_bounds.unwrap_or((0.0, 1.0));
+        let (front, back) = builder.stencil.unwrap_or_default();
+        let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo {
+            s_type: vk::PipelineDepthStencilStateCreateInfo::STRUCTURE_TYPE,
+            depth_test_enable: builder.depth_test as vk::Bool32,
+            depth_write_enable: builder.depth_write as vk::Bool32,
+            depth_compare_op: builder.depth_compare_op,
+            depth_bounds_test_enable: builder.depth_bounds.is_some() as vk::Bool32,
+            stencil_test_enable: builder.stencil.is_some() as vk::Bool32,
+            front,
+            back,
+            min_depth_bounds,
+            max_depth_bounds,
+            ..Default::default()
+        };
+        let color_blend_attachments: Vec<vk::PipelineColorBlendAttachmentState> = builder
+            .get_color_blend()
+            .iter()
+            .map(|color_blend| color_blend.get_vk_state())
+            .collect();
+        let color_blend_state = vk::PipelineColorBlendStateCreateInfo {
+            s_type: vk::PipelineColorBlendStateCreateInfo::STRUCTURE_TYPE,
+            attachment_count: color_blend_attachments.len() as u32,
+            p_attachments: color_blend_attachments.as_ptr(),
+            ..Default::default()
+        };
+        let dynamic_state = vk::PipelineDynamicStateCreateInfo {
+            s_type: vk::PipelineDynamicStateCreateInfo::STRUCTURE_TYPE,
+            dynamic_state_count: builder.dynamic_states.len() as u32,
+            p_dynamic_states: builder.dynamic_states.as_ptr(),
+            ..Default::default()
+        };
+        let mut rendering_ci = builder.rendering.get_vk_create_info();
+        let library_ci = vk::GraphicsPipelineLibraryCreateInfoEXT {
+            s_type: vk::GraphicsPipelineLibraryCreateInfoEXT::STRUCTURE_TYPE,
+            flags: parts,
+            ..Default::default()
+        };
+        let libraries: &[vk::Pipeline] = match mode {
+            CreateMode::Link(libraries) => libraries,
+            _ => &[],
+        };
+        let linked_libraries_ci = vk::PipelineLibraryCreateInfoKHR {
+            s_type: vk::PipelineLibraryCreateInfoKHR::STRUCTURE_TYPE,
+            library_count: libraries.len() as u32,
+            p_libraries: libraries.as_ptr(),
+            ..Default::default()
+        };
+        let (p_next, flags) = match mode {
+            CreateMode::Monolithic => (
+                abstraction::utility::p_next(&rendering_ci),
+                vk::PipelineCreateFlags::empty(),
+            ),
+            CreateMode::Part(_) => {
+                rendering_ci.p_next = abstraction::utility::p_next(&library_ci);
+                (
+                    abstraction::utility::p_next(&rendering_ci),
+                    vk::PipelineCreateFlags::LIBRARY_KHR
+                        | vk::PipelineCreateFlags::RETAIN_LINK_TIME_OPTIMIZATION_INFO_EXT,
+                )
+            }
+            CreateMode::Link(_) => (
+                abstraction::utility::p_next(&linked_libraries_ci),
+                if builder.link_time_optimization {
+                    vk::PipelineCreateFlags::LINK_TIME_OPTIMIZATION_EXT
+                } else {
+                    vk::PipelineCreateFlags::empty()
+                },
+            ),
+        };
+        // States of parts which are not being created are left out
+        fn state<T>(part: bool, state: &T) -> *const T {
+            if part {
+                state
+            } else {
+                std::ptr::null()
+            }
+        }
+        let vertex_input = has_part(GraphicsPipelinePart::VertexInput);
+        let pre_rasterization = has_part(GraphicsPipelinePart::PreRasterization);
+        let fragment_shader = has_part(GraphicsPipelinePart::FragmentShader);
+        let fragment_output = has_part(GraphicsPipelinePart::FragmentOutput);
+        let pipeline_ci = vk::GraphicsPipelineCreateInfo {
+            s_type: vk::GraphicsPipelineCreateInfo::STRUCTURE_TYPE,
+            p_next,
+            flags: flags | PipelineInner::get_create_flags(&builder.set_layouts),
+            stage_count: stages.len() as u32,
+            p_stages: stages.as_ptr(),
+            p_vertex_input_state: state(vertex_input, &vertex_input_state),
+            p_input_assembly_state: state(vertex_input, &input_assembly_state),
+            p_viewport_state: state(pre_rasterization, &viewport_state),
+            p_rasterization_state: state(pre_rasterization, &rasterization_state),
+            p_multisample_state: state(fragment_shader || fragment_output, &multisample_state),
+            p_depth_stencil_state: state(fragment_shader, &depth_stencil_state),
+            p_color_blend_state: state(fragment_output, &color_blend_state),
+            p_dynamic_state: state(!parts.is_empty(), &dynamic_state),
+            layout,
+            render_pass: vk::RenderPass::null(),
+            base_pipeline_index: -1,
+            ..Default::default()
+        };
+        let handle = unsafe {
+            builder.device.handle_as_ref().create_graphics_pipelines(
+                builder.device.get_pipeline_cache(),
+                &[pipeline_ci],
+                None,
+            )
+        };
+        handle
+            .map(|pipelines| pipelines[0])
+            .map_err(|(_, error)| error)
+    }
+
+    /// Get the builder the pipeline was created from
+    pub fn get_builder(&self) -> &GraphicsPipelineBuilder {
+        &self.builder
+    }
+
+    /// Bind the pipeline and set every dynamic state to the value it was built with
+    ///
+    /// The viewport and scissor are left to the caller, see
+    /// [abstraction::CommandBuffer::set_viewport] and [abstraction::CommandBuffer::set_scissor].
+    pub fn bind(&self, command_buffer: &abstraction::CommandBuffer<abstraction::Recording>) {
+        command_buffer.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, self.get_handle());
+        let builder = &self.builder;
+        let device = builder.device.handle_as_ref();
+        let handle = command_buffer.get_handle();
+        let depth_bias = builder.depth_bias.unwrap_or_default();
+        let (min_depth_bounds, max_depth_bounds) = builder.depth_bounds.unwrap_or((0.0, 1.0));
+        let (front, back) = builder.stencil.unwrap_or_default();
+        let color_blend = builder.get_color_blend();
+        // Building rejects extended dynamic state 3 states without the extension
+        let extended_dynamic_state_3 = builder.device.get_extended_dynamic_state_3_loader();
+        for dynamic_state in builder.dynamic_states.iter() {
+            unsafe {
+                match *dynamic_state {
+                    vk::DynamicState::DEPTH_BIAS => device.cmd_set_depth_bias(
+                        handle,
+                        depth_bias.constant_factor,
+                        depth_bias.clamp,
+                        depth_bias.slope_factor,
+                    ),
+                    vk::DynamicState::DEPTH_BOUNDS => {
+                        device.cmd_set_depth_bounds(handle, min_depth_bounds, max_depth_bounds)
+                    }
+                    vk::DynamicState::STENCIL_COMPARE_MASK => {
+                        device.cmd_set_stencil_compare_mask(handle, vk::StencilFaceFlags::FRONT, front.compare_mask);
+                        device.cmd_set_stencil_compare_mask(handle, vk::StencilFaceFlags::BACK, back.compare_mask);
+                    }
+                    vk::DynamicState::STENCIL_WRITE_MASK => {
+                        device.cmd_set_stencil_write_mask(handle, vk::StencilFaceFlags::FRONT, front.write_mask);
+                        device.cmd_set_stencil_write_mask(handle, vk::StencilFaceFlags::BACK, back.write_mask);
+                    }
+                    vk::DynamicState::STENCIL_REFERENCE => {
+                        device.cmd_set_stencil_reference(handle, vk::StencilFaceFlags::FRONT, front.reference);
+                        device.cmd_set_stencil_reference(handle, vk::StencilFaceFlags::BACK, back.reference);
+                    }
+                    vk::DynamicState::CULL_MODE => device.cmd_set_cull_mode(handle, builder.cull_mode),
+                    vk::DynamicState::FRONT_FACE => device.cmd_set_front_face(handle, builder.front_face),
+                    vk::DynamicState::PRIMITIVE_TOPOLOGY => {
+                        device.cmd_set_primitive_topology(handle, builder.topology)
+                    }
+                    vk::DynamicState::DEPTH_TEST_ENABLE => {
+                        device.cmd_set_depth_test_enable(handle, builder.depth_test)
+                    }
+                    vk::DynamicState::DEPTH_WRITE_ENABLE => {
+                        device.cmd_set_depth_write_enable(handle, builder.depth_write)
+                    }
+                    vk::DynamicState::DEPTH_COMPARE_OP => {
+                        device.cmd_set_depth_compare_op(handle, builder.depth_compare_op)
+                    }
+                    vk::DynamicState::DEPTH_BOUNDS_TEST_ENABLE => {
+                        device.cmd_set_depth_bounds_test_enable(handle, builder.depth_bounds.is_some())
+                    }
+                    vk::DynamicState::STENCIL_TEST_ENABLE => {
+                        device.cmd_set_stencil_test_enable(handle, builder.stencil.is_some())
+                    }
+                    vk::DynamicState::STENCIL_OP => {
+                        for (face_mask, state) in [
+                            (vk::StencilFaceFlags::FRONT, front),
+                            (vk::StencilFaceFlags::BACK, back),
+                        ] {
+                            device.cmd_set_stencil_op(
+                                handle,
+                                face_mask,
+                                state.fail_op,
+                                state.pass_op,
+                                state.depth_fail_op,
+                                state.compare_op,
+                            );
+                        }
+                    }
+                    vk::DynamicState::RASTERIZER_DISCARD_ENABLE => {
+                        device.cmd_set_rasterizer_discard_enable(handle, builder.rasterizer_discard)
+                    }
+                    vk::DynamicState::DEPTH_BIAS_ENABLE => {
+                        device.cmd_set_depth_bias_enable(handle, builder.depth_bias.is_some())
+                    }
+                    vk::DynamicState::PRIMITIVE_RESTART_ENABLE => {
+                        device.cmd_set_primitive_restart_enable(handle, builder.primitive_restart)
+                    }
+                    vk::DynamicState::POLYGON_MODE_EXT => extended_dynamic_state_3
+                        .unwrap()
+                        .cmd_set_polygon_mode(handle, builder.polygon_mode),
+                    vk::DynamicState::RASTERIZATION_SAMPLES_EXT => extended_dynamic_state_3
+                        .unwrap()
+                        .cmd_set_rasterization_samples(handle, builder.samples),
+                    vk::DynamicState::DEPTH_CLAMP_ENABLE_EXT => extended_dynamic_state_3
+                        .unwrap()
+                        .cmd_set_depth_clamp_enable(handle, builder.depth_clamp),
+                    vk::DynamicState::COLOR_BLEND_ENABLE_EXT if !color_blend.is_empty() => {
+                        let enables: Vec<vk::Bool32> = color_blend
+                            .iter()
+                            .map(|attachment| attachment.blend_enable as vk::Bool32)
+                            .collect();
+                        extended_dynamic_state_3
+                            .unwrap()
+                            .cmd_set_color_blend_enable(handle, 0, &enables);
+                    }
+                    vk::DynamicState::COLOR_BLEND_EQUATION_EXT if !color_blend.is_empty() => {
+                        let equations: Vec<vk::ColorBlendEquationEXT> = color_blend
+                            .iter()
+                            .map(|attachment| attachment.get_vk_equation())
+                            .collect();
+                        extended_dynamic_state_3
+                            .unwrap()
+                            .cmd_set_color_blend_equation(handle, 0, &equations);
+                    }
+                    vk::DynamicState::COLOR_WRITE_MASK_EXT if !color_blend.is_empty() => {
+                        let write_masks: Vec<vk::ColorComponentFlags> = color_blend
+                            .iter()
+                            .map(|attachment| attachment.color_write_mask)
+                            .collect();
+                        extended_dynamic_state_3
+                            .unwrap()
+                            .cmd_set_color_write_mask(handle, 0, &write_masks);
+                    }
+                    // Viewport and scissor are set by the caller
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+impl Drop for GraphicsPipeline {
+    fn drop(&mut self) {
+        if let Some(inner) = self.inner.take() {
+            self.deletion_queue.enqueue_deletion(inner, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ash::vk::Handle;
+
+    #[test]
+    /// Extended dynamic state 3 states are only dynamic when supported
+    fn test_dynamic_states() {
+        let unsupported = get_dynamic_states(&vk::PhysicalDeviceExtendedDynamicState3FeaturesEXT::default());
+        assert!(unsupported.contains(&vk::DynamicState::VIEWPORT));
+        assert!(unsupported.contains(&vk::DynamicState::CULL_MODE));
+        assert!(unsupported.contains(&vk::DynamicState::PRIMITIVE_RESTART_ENABLE));
+        assert!(!unsupported.contains(&vk::DynamicState::POLYGON_MODE_EXT));
+
+        let supported = get_dynamic_states(&vk::PhysicalDeviceExtendedDynamicState3FeaturesEXT {
+            extended_dynamic_state3_polygon_mode: vk::TRUE,
+            extended_dynamic_state3_color_write_mask: vk::TRUE,
+            ..Default::default()
+        });
+        assert_eq!(supported.len(), unsupported.len() + 2);
+        assert!(supported.contains(&vk::DynamicState::POLYGON_MODE_EXT));
+        assert!(supported.contains(&vk::DynamicState::COLOR_WRITE_MASK_EXT));
+        assert!(!supported.contains(&vk::DynamicState::COLOR_BLEND_ENABLE_EXT));
+
+        assert!(unsupported
+            .iter()
+            .all(|dynamic_state| !is_extended_dynamic_state_3(*dynamic_state)));
+        assert!(supported.iter().any(|dynamic_state| is_extended_dynamic_state_3(*dynamic_state)));
+
+        let alpha = ColorBlendAttachment::ALPHA.get_vk_state();
+        assert_eq!(alpha.blend_enable, vk::TRUE);
+        assert_eq!(alpha.dst_color_blend_factor, vk::BlendFactor::ONE_MINUS_SRC_ALPHA);
+    }
+
+    #[test]
+    /// Part keys only cover the stages of their own part and identify shaders by their code
+    fn test_part_keys() {
+        let stage = |stage, module, code_hash| {
+            ShaderStage::new(stage, vk::ShaderModule::from_raw(module), "main").code_hash(code_hash)
+        };
+        let key = |part, stages: &[ShaderStage]| {
+            let mut key = GraphicsPipelinePartKey::new(part);
+            hash_stages(stages, part == GraphicsPipelinePart::FragmentShader, &mut key);
+            key
+        };
+        let pre_rasterization = GraphicsPipelinePart::PreRasterization;
+        let vertex = stage(vk::ShaderStageFlags::VERTEX, 1, 10);
+        let fragment = stage(vk::ShaderStageFlags::FRAGMENT, 2, 20);
+        let stages = [vertex.clone(), fragment.clone()];
+
+        // Parts with the same state are kept apart
+        assert_ne!(
+            key(pre_rasterization, &[]),
+            key(GraphicsPipelinePart::VertexInput, &[])
+        );
+        // Changing the fragment shader leaves the pre-rasterization part alone
+        let changed = [vertex.clone(), stage(vk::ShaderStageFlags::FRAGMENT, 2, 21)];
+        assert_eq!(key(pre_rasterization, &stages), key(pre_rasterization, &changed));
+        assert_ne!(
+            key(GraphicsPipelinePart::FragmentShader, &stages),
+            key(GraphicsPipelinePart::FragmentShader, &changed)
+        );
+        // A handle reused for other code does not match, recreating the same code does
+        let reused = [stage(vk::ShaderStageFlags::VERTEX, 1, 11)];
+        let recreated = [stage(vk::ShaderStageFlags::VERTEX, 3, 10)];
+        let vertex = std::slice::from_ref(&vertex);
+        assert_ne!(key(pre_rasterization, vertex), key(pre_rasterization, &reused));
+        assert_eq!(key(pre_rasterization, vertex), key(pre_rasterization, &recreated));
     }
 }
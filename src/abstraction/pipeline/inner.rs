@@ -0,0 +1,113 @@
+use crate::abstraction::prelude as abstraction;
+use crate::abstraction::utility::deferred_deletion_queue::{
+    DeferredDeletable, DeferredDeletionQueue,
+};
+use ash::vk;
+use ash::vk::TaggedStructure;
+use std::sync::Arc;
+
+/// Queue pipelines are pushed into once dropped, see [PipelineInner]
+pub type PipelineDeletionQueue = DeferredDeletionQueue<PipelineInner>;
+
+/// The [vk::Pipeline] and [vk::PipelineLayout] owned by a pipeline
+///
+/// Pipelines may still be used by command buffers in flight once dropped, so they hand their
+/// inner over to a [PipelineDeletionQueue] which destroys it once it expires.
+pub struct PipelineInner {
+    handle: vk::Pipeline,
+    layout: vk::PipelineLayout,
+    device: abstraction::Device,
+    /// Set layouts are kept alive for as long as the pipeline layout
+    set_layouts: Vec<Arc<abstraction::DescriptorSetLayout>>,
+}
+
+impl PipelineInner {
+    /// Create the [vk::PipelineLayout] of a pipeline
+    pub(crate) fn create_layout(
+        device: &abstraction::Device,
+        set_layouts: &[Arc<abstraction::DescriptorSetLayout>],
+        push_constant_ranges: &[vk::PushConstantRange],
+    ) -> Result<vk::PipelineLayout, vk::Result> {
+        let set_layout_handles: Vec<vk::DescriptorSetLayout> = set_layouts
+            .iter()
+            .map(|set_layout| set_layout.get_handle())
+            .collect();
+        let layout_ci = vk::PipelineLayoutCreateInfo {
+            s_type: vk::PipelineLayoutCreateInfo::STRUCTURE_TYPE,
+            set_layout_count: set_layout_handles.len() as u32,
+            p_set_layouts: set_layout_handles.as_ptr(),
+            push_constant_range_count: push_constant_ranges.len() as u32,
+            p_push_constant_ranges: push_constant_ranges.as_ptr(),
+            ..Default::default()
+        };
+        unsafe { device.handle_as_ref().create_pipeline_layout(&layout_ci, None) }
+    }
+
+    /// Get the flags a pipeline using `set_layouts` has to be created with, descriptor buffer
+    /// layouts require [vk::PipelineCreateFlags::DESCRIPTOR_BUFFER_EXT]
+    pub(crate) fn get_create_flags(
+        set_layouts: &[Arc<abstraction::DescriptorSetLayout>],
+    ) -> vk::PipelineCreateFlags {
+        let uses_descriptor_buffer = set_layouts.iter().any(|set_layout| {
+            set_layout
+                .get_flags()
+                .contains(vk::DescriptorSetLayoutCreateFlags::DESCRIPTOR_BUFFER_EXT)
+        });
+        if uses_descriptor_buffer {
+            vk::PipelineCreateFlags::DESCRIPTOR_BUFFER_EXT
+        } else {
+            vk::PipelineCreateFlags::empty()
+        }
+    }
+
+    /// Take ownership of a pipeline and the layout it was created with
+    pub(crate) fn new(
+        handle: vk::Pipeline,
+        layout: vk::PipelineLayout,
+        device: abstraction::Device,
+        set_layouts: Vec<Arc<abstraction::DescriptorSetLayout>>,
+    ) -> Self {
+        Self {
+            handle,
+            layout,
+            device,
+            set_layouts,
+        }
+    }
+
+    pub fn get_handle(&self) -> vk::Pipeline {
+        self.handle
+    }
+
+    pub fn get_layout(&self) -> vk::PipelineLayout {
+        self.layout
+    }
+
+    pub fn get_set_layouts(&self) -> &[Arc<abstraction::DescriptorSetLayout>] {
+        &self.set_layouts
+    }
+
+    pub fn get_device(&self) -> abstraction::Device {
+        self.device.clone()
+    }
+}
+
+impl DeferredDeletable for PipelineInner {
+    /// Enough cycles for three frames in flight to have finished,
+    /// [crate::framework::frame_manager::FrameManager::deletion_queue] replaces it with the
+    /// actual number of frames in flight
+    const MAX_CYCLES: u64 = 3;
+
+    fn wake() {}
+}
+
+impl Drop for PipelineInner {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.handle_as_ref().destroy_pipeline(self.handle, None);
+            self.device
+                .handle_as_ref()
+                .destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
@@ -1,6 +1,22 @@
 //! Pipelines are managed
+pub mod compute_pipeline;
+#[cfg(feature = "serde")]
+pub mod description;
 pub mod graphics_pipeline;
-pub mod traits;
+pub mod inner;
+pub mod pipeline_cache;
+pub mod pipeline_library;
+pub mod reflection;
+pub mod stage;
+mod traits;
 
+pub use compute_pipeline::*;
+#[cfg(feature = "serde")]
+pub use description::*;
 pub use graphics_pipeline::*;
+pub use inner::*;
+pub use pipeline_cache::*;
+pub use pipeline_library::*;
+pub use reflection::*;
+pub use stage::*;
 pub use traits::*;
@@ -0,0 +1,228 @@
+//! Persistent pipeline caches
+//!
+//! The data of a [vk::PipelineCache] is saved along with a header identifying the device and
+//! driver which produced it and a checksum of the data. Files which are corrupt or were made by
+//! another device or driver are thrown away rather than handed to the driver.
+use ash::vk;
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+
+/// First bytes of every pipeline cache file
+pub const PIPELINE_CACHE_MAGIC: [u8; 4] = *b"DGPC";
+/// Version of the file layout, bumped whenever it changes
+const FILE_VERSION: u32 = 1;
+/// Size of the file header: magic, version, vendor id, device id, driver version, cache uuid,
+/// data size and checksum
+const FILE_HEADER_SIZE: usize = 4 + 4 + 4 + 4 + 4 + vk::UUID_SIZE + 8 + 8;
+/// Size of [vk::PipelineCacheHeaderVersionOne] at the start of the cache data
+const VK_HEADER_SIZE: usize = 4 + 4 + 4 + 4 + vk::UUID_SIZE;
+
+#[derive(Debug)]
+pub enum PipelineCacheError {
+    Io(std::io::Error),
+    Vulkan(vk::Result),
+}
+
+impl fmt::Display for PipelineCacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineCacheError::Io(error) => write!(f, "pipeline cache file: {}", error),
+            PipelineCacheError::Vulkan(result) => write!(f, "{}", result),
+        }
+    }
+}
+
+impl std::error::Error for PipelineCacheError {}
+
+impl From<std::io::Error> for PipelineCacheError {
+    fn from(error: std::io::Error) -> Self {
+        PipelineCacheError::Io(error)
+    }
+}
+
+impl From<vk::Result> for PipelineCacheError {
+    fn from(result: vk::Result) -> Self {
+        PipelineCacheError::Vulkan(result)
+    }
+}
+
+/// What happened to a pipeline cache file when loading it
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PipelineCacheLoad {
+    /// The data of the file was merged into the cache
+    Loaded,
+    /// There is no file yet
+    NotFound,
+    /// The file is truncated, has an unknown layout or its checksum does not match
+    Corrupt,
+    /// The file was made by another device, driver or driver version
+    Incompatible,
+}
+
+/// Identifies the device and driver which produced pipeline cache data
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct PipelineCacheHeader {
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub driver_version: u32,
+    pub pipeline_cache_uuid: [u8; vk::UUID_SIZE],
+}
+
+impl PipelineCacheHeader {
+    pub fn from_properties(properties: &vk::PhysicalDeviceProperties) -> Self {
+        Self {
+            vendor_id: properties.vendor_id,
+            device_id: properties.device_id,
+            driver_version: properties.driver_version,
+            pipeline_cache_uuid: properties.pipeline_cache_uuid,
+        }
+    }
+
+    /// Wrap cache `data` into the contents of a file
+    pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(FILE_HEADER_SIZE + data.len());
+        bytes.extend_from_slice(&PIPELINE_CACHE_MAGIC);
+        bytes.extend_from_slice(&FILE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.vendor_id.to_le_bytes());
+        bytes.extend_from_slice(&self.device_id.to_le_bytes());
+        bytes.extend_from_slice(&self.driver_version.to_le_bytes());
+        bytes.extend_from_slice(&self.pipeline_cache_uuid);
+        bytes.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&get_checksum(data).to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    /// Get the cache data out of the contents of a file, which must have been made by a device
+    /// and driver matching `self`
+    pub fn decode<'a>(&self, bytes: &'a [u8]) -> Result<&'a [u8], PipelineCacheLoad> {
+        if bytes.len() < FILE_HEADER_SIZE || bytes[0..4] != PIPELINE_CACHE_MAGIC {
+            return Err(PipelineCacheLoad::Corrupt);
+        }
+        let read_u32 =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let read_u64 =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        if read_u32(4) != FILE_VERSION {
+            return Err(PipelineCacheLoad::Corrupt);
+        }
+        let uuid_end = 20 + vk::UUID_SIZE;
+        let header = PipelineCacheHeader {
+            vendor_id: read_u32(8),
+            device_id: read_u32(12),
+            driver_version: read_u32(16),
+            pipeline_cache_uuid: bytes[20..uuid_end].try_into().unwrap(),
+        };
+        let data_size = read_u64(uuid_end);
+        let checksum = read_u64(uuid_end + 8);
+        let data = &bytes[FILE_HEADER_SIZE..];
+        if data.len() as u64 != data_size || get_checksum(data) != checksum {
+            return Err(PipelineCacheLoad::Corrupt);
+        }
+        if header != *self || !self.matches_vk_header(data) {
+            return Err(PipelineCacheLoad::Incompatible);
+        }
+        Ok(data)
+    }
+
+    /// Check the [vk::PipelineCacheHeaderVersionOne] the driver put in front of the data as well
+    fn matches_vk_header(&self, data: &[u8]) -> bool {
+        if data.len() < VK_HEADER_SIZE {
+            return false;
+        }
+        let read_u32 =
+            |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+        read_u32(0) as usize >= VK_HEADER_SIZE
+            && read_u32(4) == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+            && read_u32(8) == self.vendor_id
+            && read_u32(12) == self.device_id
+            && data[16..VK_HEADER_SIZE] == self.pipeline_cache_uuid
+    }
+}
+
+/// 64-bit FNV-1a hash of `data`
+fn get_checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Write `bytes` to `path` such that readers either see the previous or the complete new file
+///
+/// The bytes are written to a temporary file next to `path` which then replaces it.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let mut temporary_path = path.as_os_str().to_owned();
+    temporary_path.push(".tmp");
+    let temporary_path = Path::new(&temporary_path);
+    let result = std::fs::File::create(temporary_path).and_then(|mut file| {
+        file.write_all(bytes)?;
+        file.sync_all()
+    });
+    match result.and_then(|_| std::fs::rename(temporary_path, path)) {
+        Ok(()) => Ok(()),
+        Err(error) => {
+            let _ = std::fs::remove_file(temporary_path);
+            Err(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_data(header: &PipelineCacheHeader) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(VK_HEADER_SIZE as u32).to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&header.vendor_id.to_le_bytes());
+        data.extend_from_slice(&header.device_id.to_le_bytes());
+        data.extend_from_slice(&header.pipeline_cache_uuid);
+        data.extend_from_slice(&[7; 64]);
+        data
+    }
+
+    #[test]
+    /// Data round trips, while corrupt files and files of other drivers are rejected
+    fn test_encode_decode() {
+        let header = PipelineCacheHeader {
+            vendor_id: 0x10de,
+            device_id: 0x2684,
+            driver_version: 42,
+            pipeline_cache_uuid: [3; vk::UUID_SIZE],
+        };
+        let data = get_data(&header);
+        let mut bytes = header.encode(&data);
+        assert_eq!(header.decode(&bytes), Ok(data.as_slice()));
+
+        let updated_driver = PipelineCacheHeader {
+            driver_version: 43,
+            ..header
+        };
+        assert_eq!(
+            updated_driver.decode(&bytes),
+            Err(PipelineCacheLoad::Incompatible)
+        );
+        assert_eq!(
+            header.decode(&bytes[..bytes.len() - 1]),
+            Err(PipelineCacheLoad::Corrupt)
+        );
+        *bytes.last_mut().unwrap() ^= 1;
+        assert_eq!(header.decode(&bytes), Err(PipelineCacheLoad::Corrupt));
+    }
+
+    #[test]
+    /// Writes replace the file and leave no temporary file behind
+    fn test_write_atomic() {
+        let path =
+            std::env::temp_dir().join(format!("dagal_pipeline_cache_{}.bin", std::process::id()));
+        write_atomic(&path, b"first").unwrap();
+        write_atomic(&path, b"second").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+        let mut temporary_path = path.as_os_str().to_owned();
+        temporary_path.push(".tmp");
+        assert!(!Path::new(&temporary_path).exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
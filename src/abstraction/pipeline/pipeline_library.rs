@@ -0,0 +1,138 @@
+//! Graphics pipeline libraries
+//!
+//! With `VK_EXT_graphics_pipeline_library` graphics pipelines are built from four parts which
+//! are compiled separately and then linked. Parts are cached in a [PipelineLibraryCache], so
+//! pipelines only differing in a few parts reuse the others and only pay for a fast link.
+//! Devices without `graphicsPipelineLibraryFastLinking` build pipelines as a whole instead.
+use super::*;
+use ash::vk;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+/// One of the parts a graphics pipeline is split into
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum GraphicsPipelinePart {
+    /// Vertex input and input assembly
+    VertexInput,
+    /// Every shader stage but the fragment shader along with rasterization
+    PreRasterization,
+    /// The fragment shader along with depth and stencil testing
+    FragmentShader,
+    /// Color blending and the formats rendered into
+    FragmentOutput,
+}
+
+impl GraphicsPipelinePart {
+    pub const ALL: [Self; 4] = [
+        Self::VertexInput,
+        Self::PreRasterization,
+        Self::FragmentShader,
+        Self::FragmentOutput,
+    ];
+
+    pub fn get_vk_flags(&self) -> vk::GraphicsPipelineLibraryFlagsEXT {
+        match self {
+            Self::VertexInput => vk::GraphicsPipelineLibraryFlagsEXT::VERTEX_INPUT_INTERFACE,
+            Self::PreRasterization => {
+                vk::GraphicsPipelineLibraryFlagsEXT::PRE_RASTERIZATION_SHADERS
+            }
+            Self::FragmentShader => vk::GraphicsPipelineLibraryFlagsEXT::FRAGMENT_SHADER,
+            Self::FragmentOutput => vk::GraphicsPipelineLibraryFlagsEXT::FRAGMENT_OUTPUT_INTERFACE,
+        }
+    }
+
+    /// Whether the part is built with a [vk::PipelineLayout]
+    pub fn uses_layout(&self) -> bool {
+        matches!(self, Self::PreRasterization | Self::FragmentShader)
+    }
+}
+
+/// Identifies every state which goes into a part, see [GraphicsPipelineBuilder::get_part_key]
+///
+/// State is written into the key through [Hasher], which keeps every byte written rather than
+/// their hash so different states never share a part.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct GraphicsPipelinePartKey {
+    part: GraphicsPipelinePart,
+    state: Vec<u8>,
+}
+
+impl GraphicsPipelinePartKey {
+    pub fn new(part: GraphicsPipelinePart) -> Self {
+        Self {
+            part,
+            state: Vec::new(),
+        }
+    }
+
+    pub fn get_part(&self) -> GraphicsPipelinePart {
+        self.part
+    }
+}
+
+impl Hasher for GraphicsPipelinePartKey {
+    fn write(&mut self, bytes: &[u8]) {
+        self.state.extend_from_slice(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// Cached parts along with the key of their state
+type Libraries = HashMap<GraphicsPipelinePartKey, Arc<PipelineInner>>;
+
+/// Cache of graphics pipeline parts shared between every pipeline built with it
+///
+/// Clones share the same cache. Parts are not needed anymore once linked, so the cache may be
+/// cleared at any time.
+#[derive(Clone, Default)]
+pub struct PipelineLibraryCache {
+    libraries: Arc<Mutex<Libraries>>,
+}
+
+impl PipelineLibraryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the part identified by `key` or create it using `create`
+    ///
+    /// The cache is not locked while creating, so threads may race to create the same part in
+    /// which case the first one inserted is kept.
+    pub(crate) fn get_or_create<F: FnOnce() -> Result<PipelineInner, vk::Result>>(
+        &self,
+        key: GraphicsPipelinePartKey,
+        create: F,
+    ) -> Result<Arc<PipelineInner>, vk::Result> {
+        if let Some(library) = self.libraries.lock().unwrap().get(&key) {
+            return Ok(library.clone());
+        }
+        let library = Arc::new(create()?);
+        Ok(self
+            .libraries
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert(library)
+            .clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.libraries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Destroy every cached part
+    pub fn clear(&self) {
+        self.libraries.lock().unwrap().clear();
+    }
+}
@@ -0,0 +1,962 @@
+//! Reflection of SPIR-V shaders into pipeline and descriptor set layouts
+//!
+//! Every stage of a pipeline is reflected on its own into a [ShaderReflection], which are then
+//! merged into a single [PipelineReflection] describing the layout of the whole pipeline.
+//! Stages which declare the same binding with a different descriptor type or count cannot be
+//! merged.
+use crate::abstraction::prelude as abstraction;
+use crate::abstraction::utility::shader::{self, ShaderError, ShaderModule};
+use ash::vk;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+/// Descriptor count of bindings declared as runtime arrays
+pub const UNBOUNDED_DESCRIPTOR_COUNT: u32 = 1024;
+
+const OP_NAME: u32 = 5;
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_SPEC_CONSTANT_TRUE: u32 = 48;
+const OP_SPEC_CONSTANT_FALSE: u32 = 49;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+const OP_TYPE_ACCELERATION_STRUCTURE: u32 = 5341;
+
+const DECORATION_SPEC_ID: u32 = 1;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_CLASS_INPUT: u32 = 1;
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+const STORAGE_CLASS_OUTPUT: u32 = 3;
+const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+#[derive(Debug)]
+pub enum ReflectionError {
+    Shader(ShaderError),
+    /// The module does not declare the entry point
+    EntryPointNotFound(String),
+    /// The compute entry point declares no workgroup size
+    MissingLocalSize(String),
+    /// Two stages declare the same binding with a different descriptor type or count
+    BindingMismatch {
+        first: ReflectedBinding,
+        second: ReflectedBinding,
+    },
+}
+
+impl fmt::Display for ReflectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReflectionError::Shader(error) => write!(f, "{}", error),
+            ReflectionError::EntryPointNotFound(name) => {
+                write!(f, "entry point {} does not exist", name)
+            }
+            ReflectionError::MissingLocalSize(name) => {
+                write!(f, "entry point {} declares no workgroup size", name)
+            }
+            ReflectionError::BindingMismatch { first, second } => write!(
+                f,
+                "set {} binding {} is declared as {} x {:?} ({}) in {:?} but as {} x {:?} ({}) in {:?}",
+                first.set,
+                first.binding,
+                first.descriptor_count,
+                first.descriptor_type,
+                first.name,
+                first.stage_flags,
+                second.descriptor_count,
+                second.descriptor_type,
+                second.name,
+                second.stage_flags
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReflectionError {}
+
+impl From<ShaderError> for ReflectionError {
+    fn from(error: ShaderError) -> Self {
+        ReflectionError::Shader(error)
+    }
+}
+
+/// A descriptor binding used by a shader
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ReflectedBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    /// Number of descriptors in the binding, 0 for runtime arrays
+    pub descriptor_count: u32,
+    pub stage_flags: vk::ShaderStageFlags,
+    /// Name of the variable, empty if the shader was stripped of debug information
+    pub name: String,
+}
+
+/// A vertex input or fragment output
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ReflectedInterfaceVariable {
+    pub location: u32,
+    pub format: vk::Format,
+    pub name: String,
+}
+
+/// Type of a specialization constant
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum ReflectedScalarType {
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ReflectedSpecializationConstant {
+    pub constant_id: u32,
+    pub scalar_type: ReflectedScalarType,
+    /// Bits of the default value, booleans are [vk::TRUE] or [vk::FALSE]
+    pub default_value: u32,
+    pub name: String,
+}
+
+/// Type declarations of a SPIR-V module which matter for reflection
+#[derive(Clone, Debug)]
+enum SpirvType {
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+    AccelerationStructure,
+}
+
+/// Everything reflection reads from a SPIR-V module
+#[derive(Default)]
+struct SpirvModule {
+    types: HashMap<u32, SpirvType>,
+    /// Value of integer constants, used for array lengths
+    constants: HashMap<u32, u32>,
+    names: HashMap<u32, String>,
+    /// Stage and interface variables of every entry point
+    entry_points: HashMap<String, (vk::ShaderStageFlags, Vec<u32>)>,
+    /// Id, pointer type and storage class of every global variable
+    variables: Vec<(u32, u32, u32)>,
+    /// Id, type and default value of every specialization constant
+    spec_constants: Vec<(u32, u32, u32)>,
+    decorations: HashMap<(u32, u32), u32>,
+    buffer_blocks: HashSet<u32>,
+    member_decorations: HashMap<(u32, u32, u32), u32>,
+}
+
+impl SpirvModule {
+    fn parse(code: &[u32]) -> Result<Self, ShaderError> {
+        let mut module = Self::default();
+        let entry_points = shader::get_entry_points(code)?;
+        for (opcode, operands) in shader::get_instructions(code)? {
+            // Missing operands of malformed instructions read as 0
+            let operand = |index: usize| operands.get(index).copied().unwrap_or(0);
+            match opcode {
+                OP_NAME if operands.len() >= 2 => {
+                    module
+                        .names
+                        .insert(operand(0), shader::get_literal_string(&operands[1..]));
+                }
+                OP_ENTRY_POINT if operands.len() >= 3 => {
+                    let name = shader::get_literal_string(&operands[2..]);
+                    // The literal string is nul terminated and padded to a whole word
+                    let name_words = name.len() / 4 + 1;
+                    let stage = entry_points
+                        .iter()
+                        .find(|entry_point| entry_point.name == name)
+                        .map(|entry_point| entry_point.stage);
+                    if let Some(stage) = stage {
+                        let interface = operands[(2 + name_words).min(operands.len())..].to_vec();
+                        module
+                            .entry_points
+                            .insert(name, (stage, interface));
+                    }
+                }
+                OP_TYPE_BOOL => {
+                    module.types.insert(operand(0), SpirvType::Bool);
+                }
+                OP_TYPE_INT => {
+                    module.types.insert(
+                        operand(0),
+                        SpirvType::Int {
+                            width: operand(1),
+                            signed: operand(2) != 0,
+                        },
+                    );
+                }
+                OP_TYPE_FLOAT => {
+                    module
+                        .types
+                        .insert(operand(0), SpirvType::Float { width: operand(1) });
+                }
+                OP_TYPE_VECTOR => {
+                    module.types.insert(
+                        operand(0),
+                        SpirvType::Vector {
+                            component: operand(1),
+                            count: operand(2),
+                        },
+                    );
+                }
+                OP_TYPE_MATRIX => {
+                    module.types.insert(
+                        operand(0),
+                        SpirvType::Matrix {
+                            column: operand(1),
+                            count: operand(2),
+                        },
+                    );
+                }
+                OP_TYPE_IMAGE => {
+                    module.types.insert(
+                        operand(0),
+                        SpirvType::Image {
+                            dim: operand(2),
+                            sampled: operand(6),
+                        },
+                    );
+                }
+                OP_TYPE_SAMPLER => {
+                    module.types.insert(operand(0), SpirvType::Sampler);
+                }
+                OP_TYPE_SAMPLED_IMAGE => {
+                    module.types.insert(operand(0), SpirvType::SampledImage);
+                }
+                OP_TYPE_ARRAY => {
+                    module.types.insert(
+                        operand(0),
+                        SpirvType::Array {
+                            element: operand(1),
+                            length: operand(2),
+                        },
+                    );
+                }
+                OP_TYPE_RUNTIME_ARRAY => {
+                    module.types.insert(
+                        operand(0),
+                        SpirvType::RuntimeArray {
+                            element: operand(1),
+                        },
+                    );
+                }
+                OP_TYPE_STRUCT => {
+                    module.types.insert(
+                        operand(0),
+                        SpirvType::Struct {
+                            members: operands.get(1..).unwrap_or_default().to_vec(),
+                        },
+                    );
+                }
+                OP_TYPE_POINTER => {
+                    module.types.insert(
+                        operand(0),
+                        SpirvType::Pointer {
+                            pointee: operand(2),
+                        },
+                    );
+                }
+                OP_TYPE_ACCELERATION_STRUCTURE => {
+                    module
+                        .types
+                        .insert(operand(0), SpirvType::AccelerationStructure);
+                }
+                OP_CONSTANT if operands.len() >= 3 => {
+                    module.constants.insert(operand(1), operand(2));
+                }
+                OP_SPEC_CONSTANT if operands.len() >= 3 => {
+                    // Array lengths may be specialization constants, their default is used
+                    module.constants.insert(operand(1), operand(2));
+                    module
+                        .spec_constants
+                        .push((operand(1), operand(0), operand(2)));
+                }
+                OP_SPEC_CONSTANT_TRUE => {
+                    module
+                        .spec_constants
+                        .push((operand(1), operand(0), vk::TRUE))
+                }
+                OP_SPEC_CONSTANT_FALSE => {
+                    module
+                        .spec_constants
+                        .push((operand(1), operand(0), vk::FALSE))
+                }
+                OP_VARIABLE => module
+                    .variables
+                    .push((operand(1), operand(0), operand(2))),
+                OP_DECORATE if operands.len() >= 2 => {
+                    if operand(1) == DECORATION_BUFFER_BLOCK {
+                        module.buffer_blocks.insert(operand(0));
+                    } else if operands.len() >= 3 {
+                        module
+                            .decorations
+                            .insert((operand(0), operand(1)), operand(2));
+                    }
+                }
+                OP_MEMBER_DECORATE if operands.len() >= 4 => {
+                    module
+                        .member_decorations
+                        .insert((operand(0), operand(1), operand(2)), operand(3));
+                }
+                _ => {}
+            }
+        }
+        Ok(module)
+    }
+
+    fn get_name(&self, id: u32) -> String {
+        self.names.get(&id).cloned().unwrap_or_default()
+    }
+
+    /// Get the size in bytes of `type_id` as laid out in a buffer
+    fn get_size(&self, type_id: u32) -> u32 {
+        match self.types.get(&type_id) {
+            Some(SpirvType::Bool) => 4,
+            Some(SpirvType::Int { width, .. }) | Some(SpirvType::Float { width }) => width / 8,
+            Some(SpirvType::Vector { component, count }) => self.get_size(*component) * count,
+            Some(SpirvType::Matrix { column, count }) => self.get_size(*column) * count,
+            Some(SpirvType::Array { element, length }) => {
+                let stride = self
+                    .decorations
+                    .get(&(type_id, DECORATION_ARRAY_STRIDE))
+                    .copied()
+                    .unwrap_or_else(|| self.get_size(*element));
+                stride * self.constants.get(length).copied().unwrap_or(1)
+            }
+            Some(SpirvType::Struct { members }) => members
+                .iter()
+                .enumerate()
+                .map(|(index, member)| {
+                    let index = index as u32;
+                    let offset = self
+                        .member_decorations
+                        .get(&(type_id, index, DECORATION_OFFSET))
+                        .copied()
+                        .unwrap_or(0);
+                    let size = match (
+                        self.types.get(member),
+                        self.member_decorations
+                            .get(&(type_id, index, DECORATION_MATRIX_STRIDE)),
+                    ) {
+                        (Some(SpirvType::Matrix { count, .. }), Some(stride)) => stride * count,
+                        _ => self.get_size(*member),
+                    };
+                    offset + size
+                })
+                .max()
+                .unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    /// Get the vertex attribute format matching `type_id`
+    fn get_format(&self, type_id: u32) -> vk::Format {
+        let (component, count) = match self.types.get(&type_id) {
+            Some(SpirvType::Vector { component, count }) => (*component, *count as usize),
+            _ => (type_id, 1),
+        };
+        let formats = match self.types.get(&component) {
+            Some(SpirvType::Float { width: 32 }) => [
+                vk::Format::R32_SFLOAT,
+                vk::Format::R32G32_SFLOAT,
+                vk::Format::R32G32B32_SFLOAT,
+                vk::Format::R32G32B32A32_SFLOAT,
+            ],
+            Some(SpirvType::Float { width: 16 }) => [
+                vk::Format::R16_SFLOAT,
+                vk::Format::R16G16_SFLOAT,
+                vk::Format::R16G16B16_SFLOAT,
+                vk::Format::R16G16B16A16_SFLOAT,
+            ],
+            Some(SpirvType::Float { width: 64 }) => [
+                vk::Format::R64_SFLOAT,
+                vk::Format::R64G64_SFLOAT,
+                vk::Format::R64G64B64_SFLOAT,
+                vk::Format::R64G64B64A64_SFLOAT,
+            ],
+            Some(SpirvType::Int {
+                width: 32,
+                signed: true,
+            }) => [
+                vk::Format::R32_SINT,
+                vk::Format::R32G32_SINT,
+                vk::Format::R32G32B32_SINT,
+                vk::Format::R32G32B32A32_SINT,
+            ],
+            Some(SpirvType::Int {
+                width: 32,
+                signed: false,
+            }) => [
+                vk::Format::R32_UINT,
+                vk::Format::R32G32_UINT,
+                vk::Format::R32G32B32_UINT,
+                vk::Format::R32G32B32A32_UINT,
+            ],
+            _ => return vk::Format::UNDEFINED,
+        };
+        formats
+            .get(count.wrapping_sub(1))
+            .copied()
+            .unwrap_or(vk::Format::UNDEFINED)
+    }
+
+    /// Get the descriptor type and count of a variable of `pointer_type` in `storage_class`
+    fn get_descriptor(&self, pointer_type: u32, storage_class: u32) -> Option<(vk::DescriptorType, u32)> {
+        let mut type_id = match self.types.get(&pointer_type)? {
+            SpirvType::Pointer { pointee } => *pointee,
+            _ => return None,
+        };
+        let mut descriptor_count = 1;
+        loop {
+            match self.types.get(&type_id)? {
+                SpirvType::Array { element, length } => {
+                    descriptor_count *= self.constants.get(length).copied().unwrap_or(1);
+                    type_id = *element;
+                }
+                SpirvType::RuntimeArray { element } => {
+                    descriptor_count = 0;
+                    type_id = *element;
+                }
+                _ => break,
+            }
+        }
+        let descriptor_type = match (storage_class, self.types.get(&type_id)?) {
+            (STORAGE_CLASS_UNIFORM_CONSTANT, SpirvType::Image { dim, sampled }) => {
+                match (*dim, *sampled) {
+                    (DIM_SUBPASS_DATA, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+                    (DIM_BUFFER, 2) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                    (DIM_BUFFER, _) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                    (_, 2) => vk::DescriptorType::STORAGE_IMAGE,
+                    _ => vk::DescriptorType::SAMPLED_IMAGE,
+                }
+            }
+            (STORAGE_CLASS_UNIFORM_CONSTANT, SpirvType::Sampler) => vk::DescriptorType::SAMPLER,
+            (STORAGE_CLASS_UNIFORM_CONSTANT, SpirvType::SampledImage) => {
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+            }
+            (STORAGE_CLASS_UNIFORM_CONSTANT, SpirvType::AccelerationStructure) => {
+                vk::DescriptorType::ACCELERATION_STRUCTURE_KHR
+            }
+            // Storage buffers declared before SPIR-V 1.3 are uniform buffer blocks
+            (STORAGE_CLASS_UNIFORM, SpirvType::Struct { .. }) if self.buffer_blocks.contains(&type_id) => {
+                vk::DescriptorType::STORAGE_BUFFER
+            }
+            (STORAGE_CLASS_UNIFORM, SpirvType::Struct { .. }) => vk::DescriptorType::UNIFORM_BUFFER,
+            (STORAGE_CLASS_STORAGE_BUFFER, _) => vk::DescriptorType::STORAGE_BUFFER,
+            _ => return None,
+        };
+        Some((descriptor_type, descriptor_count))
+    }
+}
+
+/// Everything a single entry point of a shader declares
+#[derive(Clone, Debug)]
+pub struct ShaderReflection {
+    entry_point: String,
+    stage: vk::ShaderStageFlags,
+    bindings: Vec<ReflectedBinding>,
+    push_constant_range: Option<vk::PushConstantRange>,
+    vertex_inputs: Vec<ReflectedInterfaceVariable>,
+    fragment_outputs: Vec<ReflectedInterfaceVariable>,
+    specialization_constants: Vec<ReflectedSpecializationConstant>,
+    local_size: Option<[u32; 3]>,
+}
+
+impl ShaderReflection {
+    /// Reflect `entry_point` of SPIR-V `code`
+    pub fn new(code: &[u32], entry_point: &str) -> Result<Self, ReflectionError> {
+        let module = SpirvModule::parse(code)?;
+        let (stage, interface) = module
+            .entry_points
+            .get(entry_point)
+            .cloned()
+            .ok_or_else(|| ReflectionError::EntryPointNotFound(entry_point.to_string()))?;
+        // Interfaces list every global variable since SPIR-V 1.4, only inputs and outputs before
+        let lists_all_globals = shader::get_spirv_version(code)? >= (1, 4);
+        let mut bindings = Vec::new();
+        let mut push_constant_range: Option<vk::PushConstantRange> = None;
+        let mut vertex_inputs = Vec::new();
+        let mut fragment_outputs = Vec::new();
+        for (id, pointer_type, storage_class) in module.variables.iter().copied() {
+            let in_interface = interface.contains(&id);
+            let location = module.decorations.get(&(id, DECORATION_LOCATION)).copied();
+            let pointee = match module.types.get(&pointer_type) {
+                Some(SpirvType::Pointer { pointee }) => *pointee,
+                _ => continue,
+            };
+            match storage_class {
+                STORAGE_CLASS_INPUT | STORAGE_CLASS_OUTPUT => {
+                    let (Some(location), true) = (location, in_interface) else {
+                        continue;
+                    };
+                    let variable = ReflectedInterfaceVariable {
+                        location,
+                        format: module.get_format(pointee),
+                        name: module.get_name(id),
+                    };
+                    if storage_class == STORAGE_CLASS_INPUT && stage == vk::ShaderStageFlags::VERTEX {
+                        vertex_inputs.push(variable);
+                    } else if storage_class == STORAGE_CLASS_OUTPUT
+                        && stage == vk::ShaderStageFlags::FRAGMENT
+                    {
+                        fragment_outputs.push(variable);
+                    }
+                }
+                STORAGE_CLASS_PUSH_CONSTANT if in_interface || !lists_all_globals => {
+                    // Push constant blocks start at the offset of their first member
+                    let offset = match module.types.get(&pointee) {
+                        Some(SpirvType::Struct { members }) => (0..members.len() as u32)
+                            .filter_map(|index| {
+                                module
+                                    .member_decorations
+                                    .get(&(pointee, index, DECORATION_OFFSET))
+                                    .copied()
+                            })
+                            .min()
+                            .unwrap_or(0),
+                        _ => 0,
+                    };
+                    push_constant_range = Some(vk::PushConstantRange {
+                        stage_flags: stage,
+                        offset,
+                        size: module.get_size(pointee) - offset,
+                    });
+                }
+                _ if in_interface || !lists_all_globals => {
+                    let (Some(set), Some(binding)) = (
+                        module.decorations.get(&(id, DECORATION_DESCRIPTOR_SET)),
+                        module.decorations.get(&(id, DECORATION_BINDING)),
+                    ) else {
+                        continue;
+                    };
+                    if let Some((descriptor_type, descriptor_count)) =
+                        module.get_descriptor(pointer_type, storage_class)
+                    {
+                        bindings.push(ReflectedBinding {
+                            set: *set,
+                            binding: *binding,
+                            descriptor_type,
+                            descriptor_count,
+                            stage_flags: stage,
+                            name: module.get_name(id),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        bindings.sort_by_key(|binding| (binding.set, binding.binding));
+        vertex_inputs.sort_by_key(|variable| variable.location);
+        fragment_outputs.sort_by_key(|variable| variable.location);
+        let specialization_constants = module
+            .spec_constants
+            .iter()
+            .filter_map(|(id, type_id, default_value)| {
+                let scalar_type = match module.types.get(type_id)? {
+                    SpirvType::Bool => ReflectedScalarType::Bool,
+                    SpirvType::Int { width, signed } => ReflectedScalarType::Int {
+                        width: *width,
+                        signed: *signed,
+                    },
+                    SpirvType::Float { width } => ReflectedScalarType::Float { width: *width },
+                    _ => return None,
+                };
+                module
+                    .decorations
+                    .get(&(*id, DECORATION_SPEC_ID))
+                    .map(|constant_id| ReflectedSpecializationConstant {
+                        constant_id: *constant_id,
+                        scalar_type,
+                        default_value: *default_value,
+                        name: module.get_name(*id),
+                    })
+            })
+            .collect();
+        let local_size = shader::get_entry_points(code)?
+            .into_iter()
+            .find(|declared| declared.name == entry_point)
+            .and_then(|declared| declared.local_size);
+        Ok(Self {
+            entry_point: entry_point.to_string(),
+            stage,
+            bindings,
+            push_constant_range,
+            vertex_inputs,
+            fragment_outputs,
+            specialization_constants,
+            local_size,
+        })
+    }
+
+    /// Reflect `entry_point` of `module`
+    pub fn from_module(module: &ShaderModule, entry_point: &str) -> Result<Self, ReflectionError> {
+        Self::new(module.get_code(), entry_point)
+    }
+
+    pub fn get_entry_point(&self) -> &str {
+        &self.entry_point
+    }
+
+    pub fn get_stage(&self) -> vk::ShaderStageFlags {
+        self.stage
+    }
+
+    /// Get every binding, sorted by set and binding
+    pub fn get_bindings(&self) -> &[ReflectedBinding] {
+        &self.bindings
+    }
+
+    pub fn get_push_constant_range(&self) -> Option<vk::PushConstantRange> {
+        self.push_constant_range
+    }
+
+    /// Get the inputs of a vertex shader, sorted by location
+    pub fn get_vertex_inputs(&self) -> &[ReflectedInterfaceVariable] {
+        &self.vertex_inputs
+    }
+
+    /// Get the outputs of a fragment shader, sorted by location
+    pub fn get_fragment_outputs(&self) -> &[ReflectedInterfaceVariable] {
+        &self.fragment_outputs
+    }
+
+    pub fn get_specialization_constants(&self) -> &[ReflectedSpecializationConstant] {
+        &self.specialization_constants
+    }
+
+    /// Workgroup size of a compute shader, components given by specialization constants hold
+    /// their default value
+    pub fn get_local_size(&self) -> Option<[u32; 3]> {
+        self.local_size
+    }
+}
+
+/// The layout of a pipeline, merged from the reflection of all of its stages
+#[derive(Clone, Debug)]
+pub struct PipelineReflection {
+    stages: Vec<ShaderReflection>,
+    bindings: Vec<ReflectedBinding>,
+    push_constant_range: Option<vk::PushConstantRange>,
+}
+
+impl PipelineReflection {
+    /// Merge the reflection of every stage of a pipeline
+    pub fn new(stages: Vec<ShaderReflection>) -> Result<Self, ReflectionError> {
+        let mut bindings: Vec<ReflectedBinding> = Vec::new();
+        let mut push_constant_range: Option<vk::PushConstantRange> = None;
+        for stage in stages.iter() {
+            for binding in stage.bindings.iter() {
+                match bindings
+                    .iter_mut()
+                    .find(|existing| (existing.set, existing.binding) == (binding.set, binding.binding))
+                {
+                    Some(existing)
+                        if existing.descriptor_type != binding.descriptor_type
+                            || existing.descriptor_count != binding.descriptor_count =>
+                    {
+                        return Err(ReflectionError::BindingMismatch {
+                            first: existing.clone(),
+                            second: binding.clone(),
+                        });
+                    }
+                    Some(existing) => existing.stage_flags |= binding.stage_flags,
+                    None => bindings.push(binding.clone()),
+                }
+            }
+            // A single range covering the push constants of every stage
+            if let Some(range) = stage.push_constant_range {
+                push_constant_range = Some(match push_constant_range {
+                    Some(existing) => {
+                        let offset = existing.offset.min(range.offset);
+                        let end = (existing.offset + existing.size).max(range.offset + range.size);
+                        vk::PushConstantRange {
+                            stage_flags: existing.stage_flags | range.stage_flags,
+                            offset,
+                            size: end - offset,
+                        }
+                    }
+                    None => range,
+                });
+            }
+        }
+        bindings.sort_by_key(|binding| (binding.set, binding.binding));
+        Ok(Self {
+            stages,
+            bindings,
+            push_constant_range,
+        })
+    }
+
+    pub fn get_stages(&self) -> &[ShaderReflection] {
+        &self.stages
+    }
+
+    pub fn get_stage(&self, stage: vk::ShaderStageFlags) -> Option<&ShaderReflection> {
+        self.stages.iter().find(|reflection| reflection.stage == stage)
+    }
+
+    /// Get every binding of every stage, sorted by set and binding
+    pub fn get_bindings(&self) -> &[ReflectedBinding] {
+        &self.bindings
+    }
+
+    pub fn get_push_constant_ranges(&self) -> Vec<vk::PushConstantRange> {
+        self.push_constant_range.into_iter().collect()
+    }
+
+    /// Get the layout of every set up to the highest set used, sets without bindings are empty
+    ///
+    /// Runtime arrays get [UNBOUNDED_DESCRIPTOR_COUNT] partially bound descriptors.
+    pub fn get_set_layout_builders(&self) -> Vec<abstraction::DescriptorSetLayoutBuilder> {
+        let set_count = self
+            .bindings
+            .iter()
+            .map(|binding| binding.set + 1)
+            .max()
+            .unwrap_or(0);
+        (0..set_count)
+            .map(|set| {
+                self.bindings
+                    .iter()
+                    .filter(|binding| binding.set == set)
+                    .fold(abstraction::DescriptorSetLayoutBuilder::new(), |builder, binding| {
+                        if binding.descriptor_count == 0 {
+                            builder
+                                .add_binding(
+                                    binding.binding,
+                                    binding.descriptor_type,
+                                    UNBOUNDED_DESCRIPTOR_COUNT,
+                                    binding.stage_flags,
+                                )
+                                .binding_flags(binding.binding, vk::DescriptorBindingFlags::PARTIALLY_BOUND)
+                        } else {
+                            builder.add_binding(
+                                binding.binding,
+                                binding.descriptor_type,
+                                binding.descriptor_count,
+                                binding.stage_flags,
+                            )
+                        }
+                    })
+            })
+            .collect()
+    }
+
+    /// Get or create the layout of every set through `cache`
+    pub fn create_set_layouts(
+        &self,
+        cache: &abstraction::DescriptorSetLayoutCache,
+    ) -> Result<Vec<Arc<abstraction::DescriptorSetLayout>>, vk::Result> {
+        let backend = cache.get_device().get_descriptor_backend();
+        self.get_set_layout_builders()
+            .into_iter()
+            .map(|builder| cache.get_or_create(builder.backend(backend)))
+            .collect()
+    }
+
+    /// Create the [vk::PipelineLayout] of the pipeline, which the caller has to destroy
+    ///
+    /// Returned set layouts must outlive the pipeline layout.
+    pub fn create_pipeline_layout(
+        &self,
+        device: &abstraction::Device,
+        cache: &abstraction::DescriptorSetLayoutCache,
+    ) -> Result<(vk::PipelineLayout, Vec<Arc<abstraction::DescriptorSetLayout>>), vk::Result> {
+        let set_layouts = self.create_set_layouts(cache)?;
+        let layout = super::PipelineInner::create_layout(device, &set_layouts, &self.get_push_constant_ranges())?;
+        Ok((layout, set_layouts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instruction(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![((operands.len() as u32 + 1) << 16) | opcode];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    fn string(name: &str) -> Vec<u32> {
+        let mut bytes = name.as_bytes().to_vec();
+        bytes.resize(name.len() / 4 * 4 + 4, 0);
+        bytes
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect()
+    }
+
+    /// A compute shader with a storage buffer, an array of storage images, push constants and
+    /// a specialization constant
+    fn compute_code() -> Vec<u32> {
+        let mut code = vec![shader::SPIRV_MAGIC, 0x0001_0300, 0, 18, 0];
+        let parts = [
+            instruction(17, &[1]),
+            instruction(OP_ENTRY_POINT, &[&[5, 1][..], &string("main")].concat()),
+            instruction(16, &[1, 17, 64, 1, 1]),
+            instruction(OP_NAME, &[&[8][..], &string("particles")].concat()),
+            instruction(OP_NAME, &[&[17][..], &string("group_size")].concat()),
+            instruction(OP_DECORATE, &[8, DECORATION_DESCRIPTOR_SET, 0]),
+            instruction(OP_DECORATE, &[8, DECORATION_BINDING, 1]),
+            instruction(OP_DECORATE, &[13, DECORATION_DESCRIPTOR_SET, 1]),
+            instruction(OP_DECORATE, &[13, DECORATION_BINDING, 0]),
+            instruction(OP_DECORATE, &[17, DECORATION_SPEC_ID, 3]),
+            instruction(OP_MEMBER_DECORATE, &[14, 0, DECORATION_OFFSET, 0]),
+            instruction(OP_MEMBER_DECORATE, &[14, 1, DECORATION_OFFSET, 16]),
+            instruction(OP_TYPE_FLOAT, &[2, 32]),
+            instruction(OP_TYPE_INT, &[3, 32, 0]),
+            instruction(OP_TYPE_VECTOR, &[4, 2, 4]),
+            instruction(OP_TYPE_RUNTIME_ARRAY, &[5, 2]),
+            instruction(OP_TYPE_STRUCT, &[6, 5]),
+            instruction(OP_TYPE_POINTER, &[7, STORAGE_CLASS_STORAGE_BUFFER, 6]),
+            instruction(OP_VARIABLE, &[7, 8, STORAGE_CLASS_STORAGE_BUFFER]),
+            instruction(OP_TYPE_IMAGE, &[9, 2, 1, 0, 0, 0, 2, 1]),
+            instruction(OP_CONSTANT, &[3, 10, 4]),
+            instruction(OP_TYPE_ARRAY, &[11, 9, 10]),
+            instruction(OP_TYPE_POINTER, &[12, STORAGE_CLASS_UNIFORM_CONSTANT, 11]),
+            instruction(OP_VARIABLE, &[12, 13, STORAGE_CLASS_UNIFORM_CONSTANT]),
+            instruction(OP_TYPE_STRUCT, &[14, 4, 3]),
+            instruction(OP_TYPE_POINTER, &[15, STORAGE_CLASS_PUSH_CONSTANT, 14]),
+            instruction(OP_VARIABLE, &[15, 16, STORAGE_CLASS_PUSH_CONSTANT]),
+            instruction(OP_SPEC_CONSTANT, &[3, 17, 32]),
+        ];
+        for part in parts {
+            code.extend(part);
+        }
+        code
+    }
+
+    #[test]
+    /// Bindings, push constants, specialization constants and the local size are reflected
+    fn test_reflect_compute() {
+        let reflection = ShaderReflection::new(&compute_code(), "main").unwrap();
+        assert_eq!(reflection.get_stage(), vk::ShaderStageFlags::COMPUTE);
+        assert_eq!(reflection.get_local_size(), Some([64, 1, 1]));
+        let bindings: Vec<(u32, u32, vk::DescriptorType, u32, &str)> = reflection
+            .get_bindings()
+            .iter()
+            .map(|binding| {
+                (
+                    binding.set,
+                    binding.binding,
+                    binding.descriptor_type,
+                    binding.descriptor_count,
+                    binding.name.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            bindings,
+            vec![
+                (0, 1, vk::DescriptorType::STORAGE_BUFFER, 1, "particles"),
+                (1, 0, vk::DescriptorType::STORAGE_IMAGE, 4, ""),
+            ]
+        );
+        let push_constant_range = reflection.get_push_constant_range().unwrap();
+        assert_eq!((push_constant_range.offset, push_constant_range.size), (0, 20));
+        assert_eq!(
+            reflection.get_specialization_constants(),
+            &[ReflectedSpecializationConstant {
+                constant_id: 3,
+                scalar_type: ReflectedScalarType::Int {
+                    width: 32,
+                    signed: false,
+                },
+                default_value: 32,
+                name: "group_size".to_string(),
+            }]
+        );
+
+        let pipeline = PipelineReflection::new(vec![reflection]).unwrap();
+        let set_layouts = pipeline.get_set_layout_builders();
+        assert_eq!(set_layouts.len(), 2);
+        assert_eq!(set_layouts[1].get_bindings()[0].descriptor_count, 4);
+        assert!(matches!(
+            ShaderReflection::new(&compute_code(), "missing"),
+            Err(ReflectionError::EntryPointNotFound(_))
+        ));
+    }
+
+    #[test]
+    /// Stages sharing a binding are merged, disagreeing stages fail
+    fn test_merge_stages() {
+        let stage = |stage: vk::ShaderStageFlags, descriptor_type: vk::DescriptorType, size: u32| {
+            ShaderReflection {
+                entry_point: "main".to_string(),
+                stage,
+                bindings: vec![ReflectedBinding {
+                    set: 0,
+                    binding: 0,
+                    descriptor_type,
+                    descriptor_count: 1,
+                    stage_flags: stage,
+                    name: String::new(),
+                }],
+                push_constant_range: Some(vk::PushConstantRange {
+                    stage_flags: stage,
+                    offset: 0,
+                    size,
+                }),
+                vertex_inputs: Vec::new(),
+                fragment_outputs: Vec::new(),
+                specialization_constants: Vec::new(),
+                local_size: None,
+            }
+        };
+        let merged = PipelineReflection::new(vec![
+            stage(vk::ShaderStageFlags::VERTEX, vk::DescriptorType::UNIFORM_BUFFER, 64),
+            stage(vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::UNIFORM_BUFFER, 16),
+        ])
+        .unwrap();
+        assert_eq!(
+            merged.get_bindings()[0].stage_flags,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT
+        );
+        assert_eq!(merged.get_push_constant_ranges()[0].size, 64);
+
+        let mismatch = PipelineReflection::new(vec![
+            stage(vk::ShaderStageFlags::VERTEX, vk::DescriptorType::UNIFORM_BUFFER, 64),
+            stage(vk::ShaderStageFlags::FRAGMENT, vk::DescriptorType::STORAGE_BUFFER, 64),
+        ]);
+        assert!(matches!(
+            mismatch,
+            Err(ReflectionError::BindingMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,187 @@
+use ash::vk;
+use ash::vk::TaggedStructure;
+use std::ffi::CString;
+
+/// Values of the specialization constants of a [ShaderStage]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct SpecializationConstants {
+    /// Constant id, offset into `data` and size of every constant
+    entries: Vec<(u32, u32, usize)>,
+    data: Vec<u8>,
+}
+
+impl SpecializationConstants {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the raw bytes of `constant_id`, replacing any previous value
+    pub fn add_bytes(mut self, constant_id: u32, bytes: &[u8]) -> Self {
+        self.entries.retain(|(id, _, _)| *id != constant_id);
+        self.entries
+            .push((constant_id, self.data.len() as u32, bytes.len()));
+        self.data.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn add_u32(self, constant_id: u32, value: u32) -> Self {
+        self.add_bytes(constant_id, &value.to_ne_bytes())
+    }
+
+    pub fn add_i32(self, constant_id: u32, value: i32) -> Self {
+        self.add_bytes(constant_id, &value.to_ne_bytes())
+    }
+
+    pub fn add_f32(self, constant_id: u32, value: f32) -> Self {
+        self.add_bytes(constant_id, &value.to_ne_bytes())
+    }
+
+    /// Booleans are read as a [vk::Bool32]
+    pub fn add_bool(self, constant_id: u32, value: bool) -> Self {
+        self.add_u32(constant_id, value as vk::Bool32)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Get the value of `constant_id` if it was set to 32 bits
+    pub fn get_u32(&self, constant_id: u32) -> Option<u32> {
+        let (_, offset, size) = self
+            .entries
+            .iter()
+            .find(|(id, _, _)| *id == constant_id)?;
+        let bytes = self.data.get(*offset as usize..*offset as usize + size)?;
+        Some(u32::from_ne_bytes(bytes.try_into().ok()?))
+    }
+
+    pub fn get_data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn get_vk_map_entries(&self) -> Vec<vk::SpecializationMapEntry> {
+        self.entries
+            .iter()
+            .map(|(constant_id, offset, size)| vk::SpecializationMapEntry {
+                constant_id: *constant_id,
+                offset: *offset,
+                size: *size,
+            })
+            .collect()
+    }
+}
+
+/// A single shader stage of a pipeline
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ShaderStage {
+    stage: vk::ShaderStageFlags,
+    module: vk::ShaderModule,
+    /// Hash of the code of `module`, known for stages added from a
+    /// [crate::abstraction::utility::shader::ShaderModule]
+    code_hash: Option<u64>,
+    entry_point: CString,
+    specialization: SpecializationConstants,
+}
+
+impl ShaderStage {
+    pub fn new(stage: vk::ShaderStageFlags, module: vk::ShaderModule, entry_point: &str) -> Self {
+        Self {
+            stage,
+            module,
+            code_hash: None,
+            entry_point: CString::new(entry_point).unwrap(),
+            specialization: SpecializationConstants::default(),
+        }
+    }
+
+    pub fn specialization(mut self, specialization: SpecializationConstants) -> Self {
+        self.specialization = specialization;
+        self
+    }
+
+    /// Set the hash of the code of the module, see
+    /// [crate::abstraction::utility::shader::ShaderModule::get_code_hash]
+    pub fn code_hash(mut self, code_hash: u64) -> Self {
+        self.code_hash = Some(code_hash);
+        self
+    }
+
+    pub fn get_stage(&self) -> vk::ShaderStageFlags {
+        self.stage
+    }
+
+    pub fn get_module(&self) -> vk::ShaderModule {
+        self.module
+    }
+
+    pub fn get_code_hash(&self) -> Option<u64> {
+        self.code_hash
+    }
+
+    pub fn get_entry_point(&self) -> &str {
+        self.entry_point.to_str().unwrap()
+    }
+
+    pub fn get_specialization(&self) -> &SpecializationConstants {
+        &self.specialization
+    }
+
+    /// Get the [vk::SpecializationInfo] of the stage
+    ///
+    /// `map_entries` must come from [SpecializationConstants::get_vk_map_entries] of
+    /// [Self::get_specialization]. The returned struct points into `self` and `map_entries`
+    /// and must not outlive them.
+    pub fn get_vk_specialization_info(
+        &self,
+        map_entries: &[vk::SpecializationMapEntry],
+    ) -> vk::SpecializationInfo {
+        vk::SpecializationInfo {
+            map_entry_count: map_entries.len() as u32,
+            p_map_entries: map_entries.as_ptr(),
+            data_size: self.specialization.data.len(),
+            p_data: self.specialization.data.as_ptr() as *const _,
+        }
+    }
+
+    /// Get the [vk::PipelineShaderStageCreateInfo] of the stage
+    ///
+    /// The returned struct points into `self` and `specialization_info`, which must come from
+    /// [Self::get_vk_specialization_info], and must not outlive them.
+    pub fn get_vk_create_info(
+        &self,
+        specialization_info: &vk::SpecializationInfo,
+    ) -> vk::PipelineShaderStageCreateInfo {
+        vk::PipelineShaderStageCreateInfo {
+            s_type: vk::PipelineShaderStageCreateInfo::STRUCTURE_TYPE,
+            stage: self.stage,
+            module: self.module,
+            p_name: self.entry_point.as_ptr(),
+            p_specialization_info: if self.specialization.is_empty() {
+                std::ptr::null()
+            } else {
+                specialization_info
+            },
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Constants are packed one after another and replacing a constant appends its new value
+    fn test_specialization_constants() {
+        let constants = SpecializationConstants::new()
+            .add_u32(0, 64)
+            .add_bool(1, true)
+            .add_f32(0, 0.5);
+        let entries = constants.get_vk_map_entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].constant_id, entries[0].offset), (1, 4));
+        assert_eq!((entries[1].constant_id, entries[1].offset), (0, 8));
+        assert_eq!(&constants.get_data()[4..8], &vk::TRUE.to_ne_bytes());
+        assert_eq!(&constants.get_data()[8..12], &0.5f32.to_ne_bytes());
+    }
+}
@@ -1,6 +1,17 @@
+use ash::vk;
+
 pub trait Pipeline {
-    type PipelineBuilderType;
-    fn from_builder(builder: Self::PipelineBuilderType) -> Self;
+    type PipelineBuilderType: PipelineBuilder;
+
+    fn from_builder(builder: Self::PipelineBuilderType) -> Result<Self, vk::Result>
+    where
+        Self: Sized;
+
+    fn get_handle(&self) -> vk::Pipeline;
+
+    fn get_layout(&self) -> vk::PipelineLayout;
+
+    fn get_bind_point(&self) -> vk::PipelineBindPoint;
 }
 
 pub trait PipelineBuilder {}
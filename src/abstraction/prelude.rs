@@ -1,8 +1,11 @@
+pub use crate::abstraction::command::*;
 pub use crate::abstraction::descriptors::*;
 pub use crate::abstraction::device::*;
 pub use crate::abstraction::instance::*;
 pub use crate::abstraction::physical_device::*;
+pub use crate::abstraction::pipeline::*;
 pub use crate::abstraction::queue::*;
 pub use crate::abstraction::resource::*;
+pub use crate::abstraction::sync::*;
 pub use crate::abstraction::wsi::*;
 pub use crate::abstraction::utility;
\ No newline at end of file
@@ -2,6 +2,7 @@ use crate::abstraction::prelude as abstraction;
 use ash::vk;
 
 /// An abstraction for [vk::Queue]
+#[derive(Clone)]
 pub struct Queue {
     handle: vk::Queue,
     family_index: u32,
@@ -9,7 +10,26 @@ pub struct Queue {
 }
 
 impl Queue {
-    pub fn new(handle: vk::Queue) {}
+    pub fn new(device: abstraction::Device, handle: vk::Queue, family_index: u32) -> Self {
+        Self {
+            handle,
+            family_index,
+            device,
+        }
+    }
+
+    pub fn get_handle(&self) -> vk::Queue {
+        self.handle
+    }
+
+    /// Index of the queue family the queue belongs to
+    pub fn get_family_index(&self) -> u32 {
+        self.family_index
+    }
+
+    pub fn get_device(&self) -> abstraction::Device {
+        self.device.clone()
+    }
 }
 
 impl Into<u32> for Queue {
@@ -1,6 +1,10 @@
+use std::sync::{Arc, Mutex};
 use crate::abstraction::allocators::Allocator;
 use crate::ash::vk;
+use crate::abstraction::descriptors::DescriptorInfo;
 use crate::abstraction::prelude as abstraction;
+use crate::abstraction::resource::Resource;
+use crate::abstraction::resource::tracking::{BarrierBatch, ResourceAccess, SubresourceState, TrackedResource};
 
 /*
 * An abstraction for [vk::Buffer]
@@ -9,6 +13,10 @@ use crate::abstraction::prelude as abstraction;
 #[derive(Clone)]
 pub struct Buffer {
 	handle: vk::Buffer,
+	size: vk::DeviceSize,
+	usage: vk::BufferUsageFlags,
+	/// Last access of the buffer, shared between clones
+	state: Arc<Mutex<SubresourceState>>,
 }
 
 impl Buffer {
@@ -17,4 +25,57 @@ impl Buffer {
 
 		}
 	}
-}
\ No newline at end of file
+
+	/// Wrap an existing [vk::Buffer] of `size` bytes created with `usage` which has not been
+	/// accessed yet
+	pub fn from_vk(handle: vk::Buffer, size: vk::DeviceSize, usage: vk::BufferUsageFlags) -> Self {
+		Self {
+			handle,
+			size,
+			usage,
+			state: Arc::new(Mutex::new(SubresourceState::new(vk::ImageLayout::UNDEFINED))),
+		}
+	}
+
+	pub fn get_handle(&self) -> vk::Buffer {
+		self.handle
+	}
+
+	pub fn get_size(&self) -> vk::DeviceSize {
+		self.size
+	}
+
+	pub fn get_usage(&self) -> vk::BufferUsageFlags {
+		self.usage
+	}
+}
+
+impl TrackedResource for Buffer {
+	fn transition(&self, access: ResourceAccess, batch: &mut BarrierBatch) {
+		// Buffers have no layout
+		let access = ResourceAccess {
+			layout: vk::ImageLayout::UNDEFINED,
+			..access
+		};
+		if let Some(barrier) = self.state.lock().unwrap().transition(access) {
+			batch.push_buffer_barrier(self.handle, barrier);
+		}
+	}
+
+	fn set_state(&self, state: SubresourceState) {
+		*self.state.lock().unwrap() = SubresourceState {
+			layout: vk::ImageLayout::UNDEFINED,
+			..state
+		};
+	}
+}
+
+impl Resource for Buffer {
+	fn get_descriptor(&self) -> DescriptorInfo {
+		DescriptorInfo::Buffer(vk::DescriptorBufferInfo {
+			buffer: self.handle,
+			offset: 0,
+			range: self.size,
+		})
+	}
+}
@@ -1,7 +1,9 @@
+use std::sync::Mutex;
 use ash::vk;
 use crate::abstraction::descriptors::DescriptorInfo;
 use crate::abstraction::prelude as abstraction;
 use crate::abstraction::resource::Resource;
+use crate::abstraction::resource::tracking::{self, BarrierBatch, ResourceAccess, SubresourceState, TrackedResource};
 
 
 pub struct Image {
@@ -9,19 +11,148 @@ pub struct Image {
 	device: abstraction::Device,
 	sampler: vk::Sampler,
 	image_view: vk::ImageView,
-	image_layout: vk::ImageLayout,
+	format: vk::Format,
+	extent: vk::Extent3D,
+	mip_levels: u32,
+	array_layers: u32,
+	/// Last access of every subresource, indexed by `mip_level * array_layers + array_layer`
+	subresource_states: Mutex<Vec<SubresourceState>>,
+	/// Whether the image is destroyed once dropped, images of a swapchain are owned by it
+	owned: bool,
 }
 
 impl Image {
-	pub fn from_vk(image: vk::Image, device: abstraction::Device, sampler: vk::Sampler, image_view: vk::ImageView, image_layout: vk::ImageLayout) -> Self {
+	/// Wrap an existing [vk::Image] created using `image_ci`
+	///
+	/// The image is expected to currently be in `image_ci.initial_layout`.
+	pub fn from_vk(image: vk::Image, device: abstraction::Device, sampler: vk::Sampler, image_view: vk::ImageView, image_ci: &vk::ImageCreateInfo) -> Self {
+		let subresource_count = (image_ci.mip_levels * image_ci.array_layers) as usize;
 		Self {
 			handle: image,
 			device,
 			sampler,
 			image_view,
-			image_layout
+			format: image_ci.format,
+			extent: image_ci.extent,
+			mip_levels: image_ci.mip_levels,
+			array_layers: image_ci.array_layers,
+			subresource_states: Mutex::new(vec![SubresourceState::new(image_ci.initial_layout); subresource_count]),
+			owned: true,
 		}
 	}
+
+	/// Wrap an existing [vk::Image] like [Self::from_vk] without taking ownership of it
+	///
+	/// Used for images owned by something else, such as a swapchain.
+	pub fn from_vk_non_owning(image: vk::Image, device: abstraction::Device, sampler: vk::Sampler, image_view: vk::ImageView, image_ci: &vk::ImageCreateInfo) -> Self {
+		let mut image = Self::from_vk(image, device, sampler, image_view, image_ci);
+		image.owned = false;
+		image
+	}
+
+	pub fn get_handle(&self) -> vk::Image {
+		self.handle
+	}
+
+	/// Get the default [vk::ImageView] of the image
+	pub fn get_image_view(&self) -> vk::ImageView {
+		self.image_view
+	}
+
+	pub fn get_format(&self) -> vk::Format {
+		self.format
+	}
+
+	pub fn get_extent(&self) -> vk::Extent3D {
+		self.extent
+	}
+
+	pub fn get_mip_levels(&self) -> u32 {
+		self.mip_levels
+	}
+
+	pub fn get_array_layers(&self) -> u32 {
+		self.array_layers
+	}
+
+	pub fn get_device(&self) -> abstraction::Device {
+		self.device.clone()
+	}
+
+	/// Get the aspects of the image's format
+	pub fn get_aspect_mask(&self) -> vk::ImageAspectFlags {
+		format_aspect_mask(self.format)
+	}
+
+	/// Get a [vk::ImageSubresourceRange] covering every subresource of the image
+	pub fn get_full_range(&self) -> vk::ImageSubresourceRange {
+		vk::ImageSubresourceRange {
+			aspect_mask: self.get_aspect_mask(),
+			base_mip_level: 0,
+			level_count: self.mip_levels,
+			base_array_layer: 0,
+			layer_count: self.array_layers,
+		}
+	}
+
+	/// Get the current layout of the first mip level and array layer
+	pub fn get_layout(&self) -> vk::ImageLayout {
+		self.get_subresource_layout(0, 0)
+	}
+
+	/// Get the current layout of a single subresource
+	pub fn get_subresource_layout(&self, mip_level: u32, array_layer: u32) -> vk::ImageLayout {
+		self.subresource_states.lock().unwrap()[(mip_level * self.array_layers + array_layer) as usize].layout
+	}
+
+	/// Track only the subresources in `subresource_range`
+	pub fn subresources(&self, subresource_range: vk::ImageSubresourceRange) -> ImageSubresources<'_> {
+		ImageSubresources {
+			image: self,
+			subresource_range,
+		}
+	}
+}
+
+/// Get the aspects of an image of `format`
+pub fn format_aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+	match format {
+		vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => vk::ImageAspectFlags::DEPTH,
+		vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+		vk::Format::D16_UNORM_S8_UINT | vk::Format::D24_UNORM_S8_UINT | vk::Format::D32_SFLOAT_S8_UINT => vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+		_ => vk::ImageAspectFlags::COLOR,
+	}
+}
+
+/// A range of subresources of an [Image] which are transitioned together
+pub struct ImageSubresources<'a> {
+	image: &'a Image,
+	subresource_range: vk::ImageSubresourceRange,
+}
+
+impl TrackedResource for ImageSubresources<'_> {
+	fn transition(&self, access: ResourceAccess, batch: &mut BarrierBatch) {
+		let mut states = self.image.subresource_states.lock().unwrap();
+		let barriers = tracking::transition_subresources(states.as_mut_slice(), self.image.array_layers, self.subresource_range, access);
+		for (barrier, subresource_range) in barriers {
+			batch.push_image_barrier(self.image.handle, barrier, subresource_range);
+		}
+	}
+
+	fn set_state(&self, state: SubresourceState) {
+		let mut states = self.image.subresource_states.lock().unwrap();
+		tracking::set_subresources(states.as_mut_slice(), self.image.array_layers, self.subresource_range, state);
+	}
+}
+
+impl TrackedResource for Image {
+	fn transition(&self, access: ResourceAccess, batch: &mut BarrierBatch) {
+		self.subresources(self.get_full_range()).transition(access, batch);
+	}
+
+	fn set_state(&self, state: SubresourceState) {
+		self.subresources(self.get_full_range()).set_state(state);
+	}
 }
 
 impl Resource for Image {
@@ -29,15 +160,18 @@ impl Resource for Image {
 		DescriptorInfo::Image(vk::DescriptorImageInfo {
 			sampler: self.sampler,
 			image_view: self.image_view,
-			image_layout: self.image_layout,
+			image_layout: self.get_layout(),
 		})
 	}
 }
 
 impl Drop for Image {
 	fn drop(&mut self) {
+		if !self.owned {
+			return;
+		}
 		unsafe {
 			self.device.handle_as_ref().destroy_image(self.handle, None);
 		};
 	}
-}
\ No newline at end of file
+}
@@ -0,0 +1,79 @@
+use ash::vk;
+use ash::vk::TaggedStructure;
+use crate::abstraction::prelude as abstraction;
+
+/*
+* An abstraction for [vk::ImageView]
+*/
+
+pub struct ImageView {
+	handle: vk::ImageView,
+	device: abstraction::Device,
+	/// Image the view was created from
+	image: vk::Image,
+	format: vk::Format,
+	/// Extent of the first mip level the view can access
+	extent: vk::Extent3D,
+	subresource_range: vk::ImageSubresourceRange,
+}
+
+impl ImageView {
+	/// Create a new view of `image` using the image's format
+	pub fn new(image: &abstraction::Image, view_type: vk::ImageViewType, subresource_range: vk::ImageSubresourceRange) -> Result<Self, vk::Result> {
+		let device = image.get_device();
+		let image_view_ci = vk::ImageViewCreateInfo {
+			s_type: vk::ImageViewCreateInfo::STRUCTURE_TYPE,
+			image: image.get_handle(),
+			view_type,
+			format: image.get_format(),
+			components: vk::ComponentMapping::default(),
+			subresource_range,
+			..Default::default()
+		};
+		let handle = unsafe {
+			device.handle_as_ref().create_image_view(&image_view_ci, None)?
+		};
+		let extent = image.get_extent();
+		let mip_extent = |dimension: u32| (dimension >> subresource_range.base_mip_level).max(1);
+		Ok(Self {
+			handle,
+			device,
+			image: image.get_handle(),
+			format: image.get_format(),
+			extent: vk::Extent3D {
+				width: mip_extent(extent.width),
+				height: mip_extent(extent.height),
+				depth: mip_extent(extent.depth),
+			},
+			subresource_range,
+		})
+	}
+
+	pub fn get_handle(&self) -> vk::ImageView {
+		self.handle
+	}
+
+	pub fn get_image(&self) -> vk::Image {
+		self.image
+	}
+
+	pub fn get_format(&self) -> vk::Format {
+		self.format
+	}
+
+	pub fn get_extent(&self) -> vk::Extent3D {
+		self.extent
+	}
+
+	pub fn get_subresource_range(&self) -> vk::ImageSubresourceRange {
+		self.subresource_range
+	}
+}
+
+impl Drop for ImageView {
+	fn drop(&mut self) {
+		unsafe {
+			self.device.handle_as_ref().destroy_image_view(self.handle, None);
+		}
+	}
+}
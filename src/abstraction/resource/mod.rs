@@ -5,6 +5,11 @@
 pub mod traits;
 pub mod buffer;
 pub mod image;
+pub mod image_view;
+pub mod tracking;
 
 pub use traits::*;
-pub use buffer::*;
\ No newline at end of file
+pub use buffer::*;
+pub use image::*;
+pub use image_view::*;
+pub use tracking::*;
\ No newline at end of file
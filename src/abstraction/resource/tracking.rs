@@ -0,0 +1,420 @@
+use ash::vk;
+use ash::vk::TaggedStructure;
+use std::ptr;
+
+/*
+* Tracks the last access of resources to automatically emit synchronization2 barriers.
+*
+* Every buffer and every image subresource (mip level + array layer) keeps a [SubresourceState].
+* Moving a resource to a new [ResourceAccess] updates the state and emits only the barriers
+* required: reads following reads in the same layout need none, reads following a write
+* wait on the write, and writes or layout transitions wait on every access since the last write.
+*/
+
+/// Describes how a resource will be accessed
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ResourceAccess {
+	pub stage_mask: vk::PipelineStageFlags2,
+	pub access_mask: vk::AccessFlags2,
+	/// Layout the image must be in, ignored for buffers
+	pub layout: vk::ImageLayout,
+}
+
+impl ResourceAccess {
+	pub fn new(stage_mask: vk::PipelineStageFlags2, access_mask: vk::AccessFlags2, layout: vk::ImageLayout) -> Self {
+		Self {
+			stage_mask,
+			access_mask,
+			layout,
+		}
+	}
+
+	/// Whether or not the access writes to the resource
+	pub fn is_write(&self) -> bool {
+		self.access_mask.intersects(
+			vk::AccessFlags2::SHADER_WRITE
+				| vk::AccessFlags2::SHADER_STORAGE_WRITE
+				| vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
+				| vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE
+				| vk::AccessFlags2::TRANSFER_WRITE
+				| vk::AccessFlags2::HOST_WRITE
+				| vk::AccessFlags2::MEMORY_WRITE
+				| vk::AccessFlags2::ACCELERATION_STRUCTURE_WRITE_KHR
+				| vk::AccessFlags2::TRANSFORM_FEEDBACK_WRITE_EXT
+				| vk::AccessFlags2::TRANSFORM_FEEDBACK_COUNTER_WRITE_EXT
+				| vk::AccessFlags2::COMMAND_PREPROCESS_WRITE_NV
+				| vk::AccessFlags2::VIDEO_DECODE_WRITE_KHR
+				| vk::AccessFlags2::VIDEO_ENCODE_WRITE_KHR,
+		)
+	}
+}
+
+/// The parameters of a single memory barrier
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Barrier {
+	pub src_stage_mask: vk::PipelineStageFlags2,
+	pub src_access_mask: vk::AccessFlags2,
+	pub dst_stage_mask: vk::PipelineStageFlags2,
+	pub dst_access_mask: vk::AccessFlags2,
+	pub old_layout: vk::ImageLayout,
+	pub new_layout: vk::ImageLayout,
+}
+
+/// Tracked state of a buffer or a single image subresource
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SubresourceState {
+	/// Current layout, always [vk::ImageLayout::UNDEFINED] for buffers
+	pub layout: vk::ImageLayout,
+	/// Stages of the last write or layout transition
+	pub write_stage_mask: vk::PipelineStageFlags2,
+	/// Accesses of the last write
+	pub write_access_mask: vk::AccessFlags2,
+	/// Stages which have been synchronized with the last write
+	pub read_stage_mask: vk::PipelineStageFlags2,
+	/// Accesses the last write has been made visible to
+	pub read_access_mask: vk::AccessFlags2,
+}
+
+impl SubresourceState {
+	pub fn new(layout: vk::ImageLayout) -> Self {
+		Self {
+			layout,
+			write_stage_mask: vk::PipelineStageFlags2::NONE,
+			write_access_mask: vk::AccessFlags2::NONE,
+			read_stage_mask: vk::PipelineStageFlags2::NONE,
+			read_access_mask: vk::AccessFlags2::NONE,
+		}
+	}
+
+	/// State after `accesses` were made at the same time, such as by a render graph which
+	/// records its barriers without the tracker
+	///
+	/// The layout of the last access is used. If one of the accesses writes, the others are
+	/// expected to be synchronized with it already.
+	pub fn from_accesses(accesses: &[ResourceAccess]) -> Self {
+		let mut state = Self::new(accesses.last().map_or(vk::ImageLayout::UNDEFINED, |access| access.layout));
+		for access in accesses {
+			if access.is_write() {
+				state.write_stage_mask |= access.stage_mask;
+				state.write_access_mask |= access.access_mask;
+			} else {
+				state.read_stage_mask |= access.stage_mask;
+				state.read_access_mask |= access.access_mask;
+			}
+		}
+		if state.write_stage_mask.is_empty() {
+			// The reads may follow a write or a layout transition which is only visible to them
+			state.write_stage_mask = state.read_stage_mask;
+		}
+		state
+	}
+
+	/// Move to `access` returning the barrier required, if any
+	pub fn transition(&mut self, access: ResourceAccess) -> Option<Barrier> {
+		let layout_transition = access.layout != self.layout;
+		if !access.is_write() && !layout_transition {
+			let never_written = self.write_stage_mask.is_empty() && self.write_access_mask.is_empty();
+			let already_visible = self.read_stage_mask.contains(access.stage_mask)
+				&& self.read_access_mask.contains(access.access_mask);
+			self.read_stage_mask |= access.stage_mask;
+			self.read_access_mask |= access.access_mask;
+			if never_written || already_visible {
+				return None;
+			}
+			// Read after write
+			return Some(Barrier {
+				src_stage_mask: self.write_stage_mask,
+				src_access_mask: self.write_access_mask,
+				dst_stage_mask: access.stage_mask,
+				dst_access_mask: access.access_mask,
+				old_layout: self.layout,
+				new_layout: access.layout,
+			});
+		}
+		// Writes and layout transitions must wait for every access since the last write
+		let barrier = Barrier {
+			src_stage_mask: self.write_stage_mask | self.read_stage_mask,
+			src_access_mask: self.write_access_mask,
+			dst_stage_mask: access.stage_mask,
+			dst_access_mask: access.access_mask,
+			old_layout: self.layout,
+			new_layout: access.layout,
+		};
+		*self = if access.is_write() {
+			Self {
+				layout: access.layout,
+				write_stage_mask: access.stage_mask,
+				write_access_mask: access.access_mask,
+				read_stage_mask: vk::PipelineStageFlags2::NONE,
+				read_access_mask: vk::AccessFlags2::NONE,
+			}
+		} else {
+			// A layout transition followed by a read, later reads only need to wait on the
+			// stages the transition was made visible to
+			Self {
+				layout: access.layout,
+				write_stage_mask: access.stage_mask,
+				write_access_mask: vk::AccessFlags2::NONE,
+				read_stage_mask: access.stage_mask,
+				read_access_mask: access.access_mask,
+			}
+		};
+		Some(barrier)
+	}
+}
+
+/// Number of mip levels and array layers in `range`, resolving the `REMAINING_*` constants
+fn get_range_counts(states: &[SubresourceState], array_layers: u32, range: vk::ImageSubresourceRange) -> (u32, u32) {
+	let level_count = if range.level_count == vk::REMAINING_MIP_LEVELS {
+		states.len() as u32 / array_layers - range.base_mip_level
+	} else {
+		range.level_count
+	};
+	let layer_count = if range.layer_count == vk::REMAINING_ARRAY_LAYERS {
+		array_layers - range.base_array_layer
+	} else {
+		range.layer_count
+	};
+	(level_count, layer_count)
+}
+
+/// Overwrite the state of every subresource of `range` with `state`
+pub fn set_subresources(states: &mut [SubresourceState], array_layers: u32, range: vk::ImageSubresourceRange, state: SubresourceState) {
+	let (level_count, layer_count) = get_range_counts(states, array_layers, range);
+	for mip_level in range.base_mip_level..range.base_mip_level + level_count {
+		for array_layer in range.base_array_layer..range.base_array_layer + layer_count {
+			states[(mip_level * array_layers + array_layer) as usize] = state;
+		}
+	}
+}
+
+/// Move every subresource of `range` to `access` and return the barriers needed
+///
+/// `states` are indexed by `mip_level * array_layers + array_layer`. Barriers of neighbouring
+/// subresources which are identical are merged, first across array layers then across mip
+/// levels.
+pub fn transition_subresources(states: &mut [SubresourceState], array_layers: u32, range: vk::ImageSubresourceRange, access: ResourceAccess) -> Vec<(Barrier, vk::ImageSubresourceRange)> {
+	let (level_count, layer_count) = get_range_counts(states, array_layers, range);
+	let mut barriers: Vec<(Barrier, vk::ImageSubresourceRange)> = Vec::new();
+	for mip_level in range.base_mip_level..range.base_mip_level + level_count {
+		// Merge across array layers of the mip level
+		let mut mip_barriers: Vec<(Barrier, vk::ImageSubresourceRange)> = Vec::new();
+		for array_layer in range.base_array_layer..range.base_array_layer + layer_count {
+			let state = &mut states[(mip_level * array_layers + array_layer) as usize];
+			let barrier = match state.transition(access) {
+				Some(barrier) => barrier,
+				None => continue,
+			};
+			match mip_barriers.last_mut() {
+				Some((last, last_range)) if *last == barrier && last_range.base_array_layer + last_range.layer_count == array_layer => {
+					last_range.layer_count += 1;
+				}
+				_ => mip_barriers.push((barrier, vk::ImageSubresourceRange {
+					aspect_mask: range.aspect_mask,
+					base_mip_level: mip_level,
+					level_count: 1,
+					base_array_layer: array_layer,
+					layer_count: 1,
+				})),
+			}
+		}
+		// Merge with the previous mip level if it covered the same array layers
+		for (barrier, mip_range) in mip_barriers {
+			let merged = barriers.iter_mut().any(|(last, last_range)| {
+				if *last == barrier
+					&& last_range.base_mip_level + last_range.level_count == mip_level
+					&& last_range.base_array_layer == mip_range.base_array_layer
+					&& last_range.layer_count == mip_range.layer_count {
+					last_range.level_count += 1;
+					return true;
+				}
+				false
+			});
+			if !merged {
+				barriers.push((barrier, mip_range));
+			}
+		}
+	}
+	barriers
+}
+
+/// Implemented by every resource whose accesses are tracked
+pub trait TrackedResource {
+	/// Move the resource to `access` and append the barriers required to `batch`
+	fn transition(&self, access: ResourceAccess, batch: &mut BarrierBatch);
+
+	/// Overwrite the tracked state after the resource was accessed without [Self::transition]
+	fn set_state(&self, state: SubresourceState);
+}
+
+/// Barriers collected from [TrackedResource::transition] to be recorded in a single
+/// `vkCmdPipelineBarrier2`
+#[derive(Default)]
+pub struct BarrierBatch {
+	pub memory_barriers: Vec<vk::MemoryBarrier2>,
+	pub image_barriers: Vec<vk::ImageMemoryBarrier2>,
+	pub buffer_barriers: Vec<vk::BufferMemoryBarrier2>,
+}
+
+impl BarrierBatch {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.memory_barriers.is_empty() && self.image_barriers.is_empty() && self.buffer_barriers.is_empty()
+	}
+
+	pub fn push_image_barrier(&mut self, image: vk::Image, barrier: Barrier, subresource_range: vk::ImageSubresourceRange) {
+		self.image_barriers.push(vk::ImageMemoryBarrier2 {
+			s_type: vk::ImageMemoryBarrier2::STRUCTURE_TYPE,
+			p_next: ptr::null(),
+			src_stage_mask: barrier.src_stage_mask,
+			src_access_mask: barrier.src_access_mask,
+			dst_stage_mask: barrier.dst_stage_mask,
+			dst_access_mask: barrier.dst_access_mask,
+			old_layout: barrier.old_layout,
+			new_layout: barrier.new_layout,
+			src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+			dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+			image,
+			subresource_range,
+		});
+	}
+
+	pub fn push_buffer_barrier(&mut self, buffer: vk::Buffer, barrier: Barrier) {
+		self.buffer_barriers.push(vk::BufferMemoryBarrier2 {
+			s_type: vk::BufferMemoryBarrier2::STRUCTURE_TYPE,
+			p_next: ptr::null(),
+			src_stage_mask: barrier.src_stage_mask,
+			src_access_mask: barrier.src_access_mask,
+			dst_stage_mask: barrier.dst_stage_mask,
+			dst_access_mask: barrier.dst_access_mask,
+			src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+			dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+			buffer,
+			offset: 0,
+			size: vk::WHOLE_SIZE,
+		});
+	}
+
+	/// Get the [vk::DependencyInfo] of every barrier in the batch
+	///
+	/// The returned struct points into `self` and must not outlive it.
+	pub fn get_dependency_info(&self) -> vk::DependencyInfo {
+		vk::DependencyInfo {
+			s_type: vk::DependencyInfo::STRUCTURE_TYPE,
+			memory_barrier_count: self.memory_barriers.len() as u32,
+			p_memory_barriers: self.memory_barriers.as_ptr(),
+			image_memory_barrier_count: self.image_barriers.len() as u32,
+			p_image_memory_barriers: self.image_barriers.as_ptr(),
+			buffer_memory_barrier_count: self.buffer_barriers.len() as u32,
+			p_buffer_memory_barriers: self.buffer_barriers.as_ptr(),
+			..Default::default()
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn color_write() -> ResourceAccess {
+		ResourceAccess::new(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT, vk::AccessFlags2::COLOR_ATTACHMENT_WRITE, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+	}
+
+	fn fragment_read() -> ResourceAccess {
+		ResourceAccess::new(vk::PipelineStageFlags2::FRAGMENT_SHADER, vk::AccessFlags2::SHADER_SAMPLED_READ, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+	}
+
+	fn compute_read() -> ResourceAccess {
+		ResourceAccess::new(vk::PipelineStageFlags2::COMPUTE_SHADER, vk::AccessFlags2::SHADER_SAMPLED_READ, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+	}
+
+	#[test]
+	/// Reads after a write wait on it once, repeated reads need no barrier
+	fn test_read_after_write() {
+		let mut state = SubresourceState::new(vk::ImageLayout::UNDEFINED);
+		let barrier = state.transition(color_write()).unwrap();
+		assert_eq!(barrier.old_layout, vk::ImageLayout::UNDEFINED);
+		assert_eq!(barrier.src_stage_mask, vk::PipelineStageFlags2::NONE);
+
+		let barrier = state.transition(fragment_read()).unwrap();
+		assert_eq!(barrier.src_stage_mask, vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT);
+		assert_eq!(barrier.src_access_mask, vk::AccessFlags2::COLOR_ATTACHMENT_WRITE);
+		assert_eq!(barrier.new_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+		assert!(state.transition(fragment_read()).is_none());
+
+		// A read from a new stage must still be synchronized with the layout transition
+		let barrier = state.transition(compute_read()).unwrap();
+		assert_eq!(barrier.src_stage_mask, vk::PipelineStageFlags2::FRAGMENT_SHADER);
+		assert_eq!(barrier.old_layout, barrier.new_layout);
+		assert!(state.transition(compute_read()).is_none());
+	}
+
+	#[test]
+	/// Writing after reads waits on every reader without making any memory available
+	fn test_write_after_read() {
+		let mut state = SubresourceState::new(vk::ImageLayout::UNDEFINED);
+		state.transition(color_write());
+		state.transition(fragment_read());
+		state.transition(compute_read());
+		let barrier = state.transition(color_write()).unwrap();
+		assert_eq!(barrier.src_stage_mask, vk::PipelineStageFlags2::FRAGMENT_SHADER | vk::PipelineStageFlags2::COMPUTE_SHADER);
+		assert_eq!(barrier.src_access_mask, vk::AccessFlags2::NONE);
+		assert_eq!(barrier.new_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+	}
+
+	#[test]
+	/// Identical barriers of neighbouring subresources are merged
+	fn test_merge_subresources() {
+		let array_layers = 4;
+		let mip_levels = 3;
+		let mut states = vec![SubresourceState::new(vk::ImageLayout::UNDEFINED); (array_layers * mip_levels) as usize];
+		let full_range = vk::ImageSubresourceRange {
+			aspect_mask: vk::ImageAspectFlags::COLOR,
+			base_mip_level: 0,
+			level_count: vk::REMAINING_MIP_LEVELS,
+			base_array_layer: 0,
+			layer_count: vk::REMAINING_ARRAY_LAYERS,
+		};
+		let barriers = transition_subresources(&mut states, array_layers, full_range, color_write());
+		assert_eq!(barriers.len(), 1);
+		assert_eq!(barriers[0].1.level_count, mip_levels);
+		assert_eq!(barriers[0].1.layer_count, array_layers);
+
+		// Only mip 1 is read, the rest of the image stays as is
+		let mip_range = vk::ImageSubresourceRange {
+			base_mip_level: 1,
+			level_count: 1,
+			..full_range
+		};
+		let barriers = transition_subresources(&mut states, array_layers, mip_range, fragment_read());
+		assert_eq!(barriers.len(), 1);
+		assert_eq!(barriers[0].1.base_mip_level, 1);
+		assert_eq!(barriers[0].1.layer_count, array_layers);
+
+		// Mip 1 and the others now differ and require separate barriers
+		let barriers = transition_subresources(&mut states, array_layers, full_range, compute_read());
+		assert_eq!(barriers.len(), 3);
+		assert!(barriers.iter().all(|(barrier, _)| barrier.new_layout == vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL));
+	}
+
+	#[test]
+	/// State of accesses made outside the tracker still synchronizes later accesses
+	fn test_from_accesses() {
+		let mut state = SubresourceState::from_accesses(&[color_write()]);
+		let barrier = state.transition(fragment_read()).unwrap();
+		assert_eq!(barrier.src_stage_mask, vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT);
+		assert_eq!(barrier.src_access_mask, vk::AccessFlags2::COLOR_ATTACHMENT_WRITE);
+		assert_eq!(barrier.old_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
+		// Reads only need a barrier for stages they have not been made visible to
+		let mut state = SubresourceState::from_accesses(&[fragment_read()]);
+		assert!(state.transition(fragment_read()).is_none());
+		let barrier = state.transition(compute_read()).unwrap();
+		assert_eq!(barrier.src_stage_mask, vk::PipelineStageFlags2::FRAGMENT_SHADER);
+		let barrier = state.transition(color_write()).unwrap();
+		assert_eq!(barrier.src_stage_mask, vk::PipelineStageFlags2::FRAGMENT_SHADER | vk::PipelineStageFlags2::COMPUTE_SHADER);
+	}
+}
@@ -0,0 +1,273 @@
+//! Named accesses in the spirit of [vk-sync](https://github.com/Tobski/simple_vulkan_synchronization)
+//!
+//! Instead of spelling out stage masks, access masks and layouts by hand, each [AccessType]
+//! names a single way a resource is used and maps to the synchronization2 values for it.
+use crate::abstraction::prelude as abstraction;
+use ash::vk;
+
+/// Every way a resource can be accessed
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum AccessType {
+    /// No access, useful to express a resource which has not been used yet
+    Nothing,
+
+    // Reads
+    IndirectBuffer,
+    IndexBuffer,
+    VertexBuffer,
+    VertexShaderReadUniformBuffer,
+    VertexShaderReadSampledImage,
+    VertexShaderReadOther,
+    FragmentShaderReadUniformBuffer,
+    FragmentShaderReadSampledImage,
+    FragmentShaderReadColorInputAttachment,
+    FragmentShaderReadDepthStencilInputAttachment,
+    FragmentShaderReadOther,
+    ColorAttachmentRead,
+    DepthStencilAttachmentRead,
+    ComputeShaderReadUniformBuffer,
+    ComputeShaderReadSampledImage,
+    ComputeShaderReadOther,
+    AnyShaderReadUniformBuffer,
+    AnyShaderReadSampledImage,
+    AnyShaderReadOther,
+    TransferRead,
+    HostRead,
+    Present,
+
+    // Writes
+    VertexShaderWrite,
+    FragmentShaderWrite,
+    ColorAttachmentWrite,
+    DepthStencilAttachmentWrite,
+    DepthAttachmentWriteStencilReadOnly,
+    StencilAttachmentWriteDepthReadOnly,
+    ComputeShaderWrite,
+    AnyShaderWrite,
+    TransferWrite,
+    HostPreinitialized,
+    HostWrite,
+    ColorAttachmentReadWrite,
+    /// Any access on any stage, should only be used for debugging
+    General,
+}
+
+/// Stages, accesses and layout an [AccessType] maps to
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct AccessInfo {
+    pub stage_mask: vk::PipelineStageFlags2,
+    pub access_mask: vk::AccessFlags2,
+    /// Layout an image must be in for the access, [vk::ImageLayout::UNDEFINED] if any layout works
+    pub image_layout: vk::ImageLayout,
+}
+
+impl AccessType {
+    pub fn get_info(&self) -> AccessInfo {
+        use vk::AccessFlags2 as A;
+        use vk::ImageLayout as L;
+        use vk::PipelineStageFlags2 as S;
+        let fragment_tests = S::EARLY_FRAGMENT_TESTS | S::LATE_FRAGMENT_TESTS;
+        let (stage_mask, access_mask, image_layout) = match self {
+            AccessType::Nothing => (S::NONE, A::NONE, L::UNDEFINED),
+            AccessType::IndirectBuffer => (S::DRAW_INDIRECT, A::INDIRECT_COMMAND_READ, L::UNDEFINED),
+            AccessType::IndexBuffer => (S::INDEX_INPUT, A::INDEX_READ, L::UNDEFINED),
+            AccessType::VertexBuffer => (
+                S::VERTEX_ATTRIBUTE_INPUT,
+                A::VERTEX_ATTRIBUTE_READ,
+                L::UNDEFINED,
+            ),
+            AccessType::VertexShaderReadUniformBuffer => {
+                (S::VERTEX_SHADER, A::UNIFORM_READ, L::UNDEFINED)
+            }
+            AccessType::VertexShaderReadSampledImage => (
+                S::VERTEX_SHADER,
+                A::SHADER_SAMPLED_READ,
+                L::SHADER_READ_ONLY_OPTIMAL,
+            ),
+            AccessType::VertexShaderReadOther => {
+                (S::VERTEX_SHADER, A::SHADER_STORAGE_READ, L::GENERAL)
+            }
+            AccessType::FragmentShaderReadUniformBuffer => {
+                (S::FRAGMENT_SHADER, A::UNIFORM_READ, L::UNDEFINED)
+            }
+            AccessType::FragmentShaderReadSampledImage => (
+                S::FRAGMENT_SHADER,
+                A::SHADER_SAMPLED_READ,
+                L::SHADER_READ_ONLY_OPTIMAL,
+            ),
+            AccessType::FragmentShaderReadColorInputAttachment => (
+                S::FRAGMENT_SHADER,
+                A::INPUT_ATTACHMENT_READ,
+                L::SHADER_READ_ONLY_OPTIMAL,
+            ),
+            AccessType::FragmentShaderReadDepthStencilInputAttachment => (
+                S::FRAGMENT_SHADER,
+                A::INPUT_ATTACHMENT_READ,
+                L::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            ),
+            AccessType::FragmentShaderReadOther => {
+                (S::FRAGMENT_SHADER, A::SHADER_STORAGE_READ, L::GENERAL)
+            }
+            AccessType::ColorAttachmentRead => (
+                S::COLOR_ATTACHMENT_OUTPUT,
+                A::COLOR_ATTACHMENT_READ,
+                L::COLOR_ATTACHMENT_OPTIMAL,
+            ),
+            AccessType::DepthStencilAttachmentRead => (
+                fragment_tests,
+                A::DEPTH_STENCIL_ATTACHMENT_READ,
+                L::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            ),
+            AccessType::ComputeShaderReadUniformBuffer => {
+                (S::COMPUTE_SHADER, A::UNIFORM_READ, L::UNDEFINED)
+            }
+            AccessType::ComputeShaderReadSampledImage => (
+                S::COMPUTE_SHADER,
+                A::SHADER_SAMPLED_READ,
+                L::SHADER_READ_ONLY_OPTIMAL,
+            ),
+            AccessType::ComputeShaderReadOther => {
+                (S::COMPUTE_SHADER, A::SHADER_STORAGE_READ, L::GENERAL)
+            }
+            AccessType::AnyShaderReadUniformBuffer => {
+                (S::ALL_COMMANDS, A::UNIFORM_READ, L::UNDEFINED)
+            }
+            AccessType::AnyShaderReadSampledImage => (
+                S::ALL_COMMANDS,
+                A::SHADER_SAMPLED_READ,
+                L::SHADER_READ_ONLY_OPTIMAL,
+            ),
+            AccessType::AnyShaderReadOther => {
+                (S::ALL_COMMANDS, A::SHADER_STORAGE_READ, L::GENERAL)
+            }
+            AccessType::TransferRead => {
+                (S::ALL_TRANSFER, A::TRANSFER_READ, L::TRANSFER_SRC_OPTIMAL)
+            }
+            AccessType::HostRead => (S::HOST, A::HOST_READ, L::GENERAL),
+            AccessType::Present => (S::NONE, A::NONE, L::PRESENT_SRC_KHR),
+            AccessType::VertexShaderWrite => {
+                (S::VERTEX_SHADER, A::SHADER_STORAGE_WRITE, L::GENERAL)
+            }
+            AccessType::FragmentShaderWrite => {
+                (S::FRAGMENT_SHADER, A::SHADER_STORAGE_WRITE, L::GENERAL)
+            }
+            AccessType::ColorAttachmentWrite => (
+                S::COLOR_ATTACHMENT_OUTPUT,
+                A::COLOR_ATTACHMENT_WRITE,
+                L::COLOR_ATTACHMENT_OPTIMAL,
+            ),
+            AccessType::DepthStencilAttachmentWrite => (
+                fragment_tests,
+                A::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                L::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            ),
+            AccessType::DepthAttachmentWriteStencilReadOnly => (
+                fragment_tests,
+                A::DEPTH_STENCIL_ATTACHMENT_READ | A::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                L::DEPTH_ATTACHMENT_STENCIL_READ_ONLY_OPTIMAL,
+            ),
+            AccessType::StencilAttachmentWriteDepthReadOnly => (
+                fragment_tests,
+                A::DEPTH_STENCIL_ATTACHMENT_READ | A::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                L::DEPTH_READ_ONLY_STENCIL_ATTACHMENT_OPTIMAL,
+            ),
+            AccessType::ComputeShaderWrite => {
+                (S::COMPUTE_SHADER, A::SHADER_STORAGE_WRITE, L::GENERAL)
+            }
+            AccessType::AnyShaderWrite => {
+                (S::ALL_COMMANDS, A::SHADER_STORAGE_WRITE, L::GENERAL)
+            }
+            AccessType::TransferWrite => {
+                (S::ALL_TRANSFER, A::TRANSFER_WRITE, L::TRANSFER_DST_OPTIMAL)
+            }
+            AccessType::HostPreinitialized => (S::HOST, A::HOST_WRITE, L::PREINITIALIZED),
+            AccessType::HostWrite => (S::HOST, A::HOST_WRITE, L::GENERAL),
+            AccessType::ColorAttachmentReadWrite => (
+                S::COLOR_ATTACHMENT_OUTPUT,
+                A::COLOR_ATTACHMENT_READ | A::COLOR_ATTACHMENT_WRITE,
+                L::COLOR_ATTACHMENT_OPTIMAL,
+            ),
+            AccessType::General => (
+                S::ALL_COMMANDS,
+                A::MEMORY_READ | A::MEMORY_WRITE,
+                L::GENERAL,
+            ),
+        };
+        AccessInfo {
+            stage_mask,
+            access_mask,
+            image_layout,
+        }
+    }
+
+    /// Whether or not the access writes to the resource
+    pub fn is_write(&self) -> bool {
+        self.get_resource_access().is_write()
+    }
+
+    /// Get the [abstraction::ResourceAccess] used by automatic resource tracking
+    pub fn get_resource_access(&self) -> abstraction::ResourceAccess {
+        let info = self.get_info();
+        abstraction::ResourceAccess::new(info.stage_mask, info.access_mask, info.image_layout)
+    }
+}
+
+impl From<AccessType> for abstraction::ResourceAccess {
+    fn from(access_type: AccessType) -> Self {
+        access_type.get_resource_access()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Writes and reads are classified correctly
+    fn test_is_write() {
+        assert!(AccessType::ColorAttachmentWrite.is_write());
+        assert!(AccessType::HostPreinitialized.is_write());
+        assert!(AccessType::DepthAttachmentWriteStencilReadOnly.is_write());
+        assert!(!AccessType::FragmentShaderReadSampledImage.is_write());
+        assert!(!AccessType::Present.is_write());
+        assert!(!AccessType::Nothing.is_write());
+    }
+
+    #[test]
+    /// Attachment accesses map to the matching attachment stages and layouts
+    fn test_attachment_mappings() {
+        let color = AccessType::ColorAttachmentWrite.get_info();
+        assert_eq!(color.stage_mask, vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT);
+        assert_eq!(color.access_mask, vk::AccessFlags2::COLOR_ATTACHMENT_WRITE);
+        assert_eq!(color.image_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
+        let depth = AccessType::DepthStencilAttachmentWrite.get_info();
+        assert!(depth
+            .stage_mask
+            .contains(vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS));
+        assert!(depth
+            .stage_mask
+            .contains(vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS));
+        assert_eq!(
+            depth.image_layout,
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        );
+    }
+
+    #[test]
+    /// Shader reads map to the stage of the shader reading
+    fn test_shader_read_mappings() {
+        let sampled = AccessType::FragmentShaderReadSampledImage.get_info();
+        assert_eq!(sampled.stage_mask, vk::PipelineStageFlags2::FRAGMENT_SHADER);
+        assert_eq!(sampled.access_mask, vk::AccessFlags2::SHADER_SAMPLED_READ);
+        assert_eq!(sampled.image_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+        let storage = AccessType::ComputeShaderReadOther.get_info();
+        assert_eq!(storage.stage_mask, vk::PipelineStageFlags2::COMPUTE_SHADER);
+        assert_eq!(storage.access_mask, vk::AccessFlags2::SHADER_STORAGE_READ);
+        assert_eq!(storage.image_layout, vk::ImageLayout::GENERAL);
+
+        let present = AccessType::Present.get_info();
+        assert_eq!(present.image_layout, vk::ImageLayout::PRESENT_SRC_KHR);
+    }
+}
@@ -0,0 +1,557 @@
+//! Builds synchronization2 barriers from [AccessType]s
+//!
+//! Every barrier lists the accesses which happened before it and the accesses which will happen
+//! after it. Stage masks, access masks and image layouts are derived from those, only making
+//! memory available when a previous access wrote to the resource.
+use super::access::AccessType;
+use crate::abstraction::prelude as abstraction;
+use ash::vk;
+use ash::vk::TaggedStructure;
+use std::ptr;
+
+/// Decides which layout an image is expected to be in for its accesses
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum ImageLayoutPolicy {
+    /// Use the optimal layout of the access
+    #[default]
+    Optimal,
+    /// Use [vk::ImageLayout::GENERAL] for everything except presenting and preinitialized images
+    General,
+}
+
+impl ImageLayoutPolicy {
+    /// Get the single layout every one of `accesses` can be performed in
+    pub fn get_layout(&self, accesses: &[AccessType]) -> vk::ImageLayout {
+        let mut layout = vk::ImageLayout::UNDEFINED;
+        for access in accesses {
+            let access_layout = match (self, access.get_info().image_layout) {
+                (_, vk::ImageLayout::UNDEFINED) => continue,
+                (ImageLayoutPolicy::General, access_layout)
+                    if access_layout != vk::ImageLayout::PRESENT_SRC_KHR
+                        && access_layout != vk::ImageLayout::PREINITIALIZED =>
+                {
+                    vk::ImageLayout::GENERAL
+                }
+                (_, access_layout) => access_layout,
+            };
+            debug_assert!(
+                layout == vk::ImageLayout::UNDEFINED || layout == access_layout,
+                "Accesses {:?} require different layouts, use ImageLayoutPolicy::General",
+                accesses
+            );
+            layout = access_layout;
+        }
+        layout
+    }
+}
+
+/// Stage and access masks of a barrier between two sets of accesses
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct BarrierMasks {
+    src_stage_mask: vk::PipelineStageFlags2,
+    src_access_mask: vk::AccessFlags2,
+    dst_stage_mask: vk::PipelineStageFlags2,
+    dst_access_mask: vk::AccessFlags2,
+}
+
+impl BarrierMasks {
+    fn new(prev_accesses: &[AccessType], next_accesses: &[AccessType], layout_transition: bool) -> Self {
+        let mut masks = Self {
+            src_stage_mask: vk::PipelineStageFlags2::NONE,
+            src_access_mask: vk::AccessFlags2::NONE,
+            dst_stage_mask: vk::PipelineStageFlags2::NONE,
+            dst_access_mask: vk::AccessFlags2::NONE,
+        };
+        for access in prev_accesses {
+            let info = access.get_info();
+            masks.src_stage_mask |= info.stage_mask;
+            // Only writes have to be made available
+            if access.is_write() {
+                masks.src_access_mask |= info.access_mask;
+            }
+        }
+        for access in next_accesses {
+            let info = access.get_info();
+            masks.dst_stage_mask |= info.stage_mask;
+            // Reads following reads or write-after-read hazards only need an execution dependency
+            if !masks.src_access_mask.is_empty() || layout_transition {
+                masks.dst_access_mask |= info.access_mask;
+            }
+        }
+        masks
+    }
+}
+
+impl BarrierMasks {
+    /// Only keep the source stages of `prev_accesses` which are in `supported_stages`
+    ///
+    /// Accesses made on another queue are left to the semaphore wait ordering them before this
+    /// queue, which the barrier chains with by waiting on all commands.
+    fn for_supported_stages(
+        mut self,
+        prev_accesses: &[AccessType],
+        supported_stages: vk::PipelineStageFlags2,
+    ) -> Self {
+        if supported_stages == vk::PipelineStageFlags2::ALL_COMMANDS
+            || supported_stages.contains(self.src_stage_mask)
+        {
+            return self;
+        }
+        self.src_stage_mask = vk::PipelineStageFlags2::NONE;
+        self.src_access_mask = vk::AccessFlags2::NONE;
+        for access in prev_accesses {
+            let info = access.get_info();
+            let stage_mask = if info.stage_mask == vk::PipelineStageFlags2::ALL_COMMANDS {
+                info.stage_mask
+            } else {
+                info.stage_mask & supported_stages
+            };
+            if stage_mask.is_empty() {
+                continue;
+            }
+            self.src_stage_mask |= stage_mask;
+            if access.is_write() {
+                self.src_access_mask |= info.access_mask;
+            }
+        }
+        if self.src_stage_mask.is_empty() {
+            self.src_stage_mask = vk::PipelineStageFlags2::ALL_COMMANDS;
+        }
+        self
+    }
+
+    /// Only keep the masks which apply to `half` of a queue family ownership transfer
+    fn for_queue_transfer_half(mut self, half: Option<QueueTransferHalf>) -> Self {
+        match half {
+            Some(QueueTransferHalf::Release) => {
+                self.dst_stage_mask = vk::PipelineStageFlags2::NONE;
+                self.dst_access_mask = vk::AccessFlags2::NONE;
+            }
+            Some(QueueTransferHalf::Acquire) => {
+                self.src_stage_mask = vk::PipelineStageFlags2::NONE;
+                self.src_access_mask = vk::AccessFlags2::NONE;
+            }
+            None => {}
+        }
+        self
+    }
+}
+
+/// Half of a queue family ownership transfer
+///
+/// A transfer is recorded twice. The release on the source queue waits on the previous
+/// accesses, the acquire on the destination queue blocks the next accesses, and both describe
+/// the same layout transition. Each half only carries the stages of its own queue.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum QueueTransferHalf {
+    Release,
+    Acquire,
+}
+
+/// A barrier affecting all memory
+#[derive(Copy, Clone, Debug, Default)]
+pub struct GlobalBarrier<'a> {
+    pub prev_accesses: &'a [AccessType],
+    pub next_accesses: &'a [AccessType],
+}
+
+impl<'a> GlobalBarrier<'a> {
+    pub fn new(prev_accesses: &'a [AccessType], next_accesses: &'a [AccessType]) -> Self {
+        Self {
+            prev_accesses,
+            next_accesses,
+        }
+    }
+
+    pub fn get_vk_barrier(&self) -> vk::MemoryBarrier2 {
+        let masks = BarrierMasks::new(self.prev_accesses, self.next_accesses, false);
+        vk::MemoryBarrier2 {
+            s_type: vk::MemoryBarrier2::STRUCTURE_TYPE,
+            p_next: ptr::null(),
+            src_stage_mask: masks.src_stage_mask,
+            src_access_mask: masks.src_access_mask,
+            dst_stage_mask: masks.dst_stage_mask,
+            dst_access_mask: masks.dst_access_mask,
+        }
+    }
+}
+
+/// A barrier affecting a range of a buffer
+///
+/// Queue family ownership transfers are recorded as a release on the source queue and an acquire
+/// on the destination queue, see [QueueTransferHalf].
+#[derive(Copy, Clone, Debug)]
+pub struct BufferBarrier<'a> {
+    pub prev_accesses: &'a [AccessType],
+    pub next_accesses: &'a [AccessType],
+    pub src_queue_family_index: u32,
+    pub dst_queue_family_index: u32,
+    /// Which half of an ownership transfer the barrier is, [None] if it is not a transfer
+    pub queue_transfer_half: Option<QueueTransferHalf>,
+    /// Stages of the queue the barrier is recorded on, previous accesses on other stages are
+    /// left out
+    pub supported_stages: vk::PipelineStageFlags2,
+    pub buffer: vk::Buffer,
+    pub offset: vk::DeviceSize,
+    pub size: vk::DeviceSize,
+}
+
+impl<'a> BufferBarrier<'a> {
+    /// Create a barrier covering the entire buffer
+    pub fn new(
+        buffer: vk::Buffer,
+        prev_accesses: &'a [AccessType],
+        next_accesses: &'a [AccessType],
+    ) -> Self {
+        Self {
+            prev_accesses,
+            next_accesses,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            queue_transfer_half: None,
+            supported_stages: vk::PipelineStageFlags2::ALL_COMMANDS,
+            buffer,
+            offset: 0,
+            size: vk::WHOLE_SIZE,
+        }
+    }
+
+    pub fn range(mut self, offset: vk::DeviceSize, size: vk::DeviceSize) -> Self {
+        self.offset = offset;
+        self.size = size;
+        self
+    }
+
+    /// Record the barrier as `half` of transferring ownership of the buffer between queue
+    /// families
+    pub fn queue_family_transfer(
+        mut self,
+        half: QueueTransferHalf,
+        src_queue_family_index: u32,
+        dst_queue_family_index: u32,
+    ) -> Self {
+        self.queue_transfer_half = Some(half);
+        self.src_queue_family_index = src_queue_family_index;
+        self.dst_queue_family_index = dst_queue_family_index;
+        self
+    }
+
+    /// Record the barrier on a queue only supporting `supported_stages`, such as a compute or
+    /// transfer queue using a resource shared with the graphics queue
+    pub fn supported_stages(mut self, supported_stages: vk::PipelineStageFlags2) -> Self {
+        self.supported_stages = supported_stages;
+        self
+    }
+
+    pub fn get_vk_barrier(&self) -> vk::BufferMemoryBarrier2 {
+        let masks = BarrierMasks::new(self.prev_accesses, self.next_accesses, false)
+            .for_supported_stages(self.prev_accesses, self.supported_stages)
+            .for_queue_transfer_half(self.queue_transfer_half);
+        vk::BufferMemoryBarrier2 {
+            s_type: vk::BufferMemoryBarrier2::STRUCTURE_TYPE,
+            p_next: ptr::null(),
+            src_stage_mask: masks.src_stage_mask,
+            src_access_mask: masks.src_access_mask,
+            dst_stage_mask: masks.dst_stage_mask,
+            dst_access_mask: masks.dst_access_mask,
+            src_queue_family_index: self.src_queue_family_index,
+            dst_queue_family_index: self.dst_queue_family_index,
+            buffer: self.buffer,
+            offset: self.offset,
+            size: self.size,
+        }
+    }
+}
+
+/// A barrier affecting a range of subresources of an image, transitioning its layout if needed
+///
+/// Queue family ownership transfers are recorded as a release on the source queue and an acquire
+/// on the destination queue, see [QueueTransferHalf].
+#[derive(Copy, Clone, Debug)]
+pub struct ImageBarrier<'a> {
+    pub prev_accesses: &'a [AccessType],
+    pub next_accesses: &'a [AccessType],
+    pub prev_layout: ImageLayoutPolicy,
+    pub next_layout: ImageLayoutPolicy,
+    /// Whether the previous contents of the image can be discarded, always transitioning from
+    /// [vk::ImageLayout::UNDEFINED]
+    pub discard_contents: bool,
+    pub src_queue_family_index: u32,
+    pub dst_queue_family_index: u32,
+    /// Which half of an ownership transfer the barrier is, [None] if it is not a transfer
+    pub queue_transfer_half: Option<QueueTransferHalf>,
+    /// Stages of the queue the barrier is recorded on, previous accesses on other stages are
+    /// left out
+    pub supported_stages: vk::PipelineStageFlags2,
+    pub image: vk::Image,
+    pub subresource_range: vk::ImageSubresourceRange,
+}
+
+impl<'a> ImageBarrier<'a> {
+    pub fn new(
+        image: vk::Image,
+        subresource_range: vk::ImageSubresourceRange,
+        prev_accesses: &'a [AccessType],
+        next_accesses: &'a [AccessType],
+    ) -> Self {
+        Self {
+            prev_accesses,
+            next_accesses,
+            prev_layout: ImageLayoutPolicy::Optimal,
+            next_layout: ImageLayoutPolicy::Optimal,
+            discard_contents: false,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            queue_transfer_half: None,
+            supported_stages: vk::PipelineStageFlags2::ALL_COMMANDS,
+            image,
+            subresource_range,
+        }
+    }
+
+    pub fn layout_policy(mut self, prev_layout: ImageLayoutPolicy, next_layout: ImageLayoutPolicy) -> Self {
+        self.prev_layout = prev_layout;
+        self.next_layout = next_layout;
+        self
+    }
+
+    pub fn discard_contents(mut self) -> Self {
+        self.discard_contents = true;
+        self
+    }
+
+    /// Record the barrier as `half` of transferring ownership of the image between queue
+    /// families
+    pub fn queue_family_transfer(
+        mut self,
+        half: QueueTransferHalf,
+        src_queue_family_index: u32,
+        dst_queue_family_index: u32,
+    ) -> Self {
+        self.queue_transfer_half = Some(half);
+        self.src_queue_family_index = src_queue_family_index;
+        self.dst_queue_family_index = dst_queue_family_index;
+        self
+    }
+
+    /// Record the barrier on a queue only supporting `supported_stages`, such as a compute or
+    /// transfer queue using a resource shared with the graphics queue
+    pub fn supported_stages(mut self, supported_stages: vk::PipelineStageFlags2) -> Self {
+        self.supported_stages = supported_stages;
+        self
+    }
+
+    pub fn get_vk_barrier(&self) -> vk::ImageMemoryBarrier2 {
+        let old_layout = if self.discard_contents {
+            vk::ImageLayout::UNDEFINED
+        } else {
+            self.prev_layout.get_layout(self.prev_accesses)
+        };
+        let new_layout = self.next_layout.get_layout(self.next_accesses);
+        let masks = BarrierMasks::new(
+            self.prev_accesses,
+            self.next_accesses,
+            old_layout != new_layout,
+        )
+        .for_supported_stages(self.prev_accesses, self.supported_stages)
+        .for_queue_transfer_half(self.queue_transfer_half);
+        vk::ImageMemoryBarrier2 {
+            s_type: vk::ImageMemoryBarrier2::STRUCTURE_TYPE,
+            p_next: ptr::null(),
+            src_stage_mask: masks.src_stage_mask,
+            src_access_mask: masks.src_access_mask,
+            dst_stage_mask: masks.dst_stage_mask,
+            dst_access_mask: masks.dst_access_mask,
+            old_layout,
+            new_layout,
+            src_queue_family_index: self.src_queue_family_index,
+            dst_queue_family_index: self.dst_queue_family_index,
+            image: self.image,
+            subresource_range: self.subresource_range,
+        }
+    }
+}
+
+/// Collect every barrier into a single [abstraction::BarrierBatch] which can be recorded using
+/// [abstraction::CommandBuffer::flush_barriers]
+pub fn get_dependency(
+    global_barrier: Option<GlobalBarrier>,
+    buffer_barriers: &[BufferBarrier],
+    image_barriers: &[ImageBarrier],
+) -> abstraction::BarrierBatch {
+    abstraction::BarrierBatch {
+        memory_barriers: global_barrier
+            .iter()
+            .map(|barrier| barrier.get_vk_barrier())
+            .collect(),
+        buffer_barriers: buffer_barriers
+            .iter()
+            .map(|barrier| barrier.get_vk_barrier())
+            .collect(),
+        image_barriers: image_barriers
+            .iter()
+            .map(|barrier| barrier.get_vk_barrier())
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color_range() -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        }
+    }
+
+    #[test]
+    /// Rendering to an image and sampling it afterwards
+    fn test_color_attachment_to_sampled() {
+        let barrier = ImageBarrier::new(
+            vk::Image::null(),
+            color_range(),
+            &[AccessType::ColorAttachmentWrite],
+            &[AccessType::FragmentShaderReadSampledImage],
+        )
+        .get_vk_barrier();
+        assert_eq!(barrier.src_stage_mask, vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT);
+        assert_eq!(barrier.src_access_mask, vk::AccessFlags2::COLOR_ATTACHMENT_WRITE);
+        assert_eq!(barrier.dst_stage_mask, vk::PipelineStageFlags2::FRAGMENT_SHADER);
+        assert_eq!(barrier.dst_access_mask, vk::AccessFlags2::SHADER_SAMPLED_READ);
+        assert_eq!(barrier.old_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        assert_eq!(barrier.new_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        assert_eq!(barrier.src_queue_family_index, vk::QUEUE_FAMILY_IGNORED);
+    }
+
+    #[test]
+    /// Write-after-read hazards only need an execution dependency
+    fn test_write_after_read() {
+        let barrier = BufferBarrier::new(
+            vk::Buffer::null(),
+            &[AccessType::FragmentShaderReadOther],
+            &[AccessType::ComputeShaderWrite],
+        )
+        .get_vk_barrier();
+        assert_eq!(barrier.src_stage_mask, vk::PipelineStageFlags2::FRAGMENT_SHADER);
+        assert_eq!(barrier.src_access_mask, vk::AccessFlags2::NONE);
+        assert_eq!(barrier.dst_stage_mask, vk::PipelineStageFlags2::COMPUTE_SHADER);
+        assert_eq!(barrier.dst_access_mask, vk::AccessFlags2::NONE);
+    }
+
+    #[test]
+    /// Discarding contents always transitions from an undefined layout
+    fn test_discard_contents() {
+        let barrier = ImageBarrier::new(
+            vk::Image::null(),
+            color_range(),
+            &[AccessType::FragmentShaderReadSampledImage],
+            &[AccessType::ColorAttachmentWrite],
+        )
+        .discard_contents()
+        .get_vk_barrier();
+        assert_eq!(barrier.old_layout, vk::ImageLayout::UNDEFINED);
+        assert_eq!(barrier.new_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        // The layout transition must be made visible to the attachment write
+        assert_eq!(barrier.dst_access_mask, vk::AccessFlags2::COLOR_ATTACHMENT_WRITE);
+    }
+
+    #[test]
+    /// The general layout policy keeps images in the general layout except for presentation
+    fn test_general_layout_policy() {
+        let barrier = ImageBarrier::new(
+            vk::Image::null(),
+            color_range(),
+            &[AccessType::ComputeShaderWrite],
+            &[AccessType::Present],
+        )
+        .layout_policy(ImageLayoutPolicy::General, ImageLayoutPolicy::General)
+        .get_vk_barrier();
+        assert_eq!(barrier.old_layout, vk::ImageLayout::GENERAL);
+        assert_eq!(barrier.new_layout, vk::ImageLayout::PRESENT_SRC_KHR);
+    }
+
+    #[test]
+    /// Both halves of an ownership transfer keep the queue families and layouts, but only the
+    /// stages of their own queue
+    fn test_queue_family_transfer() {
+        let barrier = ImageBarrier::new(
+            vk::Image::null(),
+            color_range(),
+            &[AccessType::ComputeShaderWrite],
+            &[AccessType::FragmentShaderReadSampledImage],
+        );
+        let release = barrier
+            .queue_family_transfer(QueueTransferHalf::Release, 1, 0)
+            .get_vk_barrier();
+        let acquire = barrier
+            .queue_family_transfer(QueueTransferHalf::Acquire, 1, 0)
+            .get_vk_barrier();
+        for half in [release, acquire] {
+            assert_eq!((half.src_queue_family_index, half.dst_queue_family_index), (1, 0));
+            assert_eq!(half.old_layout, vk::ImageLayout::GENERAL);
+            assert_eq!(half.new_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        }
+        assert_eq!(release.src_stage_mask, vk::PipelineStageFlags2::COMPUTE_SHADER);
+        assert_eq!(release.src_access_mask, vk::AccessFlags2::SHADER_STORAGE_WRITE);
+        assert_eq!(release.dst_stage_mask, vk::PipelineStageFlags2::NONE);
+        assert_eq!(release.dst_access_mask, vk::AccessFlags2::NONE);
+        assert_eq!(acquire.src_stage_mask, vk::PipelineStageFlags2::NONE);
+        assert_eq!(acquire.src_access_mask, vk::AccessFlags2::NONE);
+        assert_eq!(acquire.dst_stage_mask, vk::PipelineStageFlags2::FRAGMENT_SHADER);
+        assert_eq!(acquire.dst_access_mask, vk::AccessFlags2::SHADER_SAMPLED_READ);
+    }
+
+    #[test]
+    /// Barriers on compute queues leave out the graphics stages of accesses on other queues
+    fn test_supported_stages() {
+        let compute_stages =
+            vk::PipelineStageFlags2::COMPUTE_SHADER | vk::PipelineStageFlags2::ALL_TRANSFER;
+        let barrier = BufferBarrier::new(
+            vk::Buffer::null(),
+            &[AccessType::FragmentShaderWrite, AccessType::ComputeShaderWrite],
+            &[AccessType::ComputeShaderReadOther],
+        )
+        .supported_stages(compute_stages)
+        .get_vk_barrier();
+        assert_eq!(barrier.src_stage_mask, vk::PipelineStageFlags2::COMPUTE_SHADER);
+        assert_eq!(barrier.dst_stage_mask, vk::PipelineStageFlags2::COMPUTE_SHADER);
+
+        // Only accesses on other queues, left to the semaphore wait
+        let barrier = ImageBarrier::new(
+            vk::Image::null(),
+            color_range(),
+            &[AccessType::ColorAttachmentWrite],
+            &[AccessType::ComputeShaderReadSampledImage],
+        )
+        .supported_stages(compute_stages)
+        .get_vk_barrier();
+        assert_eq!(barrier.src_stage_mask, vk::PipelineStageFlags2::ALL_COMMANDS);
+        assert_eq!(barrier.src_access_mask, vk::AccessFlags2::NONE);
+        assert_eq!(barrier.old_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+    }
+
+    #[test]
+    /// Global barriers only make writes available
+    fn test_global_barrier() {
+        let dependency = get_dependency(
+            Some(GlobalBarrier::new(
+                &[AccessType::TransferWrite, AccessType::ComputeShaderReadOther],
+                &[AccessType::VertexBuffer],
+            )),
+            &[],
+            &[],
+        );
+        let barrier = dependency.memory_barriers[0];
+        assert_eq!(
+            barrier.src_stage_mask,
+            vk::PipelineStageFlags2::ALL_TRANSFER | vk::PipelineStageFlags2::COMPUTE_SHADER
+        );
+        assert_eq!(barrier.src_access_mask, vk::AccessFlags2::TRANSFER_WRITE);
+        assert_eq!(barrier.dst_access_mask, vk::AccessFlags2::VERTEX_ATTRIBUTE_READ);
+    }
+}
@@ -0,0 +1,8 @@
+//! Contains everything relating to synchronization
+pub mod access;
+pub mod barrier;
+pub mod semaphore;
+
+pub use access::*;
+pub use barrier::*;
+pub use semaphore::*;
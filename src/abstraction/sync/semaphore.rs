@@ -0,0 +1,126 @@
+use crate::abstraction::prelude as abstraction;
+use ash::vk;
+use ash::vk::TaggedStructure;
+
+/// An abstraction for [vk::Semaphore]
+///
+/// Semaphores are either binary or timeline semaphores, functions which only make sense for
+/// timeline semaphores will return [vk::Result::ERROR_FEATURE_NOT_PRESENT] on binary semaphores.
+pub struct Semaphore {
+    handle: vk::Semaphore,
+    device: abstraction::Device,
+    semaphore_type: vk::SemaphoreType,
+}
+
+impl Semaphore {
+    /// Create a new binary semaphore
+    pub fn new(device: abstraction::Device) -> Result<Self, vk::Result> {
+        let semaphore_ci = vk::SemaphoreCreateInfo {
+            s_type: vk::SemaphoreCreateInfo::STRUCTURE_TYPE,
+            ..Default::default()
+        };
+        let handle = unsafe { device.handle_as_ref().create_semaphore(&semaphore_ci, None)? };
+        Ok(Self {
+            handle,
+            device,
+            semaphore_type: vk::SemaphoreType::BINARY,
+        })
+    }
+
+    /// Create a new timeline semaphore starting at `initial_value`
+    pub fn new_timeline(device: abstraction::Device, initial_value: u64) -> Result<Self, vk::Result> {
+        let semaphore_type_ci = vk::SemaphoreTypeCreateInfo {
+            s_type: vk::SemaphoreTypeCreateInfo::STRUCTURE_TYPE,
+            semaphore_type: vk::SemaphoreType::TIMELINE,
+            initial_value,
+            ..Default::default()
+        };
+        let semaphore_ci = vk::SemaphoreCreateInfo {
+            s_type: vk::SemaphoreCreateInfo::STRUCTURE_TYPE,
+            p_next: abstraction::utility::p_next(&semaphore_type_ci),
+            ..Default::default()
+        };
+        let handle = unsafe { device.handle_as_ref().create_semaphore(&semaphore_ci, None)? };
+        Ok(Self {
+            handle,
+            device,
+            semaphore_type: vk::SemaphoreType::TIMELINE,
+        })
+    }
+
+    pub fn is_timeline(&self) -> bool {
+        self.semaphore_type == vk::SemaphoreType::TIMELINE
+    }
+
+    /// Get the current counter value of a timeline semaphore
+    pub fn get_value(&self) -> Result<u64, vk::Result> {
+        if !self.is_timeline() {
+            return Err(vk::Result::ERROR_FEATURE_NOT_PRESENT);
+        }
+        unsafe { self.device.handle_as_ref().get_semaphore_counter_value(self.handle) }
+    }
+
+    /// Block on the CPU until a timeline semaphore reaches `value` or `timeout` nanoseconds
+    /// have passed
+    pub fn wait(&self, value: u64, timeout: u64) -> Result<(), vk::Result> {
+        if !self.is_timeline() {
+            return Err(vk::Result::ERROR_FEATURE_NOT_PRESENT);
+        }
+        let semaphore_wi = vk::SemaphoreWaitInfo {
+            s_type: vk::SemaphoreWaitInfo::STRUCTURE_TYPE,
+            semaphore_count: 1,
+            p_semaphores: &self.handle,
+            p_values: &value,
+            ..Default::default()
+        };
+        unsafe { self.device.handle_as_ref().wait_semaphores(&semaphore_wi, timeout) }
+    }
+
+    /// Signal a timeline semaphore to `value` from the CPU
+    pub fn signal(&self, value: u64) -> Result<(), vk::Result> {
+        if !self.is_timeline() {
+            return Err(vk::Result::ERROR_FEATURE_NOT_PRESENT);
+        }
+        let semaphore_si = vk::SemaphoreSignalInfo {
+            s_type: vk::SemaphoreSignalInfo::STRUCTURE_TYPE,
+            semaphore: self.handle,
+            value,
+            ..Default::default()
+        };
+        unsafe { self.device.handle_as_ref().signal_semaphore(&semaphore_si) }
+    }
+
+    /// Get a [vk::SemaphoreSubmitInfo] to wait on or signal the semaphore in a queue submission
+    ///
+    /// `value` is ignored for binary semaphores.
+    pub fn submit_info(
+        &self,
+        value: u64,
+        stage_mask: vk::PipelineStageFlags2,
+    ) -> vk::SemaphoreSubmitInfo {
+        vk::SemaphoreSubmitInfo {
+            s_type: vk::SemaphoreSubmitInfo::STRUCTURE_TYPE,
+            semaphore: self.handle,
+            value,
+            stage_mask,
+            device_index: 0,
+            ..Default::default()
+        }
+    }
+
+    pub fn get_handle(&self) -> vk::Semaphore {
+        self.handle
+    }
+
+    pub fn get_device(&self) -> abstraction::Device {
+        self.device.clone()
+    }
+}
+
+impl Drop for Semaphore {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.handle_as_ref().destroy_semaphore(self.handle, None);
+        }
+    }
+}
@@ -8,10 +8,13 @@
 //! from the CPU.
 //! This pattern is implemented by simply creating a private facing "Inner" struct as dropping
 //! the public facing struct will make the Inner inaccessible.
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 
 struct DeferredDeletionItem<T> {
     item: T,
+    /// Ran right before the item is dropped
+    deletion_function: Option<Box<dyn FnOnce() + Send>>,
     /// Cycle # which the deferred item is deleted.
     end_cycle: u64,
 }
@@ -19,7 +22,8 @@ struct DeferredDeletionItem<T> {
 /// Any struct that implements [DeferredDeletable] can be pushed into a deferred deletion
 /// queue.
 pub trait DeferredDeletable {
-    /// Maximum number of cycles that a struct should last for it not interacted with
+    /// Number of cycles an item lasts for once queued, unless the queue is given its own
+    /// lifetime through [DeferredDeletionQueue::set_max_cycles]
     const MAX_CYCLES: u64;
 
     /// Keep the struct alive
@@ -27,45 +31,169 @@ pub trait DeferredDeletable {
 }
 
 /// This is a deferred deletion queue
+///
+/// Clones share the same queue, which allows resources to hold on to a queue and enqueue
+/// themselves once dropped. Nothing is deleted unless the owner of the queue calls
+/// [Self::step] and [Self::delete_expired_items] once per frame, which
+/// [crate::framework::frame_manager::FrameManager::deletion_queue] does after waiting for the
+/// oldest frame in flight.
 pub struct DeferredDeletionQueue<T: DeferredDeletable> {
     /// Represents the # of cycles that has passed
-    cycle: u64,
+    cycle: Arc<AtomicU64>,
+    /// Number of cycles items last for once queued
+    max_cycles: Arc<AtomicU64>,
     /// Represents the items in queue
     items: Arc<Mutex<Vec<DeferredDeletionItem<T>>>>,
 }
 
+impl<T: DeferredDeletable> Clone for DeferredDeletionQueue<T> {
+    fn clone(&self) -> Self {
+        Self {
+            cycle: self.cycle.clone(),
+            max_cycles: self.max_cycles.clone(),
+            items: self.items.clone(),
+        }
+    }
+}
+
+impl<T: DeferredDeletable> Default for DeferredDeletionQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T: DeferredDeletable> DeferredDeletionQueue<T> {
     pub fn new() -> Self {
         Self {
-            cycle: 0,
+            cycle: Arc::new(AtomicU64::new(0)),
+            max_cycles: Arc::new(AtomicU64::new(T::MAX_CYCLES)),
             items: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
-    /// Queues item for deletion
-    pub fn enqueue_deletion(&mut self, resource: T, deletion_function: Option<Box<dyn FnOnce()>>) {
+    /// Set the number of cycles items last for once queued, such as the number of frames in
+    /// flight when a cycle is a frame
+    ///
+    /// Items which are already queued keep their lifetime.
+    pub fn set_max_cycles(&self, max_cycles: u64) {
+        self.max_cycles.store(max_cycles, Ordering::Release);
+    }
+
+    pub fn get_max_cycles(&self) -> u64 {
+        self.max_cycles.load(Ordering::Acquire)
+    }
+
+    /// Queues item for deletion, it is deleted once [Self::get_max_cycles] cycles have passed
+    pub fn enqueue_deletion(
+        &self,
+        resource: T,
+        deletion_function: Option<Box<dyn FnOnce() + Send>>,
+    ) {
         self.items.lock().unwrap().push(DeferredDeletionItem {
             item: resource,
-            end_cycle: T::MAX_CYCLES,
+            deletion_function,
+            end_cycle: self.cycle.load(Ordering::Acquire) + self.get_max_cycles(),
         });
     }
 
     /// Scans the entire queue to finds items in queue that have reached their
     /// end cycle
-    pub fn delete_expired_items(&mut self) {
-        self.items.lock().unwrap().retain(|item| {
-            if item.end_cycle >= self.cycle {
-                // Expired, run delete function and drop the item from queue
-                false
-            } else {
-                // Keep all items in queue that have not expired yet
-                true
+    pub fn delete_expired_items(&self) {
+        let cycle = self.cycle.load(Ordering::Acquire);
+        let expired: Vec<DeferredDeletionItem<T>> = {
+            let mut items = self.items.lock().unwrap();
+            let (expired, alive) = std::mem::take(&mut *items)
+                .into_iter()
+                .partition(|item| item.end_cycle <= cycle);
+            *items = alive;
+            expired
+        };
+        // Items are deleted outside of the lock as dropping them may enqueue more deletions
+        for item in expired {
+            if let Some(deletion_function) = item.deletion_function {
+                deletion_function();
             }
-        })
+            drop(item.item);
+        }
     }
 
     /// Step forward by one cycle
-    pub fn step(&mut self) {
-        self.cycle += 1;
+    pub fn step(&self) {
+        self.cycle.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Number of items waiting to be deleted
+    pub fn len(&self) -> usize {
+        self.items.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    struct Counted(Arc<AtomicUsize>);
+
+    impl DeferredDeletable for Counted {
+        const MAX_CYCLES: u64 = 2;
+
+        fn wake() {}
+    }
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    /// Items are only dropped once [DeferredDeletable::MAX_CYCLES] cycles have passed
+    fn test_deletion_after_max_cycles() {
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let ran = Arc::new(AtomicUsize::new(0));
+        let queue: DeferredDeletionQueue<Counted> = DeferredDeletionQueue::new();
+        let ran_clone = ran.clone();
+        queue.clone().enqueue_deletion(
+            Counted(dropped.clone()),
+            Some(Box::new(move || {
+                ran_clone.fetch_add(1, Ordering::Relaxed);
+            })),
+        );
+        queue.step();
+        queue.enqueue_deletion(Counted(dropped.clone()), None);
+        queue.delete_expired_items();
+        assert_eq!(dropped.load(Ordering::Relaxed), 0);
+        queue.step();
+        queue.delete_expired_items();
+        assert_eq!(dropped.load(Ordering::Relaxed), 1);
+        assert_eq!(ran.load(Ordering::Relaxed), 1);
+        assert_eq!(queue.len(), 1);
+        queue.step();
+        queue.delete_expired_items();
+        assert_eq!(dropped.load(Ordering::Relaxed), 2);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    /// A lifetime set on the queue replaces [DeferredDeletable::MAX_CYCLES] for new items
+    fn test_set_max_cycles() {
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let queue: DeferredDeletionQueue<Counted> = DeferredDeletionQueue::new();
+        queue.clone().set_max_cycles(4);
+        assert_eq!(queue.get_max_cycles(), 4);
+        queue.enqueue_deletion(Counted(dropped.clone()), None);
+        for _ in 0..3 {
+            queue.step();
+            queue.delete_expired_items();
+        }
+        assert_eq!(dropped.load(Ordering::Relaxed), 0);
+        queue.step();
+        queue.delete_expired_items();
+        assert_eq!(dropped.load(Ordering::Relaxed), 1);
     }
 }
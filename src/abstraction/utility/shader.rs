@@ -1 +1,549 @@
+//! SPIR-V shader modules
+//!
+//! Code is validated before it is handed to the driver: the header has to carry the SPIR-V
+//! magic number and a version the device supports. Entry points along with their stages are
+//! read from the code, so pipelines can be built from an entry point name alone.
+use crate::abstraction::prelude as abstraction;
+use ash::vk;
+use ash::vk::TaggedStructure;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
 
+/// First word of every SPIR-V module
+pub const SPIRV_MAGIC: u32 = 0x0723_0203;
+/// Number of words in the header of a SPIR-V module
+const HEADER_WORDS: usize = 5;
+const OP_ENTRY_POINT: u32 = 15;
+const OP_EXECUTION_MODE: u32 = 16;
+const OP_CONSTANT: u32 = 43;
+const OP_CONSTANT_COMPOSITE: u32 = 44;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_SPEC_CONSTANT_COMPOSITE: u32 = 51;
+const OP_DECORATE: u32 = 71;
+const OP_EXECUTION_MODE_ID: u32 = 331;
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+const EXECUTION_MODE_LOCAL_SIZE_ID: u32 = 38;
+const DECORATION_SPEC_ID: u32 = 1;
+const DECORATION_BUILT_IN: u32 = 11;
+const BUILT_IN_WORKGROUP_SIZE: u32 = 25;
+
+#[derive(Debug)]
+pub enum ShaderError {
+    /// The shader file could not be read
+    Io(std::io::Error),
+    /// Code is not a whole number of 32-bit words, or shorter than the header
+    InvalidSize(usize),
+    /// The first word is not [SPIRV_MAGIC]
+    InvalidMagic(u32),
+    /// An instruction runs past the end of the code or has a word count of zero
+    Malformed { offset: usize },
+    /// The SPIR-V version of the code is newer than the device supports
+    UnsupportedVersion {
+        version: (u32, u32),
+        max_version: (u32, u32),
+    },
+    Vulkan(vk::Result),
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Io(error) => write!(f, "failed to read shader: {}", error),
+            ShaderError::InvalidSize(size) => {
+                write!(f, "{} bytes is not a valid size for SPIR-V code", size)
+            }
+            ShaderError::InvalidMagic(magic) => {
+                write!(f, "{:#010x} is not the SPIR-V magic number", magic)
+            }
+            ShaderError::Malformed { offset } => {
+                write!(f, "malformed instruction at word {}", offset)
+            }
+            ShaderError::UnsupportedVersion {
+                version,
+                max_version,
+            } => write!(
+                f,
+                "SPIR-V {}.{} is newer than the supported SPIR-V {}.{}",
+                version.0, version.1, max_version.0, max_version.1
+            ),
+            ShaderError::Vulkan(result) => write!(f, "{}", result),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+impl From<std::io::Error> for ShaderError {
+    fn from(error: std::io::Error) -> Self {
+        ShaderError::Io(error)
+    }
+}
+
+impl From<vk::Result> for ShaderError {
+    fn from(result: vk::Result) -> Self {
+        ShaderError::Vulkan(result)
+    }
+}
+
+/// An entry point declared by a [ShaderModule]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ShaderEntryPoint {
+    pub name: String,
+    pub stage: vk::ShaderStageFlags,
+    /// Workgroup size declared through the `LocalSize` or `LocalSizeId` execution mode, or
+    /// the `WorkgroupSize` built-in, which takes precedence over both
+    ///
+    /// Components given by specialization constants hold their default value.
+    pub local_size: Option<[u32; 3]>,
+    /// Specialization constant id of every component of `local_size` given by one
+    pub local_size_constant_ids: [Option<u32>; 3],
+}
+
+/// Convert raw bytes into SPIR-V words
+///
+/// Bytes are copied, so they do not have to be aligned to 4 bytes. Code stored with the
+/// opposite endianness is detected through the magic number and swapped.
+pub fn spirv_from_bytes(bytes: &[u8]) -> Result<Vec<u32>, ShaderError> {
+    if !bytes.len().is_multiple_of(4) || bytes.len() < HEADER_WORDS * 4 {
+        return Err(ShaderError::InvalidSize(bytes.len()));
+    }
+    let mut code: Vec<u32> = bytes
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect();
+    if code[0] == SPIRV_MAGIC.swap_bytes() {
+        for word in code.iter_mut() {
+            *word = word.swap_bytes();
+        }
+    }
+    if code[0] != SPIRV_MAGIC {
+        return Err(ShaderError::InvalidMagic(code[0]));
+    }
+    Ok(code)
+}
+
+/// Get the major and minor SPIR-V version of `code`
+pub fn get_spirv_version(code: &[u32]) -> Result<(u32, u32), ShaderError> {
+    if code.len() < HEADER_WORDS {
+        return Err(ShaderError::InvalidSize(code.len() * 4));
+    }
+    if code[0] != SPIRV_MAGIC {
+        return Err(ShaderError::InvalidMagic(code[0]));
+    }
+    Ok(((code[1] >> 16) & 0xff, (code[1] >> 8) & 0xff))
+}
+
+/// Get the newest SPIR-V version a device supporting Vulkan `api_version` can consume
+pub fn get_max_spirv_version(api_version: u32) -> (u32, u32) {
+    match (
+        vk::api_version_major(api_version),
+        vk::api_version_minor(api_version),
+    ) {
+        (1, 0) => (1, 0),
+        (1, 1) => (1, 3),
+        (1, 2) => (1, 5),
+        _ => (1, 6),
+    }
+}
+
+/// Get the stage of a SPIR-V execution model
+fn get_stage(execution_model: u32) -> Option<vk::ShaderStageFlags> {
+    Some(match execution_model {
+        0 => vk::ShaderStageFlags::VERTEX,
+        1 => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+        2 => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+        3 => vk::ShaderStageFlags::GEOMETRY,
+        4 => vk::ShaderStageFlags::FRAGMENT,
+        5 => vk::ShaderStageFlags::COMPUTE,
+        5267 => vk::ShaderStageFlags::TASK_NV,
+        5268 => vk::ShaderStageFlags::MESH_NV,
+        5313 => vk::ShaderStageFlags::RAYGEN_KHR,
+        5314 => vk::ShaderStageFlags::INTERSECTION_KHR,
+        5315 => vk::ShaderStageFlags::ANY_HIT_KHR,
+        5316 => vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+        5317 => vk::ShaderStageFlags::MISS_KHR,
+        5318 => vk::ShaderStageFlags::CALLABLE_KHR,
+        5364 => vk::ShaderStageFlags::TASK_EXT,
+        5365 => vk::ShaderStageFlags::MESH_EXT,
+        _ => return None,
+    })
+}
+
+/// Decode a nul terminated SPIR-V literal string
+pub(crate) fn get_literal_string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .take_while(|byte| *byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Split `code` into the opcode and operands of every instruction after the header
+pub(crate) fn get_instructions(code: &[u32]) -> Result<Vec<(u32, &[u32])>, ShaderError> {
+    let mut instructions = Vec::new();
+    let mut offset = HEADER_WORDS;
+    while offset < code.len() {
+        let word_count = (code[offset] >> 16) as usize;
+        if word_count == 0 || offset + word_count > code.len() {
+            return Err(ShaderError::Malformed { offset });
+        }
+        instructions.push((code[offset] & 0xffff, &code[offset + 1..offset + word_count]));
+        offset += word_count;
+    }
+    Ok(instructions)
+}
+
+/// Get every entry point declared in `code`
+pub fn get_entry_points(code: &[u32]) -> Result<Vec<ShaderEntryPoint>, ShaderError> {
+    get_spirv_version(code)?;
+    let instructions = get_instructions(code)?;
+    // Entry points are referred to by the id of their function
+    let mut entry_points: Vec<(u32, ShaderEntryPoint)> = Vec::new();
+    // Ids of the `LocalSizeId` components of every function, resolved once constants are known
+    let mut local_size_ids: Vec<(u32, [u32; 3])> = Vec::new();
+    let mut constants: HashMap<u32, u32> = HashMap::new();
+    let mut composites: HashMap<u32, [u32; 3]> = HashMap::new();
+    let mut spec_ids: HashMap<u32, u32> = HashMap::new();
+    let mut workgroup_size = None;
+    for (opcode, operands) in instructions.iter() {
+        match *opcode {
+            OP_ENTRY_POINT if operands.len() >= 3 => {
+                if let Some(stage) = get_stage(operands[0]) {
+                    entry_points.push((
+                        operands[1],
+                        ShaderEntryPoint {
+                            name: get_literal_string(&operands[2..]),
+                            stage,
+                            local_size: None,
+                            local_size_constant_ids: [None; 3],
+                        },
+                    ));
+                }
+            }
+            OP_EXECUTION_MODE
+                if operands.len() >= 5 && operands[1] == EXECUTION_MODE_LOCAL_SIZE =>
+            {
+                for (id, entry_point) in entry_points.iter_mut() {
+                    if *id == operands[0] {
+                        entry_point.local_size = Some([operands[2], operands[3], operands[4]]);
+                    }
+                }
+            }
+            OP_EXECUTION_MODE_ID
+                if operands.len() >= 5 && operands[1] == EXECUTION_MODE_LOCAL_SIZE_ID =>
+            {
+                local_size_ids.push((operands[0], [operands[2], operands[3], operands[4]]));
+            }
+            OP_CONSTANT | OP_SPEC_CONSTANT if operands.len() >= 3 => {
+                constants.insert(operands[1], operands[2]);
+            }
+            OP_CONSTANT_COMPOSITE | OP_SPEC_CONSTANT_COMPOSITE if operands.len() == 5 => {
+                composites.insert(operands[1], [operands[2], operands[3], operands[4]]);
+            }
+            OP_DECORATE if operands.len() >= 3 && operands[1] == DECORATION_SPEC_ID => {
+                spec_ids.insert(operands[0], operands[2]);
+            }
+            OP_DECORATE
+                if operands.len() >= 3
+                    && operands[1] == DECORATION_BUILT_IN
+                    && operands[2] == BUILT_IN_WORKGROUP_SIZE =>
+            {
+                workgroup_size = Some(operands[0]);
+            }
+            _ => {}
+        }
+    }
+    // Resolve the default value and specialization constant id of every component
+    let resolve = |ids: [u32; 3]| -> Option<([u32; 3], [Option<u32>; 3])> {
+        let mut local_size = [0; 3];
+        for (size, id) in local_size.iter_mut().zip(ids) {
+            *size = *constants.get(&id)?;
+        }
+        Some((local_size, ids.map(|id| spec_ids.get(&id).copied())))
+    };
+    for (function, ids) in local_size_ids {
+        if let Some((local_size, constant_ids)) = resolve(ids) {
+            for (id, entry_point) in entry_points.iter_mut() {
+                if *id == function {
+                    entry_point.local_size = Some(local_size);
+                    entry_point.local_size_constant_ids = constant_ids;
+                }
+            }
+        }
+    }
+    // The built-in applies to every entry point of the module which has a workgroup
+    let workgroup_stages = vk::ShaderStageFlags::COMPUTE
+        | vk::ShaderStageFlags::TASK_EXT
+        | vk::ShaderStageFlags::MESH_EXT
+        | vk::ShaderStageFlags::TASK_NV
+        | vk::ShaderStageFlags::MESH_NV;
+    let workgroup_size = workgroup_size
+        .and_then(|id| composites.get(&id))
+        .and_then(|ids| resolve(*ids));
+    if let Some((local_size, constant_ids)) = workgroup_size {
+        for (_, entry_point) in entry_points.iter_mut() {
+            if workgroup_stages.contains(entry_point.stage) {
+                entry_point.local_size = Some(local_size);
+                entry_point.local_size_constant_ids = constant_ids;
+            }
+        }
+    }
+    Ok(entry_points
+        .into_iter()
+        .map(|(_, entry_point)| entry_point)
+        .collect())
+}
+
+/// An abstraction for [vk::ShaderModule]
+pub struct ShaderModule {
+    handle: vk::ShaderModule,
+    device: abstraction::Device,
+    code: Vec<u32>,
+    /// Hash of `code`, identifies the module even once its handle is destroyed and reused
+    code_hash: u64,
+    entry_points: Vec<ShaderEntryPoint>,
+}
+
+impl ShaderModule {
+    /// Create a module from SPIR-V words
+    pub fn from_spirv(device: abstraction::Device, code: &[u32]) -> Result<Self, ShaderError> {
+        let version = get_spirv_version(code)?;
+        let max_version = get_max_spirv_version(device.get_api_version());
+        if version > max_version {
+            return Err(ShaderError::UnsupportedVersion {
+                version,
+                max_version,
+            });
+        }
+        let entry_points = get_entry_points(code)?;
+        let module_ci = vk::ShaderModuleCreateInfo {
+            s_type: vk::ShaderModuleCreateInfo::STRUCTURE_TYPE,
+            code_size: std::mem::size_of_val(code),
+            p_code: code.as_ptr(),
+            ..Default::default()
+        };
+        let handle = unsafe { device.handle_as_ref().create_shader_module(&module_ci, None)? };
+        let mut hasher = DefaultHasher::new();
+        code.hash(&mut hasher);
+        Ok(Self {
+            handle,
+            device,
+            code: code.to_vec(),
+            code_hash: hasher.finish(),
+            entry_points,
+        })
+    }
+
+    /// Create a module from SPIR-V bytes, such as the output of `include_bytes!`
+    ///
+    /// See [spirv_from_bytes], `bytes` do not have to be aligned.
+    pub fn from_bytes(device: abstraction::Device, bytes: &[u8]) -> Result<Self, ShaderError> {
+        Self::from_spirv(device, &spirv_from_bytes(bytes)?)
+    }
+
+    /// Create a module from a SPIR-V file
+    pub fn from_file<P: AsRef<Path>>(device: abstraction::Device, path: P) -> Result<Self, ShaderError> {
+        Self::from_bytes(device, &std::fs::read(path)?)
+    }
+
+    pub fn get_handle(&self) -> vk::ShaderModule {
+        self.handle
+    }
+
+    pub fn get_code(&self) -> &[u32] {
+        &self.code
+    }
+
+    pub fn get_code_hash(&self) -> u64 {
+        self.code_hash
+    }
+
+    pub fn get_entry_points(&self) -> &[ShaderEntryPoint] {
+        &self.entry_points
+    }
+
+    pub fn get_entry_point(&self, name: &str) -> Option<&ShaderEntryPoint> {
+        self.entry_points
+            .iter()
+            .find(|entry_point| entry_point.name == name)
+    }
+
+    pub fn get_device(&self) -> abstraction::Device {
+        self.device.clone()
+    }
+}
+
+impl Drop for ShaderModule {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .handle_as_ref()
+                .destroy_shader_module(self.handle, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A compute entry point with a local size and a fragment entry point with an interface
+    fn test_code() -> Vec<u32> {
+        vec![
+            SPIRV_MAGIC,
+            0x0001_0300,
+            0,
+            10,
+            0,
+            // OpCapability Shader
+            (2 << 16) | 17,
+            1,
+            // OpEntryPoint GLCompute %1 "main"
+            (5 << 16) | OP_ENTRY_POINT,
+            5,
+            1,
+            u32::from_le_bytes(*b"main"),
+            0,
+            // OpEntryPoint Fragment %2 "frag" %7
+            (6 << 16) | OP_ENTRY_POINT,
+            4,
+            2,
+            u32::from_le_bytes(*b"frag"),
+            0,
+            7,
+            // OpExecutionMode %1 LocalSize 8 4 1
+            (6 << 16) | OP_EXECUTION_MODE,
+            1,
+            EXECUTION_MODE_LOCAL_SIZE,
+            8,
+            4,
+            1,
+        ]
+    }
+
+    #[test]
+    /// Entry points are read along with their stage and local size
+    fn test_entry_points() {
+        let code = test_code();
+        assert_eq!(get_spirv_version(&code).unwrap(), (1, 3));
+        let entry_points = get_entry_points(&code).unwrap();
+        assert_eq!(
+            entry_points,
+            vec![
+                ShaderEntryPoint {
+                    name: "main".to_string(),
+                    stage: vk::ShaderStageFlags::COMPUTE,
+                    local_size: Some([8, 4, 1]),
+                    local_size_constant_ids: [None; 3],
+                },
+                ShaderEntryPoint {
+                    name: "frag".to_string(),
+                    stage: vk::ShaderStageFlags::FRAGMENT,
+                    local_size: None,
+                    local_size_constant_ids: [None; 3],
+                },
+            ]
+        );
+
+        let mut truncated = code.clone();
+        truncated.pop();
+        assert!(matches!(
+            get_entry_points(&truncated),
+            Err(ShaderError::Malformed { offset: 18 })
+        ));
+        assert_eq!(get_max_spirv_version(vk::API_VERSION_1_1), (1, 3));
+    }
+
+    #[test]
+    /// Misaligned and byte swapped code is loaded, invalid code is rejected
+    fn test_spirv_from_bytes() {
+        let code = test_code();
+        let bytes: Vec<u8> = code.iter().flat_map(|word| word.to_le_bytes()).collect();
+        // Offset by one byte to misalign the slice
+        let mut misaligned = vec![0u8];
+        misaligned.extend_from_slice(&bytes);
+        assert_eq!(spirv_from_bytes(&misaligned[1..]).unwrap(), code);
+
+        let swapped: Vec<u8> = code.iter().flat_map(|word| word.to_be_bytes()).collect();
+        assert_eq!(spirv_from_bytes(&swapped).unwrap(), code);
+
+        assert!(matches!(
+            spirv_from_bytes(&bytes[..bytes.len() - 2]),
+            Err(ShaderError::InvalidSize(_))
+        ));
+        assert!(matches!(
+            spirv_from_bytes(&[0u8; 20]),
+            Err(ShaderError::InvalidMagic(0))
+        ));
+    }
+
+    #[test]
+    /// `LocalSizeId` and the `WorkgroupSize` built-in resolve their constants, keeping the id of
+    /// specialization constants
+    fn test_local_size_constants() {
+        let header = [SPIRV_MAGIC, 0x0001_0600, 0, 20, 0];
+        let declarations = [
+            // OpEntryPoint GLCompute %1 "main"
+            (5 << 16) | OP_ENTRY_POINT,
+            5,
+            1,
+            u32::from_le_bytes(*b"main"),
+            0,
+            // OpExecutionModeId %1 LocalSizeId %10 %11 %12
+            (6 << 16) | OP_EXECUTION_MODE_ID,
+            1,
+            EXECUTION_MODE_LOCAL_SIZE_ID,
+            10,
+            11,
+            12,
+            // OpDecorate %10 SpecId 3
+            (4 << 16) | OP_DECORATE,
+            10,
+            DECORATION_SPEC_ID,
+            3,
+            // %10 = OpSpecConstant %2 32
+            (4 << 16) | OP_SPEC_CONSTANT,
+            2,
+            10,
+            32,
+            // %11 = OpConstant %2 2
+            (4 << 16) | OP_CONSTANT,
+            2,
+            11,
+            2,
+            // %12 = OpConstant %2 1
+            (4 << 16) | OP_CONSTANT,
+            2,
+            12,
+            1,
+        ];
+        let code: Vec<u32> = header.iter().chain(declarations.iter()).copied().collect();
+        let entry_points = get_entry_points(&code).unwrap();
+        assert_eq!(entry_points[0].local_size, Some([32, 2, 1]));
+        assert_eq!(entry_points[0].local_size_constant_ids, [Some(3), None, None]);
+
+        // The built-in overrides the execution mode
+        let built_in = [
+            // OpDecorate %13 BuiltIn WorkgroupSize
+            (4 << 16) | OP_DECORATE,
+            13,
+            DECORATION_BUILT_IN,
+            BUILT_IN_WORKGROUP_SIZE,
+            // %13 = OpSpecConstantComposite %3 %11 %10 %12
+            (6 << 16) | OP_SPEC_CONSTANT_COMPOSITE,
+            3,
+            13,
+            11,
+            10,
+            12,
+        ];
+        let code: Vec<u32> = code.iter().chain(built_in.iter()).copied().collect();
+        let entry_points = get_entry_points(&code).unwrap();
+        assert_eq!(entry_points[0].local_size, Some([2, 32, 1]));
+        assert_eq!(entry_points[0].local_size_constant_ids, [None, Some(3), None]);
+    }
+}
@@ -4,6 +4,7 @@
 use std::marker::PhantomData;
 
 /// An opaque [Handle<T>] representing a resource state that MIGHT be backed in [Storage<T>]
+#[derive(Debug)]
 pub struct Handle<T> {
     /// Unique identifier of the [Handle]
     /// Indicates it index into the `resource` member of [Storage]
@@ -15,6 +16,41 @@ pub struct Handle<T> {
     phantom_marker: PhantomData<T>,
 }
 
+impl<T> Handle<T> {
+    /// Index of the resource in [Storage]
+    pub fn get_identifier(&self) -> u64 {
+        self.identifier
+    }
+
+    pub fn get_revision(&self) -> u64 {
+        self.revision
+    }
+}
+
+// Derives would require `T` to implement the traits as well
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.identifier == other.identifier && self.revision == other.revision
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> std::hash::Hash for Handle<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.identifier.hash(state);
+        self.revision.hash(state);
+    }
+}
+
 /// Manages [Handle<T>] and backs their items they MIGHT represent in storage
 pub struct Storage<T> {
     /// Where all resources are stored in
@@ -25,15 +61,28 @@ pub struct Storage<T> {
     phantom_marker: PhantomData<T>,
 }
 
+impl<T> Default for Storage<T> {
+    fn default() -> Self {
+        Self {
+            storage: Vec::new(),
+            revisions: Vec::new(),
+            phantom_marker: Default::default(),
+        }
+    }
+}
+
 impl<T> Storage<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
     /// Internal use only and is used to update the revision of [Handle] in [Storage]
     fn update_revision(&mut self, index: usize) -> u64 {
-        let mut revision = self.revisions.get(index);
-        if let Some(revision) = revision {
-            self.revisions.insert(index, revision + 1);
-            *self.revisions.get(index).unwrap()
+        if let Some(revision) = self.revisions.get_mut(index) {
+            *revision += 1;
+            *revision
         } else {
-            self.revisions.insert(index, 0);
+            self.revisions.push(0);
             0
         }
     }
@@ -69,9 +118,15 @@ impl<T> Storage<T> {
     }
 
     /// Override a resource and get a new [Handle] representing the new resource
+    ///
+    /// `location` may at most be one past the last resource in [Storage].
     pub fn insert(&mut self, location: usize, resource: T) -> Handle<T> {
         let revision = self.update_revision(location);
-        self.storage.insert(location, resource);
+        if location < self.storage.len() {
+            self.storage[location] = resource;
+        } else {
+            self.storage.push(resource);
+        }
         Handle {
             identifier: location as u64,
             revision,
@@ -79,18 +134,38 @@ impl<T> Storage<T> {
         }
     }
 
+    /// Number of resources in [Storage], including ones whose handles were invalidated
+    pub fn len(&self) -> usize {
+        self.storage.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.storage.is_empty()
+    }
+
     /// Retrieve resource backed by [Handle]
     /// Returns [None] if the handle passed in is outdated or does not exist
     pub fn get(&self, handle: Handle<T>) -> Option<&T> {
-        // check if handle is out of date or not
-        let storage_revision = self.revisions.get(handle.identifier as usize);
-        if storage_revision.is_none() {
-            return None;
-        } else if let Some(storage_revision) = storage_revision {
-            if *storage_revision == handle.revision {
-                return self.storage.get(*storage_revision as usize);
-            }
+        if self.is_valid(handle) {
+            self.storage.get(handle.identifier as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Check if `handle` still refers to the latest revision of its resource
+    pub fn is_valid(&self, handle: Handle<T>) -> bool {
+        self.revisions.get(handle.identifier as usize) == Some(&handle.revision)
+    }
+
+    /// Outdate every [Handle] to the resource of `handle` without replacing it
+    ///
+    /// Returns false if `handle` was already outdated.
+    pub fn invalidate(&mut self, handle: Handle<T>) -> bool {
+        if !self.is_valid(handle) {
+            return false;
         }
-        None
+        self.update_revision(handle.identifier as usize);
+        true
     }
 }
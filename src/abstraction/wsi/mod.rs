@@ -2,10 +2,12 @@
 * Responsible for handling window integration with Vulkan
 */
 
+pub mod offscreen;
 pub mod surface;
-mod window;
-mod swapchain;
+pub mod swapchain;
+pub mod window;
 
+pub use offscreen::*;
 pub use surface::*;
+pub use swapchain::*;
 pub use window::*;
-pub use swapchain::*;
\ No newline at end of file
@@ -0,0 +1,501 @@
+/*
+* A stand-in for [vk::SwapchainKHR] which renders into plain images, for machines without a display
+*/
+
+use ash::vk;
+use ash::vk::TaggedStructure;
+use crate::abstraction::prelude as abstraction;
+
+/// A frame read back from an [OffscreenSwapchain] when presented
+#[derive(Copy, Clone, Debug)]
+pub struct OffscreenFrame<'a> {
+	/// Number of frames presented before this one
+	pub frame_number: u64,
+	pub image_index: u32,
+	pub extent: vk::Extent2D,
+	/// Tightly packed RGBA8 pixels, rows from top to bottom
+	pub pixels: &'a [u8],
+}
+
+/// Where presented frames of an [OffscreenSwapchain] end up
+pub enum FrameSink {
+	/// Write every frame to `frame_{frame_number:05}.png` in the directory
+	#[cfg(feature = "png")]
+	Png(std::path::PathBuf),
+	Callback(Box<dyn FnMut(&OffscreenFrame) + Send>),
+}
+
+/// Write `frame` to a PNG file at `path`
+#[cfg(feature = "png")]
+pub fn write_png(path: &std::path::Path, frame: &OffscreenFrame) -> std::io::Result<()> {
+	let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+	let mut encoder = png::Encoder::new(file, frame.extent.width, frame.extent.height);
+	encoder.set_color(png::ColorType::Rgba);
+	encoder.set_depth(png::BitDepth::Eight);
+	encoder.write_header()
+		.and_then(|mut writer| writer.write_image_data(frame.pixels))
+		.map_err(std::io::Error::other)
+}
+
+/// Reorder the pixels of an image of `format` into RGBA8 in place
+///
+/// Fails with [vk::Result::ERROR_FORMAT_NOT_SUPPORTED] for anything but 8-bit RGBA and BGRA.
+pub(crate) fn to_rgba8(format: vk::Format, pixels: &mut [u8]) -> Result<(), vk::Result> {
+	match format {
+		vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => Ok(()),
+		vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => {
+			for pixel in pixels.chunks_exact_mut(4) {
+				pixel.swap(0, 2);
+			}
+			Ok(())
+		}
+		_ => Err(vk::Result::ERROR_FORMAT_NOT_SUPPORTED),
+	}
+}
+
+/// Stage, access and layout on one side of a barrier
+type BarrierScope = (vk::PipelineStageFlags2, vk::AccessFlags2, vk::ImageLayout);
+
+/// Barrier moving the color of a presented image from `src` to `dst`
+fn get_present_barrier(image: vk::Image, src: BarrierScope, dst: BarrierScope) -> vk::ImageMemoryBarrier2 {
+	vk::ImageMemoryBarrier2 {
+		s_type: vk::ImageMemoryBarrier2::STRUCTURE_TYPE,
+		src_stage_mask: src.0,
+		src_access_mask: src.1,
+		dst_stage_mask: dst.0,
+		dst_access_mask: dst.1,
+		old_layout: src.2,
+		new_layout: dst.2,
+		src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+		dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+		image,
+		subresource_range: vk::ImageSubresourceRange {
+			aspect_mask: vk::ImageAspectFlags::COLOR,
+			base_mip_level: 0,
+			level_count: 1,
+			base_array_layer: 0,
+			layer_count: 1,
+		},
+		..Default::default()
+	}
+}
+
+/// Get the [vk::DependencyInfo] of `image_barriers`, which it points into
+fn get_dependency_info(image_barriers: &[vk::ImageMemoryBarrier2]) -> vk::DependencyInfo {
+	vk::DependencyInfo {
+		s_type: vk::DependencyInfo::STRUCTURE_TYPE,
+		image_memory_barrier_count: image_barriers.len() as u32,
+		p_image_memory_barriers: image_barriers.as_ptr(),
+		..Default::default()
+	}
+}
+
+/// Allocate memory with `flags` for `memory_requirements`
+fn allocate_memory(device: &abstraction::Device, memory_requirements: vk::MemoryRequirements, flags: vk::MemoryPropertyFlags) -> Result<vk::DeviceMemory, vk::Result> {
+	let memory_type_index = device.find_memory_type_index(memory_requirements.memory_type_bits, flags)
+		.ok_or(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY)?;
+	let allocate_info = vk::MemoryAllocateInfo {
+		s_type: vk::MemoryAllocateInfo::STRUCTURE_TYPE,
+		allocation_size: memory_requirements.size,
+		memory_type_index,
+		..Default::default()
+	};
+	unsafe { device.handle_as_ref().allocate_memory(&allocate_info, None) }
+}
+
+/// Create an image along with device local memory and a view of it
+fn create_image(device: &abstraction::Device, image_ci: &vk::ImageCreateInfo) -> Result<(vk::Image, vk::DeviceMemory, vk::ImageView), vk::Result> {
+	let handle = device.handle_as_ref();
+	let image = unsafe { handle.create_image(image_ci, None)? };
+	let memory_requirements = unsafe { handle.get_image_memory_requirements(image) };
+	let memory = allocate_memory(device, memory_requirements, vk::MemoryPropertyFlags::DEVICE_LOCAL)
+		.and_then(|memory| match unsafe { handle.bind_image_memory(image, memory, 0) } {
+			Ok(()) => Ok(memory),
+			Err(error) => {
+				unsafe { handle.free_memory(memory, None) };
+				Err(error)
+			}
+		})
+		.inspect_err(|_| unsafe { handle.destroy_image(image, None) })?;
+	let image_view_ci = vk::ImageViewCreateInfo {
+		s_type: vk::ImageViewCreateInfo::STRUCTURE_TYPE,
+		image,
+		view_type: vk::ImageViewType::TYPE_2D,
+		format: image_ci.format,
+		subresource_range: vk::ImageSubresourceRange {
+			aspect_mask: vk::ImageAspectFlags::COLOR,
+			base_mip_level: 0,
+			level_count: 1,
+			base_array_layer: 0,
+			layer_count: 1,
+		},
+		..Default::default()
+	};
+	match unsafe { handle.create_image_view(&image_view_ci, None) } {
+		Ok(image_view) => Ok((image, memory, image_view)),
+		Err(error) => {
+			unsafe {
+				handle.destroy_image(image, None);
+				handle.free_memory(memory, None);
+			}
+			Err(error)
+		}
+	}
+}
+
+/// An offscreen stand-in for a [abstraction::Swapchain] with the same acquire and present API
+///
+/// Images are plain device local [abstraction::Image]s. Presenting copies the image into a
+/// host visible buffer, waits for the copy and hands the pixels to a [FrameSink], so an image
+/// is always available again once presented. Only 8-bit RGBA and BGRA formats are supported.
+pub struct OffscreenSwapchain {
+	device: abstraction::Device,
+	/// Queue acquired images are signaled from and presented images are read back on
+	queue: abstraction::Queue,
+	config: abstraction::SwapchainConfig,
+	format: vk::Format,
+	extent: vk::Extent2D,
+	images: Vec<abstraction::Image>,
+	image_views: Vec<vk::ImageView>,
+	image_memory: Vec<vk::DeviceMemory>,
+	readback: abstraction::Buffer,
+	readback_memory: vk::DeviceMemory,
+	command_pool: abstraction::CommandPool,
+	/// Signaled once a presented image has been read back
+	fence: vk::Fence,
+	next_image: u32,
+	frame_number: u64,
+	sink: FrameSink,
+	sink_error: Option<std::io::Error>,
+}
+
+impl OffscreenSwapchain {
+	/// Create `config.image_count` images of the first of `config.formats` and `config.extent`
+	///
+	/// `queue` signals acquired images and reads back presented ones, so the images are owned by
+	/// its queue family when acquired and must be owned by it again when presented.
+	pub fn new(queue: abstraction::Queue, config: abstraction::SwapchainConfig, sink: FrameSink) -> Result<Self, vk::Result> {
+		let device = queue.get_device();
+		let format = config.formats.first()
+			.map(|format| format.format)
+			.unwrap_or(vk::Format::R8G8B8A8_SRGB);
+		to_rgba8(format, &mut [])?;
+		let fence_ci = vk::FenceCreateInfo {
+			s_type: vk::FenceCreateInfo::STRUCTURE_TYPE,
+			..Default::default()
+		};
+		let command_pool = abstraction::CommandPool::new(device.clone(), queue.get_family_index(), vk::CommandPoolCreateFlags::TRANSIENT)?;
+		let fence = unsafe { device.handle_as_ref().create_fence(&fence_ci, None)? };
+		let mut swapchain = Self {
+			device,
+			queue,
+			config,
+			format,
+			extent: vk::Extent2D::default(),
+			images: Vec::new(),
+			image_views: Vec::new(),
+			image_memory: Vec::new(),
+			readback: abstraction::Buffer::from_vk(vk::Buffer::null(), 0, vk::BufferUsageFlags::empty()),
+			readback_memory: vk::DeviceMemory::null(),
+			command_pool,
+			fence,
+			next_image: 0,
+			frame_number: 0,
+			sink,
+			sink_error: None,
+		};
+		let extent = swapchain.config.extent;
+		swapchain.create(extent)?;
+		Ok(swapchain)
+	}
+
+	/// Create the images and readback buffer for `extent`
+	fn create(&mut self, extent: vk::Extent2D) -> Result<(), vk::Result> {
+		if extent.width == 0 || extent.height == 0 {
+			return Err(vk::Result::ERROR_OUT_OF_DATE_KHR);
+		}
+		self.extent = extent;
+		self.next_image = 0;
+		let device = self.device.handle_as_ref();
+		let image_ci = vk::ImageCreateInfo {
+			s_type: vk::ImageCreateInfo::STRUCTURE_TYPE,
+			image_type: vk::ImageType::TYPE_2D,
+			format: self.format,
+			extent: vk::Extent3D {
+				width: extent.width,
+				height: extent.height,
+				depth: 1,
+			},
+			mip_levels: 1,
+			array_layers: 1,
+			samples: vk::SampleCountFlags::TYPE_1,
+			tiling: vk::ImageTiling::OPTIMAL,
+			usage: self.config.image_usage | self.config.optional_image_usage | vk::ImageUsageFlags::TRANSFER_SRC,
+			sharing_mode: vk::SharingMode::EXCLUSIVE,
+			initial_layout: vk::ImageLayout::UNDEFINED,
+			..Default::default()
+		};
+		// Images created so far are destroyed along with the swapchain on failure
+		for _ in 0..self.config.image_count.max(1) {
+			let (image, memory, image_view) = create_image(&self.device, &image_ci)?;
+			self.image_memory.push(memory);
+			self.image_views.push(image_view);
+			self.images.push(abstraction::Image::from_vk(image, self.device.clone(), vk::Sampler::null(), image_view, &image_ci));
+		}
+
+		let size = extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * 4;
+		let buffer_ci = vk::BufferCreateInfo {
+			s_type: vk::BufferCreateInfo::STRUCTURE_TYPE,
+			size,
+			usage: vk::BufferUsageFlags::TRANSFER_DST,
+			sharing_mode: vk::SharingMode::EXCLUSIVE,
+			..Default::default()
+		};
+		let buffer = unsafe { device.create_buffer(&buffer_ci, None)? };
+		self.readback = abstraction::Buffer::from_vk(buffer, size, buffer_ci.usage);
+		self.readback_memory = allocate_memory(&self.device, unsafe { device.get_buffer_memory_requirements(buffer) }, vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT)?;
+		unsafe { device.bind_buffer_memory(buffer, self.readback_memory, 0) }
+	}
+
+	/// Destroy the images and readback buffer
+	fn destroy(&mut self) {
+		self.images.clear();
+		unsafe {
+			let device = self.device.handle_as_ref();
+			for image_view in self.image_views.drain(..) {
+				device.destroy_image_view(image_view, None);
+			}
+			for memory in self.image_memory.drain(..) {
+				device.free_memory(memory, None);
+			}
+			device.destroy_buffer(self.readback.get_handle(), None);
+			device.free_memory(self.readback_memory, None);
+		}
+		self.readback = abstraction::Buffer::from_vk(vk::Buffer::null(), 0, vk::BufferUsageFlags::empty());
+		self.readback_memory = vk::DeviceMemory::null();
+	}
+
+	/// Replace the images with ones of `extent`
+	pub fn recreate(&mut self, extent: vk::Extent2D) -> Result<(), vk::Result> {
+		unsafe {
+			self.device.handle_as_ref().queue_wait_idle(self.queue.get_handle())?;
+		}
+		self.destroy();
+		self.config.extent = extent;
+		self.create(extent)
+	}
+
+	/// Hand out the next image, signaling `semaphore` and `fence` right away
+	///
+	/// Images are never in use once presented, so there is nothing to wait for.
+	pub fn acquire_next_image(&mut self, _timeout: u64, semaphore: Option<&abstraction::Semaphore>, fence: vk::Fence) -> Result<(u32, bool), vk::Result> {
+		// Recreating for a zero extent, or failing to, leaves no images until the next recreate
+		if self.images.is_empty() {
+			return Err(vk::Result::ERROR_OUT_OF_DATE_KHR);
+		}
+		let image_index = self.next_image;
+		self.next_image = (self.next_image + 1) % self.images.len() as u32;
+		let signal_semaphores: Vec<vk::SemaphoreSubmitInfo> = semaphore.iter()
+			.map(|semaphore| semaphore.submit_info(0, vk::PipelineStageFlags2::ALL_COMMANDS))
+			.collect();
+		if !signal_semaphores.is_empty() || fence != vk::Fence::null() {
+			let submit_info = vk::SubmitInfo2 {
+				s_type: vk::SubmitInfo2::STRUCTURE_TYPE,
+				signal_semaphore_info_count: signal_semaphores.len() as u32,
+				p_signal_semaphore_infos: signal_semaphores.as_ptr(),
+				..Default::default()
+			};
+			unsafe {
+				self.device.handle_as_ref().queue_submit2(self.queue.get_handle(), &[submit_info], fence)?;
+			}
+		}
+		Ok((image_index, false))
+	}
+
+	/// Read back the image at `image_index` once `wait_semaphores` are signaled and hand it to
+	/// the [FrameSink]
+	///
+	/// Blocks until the image has been read back. The image must be in
+	/// [vk::ImageLayout::PRESENT_SRC_KHR] and is left in it. Fails with
+	/// [vk::Result::ERROR_UNKNOWN] if the frame could not be written, see
+	/// [Self::take_sink_error].
+	///
+	/// The read back is always submitted to the queue the swapchain was created with, as its
+	/// command pool belongs to that queue's family. `_queue` is only there to match
+	/// [abstraction::Swapchain::present].
+	pub fn present(&mut self, _queue: &abstraction::Queue, image_index: u32, wait_semaphores: &[&abstraction::Semaphore]) -> Result<bool, vk::Result> {
+		let image = self.images.get(image_index as usize).ok_or(vk::Result::ERROR_OUT_OF_DATE_KHR)?;
+		let device = self.device.handle_as_ref();
+		// Safe as the previous present waited for its command buffer
+		unsafe { self.command_pool.reset()? };
+		let command_buffer = self.command_pool.allocate()?
+			.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+		// The image is owned by the caller, so its layout is given by the contract of presenting
+		// rather than whatever state it tracks
+		command_buffer.pipeline_barrier2(&get_dependency_info(&[get_present_barrier(
+			image.get_handle(),
+			(vk::PipelineStageFlags2::ALL_COMMANDS, vk::AccessFlags2::NONE, vk::ImageLayout::PRESENT_SRC_KHR),
+			(vk::PipelineStageFlags2::COPY, vk::AccessFlags2::TRANSFER_READ, vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+		)]));
+		command_buffer.transition(&self.readback, abstraction::AccessType::TransferWrite.into());
+		command_buffer.copy_image_to_buffer(image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, &self.readback, &[vk::BufferImageCopy {
+			buffer_offset: 0,
+			buffer_row_length: 0,
+			buffer_image_height: 0,
+			image_subresource: vk::ImageSubresourceLayers {
+				aspect_mask: vk::ImageAspectFlags::COLOR,
+				mip_level: 0,
+				base_array_layer: 0,
+				layer_count: 1,
+			},
+			image_offset: vk::Offset3D::default(),
+			image_extent: image.get_extent(),
+		}]);
+		command_buffer.pipeline_barrier2(&get_dependency_info(&[get_present_barrier(
+			image.get_handle(),
+			(vk::PipelineStageFlags2::COPY, vk::AccessFlags2::NONE, vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+			(vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE, vk::ImageLayout::PRESENT_SRC_KHR),
+		)]));
+		command_buffer.transition(&self.readback, abstraction::AccessType::HostRead.into());
+		let wait_semaphores: Vec<vk::SemaphoreSubmitInfo> = wait_semaphores.iter()
+			.map(|semaphore| semaphore.submit_info(0, vk::PipelineStageFlags2::ALL_COMMANDS))
+			.collect();
+		command_buffer.end()?.submit(&self.queue, &wait_semaphores, &[], self.fence)?;
+		let mut pixels = vec![0u8; self.readback.get_size() as usize];
+		unsafe {
+			device.wait_for_fences(&[self.fence], true, u64::MAX)?;
+			device.reset_fences(&[self.fence])?;
+			let mapped = device.map_memory(self.readback_memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())?;
+			std::ptr::copy_nonoverlapping(mapped as *const u8, pixels.as_mut_ptr(), pixels.len());
+			device.unmap_memory(self.readback_memory);
+		}
+		to_rgba8(self.format, &mut pixels)?;
+
+		let frame = OffscreenFrame {
+			frame_number: self.frame_number,
+			image_index,
+			extent: self.extent,
+			pixels: &pixels,
+		};
+		let result = match &mut self.sink {
+			#[cfg(feature = "png")]
+			FrameSink::Png(directory) => write_png(&directory.join(format!("frame_{:05}.png", self.frame_number)), &frame),
+			FrameSink::Callback(callback) => {
+				callback(&frame);
+				Ok(())
+			}
+		};
+		self.frame_number += 1;
+		if let Err(error) = result {
+			self.sink_error = Some(error);
+			return Err(vk::Result::ERROR_UNKNOWN);
+		}
+		Ok(false)
+	}
+
+	/// Take the error of the last frame which could not be written
+	pub fn take_sink_error(&mut self) -> Option<std::io::Error> {
+		self.sink_error.take()
+	}
+
+	pub fn get_device(&self) -> abstraction::Device {
+		self.device.clone()
+	}
+
+	pub fn get_config(&self) -> &abstraction::SwapchainConfig {
+		&self.config
+	}
+
+	pub fn get_format(&self) -> vk::Format {
+		self.format
+	}
+
+	pub fn get_extent(&self) -> vk::Extent2D {
+		self.extent
+	}
+
+	pub fn get_images(&self) -> &[abstraction::Image] {
+		&self.images
+	}
+
+	/// Get the view of every image, in the same order as [Self::get_images]
+	pub fn get_image_views(&self) -> &[vk::ImageView] {
+		&self.image_views
+	}
+
+	/// Get the number of frames presented so far
+	pub fn get_frame_number(&self) -> u64 {
+		self.frame_number
+	}
+}
+
+impl abstraction::PresentTarget for OffscreenSwapchain {
+	fn acquire_next_image(&mut self, timeout: u64, semaphore: Option<&abstraction::Semaphore>, fence: vk::Fence) -> Result<(u32, bool), vk::Result> {
+		OffscreenSwapchain::acquire_next_image(self, timeout, semaphore, fence)
+	}
+
+	fn present(&mut self, queue: &abstraction::Queue, image_index: u32, wait_semaphores: &[&abstraction::Semaphore]) -> Result<bool, vk::Result> {
+		OffscreenSwapchain::present(self, queue, image_index, wait_semaphores)
+	}
+
+	fn recreate(&mut self, extent: vk::Extent2D) -> Result<(), vk::Result> {
+		OffscreenSwapchain::recreate(self, extent)
+	}
+
+	fn get_device(&self) -> abstraction::Device {
+		OffscreenSwapchain::get_device(self)
+	}
+
+	fn get_extent(&self) -> vk::Extent2D {
+		OffscreenSwapchain::get_extent(self)
+	}
+
+	fn get_images(&self) -> &[abstraction::Image] {
+		OffscreenSwapchain::get_images(self)
+	}
+}
+
+impl Drop for OffscreenSwapchain {
+	fn drop(&mut self) {
+		self.destroy();
+		unsafe {
+			self.device.handle_as_ref().destroy_fence(self.fence, None);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	/// BGRA pixels are swizzled into RGBA while other formats are rejected
+	fn test_to_rgba8() {
+		let mut pixels = [1, 2, 3, 4, 5, 6, 7, 8];
+		to_rgba8(vk::Format::R8G8B8A8_SRGB, &mut pixels).unwrap();
+		assert_eq!(pixels, [1, 2, 3, 4, 5, 6, 7, 8]);
+		to_rgba8(vk::Format::B8G8R8A8_UNORM, &mut pixels).unwrap();
+		assert_eq!(pixels, [3, 2, 1, 4, 7, 6, 5, 8]);
+		assert_eq!(to_rgba8(vk::Format::R16G16B16A16_SFLOAT, &mut pixels), Err(vk::Result::ERROR_FORMAT_NOT_SUPPORTED));
+	}
+
+	#[cfg(feature = "png")]
+	#[test]
+	/// Written frames decode to the same pixels
+	fn test_write_png() {
+		let path = std::env::temp_dir().join(format!("dagal_offscreen_{}.png", std::process::id()));
+		let pixels: Vec<u8> = (0..2 * 3 * 4).map(|value| value as u8).collect();
+		let frame = OffscreenFrame {
+			frame_number: 0,
+			image_index: 0,
+			extent: vk::Extent2D { width: 2, height: 3 },
+			pixels: &pixels,
+		};
+		write_png(&path, &frame).unwrap();
+		let mut reader = png::Decoder::new(std::fs::File::open(&path).unwrap()).read_info().unwrap();
+		let mut decoded = vec![0; reader.output_buffer_size()];
+		reader.next_frame(&mut decoded).unwrap();
+		assert_eq!(decoded, pixels);
+		std::fs::remove_file(&path).unwrap();
+	}
+}
@@ -1,11 +1,12 @@
 use std::sync::Arc;
 use ash::vk;
-use raw_window_handle::{RawDisplayHandle, RawWindowHandle, WindowHandle};
+use ash::vk::TaggedStructure;
+use raw_window_handle::{RawDisplayHandle, RawWindowHandle};
 use crate::abstraction::prelude as abstraction;
 
 
 #[derive(Clone)]
-struct SurfaceLoaderInner {
+pub struct SurfaceLoaderInner {
 	handle: ash::extensions::khr::Surface,
 }
 
@@ -21,11 +22,28 @@ pub struct SurfaceLoader {
 }
 
 impl SurfaceLoader {
+	pub fn new(instance: &abstraction::Instance) -> Self {
+		let handle = ash::extensions::khr::Surface::new(instance.get_vk_entry(), instance.get_vk_instance());
+		Self {
+			handle: Arc::new(SurfaceLoaderInner {
+				handle,
+			}),
+		}
+	}
+
 	pub fn get_handle(&self) -> Arc<SurfaceLoaderInner> {
 		self.handle.clone()
 	}
 }
 
+/// Get the instance extensions needed by [Surface::headless]
+pub fn get_headless_surface_extensions() -> Vec<String> {
+	[ash::extensions::khr::Surface::name(), ash::extensions::ext::HeadlessSurface::name()]
+		.iter()
+		.map(|name| name.to_string_lossy().into_owned())
+		.collect()
+}
+
 pub struct Surface {
 	handle: vk::SurfaceKHR,
 	loader: SurfaceLoader,
@@ -33,13 +51,37 @@ pub struct Surface {
 
 impl Surface {
 	pub fn new(instance: &abstraction::Instance, loader: SurfaceLoader, raw_display_handle: RawDisplayHandle, raw_window_handle: RawWindowHandle) -> Self {
+		Self::from_raw_handles(instance, loader, raw_display_handle, raw_window_handle).unwrap()
+	}
+
+	/// Create a surface for a window, failing if the window system is not supported
+	pub fn from_raw_handles(instance: &abstraction::Instance, loader: SurfaceLoader, raw_display_handle: RawDisplayHandle, raw_window_handle: RawWindowHandle) -> Result<Self, vk::Result> {
 		let handle = unsafe {
-			ash_window::create_surface(instance.get_vk_entry(), instance.get_vk_instance(), raw_display_handle, raw_window_handle, None).unwrap()
+			ash_window::create_surface(instance.get_vk_entry(), instance.get_vk_instance(), raw_display_handle, raw_window_handle, None)?
 		};
-		Self {
+		Ok(Self {
 			handle,
 			loader,
-		}
+		})
+	}
+
+	/// Create a surface which is not backed by any window system, such as on machines without a
+	/// display
+	///
+	/// The instance must have been created with [get_headless_surface_extensions].
+	pub fn headless(instance: &abstraction::Instance, loader: SurfaceLoader) -> Result<Self, vk::Result> {
+		let headless_loader = ash::extensions::ext::HeadlessSurface::new(instance.get_vk_entry(), instance.get_vk_instance());
+		let headless_surface_ci = vk::HeadlessSurfaceCreateInfoEXT {
+			s_type: vk::HeadlessSurfaceCreateInfoEXT::STRUCTURE_TYPE,
+			..Default::default()
+		};
+		let handle = unsafe {
+			headless_loader.create_headless_surface(&headless_surface_ci, None)?
+		};
+		Ok(Self {
+			handle,
+			loader,
+		})
 	}
 
 	pub fn get_loader(&self) -> SurfaceLoader {
@@ -49,6 +91,27 @@ impl Surface {
 	pub fn get_handle(&self) -> vk::SurfaceKHR {
 		self.handle
 	}
+
+	/// Get the capabilities of the surface on `physical_device`
+	pub fn get_capabilities(&self, physical_device: vk::PhysicalDevice) -> Result<vk::SurfaceCapabilitiesKHR, vk::Result> {
+		unsafe {
+			self.loader.handle.handle.get_physical_device_surface_capabilities(physical_device, self.handle)
+		}
+	}
+
+	/// Get every format and color space swapchains of the surface can use on `physical_device`
+	pub fn get_formats(&self, physical_device: vk::PhysicalDevice) -> Result<Vec<vk::SurfaceFormatKHR>, vk::Result> {
+		unsafe {
+			self.loader.handle.handle.get_physical_device_surface_formats(physical_device, self.handle)
+		}
+	}
+
+	/// Get every present mode swapchains of the surface can use on `physical_device`
+	pub fn get_present_modes(&self, physical_device: vk::PhysicalDevice) -> Result<Vec<vk::PresentModeKHR>, vk::Result> {
+		unsafe {
+			self.loader.handle.handle.get_physical_device_surface_present_modes(physical_device, self.handle)
+		}
+	}
 }
 
 impl Drop for Surface {
@@ -57,4 +120,4 @@ impl Drop for Surface {
 			self.loader.handle.get_handle().destroy_surface(self.handle, None);
 		}
 	}
-}
\ No newline at end of file
+}
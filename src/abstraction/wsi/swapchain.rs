@@ -4,48 +4,334 @@
 
 use std::sync::Arc;
 use ash::vk;
+use ash::vk::TaggedStructure;
 use crate::abstraction::prelude as abstraction;
 
-struct SwapchainLoaderInner {
-	handle: ash::extensions::khr::Swapchain,
-	device: abstraction::Device,
+#[derive(Clone)]
+pub struct SwapchainLoader {
+	handle: Arc<ash::extensions::khr::Swapchain>,
 }
 
-impl SwapchainLoaderInner {
-	pub fn get_handle(&self) -> ash::extensions::khr::Swapchain {
-		self.handle.clone()
+impl SwapchainLoader {
+	pub fn new(instance: &abstraction::Instance, device: &ash::Device) -> Self {
+		let handle = ash::extensions::khr::Swapchain::new(instance.get_vk_instance(), device);
+		Self {
+			handle: Arc::new(handle),
+		}
+	}
+
+	pub fn get_handle(&self) -> &ash::extensions::khr::Swapchain {
+		&self.handle
 	}
 }
 
-#[derive(Copy, Clone)]
-pub struct SwapchainLoader {
-	handle: Arc<SwapchainLoaderInner>
+/// What a [Swapchain] should look like, settings the surface does not support are substituted
+#[derive(Clone, Debug)]
+pub struct SwapchainConfig {
+	/// Formats and color spaces in order of preference, falls back to the first the surface supports
+	pub formats: Vec<vk::SurfaceFormatKHR>,
+	/// Present modes in order of preference, falls back to [vk::PresentModeKHR::FIFO]
+	pub present_modes: Vec<vk::PresentModeKHR>,
+	/// Extent of the images, only used if the surface leaves the extent up to the swapchain
+	pub extent: vk::Extent2D,
+	/// Number of images, clamped to what the surface supports
+	pub image_count: u32,
+	/// Usage the images need, creating the swapchain fails with
+	/// [vk::Result::ERROR_FEATURE_NOT_PRESENT] if the surface does not support it
+	pub image_usage: vk::ImageUsageFlags,
+	/// Usage the images get in addition to [Self::image_usage] if the surface supports it
+	pub optional_image_usage: vk::ImageUsageFlags,
+	/// Queue families the images are used by, shared concurrently if more than one
+	pub queue_family_indices: Vec<u32>,
 }
 
-impl SwapchainLoader {
-	pub fn new(instance: &abstraction::Instance, device: abstraction::Device) -> Self {
-		let handle = unsafe {
-			ash::extensions::khr::Swapchain::new(instance.get_vk_instance(), device.handle_as_ref())
-		};
+impl Default for SwapchainConfig {
+	fn default() -> Self {
 		Self {
-			handle: Arc::new(SwapchainLoaderInner {
-				handle,
-				device,
-			})
+			formats: vec![
+				vk::SurfaceFormatKHR {
+					format: vk::Format::B8G8R8A8_SRGB,
+					color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+				},
+				vk::SurfaceFormatKHR {
+					format: vk::Format::R8G8B8A8_SRGB,
+					color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+				},
+			],
+			present_modes: vec![vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::FIFO],
+			extent: vk::Extent2D::default(),
+			image_count: 3,
+			image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+			optional_image_usage: vk::ImageUsageFlags::TRANSFER_DST,
+			queue_family_indices: Vec::new(),
 		}
 	}
+}
 
-	pub fn get_handle(&self) -> SwapchainLoaderInner {
-		self.get_handle()
+/// Pick the first of `preferred` which is `available`, otherwise the first available one
+pub(crate) fn choose_surface_format(available: &[vk::SurfaceFormatKHR], preferred: &[vk::SurfaceFormatKHR]) -> vk::SurfaceFormatKHR {
+	// A single undefined format means any format may be used
+	if let ([only], Some(first)) = (available, preferred.first()) {
+		if only.format == vk::Format::UNDEFINED {
+			return *first;
+		}
 	}
+	preferred.iter()
+		.find(|preferred| available.iter().any(|available| available == *preferred))
+		.or(available.first())
+		.copied()
+		.unwrap_or_default()
+}
+
+/// Pick the first of `preferred` which is `available`, otherwise FIFO which is always supported
+pub(crate) fn choose_present_mode(available: &[vk::PresentModeKHR], preferred: &[vk::PresentModeKHR]) -> vk::PresentModeKHR {
+	preferred.iter()
+		.find(|preferred| available.contains(preferred))
+		.copied()
+		.unwrap_or(vk::PresentModeKHR::FIFO)
+}
+
+/// Use the extent of the surface if it has one, otherwise clamp `desired` to the supported extents
+pub(crate) fn choose_extent(capabilities: &vk::SurfaceCapabilitiesKHR, desired: vk::Extent2D) -> vk::Extent2D {
+	if capabilities.current_extent.width != u32::MAX {
+		return capabilities.current_extent;
+	}
+	vk::Extent2D {
+		width: desired.width.clamp(capabilities.min_image_extent.width, capabilities.max_image_extent.width),
+		height: desired.height.clamp(capabilities.min_image_extent.height, capabilities.max_image_extent.height),
+	}
+}
+
+/// Clamp `desired` to the supported image counts, a maximum of 0 means there is no maximum
+pub(crate) fn choose_image_count(capabilities: &vk::SurfaceCapabilitiesKHR, desired: u32) -> u32 {
+	let image_count = desired.max(capabilities.min_image_count);
+	if capabilities.max_image_count == 0 {
+		image_count
+	} else {
+		image_count.min(capabilities.max_image_count)
+	}
+}
+
+/// Add the `optional` usage which is `supported` to `required`, [None] if `required` is not supported
+pub(crate) fn choose_image_usage(supported: vk::ImageUsageFlags, required: vk::ImageUsageFlags, optional: vk::ImageUsageFlags) -> Option<vk::ImageUsageFlags> {
+	supported.contains(required).then_some(required | (optional & supported))
 }
 
+/// Pick the first supported composite alpha, preferring opaque
+fn choose_composite_alpha(supported: vk::CompositeAlphaFlagsKHR) -> vk::CompositeAlphaFlagsKHR {
+	[
+		vk::CompositeAlphaFlagsKHR::OPAQUE,
+		vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+		vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+		vk::CompositeAlphaFlagsKHR::INHERIT,
+	]
+		.into_iter()
+		.find(|composite_alpha| supported.contains(*composite_alpha))
+		.unwrap_or(vk::CompositeAlphaFlagsKHR::OPAQUE)
+}
+
+/// A swapchain presenting to a [abstraction::Surface], which has to outlive it
+///
+/// Images are owned by the swapchain and wrapped as non-owning [abstraction::Image]s, each
+/// along with a view covering the whole image.
 pub struct Swapchain {
 	handle: vk::SwapchainKHR,
 	device: abstraction::Device,
+	loader: SwapchainLoader,
+	surface: vk::SurfaceKHR,
+	surface_loader: abstraction::SurfaceLoader,
+	config: SwapchainConfig,
+	format: vk::SurfaceFormatKHR,
+	present_mode: vk::PresentModeKHR,
+	extent: vk::Extent2D,
+	image_usage: vk::ImageUsageFlags,
+	images: Vec<abstraction::Image>,
+	image_views: Vec<vk::ImageView>,
 }
 
 impl Swapchain {
+	/// Create a swapchain for `surface` as close to `config` as the surface supports
+	///
+	/// Fails with [vk::Result::ERROR_EXTENSION_NOT_PRESENT] if the device does not support
+	/// `VK_KHR_swapchain`, and with [vk::Result::ERROR_OUT_OF_DATE_KHR] if the surface has no
+	/// area, such as a minimized window.
+	pub fn new(device: abstraction::Device, surface: &abstraction::Surface, config: SwapchainConfig) -> Result<Self, vk::Result> {
+		let loader = device.get_swapchain_loader()
+			.cloned()
+			.ok_or(vk::Result::ERROR_EXTENSION_NOT_PRESENT)?;
+		let mut swapchain = Self {
+			handle: vk::SwapchainKHR::null(),
+			device,
+			loader,
+			surface: surface.get_handle(),
+			surface_loader: surface.get_loader(),
+			config,
+			format: vk::SurfaceFormatKHR::default(),
+			present_mode: vk::PresentModeKHR::FIFO,
+			extent: vk::Extent2D::default(),
+			image_usage: vk::ImageUsageFlags::empty(),
+			images: Vec::new(),
+			image_views: Vec::new(),
+		};
+		swapchain.create()?;
+		Ok(swapchain)
+	}
+
+	/// Replace the swapchain with a new one, such as after the surface was resized
+	///
+	/// `extent` is only used if the surface leaves the extent up to the swapchain. The previous
+	/// swapchain is passed as `old_swapchain` and destroyed along with its views right away, so
+	/// its images must not be in use anymore.
+	pub fn recreate(&mut self, extent: vk::Extent2D) -> Result<(), vk::Result> {
+		self.config.extent = extent;
+		self.create()
+	}
+
+	/// Create a new swapchain, retiring the current one if any
+	fn create(&mut self) -> Result<(), vk::Result> {
+		let physical_device = self.device.get_physical_device();
+		let surface_loader = self.surface_loader.get_handle().get_handle();
+		let (capabilities, formats, present_modes) = unsafe {
+			(
+				surface_loader.get_physical_device_surface_capabilities(physical_device, self.surface)?,
+				surface_loader.get_physical_device_surface_formats(physical_device, self.surface)?,
+				surface_loader.get_physical_device_surface_present_modes(physical_device, self.surface)?,
+			)
+		};
+		let format = choose_surface_format(&formats, &self.config.formats);
+		let present_mode = choose_present_mode(&present_modes, &self.config.present_modes);
+		let extent = choose_extent(&capabilities, self.config.extent);
+		if extent.width == 0 || extent.height == 0 {
+			return Err(vk::Result::ERROR_OUT_OF_DATE_KHR);
+		}
+		let image_usage = choose_image_usage(capabilities.supported_usage_flags, self.config.image_usage, self.config.optional_image_usage)
+			.ok_or(vk::Result::ERROR_FEATURE_NOT_PRESENT)?;
+		let image_sharing_mode = if self.config.queue_family_indices.len() > 1 {
+			vk::SharingMode::CONCURRENT
+		} else {
+			vk::SharingMode::EXCLUSIVE
+		};
+		let swapchain_ci = vk::SwapchainCreateInfoKHR {
+			s_type: vk::SwapchainCreateInfoKHR::STRUCTURE_TYPE,
+			surface: self.surface,
+			min_image_count: choose_image_count(&capabilities, self.config.image_count),
+			image_format: format.format,
+			image_color_space: format.color_space,
+			image_extent: extent,
+			image_array_layers: 1,
+			image_usage,
+			image_sharing_mode,
+			queue_family_index_count: self.config.queue_family_indices.len() as u32,
+			p_queue_family_indices: self.config.queue_family_indices.as_ptr(),
+			pre_transform: capabilities.current_transform,
+			composite_alpha: choose_composite_alpha(capabilities.supported_composite_alpha),
+			present_mode,
+			clipped: vk::TRUE,
+			old_swapchain: self.handle,
+			..Default::default()
+		};
+		let loader = self.loader.get_handle();
+		let handle = unsafe { loader.create_swapchain(&swapchain_ci, None)? };
+		let images = match unsafe { loader.get_swapchain_images(handle) } {
+			Ok(images) => images,
+			Err(error) => {
+				unsafe { loader.destroy_swapchain(handle, None) };
+				return Err(error);
+			}
+		};
+		// The previous swapchain has been retired by the new one
+		self.destroy();
+		self.handle = handle;
+		self.format = format;
+		self.present_mode = present_mode;
+		self.extent = extent;
+		self.image_usage = image_usage;
+
+		let image_ci = vk::ImageCreateInfo {
+			s_type: vk::ImageCreateInfo::STRUCTURE_TYPE,
+			image_type: vk::ImageType::TYPE_2D,
+			format: format.format,
+			extent: vk::Extent3D {
+				width: extent.width,
+				height: extent.height,
+				depth: 1,
+			},
+			mip_levels: 1,
+			array_layers: 1,
+			samples: vk::SampleCountFlags::TYPE_1,
+			tiling: vk::ImageTiling::OPTIMAL,
+			usage: image_usage,
+			sharing_mode: image_sharing_mode,
+			initial_layout: vk::ImageLayout::UNDEFINED,
+			..Default::default()
+		};
+		for image in images {
+			let image_view_ci = vk::ImageViewCreateInfo {
+				s_type: vk::ImageViewCreateInfo::STRUCTURE_TYPE,
+				image,
+				view_type: vk::ImageViewType::TYPE_2D,
+				format: format.format,
+				components: vk::ComponentMapping::default(),
+				subresource_range: vk::ImageSubresourceRange {
+					aspect_mask: vk::ImageAspectFlags::COLOR,
+					base_mip_level: 0,
+					level_count: 1,
+					base_array_layer: 0,
+					layer_count: 1,
+				},
+				..Default::default()
+			};
+			// Views created so far are destroyed along with the swapchain on failure
+			let image_view = unsafe { self.device.handle_as_ref().create_image_view(&image_view_ci, None)? };
+			self.image_views.push(image_view);
+			self.images.push(abstraction::Image::from_vk_non_owning(image, self.device.clone(), vk::Sampler::null(), image_view, &image_ci));
+		}
+		Ok(())
+	}
+
+	/// Destroy the views and the swapchain itself
+	fn destroy(&mut self) {
+		self.images.clear();
+		unsafe {
+			for image_view in self.image_views.drain(..) {
+				self.device.handle_as_ref().destroy_image_view(image_view, None);
+			}
+			self.loader.get_handle().destroy_swapchain(self.handle, None);
+		}
+		self.handle = vk::SwapchainKHR::null();
+	}
+
+	/// Acquire the next image to render into, returning its index and whether the swapchain is
+	/// suboptimal
+	///
+	/// `semaphore` and `fence` are signaled once the image can be used. Fails with
+	/// [vk::Result::ERROR_OUT_OF_DATE_KHR] once the swapchain has to be recreated.
+	pub fn acquire_next_image(&self, timeout: u64, semaphore: Option<&abstraction::Semaphore>, fence: vk::Fence) -> Result<(u32, bool), vk::Result> {
+		let semaphore = semaphore.map(|semaphore| semaphore.get_handle()).unwrap_or_default();
+		unsafe {
+			self.loader.get_handle().acquire_next_image(self.handle, timeout, semaphore, fence)
+		}
+	}
+
+	/// Present the image at `image_index` once `wait_semaphores` are signaled, returning whether
+	/// the swapchain is suboptimal
+	pub fn present(&self, queue: &abstraction::Queue, image_index: u32, wait_semaphores: &[&abstraction::Semaphore]) -> Result<bool, vk::Result> {
+		let wait_semaphores: Vec<vk::Semaphore> = wait_semaphores.iter()
+			.map(|semaphore| semaphore.get_handle())
+			.collect();
+		let present_info = vk::PresentInfoKHR {
+			s_type: vk::PresentInfoKHR::STRUCTURE_TYPE,
+			wait_semaphore_count: wait_semaphores.len() as u32,
+			p_wait_semaphores: wait_semaphores.as_ptr(),
+			swapchain_count: 1,
+			p_swapchains: &self.handle,
+			p_image_indices: &image_index,
+			..Default::default()
+		};
+		unsafe {
+			self.loader.get_handle().queue_present(queue.get_handle(), &present_info)
+		}
+	}
 
 	pub fn get_handle(&self) -> vk::SwapchainKHR {
 		self.handle
@@ -54,10 +340,128 @@ impl Swapchain {
 	pub fn get_device(&self) -> abstraction::Device {
 		self.device.clone()
 	}
+
+	pub fn get_config(&self) -> &SwapchainConfig {
+		&self.config
+	}
+
+	pub fn get_format(&self) -> vk::SurfaceFormatKHR {
+		self.format
+	}
+
+	pub fn get_present_mode(&self) -> vk::PresentModeKHR {
+		self.present_mode
+	}
+
+	pub fn get_extent(&self) -> vk::Extent2D {
+		self.extent
+	}
+
+	/// Usage the images were created with, [SwapchainConfig::image_usage] along with the
+	/// supported part of [SwapchainConfig::optional_image_usage]
+	pub fn get_image_usage(&self) -> vk::ImageUsageFlags {
+		self.image_usage
+	}
+
+	pub fn get_images(&self) -> &[abstraction::Image] {
+		&self.images
+	}
+
+	/// Get the view of every image, in the same order as [Self::get_images]
+	pub fn get_image_views(&self) -> &[vk::ImageView] {
+		&self.image_views
+	}
 }
 
 impl Drop for Swapchain {
 	fn drop(&mut self) {
+		self.destroy();
+	}
+}
+
+/// Something frames are acquired from and presented to, either a [Swapchain] or an
+/// [abstraction::OffscreenSwapchain]
+pub trait PresentTarget {
+	/// See [Swapchain::acquire_next_image]
+	fn acquire_next_image(&mut self, timeout: u64, semaphore: Option<&abstraction::Semaphore>, fence: vk::Fence) -> Result<(u32, bool), vk::Result>;
+
+	/// See [Swapchain::present]
+	fn present(&mut self, queue: &abstraction::Queue, image_index: u32, wait_semaphores: &[&abstraction::Semaphore]) -> Result<bool, vk::Result>;
+
+	/// See [Swapchain::recreate]
+	fn recreate(&mut self, extent: vk::Extent2D) -> Result<(), vk::Result>;
+
+	fn get_device(&self) -> abstraction::Device;
+
+	fn get_extent(&self) -> vk::Extent2D;
+
+	fn get_images(&self) -> &[abstraction::Image];
+}
+
+impl PresentTarget for Swapchain {
+	fn acquire_next_image(&mut self, timeout: u64, semaphore: Option<&abstraction::Semaphore>, fence: vk::Fence) -> Result<(u32, bool), vk::Result> {
+		Swapchain::acquire_next_image(self, timeout, semaphore, fence)
+	}
+
+	fn present(&mut self, queue: &abstraction::Queue, image_index: u32, wait_semaphores: &[&abstraction::Semaphore]) -> Result<bool, vk::Result> {
+		Swapchain::present(self, queue, image_index, wait_semaphores)
+	}
+
+	fn recreate(&mut self, extent: vk::Extent2D) -> Result<(), vk::Result> {
+		Swapchain::recreate(self, extent)
+	}
+
+	fn get_device(&self) -> abstraction::Device {
+		Swapchain::get_device(self)
+	}
+
+	fn get_extent(&self) -> vk::Extent2D {
+		Swapchain::get_extent(self)
+	}
+
+	fn get_images(&self) -> &[abstraction::Image] {
+		Swapchain::get_images(self)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	/// Preferences are honored where supported and substituted otherwise
+	fn test_choose() {
+		let srgb = vk::SurfaceFormatKHR {
+			format: vk::Format::B8G8R8A8_SRGB,
+			color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+		};
+		let unorm = vk::SurfaceFormatKHR {
+			format: vk::Format::B8G8R8A8_UNORM,
+			color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+		};
+		assert_eq!(choose_surface_format(&[unorm, srgb], &[srgb]), srgb);
+		assert_eq!(choose_surface_format(&[unorm], &[srgb]), unorm);
+		assert_eq!(choose_present_mode(&[vk::PresentModeKHR::FIFO], &[vk::PresentModeKHR::MAILBOX]), vk::PresentModeKHR::FIFO);
+
+		let mut capabilities = vk::SurfaceCapabilitiesKHR {
+			min_image_count: 2,
+			max_image_count: 0,
+			current_extent: vk::Extent2D { width: u32::MAX, height: u32::MAX },
+			min_image_extent: vk::Extent2D { width: 1, height: 1 },
+			max_image_extent: vk::Extent2D { width: 4096, height: 4096 },
+			..Default::default()
+		};
+		assert_eq!(choose_extent(&capabilities, vk::Extent2D { width: 8192, height: 720 }), vk::Extent2D { width: 4096, height: 720 });
+		assert_eq!(choose_image_count(&capabilities, 8), 8);
+		capabilities.current_extent = vk::Extent2D { width: 1280, height: 720 };
+		capabilities.max_image_count = 3;
+		assert_eq!(choose_extent(&capabilities, vk::Extent2D { width: 8192, height: 720 }), vk::Extent2D { width: 1280, height: 720 });
+		assert_eq!(choose_image_count(&capabilities, 8), 3);
+		assert_eq!(choose_image_count(&capabilities, 1), 2);
 
+		let supported = vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC;
+		let config = SwapchainConfig::default();
+		assert_eq!(choose_image_usage(supported, config.image_usage, config.optional_image_usage), Some(vk::ImageUsageFlags::COLOR_ATTACHMENT));
+		assert_eq!(choose_image_usage(supported, vk::ImageUsageFlags::STORAGE, config.optional_image_usage), None);
 	}
 }
@@ -0,0 +1,58 @@
+/*
+* Integrates windows with [crate::abstraction::Surface], along with winit behind the `winit` feature
+*/
+
+use std::ffi::CStr;
+use ash::vk;
+use raw_window_handle::HasRawDisplayHandle;
+#[cfg(feature = "winit")]
+use crate::abstraction::prelude as abstraction;
+
+/// Get the instance extensions needed to create surfaces on the window system of `display`
+///
+/// `display` may be anything which exposes a display handle, such as a winit event loop before
+/// any window exists. Pass the extensions to [crate::abstraction::InstanceCreateInfo::required_extensions].
+pub fn get_required_surface_extensions(display: &impl HasRawDisplayHandle) -> Result<Vec<String>, vk::Result> {
+	let extensions = ash_window::enumerate_required_extensions(display.raw_display_handle())?;
+	Ok(extensions.iter()
+		.map(|extension| unsafe { CStr::from_ptr(*extension) }.to_string_lossy().into_owned())
+		.collect())
+}
+
+#[cfg(feature = "winit")]
+impl abstraction::Surface {
+	/// Create a surface for a winit `window`
+	pub fn from_window(instance: &abstraction::Instance, window: &winit::window::Window) -> Result<Self, vk::Result> {
+		use raw_window_handle::HasRawWindowHandle;
+		Self::from_raw_handles(instance, abstraction::SurfaceLoader::new(instance), window.raw_display_handle(), window.raw_window_handle())
+	}
+}
+
+/// Get the new extent of the window if `event` resized it, the swapchain should then be
+/// recreated with it
+///
+/// A zero extent means the window was minimized, in which case nothing should be presented
+/// until it is resized again.
+#[cfg(feature = "winit")]
+pub fn get_resized_extent(event: &winit::event::WindowEvent) -> Option<vk::Extent2D> {
+	match event {
+		winit::event::WindowEvent::Resized(size) => Some(vk::Extent2D {
+			width: size.width,
+			height: size.height,
+		}),
+		_ => None,
+	}
+}
+
+#[cfg(all(test, feature = "winit"))]
+mod tests {
+	use super::*;
+
+	#[test]
+	/// Only resize events carry an extent
+	fn test_get_resized_extent() {
+		let resized = winit::event::WindowEvent::Resized(winit::dpi::PhysicalSize::new(1280, 0));
+		assert_eq!(get_resized_extent(&resized), Some(vk::Extent2D { width: 1280, height: 0 }));
+		assert_eq!(get_resized_extent(&winit::event::WindowEvent::CloseRequested), None);
+	}
+}
@@ -0,0 +1,4 @@
+//! Utilities for recording commands across multiple threads
+pub mod threaded_allocator;
+
+pub use threaded_allocator::*;
@@ -0,0 +1,136 @@
+//! [abstraction::CommandPool] cannot be used from several threads at once. [ThreadedCommandAllocator]
+//! gives every thread which records commands its own pool for each frame in flight.
+//!
+//! Pools of a frame are only reset once the frame's timeline value has been reached, at which
+//! point none of the command buffers allocated from them can still be pending on the GPU.
+use crate::abstraction::prelude as abstraction;
+use ash::vk;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread::ThreadId;
+
+/// Every command pool used by a single frame in flight
+struct FrameCommandPools {
+    /// Timeline value which is reached once every command buffer of the frame has retired
+    retire_value: u64,
+    pools: HashMap<ThreadId, abstraction::CommandPool>,
+}
+
+pub struct ThreadedCommandAllocator {
+    device: abstraction::Device,
+    queue_family_index: u32,
+    frames: Vec<Mutex<FrameCommandPools>>,
+}
+
+impl ThreadedCommandAllocator {
+    /// Allocate from pools of `queue_family_index` for `frames_in_flight` frames
+    ///
+    /// # Panics
+    ///
+    /// If `frames_in_flight` is zero.
+    pub fn new(device: abstraction::Device, queue_family_index: u32, frames_in_flight: usize) -> Self {
+        assert!(frames_in_flight > 0, "at least one frame in flight is needed");
+        let frames = (0..frames_in_flight)
+            .map(|_| {
+                Mutex::new(FrameCommandPools {
+                    retire_value: 0,
+                    pools: HashMap::new(),
+                })
+            })
+            .collect();
+        Self {
+            device,
+            queue_family_index,
+            frames,
+        }
+    }
+
+    /// Prepares the pools of `frame_index` to be used again
+    ///
+    /// Blocks until `timeline` reaches the retire value passed to [Self::end_frame] the last
+    /// time the frame was used, then resets every thread's pool of the frame.
+    ///
+    /// # Safety
+    ///
+    /// `timeline` must be the semaphore whose values were passed to [Self::end_frame], and every
+    /// command buffer allocated for `frame_index` must have been submitted such that reaching
+    /// that value means it has finished executing. Command buffers of the frame which were
+    /// never submitted must not be in use by any other thread.
+    pub unsafe fn begin_frame(
+        &self,
+        frame_index: usize,
+        timeline: &abstraction::Semaphore,
+    ) -> Result<(), vk::Result> {
+        let mut frame = self.get_frame(frame_index).lock().unwrap();
+        if frame.retire_value > 0 {
+            timeline.wait(frame.retire_value, u64::MAX)?;
+        }
+        for pool in frame.pools.values_mut() {
+            // The caller guarantees the timeline value means every command buffer has finished
+            pool.reset()?;
+        }
+        Ok(())
+    }
+
+    /// Record the timeline value which is signalled once every command buffer allocated for
+    /// `frame_index` has finished executing
+    pub fn end_frame(&self, frame_index: usize, retire_value: u64) {
+        self.get_frame(frame_index).lock().unwrap().retire_value = retire_value;
+    }
+
+    /// Allocate a primary command buffer from the calling thread's pool
+    pub fn allocate(
+        &self,
+        frame_index: usize,
+    ) -> Result<abstraction::CommandBuffer<abstraction::Initial>, vk::Result> {
+        self.with_thread_pool(frame_index, |pool| pool.allocate())
+    }
+
+    /// Allocate a secondary command buffer from the calling thread's pool
+    ///
+    /// Secondary command buffers allow draw calls of a single dynamic rendering pass to be
+    /// recorded in parallel using [abstraction::CommandBuffer::begin_secondary], before being
+    /// executed by the primary command buffer using [abstraction::CommandBuffer::execute_commands].
+    pub fn allocate_secondary(
+        &self,
+        frame_index: usize,
+    ) -> Result<abstraction::CommandBuffer<abstraction::Initial>, vk::Result> {
+        self.with_thread_pool(frame_index, |pool| pool.allocate_secondary())
+    }
+
+    /// Run `f` on the calling thread's pool for `frame_index`, creating the pool if the thread
+    /// has not allocated from the frame before
+    ///
+    /// The frame is only locked while allocating, recording happens without any locks as no
+    /// other thread will ever allocate from the same pool.
+    fn with_thread_pool<R>(
+        &self,
+        frame_index: usize,
+        f: impl FnOnce(&mut abstraction::CommandPool) -> Result<R, vk::Result>,
+    ) -> Result<R, vk::Result> {
+        let mut frame = self.get_frame(frame_index).lock().unwrap();
+        let thread_id = std::thread::current().id();
+        let pool = match frame.pools.entry(thread_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(abstraction::CommandPool::new(
+                self.device.clone(),
+                self.queue_family_index,
+                vk::CommandPoolCreateFlags::TRANSIENT,
+            )?),
+        };
+        f(pool)
+    }
+
+    fn get_frame(&self, frame_index: usize) -> &Mutex<FrameCommandPools> {
+        &self.frames[frame_index % self.frames.len()]
+    }
+
+    pub fn get_frames_in_flight(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn get_queue_family_index(&self) -> u32 {
+        self.queue_family_index
+    }
+}
@@ -0,0 +1,513 @@
+use crate::abstraction::prelude as abstraction;
+use crate::framework::command::ThreadedCommandAllocator;
+use abstraction::PresentTarget;
+use ash::vk;
+use ash::vk::TaggedStructure;
+
+/// A frame handed out by [FrameManager::begin_frame]
+#[derive(Copy, Clone, Debug)]
+pub struct FrameInfo {
+    /// Index of the frame in flight, pass it to the [ThreadedCommandAllocator]
+    pub frame_index: usize,
+    /// Index of the swapchain image to render into
+    pub image_index: u32,
+    /// Number of frames submitted before this one
+    pub frame_number: u64,
+    pub extent: vk::Extent2D,
+}
+
+/// Resources of a single frame in flight
+struct Frame {
+    /// Signaled once the acquired swapchain image can be rendered into
+    image_available: abstraction::Semaphore,
+}
+
+/// Acquires, submits and presents frames in flight to a [abstraction::Swapchain]
+///
+/// Any other [PresentTarget] works as well, such as an [abstraction::OffscreenSwapchain] to run
+/// the same loop without a window system.
+///
+/// Frame `n` signals the timeline semaphore to `n + 1` once its commands have finished, which
+/// is waited on before the frame's resources are used again by frame `n + frames_in_flight`.
+/// ```ignore
+/// if let Some(frame) = frame_manager.begin_frame()? {
+///     let command_buffer = frame_manager
+///         .get_command_allocator()
+///         .allocate(frame.frame_index)?
+///         .begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+///     // Render into the image and transition it to PRESENT_SRC_KHR
+///     frame_manager.end_frame(&[command_buffer.end()?])?;
+/// }
+/// ```
+pub struct FrameManager<S: PresentTarget = abstraction::Swapchain> {
+    device: abstraction::Device,
+    queue: abstraction::Queue,
+    swapchain: S,
+    frames: Vec<Frame>,
+    /// Signaled once an image may be presented, one for each swapchain image as a present
+    /// cannot be waited on
+    render_finished: Vec<abstraction::Semaphore>,
+    timeline: abstraction::Semaphore,
+    command_allocator: ThreadedCommandAllocator,
+    /// Stages of the submitted commands which wait for the swapchain image to be acquired
+    wait_stage_mask: vk::PipelineStageFlags2,
+    /// Extent of the window, used whenever the surface leaves it up to the swapchain
+    extent: vk::Extent2D,
+    needs_recreate: bool,
+    frame_number: u64,
+    /// Image acquired by [Self::begin_frame] which has not been presented yet
+    acquired_image: Option<u32>,
+    /// Stepped once per submitted frame, see [Self::deletion_queue]
+    deletion_queue: Option<abstraction::PipelineDeletionQueue>,
+}
+
+impl<S: PresentTarget> FrameManager<S> {
+    /// Manage `frames_in_flight` frames presented to `swapchain` from `queue`
+    pub fn new(
+        queue: abstraction::Queue,
+        swapchain: S,
+        frames_in_flight: usize,
+    ) -> Result<Self, vk::Result> {
+        assert!(frames_in_flight > 0);
+        let device = swapchain.get_device();
+        let frames = (0..frames_in_flight)
+            .map(|_| {
+                Ok(Frame {
+                    image_available: abstraction::Semaphore::new(device.clone())?,
+                })
+            })
+            .collect::<Result<Vec<_>, vk::Result>>()?;
+        let render_finished = (0..swapchain.get_images().len())
+            .map(|_| abstraction::Semaphore::new(device.clone()))
+            .collect::<Result<Vec<_>, vk::Result>>()?;
+        Ok(Self {
+            timeline: abstraction::Semaphore::new_timeline(device.clone(), 0)?,
+            command_allocator: ThreadedCommandAllocator::new(
+                device.clone(),
+                queue.get_family_index(),
+                frames_in_flight,
+            ),
+            wait_stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags2::ALL_TRANSFER,
+            extent: swapchain.get_extent(),
+            device,
+            queue,
+            swapchain,
+            frames,
+            render_finished,
+            needs_recreate: false,
+            frame_number: 0,
+            acquired_image: None,
+            deletion_queue: None,
+        })
+    }
+
+    /// Let the manager drive `deletion_queue`, so dropped pipelines are destroyed once every
+    /// frame which may have used them has finished
+    ///
+    /// Every submitted frame is one cycle of the queue and items last for as many cycles as
+    /// there are frames in flight. Expired items are deleted by [Self::begin_frame] after it
+    /// waited for the oldest frame in flight.
+    pub fn deletion_queue(mut self, deletion_queue: abstraction::PipelineDeletionQueue) -> Self {
+        deletion_queue.set_max_cycles(self.frames.len() as u64);
+        self.deletion_queue = Some(deletion_queue);
+        self
+    }
+
+    /// Set the stages which wait for the swapchain image to be acquired, defaults to color
+    /// attachment output and transfers
+    pub fn wait_stage_mask(mut self, wait_stage_mask: vk::PipelineStageFlags2) -> Self {
+        self.wait_stage_mask = wait_stage_mask;
+        self
+    }
+
+    /// Let the manager know the window was resized, the swapchain is recreated on the next
+    /// [Self::begin_frame]
+    ///
+    /// A zero `extent` skips frames until the window is resized again.
+    pub fn resize(&mut self, extent: vk::Extent2D) {
+        self.extent = extent;
+        self.needs_recreate = true;
+    }
+
+    /// Pass a winit window event along, resizing if the window was resized
+    #[cfg(feature = "winit")]
+    pub fn handle_window_event(&mut self, event: &winit::event::WindowEvent) {
+        if let Some(extent) = abstraction::get_resized_extent(event) {
+            self.resize(extent);
+        }
+    }
+
+    /// Wait for the next frame in flight and acquire a swapchain image for it
+    ///
+    /// Returns [None] if there is nothing to render into, such as while the window is
+    /// minimized, in which case [Self::end_frame] must not be called.
+    pub fn begin_frame(&mut self) -> Result<Option<FrameInfo>, vk::Result> {
+        assert!(
+            self.acquired_image.is_none(),
+            "begin_frame called twice without end_frame"
+        );
+        let frame_index = self.get_frame_index();
+        // Waits for the frame's previous submission, which also waited on its acquire semaphore.
+        // Safe as the frame's command buffers are submitted by end_frame, which signals the
+        // timeline value it passed to the allocator once they have finished executing.
+        unsafe {
+            self.command_allocator
+                .begin_frame(frame_index, &self.timeline)?;
+        }
+        if let Some(deletion_queue) = self.deletion_queue.as_ref() {
+            deletion_queue.delete_expired_items();
+        }
+        // Retry once if the swapchain turns out to be out of date while acquiring
+        for _ in 0..2 {
+            if self.needs_recreate && !self.recreate()? {
+                return Ok(None);
+            }
+            let acquired = self.swapchain.acquire_next_image(
+                u64::MAX,
+                Some(&self.frames[frame_index].image_available),
+                vk::Fence::null(),
+            );
+            match acquired {
+                Ok((image_index, suboptimal)) => {
+                    // A suboptimal swapchain can still be presented to, so finish the frame first
+                    self.needs_recreate |= suboptimal;
+                    self.acquired_image = Some(image_index);
+                    return Ok(Some(FrameInfo {
+                        frame_index,
+                        image_index,
+                        frame_number: self.frame_number,
+                        extent: self.swapchain.get_extent(),
+                    }));
+                }
+                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.needs_recreate = true,
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Submit `command_buffers` for the frame started by [Self::begin_frame] and present its
+    /// image
+    ///
+    /// The commands must leave the image in [vk::ImageLayout::PRESENT_SRC_KHR].
+    pub fn end_frame(
+        &mut self,
+        command_buffers: &[abstraction::CommandBuffer<abstraction::Executable>],
+    ) -> Result<(), vk::Result> {
+        let image_index = self
+            .acquired_image
+            .take()
+            .expect("end_frame called without a frame from begin_frame");
+        let frame_index = self.get_frame_index();
+        let retire_value = self.frame_number + 1;
+        let render_finished = &self.render_finished[image_index as usize];
+
+        let wait_semaphores = [self.frames[frame_index]
+            .image_available
+            .submit_info(0, self.wait_stage_mask)];
+        let signal_semaphores = [
+            self.timeline
+                .submit_info(retire_value, vk::PipelineStageFlags2::ALL_COMMANDS),
+            render_finished.submit_info(0, vk::PipelineStageFlags2::ALL_COMMANDS),
+        ];
+        let command_buffer_infos: Vec<vk::CommandBufferSubmitInfo> = command_buffers
+            .iter()
+            .map(|command_buffer| command_buffer.submit_info())
+            .collect();
+        let submit_info = vk::SubmitInfo2 {
+            s_type: vk::SubmitInfo2::STRUCTURE_TYPE,
+            wait_semaphore_info_count: wait_semaphores.len() as u32,
+            p_wait_semaphore_infos: wait_semaphores.as_ptr(),
+            command_buffer_info_count: command_buffer_infos.len() as u32,
+            p_command_buffer_infos: command_buffer_infos.as_ptr(),
+            signal_semaphore_info_count: signal_semaphores.len() as u32,
+            p_signal_semaphore_infos: signal_semaphores.as_ptr(),
+            ..Default::default()
+        };
+        unsafe {
+            self.device.handle_as_ref().queue_submit2(
+                self.queue.get_handle(),
+                &[submit_info],
+                vk::Fence::null(),
+            )?;
+        }
+        self.command_allocator.end_frame(frame_index, retire_value);
+        self.frame_number = retire_value;
+        if let Some(deletion_queue) = self.deletion_queue.as_ref() {
+            deletion_queue.step();
+        }
+
+        match self
+            .swapchain
+            .present(&self.queue, image_index, &[render_finished])
+        {
+            Ok(suboptimal) => self.needs_recreate |= suboptimal,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.needs_recreate = true,
+            Err(error) => return Err(error),
+        }
+        Ok(())
+    }
+
+    /// Recreate the swapchain, returning whether there is something to render into
+    fn recreate(&mut self) -> Result<bool, vk::Result> {
+        if self.extent.width == 0 || self.extent.height == 0 {
+            return Ok(false);
+        }
+        // Images of the old swapchain are destroyed right away, so wait for every frame
+        // including their presents
+        unsafe {
+            self.device
+                .handle_as_ref()
+                .queue_wait_idle(self.queue.get_handle())?;
+        }
+        match self.swapchain.recreate(self.extent) {
+            Ok(()) => {}
+            // The surface has no area, so keep trying until it has
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => return Ok(false),
+            Err(error) => return Err(error),
+        }
+        self.needs_recreate = false;
+        let image_count = self.swapchain.get_images().len();
+        if self.render_finished.len() != image_count {
+            self.render_finished = (0..image_count)
+                .map(|_| abstraction::Semaphore::new(self.device.clone()))
+                .collect::<Result<Vec<_>, vk::Result>>()?;
+        }
+        Ok(true)
+    }
+
+    /// Block until every submitted frame has finished executing
+    pub fn wait_idle(&self) -> Result<(), vk::Result> {
+        if self.frame_number == 0 {
+            return Ok(());
+        }
+        self.timeline.wait(self.frame_number, u64::MAX)
+    }
+
+    fn get_frame_index(&self) -> usize {
+        (self.frame_number % self.frames.len() as u64) as usize
+    }
+
+    pub fn get_swapchain(&self) -> &S {
+        &self.swapchain
+    }
+
+    /// Get the allocator of the frame's command buffers, which are reset by [Self::begin_frame]
+    /// once the frame has finished, so they must be submitted through [Self::end_frame]
+    pub fn get_command_allocator(&self) -> &ThreadedCommandAllocator {
+        &self.command_allocator
+    }
+
+    /// Get the timeline semaphore, which frame `n` signals to `n + 1` once it has finished
+    pub fn get_timeline(&self) -> &abstraction::Semaphore {
+        &self.timeline
+    }
+
+    pub fn get_queue(&self) -> &abstraction::Queue {
+        &self.queue
+    }
+
+    pub fn get_frames_in_flight(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Get the number of frames submitted so far
+    pub fn get_frame_number(&self) -> u64 {
+        self.frame_number
+    }
+}
+
+impl<S: PresentTarget> Drop for FrameManager<S> {
+    fn drop(&mut self) {
+        // Semaphores and command pools must not be in use once destroyed
+        unsafe {
+            let _ = self
+                .device
+                .handle_as_ref()
+                .queue_wait_idle(self.queue.get_handle());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::{c_char, CString};
+    use std::sync::{Arc, Mutex};
+
+    /// Barrier moving the color of `image` from `old_layout` to `new_layout` around a clear
+    fn get_clear_barrier(
+        image: vk::Image,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+    ) -> vk::ImageMemoryBarrier2 {
+        vk::ImageMemoryBarrier2 {
+            s_type: vk::ImageMemoryBarrier2::STRUCTURE_TYPE,
+            src_stage_mask: vk::PipelineStageFlags2::ALL_TRANSFER,
+            src_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
+            dst_stage_mask: vk::PipelineStageFlags2::ALL_TRANSFER,
+            dst_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
+            old_layout,
+            new_layout,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image,
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    /// A frame cleared through the manager reaches the sink of an offscreen swapchain
+    ///
+    /// Needs a device which supports Vulkan 1.3 and headless surfaces.
+    fn test_offscreen_frame() {
+        let entry = ash::Entry::linked();
+        let extensions: Vec<CString> = abstraction::get_headless_surface_extensions()
+            .into_iter()
+            .map(|name| CString::new(name).unwrap())
+            .collect();
+        let extension_names: Vec<*const c_char> =
+            extensions.iter().map(|name| name.as_ptr()).collect();
+        let app_info = vk::ApplicationInfo {
+            s_type: vk::ApplicationInfo::STRUCTURE_TYPE,
+            api_version: vk::API_VERSION_1_3,
+            ..Default::default()
+        };
+        let instance_ci = vk::InstanceCreateInfo {
+            s_type: vk::InstanceCreateInfo::STRUCTURE_TYPE,
+            p_application_info: &app_info,
+            enabled_extension_count: extension_names.len() as u32,
+            pp_enabled_extension_names: extension_names.as_ptr(),
+            ..Default::default()
+        };
+        let ash_instance = unsafe { entry.create_instance(&instance_ci, None).unwrap() };
+        let instance = abstraction::Instance::new(ash_instance, entry);
+        let surface =
+            abstraction::Surface::headless(&instance, abstraction::SurfaceLoader::new(&instance))
+                .unwrap();
+        let mut requirements = abstraction::get_minimum_viable_requirements();
+        requirements.queues.push(abstraction::QueueRequirements {
+            queue_flags: vk::QueueFlags::GRAPHICS,
+            dedicated: false,
+            count: 1,
+        });
+        let physical_device =
+            abstraction::select_suitable_physical_device(instance.clone(), Some(requirements))
+                .pop()
+                .unwrap();
+        let device = abstraction::Device::new(&instance, physical_device).unwrap();
+        let capabilities = surface
+            .get_capabilities(device.get_physical_device())
+            .unwrap();
+        let queue_family = device
+            .get_queue_families()
+            .iter()
+            .find(|queue_family| queue_family.get_flags().contains(vk::QueueFlags::GRAPHICS))
+            .unwrap();
+        let queue = device.get_queue(queue_family.get_index(), 0);
+
+        let presented = Arc::new(Mutex::new(Vec::new()));
+        let sink_presented = presented.clone();
+        let sink = abstraction::FrameSink::Callback(Box::new(
+            move |frame: &abstraction::OffscreenFrame| {
+                sink_presented.lock().unwrap().push((
+                    frame.frame_number,
+                    frame.image_index,
+                    frame.extent,
+                    frame.pixels.to_vec(),
+                ));
+            },
+        ));
+        let extent = vk::Extent2D {
+            width: 4,
+            height: 2,
+        };
+        let config = abstraction::SwapchainConfig {
+            formats: vec![vk::SurfaceFormatKHR {
+                format: vk::Format::R8G8B8A8_UNORM,
+                color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            }],
+            extent,
+            image_count: capabilities.min_image_count.max(2),
+            ..Default::default()
+        };
+        let swapchain = abstraction::OffscreenSwapchain::new(queue.clone(), config, sink).unwrap();
+        let mut frame_manager = FrameManager::new(queue, swapchain, 2).unwrap();
+
+        let frame = frame_manager.begin_frame().unwrap().unwrap();
+        assert_eq!(frame.frame_number, 0);
+        assert_eq!(frame.extent, extent);
+        let image = frame_manager.get_swapchain().get_images()[frame.image_index as usize]
+            .get_handle();
+        let command_buffer = frame_manager
+            .get_command_allocator()
+            .allocate(frame.frame_index)
+            .unwrap()
+            .begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+            .unwrap();
+        command_buffer.pipeline_barrier2(&vk::DependencyInfo {
+            s_type: vk::DependencyInfo::STRUCTURE_TYPE,
+            image_memory_barrier_count: 1,
+            p_image_memory_barriers: &get_clear_barrier(
+                image,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            ),
+            ..Default::default()
+        });
+        unsafe {
+            device.handle_as_ref().cmd_clear_color_image(
+                command_buffer.get_handle(),
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &vk::ClearColorValue {
+                    float32: [1.0, 0.0, 0.0, 1.0],
+                },
+                &[vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                }],
+            );
+        }
+        command_buffer.pipeline_barrier2(&vk::DependencyInfo {
+            s_type: vk::DependencyInfo::STRUCTURE_TYPE,
+            image_memory_barrier_count: 1,
+            p_image_memory_barriers: &get_clear_barrier(
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::PRESENT_SRC_KHR,
+            ),
+            ..Default::default()
+        });
+        frame_manager
+            .end_frame(&[command_buffer.end().unwrap()])
+            .unwrap();
+        assert_eq!(frame_manager.get_frame_number(), 1);
+
+        let presented = presented.lock().unwrap();
+        assert_eq!(presented.len(), 1);
+        let (frame_number, image_index, presented_extent, pixels) = &presented[0];
+        assert_eq!(*frame_number, 0);
+        assert_eq!(*image_index, frame.image_index);
+        assert_eq!(*presented_extent, extent);
+        assert_eq!(pixels.len(), 4 * 2 * 4);
+        assert!(pixels.chunks_exact(4).all(|pixel| pixel == [255, 0, 0, 255]));
+
+        // Clean up
+        drop(frame_manager);
+        drop(device);
+        drop(surface);
+        drop(instance);
+    }
+}
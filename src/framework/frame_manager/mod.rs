@@ -0,0 +1,10 @@
+//! Frames in flight presented to a swapchain
+//!
+//! [FrameManager] runs the acquire, submit and present loop for a fixed number of frames in
+//! flight. Every frame has its own acquire semaphore and command pools, which are only reused
+//! once a timeline semaphore shows the frame's commands have finished. Out of date and
+//! suboptimal swapchains are recreated on the next [FrameManager::begin_frame], and frames are
+//! skipped while the surface has no area such as when the window is minimized.
+pub mod manager;
+
+pub use manager::*;
@@ -1,2 +1,7 @@
 mod bootstrap;
+pub mod command;
+pub mod frame_manager;
 pub mod platform;
+pub mod render_graph;
+pub mod resource_table;
+pub mod shader_registry;
@@ -0,0 +1,335 @@
+//! Lets transient resources whose lifetimes do not overlap share memory
+//!
+//! Lifetimes are the range of compiled passes using a resource. Resources are placed from the
+//! largest to the smallest into blocks of the same memory type, at the lowest aligned offset
+//! which does not overlap the memory of a resource alive at the same time. Buffers and images
+//! never share a block, so `bufferImageGranularity` does not have to be respected.
+//!
+//! Contents of aliased memory are undefined once another resource used it, so the first use of
+//! every aliased resource has to wait on the last accesses of the resources it replaces and
+//! discard the previous contents.
+use super::compiler::{CompiledGraph, GraphBarrier};
+use super::resource::ResourceId;
+use ash::vk;
+
+/// Memory needed by a single transient resource
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct AliasingRequest {
+    pub resource: ResourceId,
+    pub size: vk::DeviceSize,
+    pub alignment: vk::DeviceSize,
+    pub memory_type_index: u32,
+    pub is_image: bool,
+    /// Index of the first compiled pass using the resource
+    pub first_pass: usize,
+    /// Index of the last compiled pass using the resource
+    pub last_pass: usize,
+}
+
+impl AliasingRequest {
+    fn overlaps_lifetime(&self, other: &AliasingRequest) -> bool {
+        self.first_pass <= other.last_pass && other.first_pass <= self.last_pass
+    }
+}
+
+/// A single allocation shared by several resources
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct MemoryBlock {
+    pub memory_type_index: u32,
+    pub size: vk::DeviceSize,
+    pub is_image: bool,
+}
+
+/// Where a resource is bound to
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Placement {
+    pub resource: ResourceId,
+    /// Index into [AliasingPlan::blocks]
+    pub block: usize,
+    pub offset: vk::DeviceSize,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct AliasingStatistics {
+    /// Memory needed if every resource had its own allocation
+    pub requested_size: vk::DeviceSize,
+    /// Memory actually allocated
+    pub allocated_size: vk::DeviceSize,
+}
+
+impl AliasingStatistics {
+    pub fn get_saved_size(&self) -> vk::DeviceSize {
+        self.requested_size - self.allocated_size
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct AliasingPlan {
+    pub blocks: Vec<MemoryBlock>,
+    pub placements: Vec<Placement>,
+    pub statistics: AliasingStatistics,
+    requests: Vec<AliasingRequest>,
+}
+
+impl AliasingPlan {
+    /// Place every request into as little memory as possible
+    pub fn new(requests: &[AliasingRequest]) -> Self {
+        let mut sorted: Vec<&AliasingRequest> = requests.iter().collect();
+        sorted.sort_by(|a, b| b.size.cmp(&a.size).then(a.first_pass.cmp(&b.first_pass)));
+
+        let mut plan = Self {
+            requests: requests.to_vec(),
+            ..Default::default()
+        };
+        for request in sorted {
+            plan.statistics.requested_size += request.size;
+            let placement = (0..plan.blocks.len()).find_map(|block| {
+                plan.find_offset(block, request).map(|offset| Placement {
+                    resource: request.resource,
+                    block,
+                    offset,
+                })
+            });
+            let placement = match placement {
+                Some(placement) => placement,
+                None => {
+                    plan.blocks.push(MemoryBlock {
+                        memory_type_index: request.memory_type_index,
+                        size: request.size,
+                        is_image: request.is_image,
+                    });
+                    plan.statistics.allocated_size += request.size;
+                    Placement {
+                        resource: request.resource,
+                        block: plan.blocks.len() - 1,
+                        offset: 0,
+                    }
+                }
+            };
+            plan.placements.push(placement);
+        }
+        plan
+    }
+
+    /// Find the lowest offset in `block` where `request` does not overlap any resource alive
+    /// at the same time
+    fn find_offset(&self, block: usize, request: &AliasingRequest) -> Option<vk::DeviceSize> {
+        let memory_block = &self.blocks[block];
+        if memory_block.memory_type_index != request.memory_type_index
+            || memory_block.is_image != request.is_image
+        {
+            return None;
+        }
+        let mut occupied: Vec<(vk::DeviceSize, vk::DeviceSize)> = self
+            .placements
+            .iter()
+            .filter(|placement| placement.block == block)
+            .filter(|placement| self.get_request(placement.resource).overlaps_lifetime(request))
+            .map(|placement| {
+                (
+                    placement.offset,
+                    placement.offset + self.get_request(placement.resource).size,
+                )
+            })
+            .collect();
+        occupied.sort();
+
+        let alignment = request.alignment.max(1);
+        let mut offset: vk::DeviceSize = 0;
+        for (start, end) in occupied {
+            if offset + request.size <= start {
+                break;
+            }
+            offset = offset.max(end.div_ceil(alignment) * alignment);
+        }
+        if offset + request.size <= memory_block.size {
+            Some(offset)
+        } else {
+            None
+        }
+    }
+
+    fn get_request(&self, resource: ResourceId) -> &AliasingRequest {
+        self.requests
+            .iter()
+            .find(|request| request.resource == resource)
+            .unwrap()
+    }
+
+    pub fn get_placement(&self, resource: ResourceId) -> Option<&Placement> {
+        self.placements
+            .iter()
+            .find(|placement| placement.resource == resource)
+    }
+
+    /// Get every resource which used the memory of `resource` before it
+    pub fn get_aliased_resources(&self, resource: ResourceId) -> Vec<ResourceId> {
+        let placement = match self.get_placement(resource) {
+            Some(placement) => placement,
+            None => return Vec::new(),
+        };
+        let request = self.get_request(resource);
+        self.placements
+            .iter()
+            .filter(|other| other.block == placement.block && other.resource != resource)
+            .filter(|other| {
+                let other_request = self.get_request(other.resource);
+                other_request.last_pass < request.first_pass
+                    && other.offset < placement.offset + request.size
+                    && placement.offset < other.offset + other_request.size
+            })
+            .map(|other| other.resource)
+            .collect()
+    }
+
+    /// Add the barriers needed before aliased resources are used for the first time
+    pub fn apply(&self, compiled: &CompiledGraph) -> CompiledGraph {
+        let mut aliased = compiled.clone();
+        for placement in self.placements.iter() {
+            let usage = match compiled.get_resource_usage(placement.resource) {
+                Some(usage) => usage,
+                None => continue,
+            };
+            let mut prev_accesses = Vec::new();
+            for resource in self.get_aliased_resources(placement.resource) {
+                if let Some(aliased_usage) = compiled.get_resource_usage(resource) {
+                    for access in aliased_usage.last_accesses.iter() {
+                        if !prev_accesses.contains(access) {
+                            prev_accesses.push(*access);
+                        }
+                    }
+                }
+            }
+            if prev_accesses.is_empty() {
+                continue;
+            }
+
+            let barriers = &mut aliased.passes[usage.first_pass].barriers;
+            match barriers
+                .iter_mut()
+                .find(|barrier| barrier.resource == placement.resource)
+            {
+                Some(barrier) => {
+                    for access in prev_accesses {
+                        if !barrier.prev_accesses.contains(&access) {
+                            barrier.prev_accesses.push(access);
+                        }
+                    }
+                    barrier.discard_contents = true;
+                }
+                None => barriers.push(GraphBarrier {
+                    resource: placement.resource,
+                    prev_accesses,
+                    next_accesses: usage.first_accesses.clone(),
+                    discard_contents: true,
+                }),
+            }
+        }
+        aliased
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::abstraction::prelude as abstraction;
+    use crate::framework::render_graph::{BufferDesc, RenderGraph};
+
+    fn request(resource: usize, size: vk::DeviceSize, first_pass: usize, last_pass: usize) -> AliasingRequest {
+        AliasingRequest {
+            resource: ResourceId(resource),
+            size,
+            alignment: 256,
+            memory_type_index: 0,
+            is_image: true,
+            first_pass,
+            last_pass,
+        }
+    }
+
+    #[test]
+    /// Resources with disjoint lifetimes share memory, overlapping ones do not
+    fn test_interval_packing() {
+        let plan = AliasingPlan::new(&[
+            request(0, 4096, 0, 1),
+            request(1, 4096, 1, 2),
+            request(2, 2048, 2, 3),
+            request(3, 1000, 3, 3),
+        ]);
+        assert_eq!(plan.blocks.len(), 2);
+        assert_eq!(plan.get_placement(ResourceId(0)).unwrap().offset, 0);
+        // Alive at the same time as resource 0 so it needs its own memory
+        assert_eq!(plan.get_placement(ResourceId(1)).unwrap().block, 1);
+        // Resource 0 is dead by pass 2
+        assert_eq!(plan.get_placement(ResourceId(2)).unwrap().block, 0);
+        // Placed after resource 2 in the first block, respecting the alignment
+        let fourth = plan.get_placement(ResourceId(3)).unwrap();
+        assert_eq!((fourth.block, fourth.offset), (0, 2048));
+        assert_eq!(plan.statistics.requested_size, 4096 + 4096 + 2048 + 1000);
+        assert_eq!(plan.statistics.allocated_size, 8192);
+        assert_eq!(plan.statistics.get_saved_size(), 3048);
+    }
+
+    #[test]
+    /// Memory types and resource kinds are never mixed within a block
+    fn test_memory_type_separation() {
+        let mut buffer = request(1, 1024, 2, 3);
+        buffer.is_image = false;
+        let mut other_type = request(2, 1024, 2, 3);
+        other_type.memory_type_index = 1;
+        let plan = AliasingPlan::new(&[request(0, 1024, 0, 1), buffer, other_type]);
+        assert_eq!(plan.blocks.len(), 3);
+        assert_eq!(plan.statistics.get_saved_size(), 0);
+    }
+
+    #[test]
+    /// The first use of an aliased resource waits on the last use of the resource it replaces
+    fn test_aliasing_barriers() {
+        let mut graph = RenderGraph::new();
+        let first = graph.create_buffer("first", BufferDesc::new(1024));
+        let second = graph.create_buffer("second", BufferDesc::new(1024));
+        graph
+            .add_pass("write first")
+            .write(first, abstraction::AccessType::ComputeShaderWrite);
+        graph
+            .add_pass("read first")
+            .read(first, abstraction::AccessType::ComputeShaderReadOther)
+            .side_effects();
+        graph
+            .add_pass("write second")
+            .write(second, abstraction::AccessType::TransferWrite)
+            .side_effects();
+        let compiled = graph.compile().unwrap();
+        let requests: Vec<AliasingRequest> = compiled
+            .resources
+            .iter()
+            .map(|usage| AliasingRequest {
+                resource: usage.resource,
+                size: 1024,
+                alignment: 16,
+                memory_type_index: 0,
+                is_image: false,
+                first_pass: usage.first_pass,
+                last_pass: usage.last_pass,
+            })
+            .collect();
+        let plan = AliasingPlan::new(&requests);
+        assert_eq!(plan.get_aliased_resources(second.into()), vec![first.into()]);
+
+        // The buffer's first write has no barrier on its own
+        assert!(compiled.passes[2].barriers.is_empty());
+        let aliased = plan.apply(&compiled);
+        assert_eq!(
+            aliased.passes[2].barriers,
+            vec![GraphBarrier {
+                resource: second.into(),
+                prev_accesses: vec![
+                    abstraction::AccessType::ComputeShaderWrite,
+                    abstraction::AccessType::ComputeShaderReadOther
+                ],
+                next_accesses: vec![abstraction::AccessType::TransferWrite],
+                discard_contents: true,
+            }]
+        );
+    }
+}
@@ -0,0 +1,496 @@
+//! Turns the declarations of a [super::RenderGraph] into an ordered list of passes and barriers
+//!
+//! Compilation never touches the GPU. Passes are culled if nothing depends on what they write,
+//! the remaining passes keep their declaration order, which already respects every dependency
+//! as passes can only depend on earlier ones, and the barriers needed before every pass are
+//! derived by replaying the accesses of every resource in that order.
+use super::pass::{PassId, PassInfo};
+use super::resource::{self, ResourceDesc, ResourceId, ResourceInfo, ResourceOrigin};
+use crate::abstraction::prelude as abstraction;
+use ash::vk;
+use std::collections::BTreeSet;
+use std::fmt;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RenderGraphError {
+    /// A pass reads a transient resource which no earlier pass writes to
+    ReadBeforeWrite { pass: PassId, resource: ResourceId },
+    /// A pass accesses a resource which does not belong to the graph
+    UnknownResource { pass: PassId, resource: ResourceId },
+}
+
+impl fmt::Display for RenderGraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderGraphError::ReadBeforeWrite { pass, resource } => write!(
+                f,
+                "pass {} reads transient resource {} before it is written to",
+                pass.0, resource.0
+            ),
+            RenderGraphError::UnknownResource { pass, resource } => write!(
+                f,
+                "pass {} accesses resource {} which does not exist",
+                pass.0, resource.0
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RenderGraphError {}
+
+/// Contents of a resource after a specific pass wrote to it, version 0 is the contents before
+/// the graph executes
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ResourceVersion {
+    pub resource: ResourceId,
+    pub version: u32,
+}
+
+/// A barrier on a whole resource between the accesses of earlier passes and the next pass
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GraphBarrier {
+    pub resource: ResourceId,
+    pub prev_accesses: Vec<abstraction::AccessType>,
+    pub next_accesses: Vec<abstraction::AccessType>,
+    /// The previous contents of the resource are undefined and can be discarded
+    pub discard_contents: bool,
+}
+
+/// A pass which survived culling
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CompiledPass {
+    pub pass: PassId,
+    /// Barriers which have to be recorded before the pass
+    pub barriers: Vec<GraphBarrier>,
+    pub reads: Vec<ResourceVersion>,
+    pub writes: Vec<ResourceVersion>,
+}
+
+/// How a resource is used by the compiled passes
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ResourceUsage {
+    pub resource: ResourceId,
+    pub image_usage: vk::ImageUsageFlags,
+    pub buffer_usage: vk::BufferUsageFlags,
+    /// Index into [CompiledGraph::passes] of the first pass using the resource
+    pub first_pass: usize,
+    /// Index into [CompiledGraph::passes] of the last pass using the resource
+    pub last_pass: usize,
+    /// Accesses of the first pass using the resource
+    pub first_accesses: Vec<abstraction::AccessType>,
+    /// Accesses a later user of the resource's memory has to wait on, including the final
+    /// access of imported resources
+    pub last_accesses: Vec<abstraction::AccessType>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct CompiledGraph {
+    /// Passes in the order they execute in
+    pub passes: Vec<CompiledPass>,
+    /// Barriers moving imported resources to their final access
+    pub final_barriers: Vec<GraphBarrier>,
+    /// Every resource used by at least one compiled pass
+    pub resources: Vec<ResourceUsage>,
+    pub culled_passes: Vec<PassId>,
+}
+
+impl CompiledGraph {
+    pub fn get_resource_usage(&self, resource: ResourceId) -> Option<&ResourceUsage> {
+        self.resources
+            .iter()
+            .find(|usage| usage.resource == resource)
+    }
+}
+
+/// Accesses of a pass grouped per resource, keeping the order resources were first accessed in
+pub(crate) fn group_accesses(pass: &PassInfo) -> Vec<(ResourceId, Vec<abstraction::AccessType>)> {
+    let mut grouped: Vec<(ResourceId, Vec<abstraction::AccessType>)> = Vec::new();
+    for access in pass.accesses.iter() {
+        if access.access == abstraction::AccessType::Nothing {
+            continue;
+        }
+        match grouped
+            .iter_mut()
+            .find(|(resource, _)| *resource == access.resource)
+        {
+            Some((_, accesses)) => {
+                if !accesses.contains(&access.access) {
+                    accesses.push(access.access)
+                }
+            }
+            None => grouped.push((access.resource, vec![access.access])),
+        }
+    }
+    grouped
+}
+
+/// Last known accesses of a resource while replaying the passes
+struct ResourceState {
+    /// Accesses which the next write or layout transition has to wait on
+    last_accesses: Vec<abstraction::AccessType>,
+    /// Reads which already have a dependency on [Self::last_accesses]
+    reads: Vec<abstraction::AccessType>,
+    layout: vk::ImageLayout,
+    /// Whether the contents of the resource are defined
+    initialized: bool,
+    version: u32,
+}
+
+impl ResourceState {
+    fn new(resource: &ResourceInfo) -> Self {
+        match resource.origin {
+            ResourceOrigin::Transient => Self {
+                last_accesses: Vec::new(),
+                reads: Vec::new(),
+                layout: vk::ImageLayout::UNDEFINED,
+                initialized: false,
+                version: 0,
+            },
+            ResourceOrigin::Imported { initial_access, .. } => {
+                let last_accesses = if initial_access == abstraction::AccessType::Nothing {
+                    Vec::new()
+                } else {
+                    vec![initial_access]
+                };
+                Self {
+                    layout: get_layout(resource, &last_accesses),
+                    last_accesses,
+                    reads: Vec::new(),
+                    initialized: true,
+                    version: 0,
+                }
+            }
+        }
+    }
+
+    /// Move the resource to `accesses`, returning the barrier required to do so
+    fn transition(
+        &mut self,
+        resource_id: ResourceId,
+        resource: &ResourceInfo,
+        accesses: &[abstraction::AccessType],
+    ) -> Option<GraphBarrier> {
+        let is_write = accesses.iter().any(|access| access.is_write());
+        let layout = get_layout(resource, accesses);
+        if !is_write && layout == self.layout {
+            // Reads which are already visible do not need another barrier
+            let new_reads: Vec<abstraction::AccessType> = accesses
+                .iter()
+                .filter(|access| !self.reads.contains(access))
+                .copied()
+                .collect();
+            if new_reads.is_empty() {
+                return None;
+            }
+            self.reads.extend(new_reads);
+            if self.last_accesses.is_empty() {
+                return None;
+            }
+            return Some(GraphBarrier {
+                resource: resource_id,
+                prev_accesses: self.last_accesses.clone(),
+                next_accesses: accesses.to_vec(),
+                discard_contents: false,
+            });
+        }
+
+        // Writes and layout transitions wait on every access since the last write
+        let mut prev_accesses = self.last_accesses.clone();
+        for read in self.reads.iter() {
+            if !prev_accesses.contains(read) {
+                prev_accesses.push(*read);
+            }
+        }
+        let barrier = if prev_accesses.is_empty() && layout == self.layout {
+            None
+        } else {
+            Some(GraphBarrier {
+                resource: resource_id,
+                prev_accesses,
+                next_accesses: accesses.to_vec(),
+                discard_contents: !self.initialized,
+            })
+        };
+        if is_write {
+            self.last_accesses = accesses.to_vec();
+            self.reads = Vec::new();
+        } else {
+            // Later reads still have to make the last write visible to their own stages
+            self.last_accesses.retain(|access| access.is_write());
+            self.last_accesses.extend_from_slice(accesses);
+            self.reads = accesses.to_vec();
+        }
+        self.layout = layout;
+        self.initialized = true;
+        barrier
+    }
+}
+
+/// Layout `resource` has to be in for `accesses`, buffers never have a layout
+fn get_layout(resource: &ResourceInfo, accesses: &[abstraction::AccessType]) -> vk::ImageLayout {
+    match resource.desc {
+        ResourceDesc::Image(_) => abstraction::ImageLayoutPolicy::Optimal.get_layout(accesses),
+        ResourceDesc::Buffer(_) => vk::ImageLayout::UNDEFINED,
+    }
+}
+
+/// Compile the passes and resources of a graph
+pub fn compile(
+    resources: &[ResourceInfo],
+    passes: &[PassInfo],
+) -> Result<CompiledGraph, RenderGraphError> {
+    let pass_accesses: Vec<Vec<(ResourceId, Vec<abstraction::AccessType>)>> =
+        passes.iter().map(group_accesses).collect();
+
+    // Find the producers of every pass in declaration order
+    let mut producers: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); passes.len()];
+    let mut last_writers: Vec<Option<usize>> = vec![None; resources.len()];
+    let mut alive: Vec<bool> = vec![false; passes.len()];
+    for (pass_index, accesses) in pass_accesses.iter().enumerate() {
+        alive[pass_index] = passes[pass_index].side_effects;
+        for (resource_id, accesses) in accesses.iter() {
+            let resource = resources.get(resource_id.0).ok_or(RenderGraphError::UnknownResource {
+                pass: PassId(pass_index),
+                resource: *resource_id,
+            })?;
+            let is_write = accesses.iter().any(|access| access.is_write());
+            let is_read = accesses.iter().any(|access| !access.is_write());
+            match last_writers[resource_id.0] {
+                Some(writer) => {
+                    producers[pass_index].insert(writer);
+                }
+                None if is_read && resource.is_transient() => {
+                    return Err(RenderGraphError::ReadBeforeWrite {
+                        pass: PassId(pass_index),
+                        resource: *resource_id,
+                    });
+                }
+                None => {}
+            }
+            if is_write {
+                last_writers[resource_id.0] = Some(pass_index);
+                // Imported resources outlive the graph, so writing to them is an output
+                if !resource.is_transient() {
+                    alive[pass_index] = true;
+                }
+            }
+        }
+    }
+
+    // Producers are always declared before the passes consuming their outputs
+    for pass_index in (0..passes.len()).rev() {
+        if alive[pass_index] {
+            for producer in producers[pass_index].iter() {
+                alive[*producer] = true;
+            }
+        }
+    }
+
+    // Passes only depend on earlier passes, so the declaration order is a valid execution order
+    let order: Vec<usize> = (0..passes.len()).filter(|pass_index| alive[*pass_index]).collect();
+
+    // Replay every access in execution order to find the barriers
+    let mut states: Vec<ResourceState> = resources.iter().map(ResourceState::new).collect();
+    let mut usages: Vec<Option<ResourceUsage>> = vec![None; resources.len()];
+    let mut compiled = CompiledGraph::default();
+    for (compiled_index, pass_index) in order.iter().enumerate() {
+        let mut compiled_pass = CompiledPass {
+            pass: PassId(*pass_index),
+            barriers: Vec::new(),
+            reads: Vec::new(),
+            writes: Vec::new(),
+        };
+        for (resource_id, accesses) in pass_accesses[*pass_index].iter() {
+            let state = &mut states[resource_id.0];
+            if accesses.iter().any(|access| !access.is_write()) {
+                compiled_pass.reads.push(ResourceVersion {
+                    resource: *resource_id,
+                    version: state.version,
+                });
+            }
+            if accesses.iter().any(|access| access.is_write()) {
+                state.version += 1;
+                compiled_pass.writes.push(ResourceVersion {
+                    resource: *resource_id,
+                    version: state.version,
+                });
+            }
+            if let Some(barrier) = state.transition(*resource_id, &resources[resource_id.0], accesses) {
+                compiled_pass.barriers.push(barrier);
+            }
+
+            let usage = usages[resource_id.0].get_or_insert(ResourceUsage {
+                resource: *resource_id,
+                image_usage: vk::ImageUsageFlags::empty(),
+                buffer_usage: vk::BufferUsageFlags::empty(),
+                first_pass: compiled_index,
+                last_pass: compiled_index,
+                first_accesses: accesses.clone(),
+                last_accesses: Vec::new(),
+            });
+            usage.last_pass = compiled_index;
+            for access in accesses.iter() {
+                usage.image_usage |= resource::get_image_usage(*access);
+                usage.buffer_usage |= resource::get_buffer_usage(*access);
+            }
+        }
+        compiled.passes.push(compiled_pass);
+    }
+
+    for (resource_index, resource) in resources.iter().enumerate() {
+        if let ResourceOrigin::Imported {
+            final_access: Some(final_access),
+            ..
+        } = resource.origin
+        {
+            if let Some(barrier) =
+                states[resource_index].transition(ResourceId(resource_index), resource, &[final_access])
+            {
+                compiled.final_barriers.push(barrier);
+            }
+        }
+    }
+
+    for usage in usages.iter_mut().flatten() {
+        let state = &states[usage.resource.0];
+        usage.last_accesses = state.last_accesses.clone();
+        for read in state.reads.iter() {
+            if !usage.last_accesses.contains(read) {
+                usage.last_accesses.push(*read);
+            }
+        }
+    }
+    compiled.resources = usages.into_iter().flatten().collect();
+    compiled.culled_passes = (0..passes.len())
+        .filter(|pass_index| !alive[*pass_index])
+        .map(PassId)
+        .collect();
+    Ok(compiled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framework::render_graph::{BufferDesc, ImageDesc, RenderGraph};
+    use abstraction::AccessType;
+
+    #[test]
+    /// Passes whose outputs are never used are culled along with their exclusive inputs
+    fn test_culling() {
+        let mut graph = RenderGraph::new();
+        let shadow = graph.create_image("shadow", ImageDesc::new_2d(vk::Format::D32_SFLOAT, 1024, 1024));
+        let unused = graph.create_image("unused", ImageDesc::new_2d(vk::Format::R8G8B8A8_UNORM, 64, 64));
+        let shadow_pass = graph
+            .add_pass("shadow")
+            .write(shadow, AccessType::DepthStencilAttachmentWrite)
+            .get_id();
+        let unused_pass = graph
+            .add_pass("unused")
+            .read(shadow, AccessType::FragmentShaderReadSampledImage)
+            .write(unused, AccessType::ColorAttachmentWrite)
+            .get_id();
+        let lighting_pass = graph
+            .add_pass("lighting")
+            .read(shadow, AccessType::FragmentShaderReadSampledImage)
+            .side_effects()
+            .get_id();
+        let compiled = graph.compile().unwrap();
+        let order: Vec<PassId> = compiled.passes.iter().map(|pass| pass.pass).collect();
+        assert_eq!(order, vec![shadow_pass, lighting_pass]);
+        assert_eq!(compiled.culled_passes, vec![unused_pass]);
+        assert!(compiled.get_resource_usage(unused.into()).is_none());
+    }
+
+    #[test]
+    /// Barriers transition layouts and skip reads which are already visible
+    fn test_barriers() {
+        let mut graph = RenderGraph::new();
+        let color = graph.create_image("color", ImageDesc::new_2d(vk::Format::R8G8B8A8_UNORM, 64, 64));
+        graph
+            .add_pass("draw")
+            .write(color, AccessType::ColorAttachmentWrite);
+        graph
+            .add_pass("blur")
+            .read(color, AccessType::FragmentShaderReadSampledImage)
+            .side_effects();
+        graph
+            .add_pass("bloom")
+            .read(color, AccessType::FragmentShaderReadSampledImage)
+            .side_effects();
+        let compiled = graph.compile().unwrap();
+
+        let first_write = &compiled.passes[0].barriers[0];
+        assert!(first_write.discard_contents);
+        assert!(first_write.prev_accesses.is_empty());
+        assert_eq!(
+            compiled.passes[1].barriers,
+            vec![GraphBarrier {
+                resource: color.into(),
+                prev_accesses: vec![AccessType::ColorAttachmentWrite],
+                next_accesses: vec![AccessType::FragmentShaderReadSampledImage],
+                discard_contents: false,
+            }]
+        );
+        assert!(compiled.passes[2].barriers.is_empty());
+
+        let usage = compiled.get_resource_usage(color.into()).unwrap();
+        assert_eq!(
+            usage.image_usage,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED
+        );
+        assert_eq!((usage.first_pass, usage.last_pass), (0, 2));
+    }
+
+    #[test]
+    /// Writing a buffer after reading it waits on the read and bumps the version
+    fn test_write_after_read() {
+        let mut graph = RenderGraph::new();
+        let particles = graph.create_buffer("particles", BufferDesc::new(1024));
+        graph
+            .add_pass("spawn")
+            .write(particles, AccessType::ComputeShaderWrite);
+        graph
+            .add_pass("draw")
+            .read(particles, AccessType::VertexBuffer)
+            .side_effects();
+        graph
+            .add_pass("simulate")
+            .write(particles, AccessType::ComputeShaderWrite)
+            .side_effects();
+        let compiled = graph.compile().unwrap();
+        assert_eq!(compiled.passes.len(), 3);
+        // Buffers need no barrier before their first write
+        assert!(compiled.passes[0].barriers.is_empty());
+        assert_eq!(
+            compiled.passes[2].barriers[0].prev_accesses,
+            vec![AccessType::ComputeShaderWrite, AccessType::VertexBuffer]
+        );
+        assert_eq!(
+            compiled.passes[1].reads,
+            vec![ResourceVersion {
+                resource: particles.into(),
+                version: 1
+            }]
+        );
+        assert_eq!(compiled.passes[2].writes[0].version, 2);
+    }
+
+    #[test]
+    /// Reading a transient resource nobody wrote to is an error
+    fn test_read_before_write() {
+        let mut graph = RenderGraph::new();
+        let buffer = graph.create_buffer("buffer", BufferDesc::new(16));
+        let pass = graph
+            .add_pass("read")
+            .read(buffer, AccessType::ComputeShaderReadOther)
+            .side_effects()
+            .get_id();
+        assert_eq!(
+            graph.compile(),
+            Err(RenderGraphError::ReadBeforeWrite {
+                pass,
+                resource: buffer.into()
+            })
+        );
+    }
+}
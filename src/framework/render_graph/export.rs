@@ -0,0 +1,647 @@
+//! Exports compiled graphs as Graphviz DOT or JSON and compares them
+//!
+//! Exports contain the passes in execution order, the versions of every resource they read and
+//! write, the barriers recorded before them and, when provided, the queue every pass runs on
+//! and the memory block every transient resource is aliased into. [GraphDiff] compares two
+//! exports by pass and resource names, so changes adding barriers show up in review.
+use super::aliasing::AliasingPlan;
+use super::compiler::{CompiledGraph, GraphBarrier, ResourceVersion};
+use super::pass::PassInfo;
+use super::resource::{ResourceDesc, ResourceInfo};
+use super::schedule::{OwnershipTransfer, QueueType, Schedule};
+use super::RenderGraph;
+use crate::abstraction::prelude as abstraction;
+use std::fmt;
+use std::fmt::Write;
+
+/// Escape `value` so it can be used inside of a quoted JSON or DOT string
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for character in value.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            character if (character as u32) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", character as u32);
+            }
+            character => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+fn json_accesses(accesses: &[abstraction::AccessType]) -> String {
+    let accesses: Vec<String> = accesses
+        .iter()
+        .map(|access| format!("\"{:?}\"", access))
+        .collect();
+    format!("[{}]", accesses.join(","))
+}
+
+/// A barrier identified by names instead of indices, allowing barriers of different graphs to
+/// be compared
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct BarrierSummary {
+    /// Where the barrier is recorded, such as `before lighting`
+    pub location: String,
+    pub resource: String,
+    pub prev_accesses: Vec<abstraction::AccessType>,
+    pub next_accesses: Vec<abstraction::AccessType>,
+    pub discard_contents: bool,
+    /// Source and destination queue family of an ownership transfer
+    pub queue_family_transfer: Option<(u32, u32)>,
+}
+
+impl fmt::Display for BarrierSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} on {}: {:?} -> {:?}",
+            self.location, self.resource, self.prev_accesses, self.next_accesses
+        )?;
+        if self.discard_contents {
+            write!(f, " (discard)")?;
+        }
+        if let Some((src_family_index, dst_family_index)) = self.queue_family_transfer {
+            write!(f, " (family {} -> {})", src_family_index, dst_family_index)?;
+        }
+        Ok(())
+    }
+}
+
+/// A compiled graph along with the names of its passes and resources
+pub struct GraphExport<'e> {
+    resources: &'e [ResourceInfo],
+    passes: &'e [PassInfo],
+    compiled: &'e CompiledGraph,
+    schedule: Option<&'e Schedule>,
+    aliasing: Option<&'e AliasingPlan>,
+}
+
+impl<'e> GraphExport<'e> {
+    pub fn new(graph: &'e RenderGraph, compiled: &'e CompiledGraph) -> Self {
+        Self {
+            resources: graph.get_resources(),
+            passes: graph.get_passes(),
+            compiled,
+            schedule: None,
+            aliasing: None,
+        }
+    }
+
+    /// Include queue assignments, waits and ownership transfers
+    pub fn schedule(mut self, schedule: &'e Schedule) -> Self {
+        self.schedule = Some(schedule);
+        self
+    }
+
+    /// Include the memory blocks transient resources are aliased into
+    pub fn aliasing(mut self, aliasing: &'e AliasingPlan) -> Self {
+        self.aliasing = Some(aliasing);
+        self
+    }
+
+    fn get_pass_name(&self, compiled_index: usize) -> &str {
+        &self.passes[self.compiled.passes[compiled_index].pass.0].name
+    }
+
+    fn get_resource_name(&self, version: &ResourceVersion) -> String {
+        format!(
+            "{} v{}",
+            self.resources[version.resource.0].name, version.version
+        )
+    }
+
+    fn get_queue(&self, compiled_index: usize) -> QueueType {
+        self.schedule
+            .and_then(|schedule| schedule.get_queue(compiled_index))
+            .unwrap_or_default()
+    }
+
+    fn summarize(
+        &self,
+        location: String,
+        barrier: &GraphBarrier,
+        queue_family_transfer: Option<(u32, u32)>,
+    ) -> BarrierSummary {
+        BarrierSummary {
+            location,
+            resource: self.resources[barrier.resource.0].name.clone(),
+            prev_accesses: barrier.prev_accesses.clone(),
+            next_accesses: barrier.next_accesses.clone(),
+            discard_contents: barrier.discard_contents,
+            queue_family_transfer,
+        }
+    }
+
+    /// Get every barrier of the graph, including ownership transfers of the schedule
+    pub fn get_barriers(&self) -> Vec<BarrierSummary> {
+        let mut barriers = Vec::new();
+        for (compiled_index, compiled_pass) in self.compiled.passes.iter().enumerate() {
+            let location = format!("before {}", self.get_pass_name(compiled_index));
+            for barrier in compiled_pass.barriers.iter() {
+                barriers.push(self.summarize(location.clone(), barrier, None));
+            }
+        }
+        for barrier in self.compiled.final_barriers.iter() {
+            barriers.push(self.summarize("after graph".to_string(), barrier, None));
+        }
+        let transfer = |location: String, transfer: &OwnershipTransfer| {
+            self.summarize(
+                location,
+                &transfer.barrier,
+                Some((transfer.src_family_index, transfer.dst_family_index)),
+            )
+        };
+        for submission in self
+            .schedule
+            .iter()
+            .flat_map(|schedule| schedule.submissions.iter())
+        {
+            let first = submission
+                .passes
+                .first()
+                .map(|pass| self.get_pass_name(*pass).to_string())
+                .unwrap_or_else(|| "graph end".to_string());
+            let last = submission
+                .passes
+                .last()
+                .map(|pass| self.get_pass_name(*pass).to_string())
+                .unwrap_or_else(|| "graph end".to_string());
+            for acquire in submission.acquires.iter() {
+                barriers.push(transfer(format!("acquire before {}", first), acquire));
+            }
+            for release in submission.releases.iter() {
+                barriers.push(transfer(format!("release after {}", last), release));
+            }
+        }
+        barriers
+    }
+
+    /// Export the graph in Graphviz DOT
+    ///
+    /// Passes are boxes listing the barriers recorded before them, resource versions are
+    /// ellipses. Culled passes are dashed and cross-queue waits are red dashed edges.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        let _ = writeln!(dot, "digraph render_graph {{");
+        let _ = writeln!(dot, "    rankdir=LR;");
+        let _ = writeln!(dot, "    node [fontname=\"monospace\"];");
+        for (compiled_index, compiled_pass) in self.compiled.passes.iter().enumerate() {
+            let mut label = format!("{}: {}", compiled_index, self.get_pass_name(compiled_index));
+            if self.schedule.is_some() {
+                let _ = write!(label, "\nqueue: {:?}", self.get_queue(compiled_index));
+            }
+            for barrier in compiled_pass.barriers.iter() {
+                let _ = write!(
+                    label,
+                    "\nbarrier {}: {:?} -> {:?}",
+                    self.resources[barrier.resource.0].name,
+                    barrier.prev_accesses,
+                    barrier.next_accesses
+                );
+            }
+            let _ = writeln!(
+                dot,
+                "    pass_{} [shape=box, label=\"{}\"];",
+                compiled_pass.pass.0,
+                escape(&label)
+            );
+            for read in compiled_pass.reads.iter() {
+                let _ = writeln!(
+                    dot,
+                    "    resource_{}_{} -> pass_{};",
+                    read.resource.0, read.version, compiled_pass.pass.0
+                );
+            }
+            for write in compiled_pass.writes.iter() {
+                let _ = writeln!(
+                    dot,
+                    "    pass_{} -> resource_{}_{};",
+                    compiled_pass.pass.0, write.resource.0, write.version
+                );
+            }
+        }
+        for pass in self.compiled.culled_passes.iter() {
+            let _ = writeln!(
+                dot,
+                "    pass_{} [shape=box, style=dashed, color=gray, label=\"{} (culled)\"];",
+                pass.0,
+                escape(&self.passes[pass.0].name)
+            );
+        }
+
+        let mut versions: Vec<ResourceVersion> = self
+            .compiled
+            .passes
+            .iter()
+            .flat_map(|pass| pass.reads.iter().chain(pass.writes.iter()))
+            .copied()
+            .collect();
+        versions.sort_by_key(|version| (version.resource, version.version));
+        versions.dedup();
+        for version in versions.iter() {
+            let mut label = self.get_resource_name(version);
+            if let Some(placement) = self
+                .aliasing
+                .and_then(|aliasing| aliasing.get_placement(version.resource))
+            {
+                let _ = write!(label, "\nblock {} @ {}", placement.block, placement.offset);
+            }
+            let _ = writeln!(
+                dot,
+                "    resource_{}_{} [shape=ellipse, label=\"{}\"];",
+                version.resource.0,
+                version.version,
+                escape(&label)
+            );
+        }
+
+        if let Some(schedule) = self.schedule {
+            for submission in schedule.submissions.iter() {
+                let first = match submission.passes.first() {
+                    Some(first) => self.compiled.passes[*first].pass.0,
+                    None => continue,
+                };
+                for wait in submission.waits.iter() {
+                    let signalling = schedule.submissions.iter().find(|other| {
+                        other.queue == wait.queue && other.signal_value == wait.value
+                    });
+                    if let Some(last) = signalling.and_then(|other| other.passes.last()) {
+                        let _ = writeln!(
+                            dot,
+                            "    pass_{} -> pass_{} [style=dashed, color=red, label=\"wait {:?} {}\"];",
+                            self.compiled.passes[*last].pass.0,
+                            first,
+                            wait.queue,
+                            wait.value
+                        );
+                    }
+                }
+            }
+        }
+        let _ = writeln!(dot, "}}");
+        dot
+    }
+
+    /// Export the graph as JSON
+    pub fn to_json(&self) -> String {
+        let json_versions = |versions: &[ResourceVersion]| {
+            let versions: Vec<String> = versions
+                .iter()
+                .map(|version| {
+                    format!(
+                        "{{\"resource\":\"{}\",\"version\":{}}}",
+                        escape(&self.resources[version.resource.0].name),
+                        version.version
+                    )
+                })
+                .collect();
+            format!("[{}]", versions.join(","))
+        };
+        let json_barrier = |barrier: &GraphBarrier| {
+            format!(
+                "{{\"resource\":\"{}\",\"prev_accesses\":{},\"next_accesses\":{},\"discard_contents\":{}}}",
+                escape(&self.resources[barrier.resource.0].name),
+                json_accesses(&barrier.prev_accesses),
+                json_accesses(&barrier.next_accesses),
+                barrier.discard_contents
+            )
+        };
+        let json_barriers = |barriers: &[GraphBarrier]| {
+            let barriers: Vec<String> = barriers.iter().map(json_barrier).collect();
+            format!("[{}]", barriers.join(","))
+        };
+        let json_transfers = |transfers: &[OwnershipTransfer]| {
+            let transfers: Vec<String> = transfers
+                .iter()
+                .map(|transfer| {
+                    format!(
+                        "{{\"barrier\":{},\"src_family_index\":{},\"dst_family_index\":{}}}",
+                        json_barrier(&transfer.barrier),
+                        transfer.src_family_index,
+                        transfer.dst_family_index
+                    )
+                })
+                .collect();
+            format!("[{}]", transfers.join(","))
+        };
+
+        let passes: Vec<String> = self
+            .compiled
+            .passes
+            .iter()
+            .enumerate()
+            .map(|(compiled_index, compiled_pass)| {
+                format!(
+                    "{{\"name\":\"{}\",\"queue\":\"{:?}\",\"reads\":{},\"writes\":{},\"barriers\":{}}}",
+                    escape(self.get_pass_name(compiled_index)),
+                    self.get_queue(compiled_index),
+                    json_versions(&compiled_pass.reads),
+                    json_versions(&compiled_pass.writes),
+                    json_barriers(&compiled_pass.barriers)
+                )
+            })
+            .collect();
+        let culled_passes: Vec<String> = self
+            .compiled
+            .culled_passes
+            .iter()
+            .map(|pass| format!("\"{}\"", escape(&self.passes[pass.0].name)))
+            .collect();
+        let resources: Vec<String> = self
+            .compiled
+            .resources
+            .iter()
+            .map(|usage| {
+                let resource = &self.resources[usage.resource.0];
+                let mut json = format!(
+                    "{{\"name\":\"{}\",\"kind\":\"{}\",\"transient\":{},\"first_pass\":{},\"last_pass\":{}",
+                    escape(&resource.name),
+                    match resource.desc {
+                        ResourceDesc::Image(_) => "image",
+                        ResourceDesc::Buffer(_) => "buffer",
+                    },
+                    resource.is_transient(),
+                    usage.first_pass,
+                    usage.last_pass
+                );
+                if let Some(placement) = self
+                    .aliasing
+                    .and_then(|aliasing| aliasing.get_placement(usage.resource))
+                {
+                    let _ = write!(
+                        json,
+                        ",\"memory_block\":{},\"memory_offset\":{}",
+                        placement.block, placement.offset
+                    );
+                }
+                json.push('}');
+                json
+            })
+            .collect();
+
+        let mut json = format!(
+            "{{\"passes\":[{}],\"culled_passes\":[{}],\"resources\":[{}],\"final_barriers\":{}",
+            passes.join(","),
+            culled_passes.join(","),
+            resources.join(","),
+            json_barriers(&self.compiled.final_barriers)
+        );
+        if let Some(schedule) = self.schedule {
+            let submissions: Vec<String> = schedule
+                .submissions
+                .iter()
+                .map(|submission| {
+                    let passes: Vec<String> = submission
+                        .passes
+                        .iter()
+                        .map(|pass| format!("\"{}\"", escape(self.get_pass_name(*pass))))
+                        .collect();
+                    let waits: Vec<String> = submission
+                        .waits
+                        .iter()
+                        .map(|wait| format!("{{\"queue\":\"{:?}\",\"value\":{}}}", wait.queue, wait.value))
+                        .collect();
+                    format!(
+                        "{{\"queue\":\"{:?}\",\"family_index\":{},\"passes\":[{}],\"waits\":[{}],\"signal_value\":{},\"acquires\":{},\"releases\":{}}}",
+                        submission.queue,
+                        submission.family_index,
+                        passes.join(","),
+                        waits.join(","),
+                        submission.signal_value,
+                        json_transfers(&submission.acquires),
+                        json_transfers(&submission.releases)
+                    )
+                })
+                .collect();
+            let _ = write!(json, ",\"submissions\":[{}]", submissions.join(","));
+        }
+        if let Some(aliasing) = self.aliasing {
+            let blocks: Vec<String> = aliasing
+                .blocks
+                .iter()
+                .enumerate()
+                .map(|(block_index, block)| {
+                    let resources: Vec<String> = aliasing
+                        .placements
+                        .iter()
+                        .filter(|placement| placement.block == block_index)
+                        .map(|placement| {
+                            format!("\"{}\"", escape(&self.resources[placement.resource.0].name))
+                        })
+                        .collect();
+                    format!(
+                        "{{\"memory_type_index\":{},\"size\":{},\"resources\":[{}]}}",
+                        block.memory_type_index,
+                        block.size,
+                        resources.join(",")
+                    )
+                })
+                .collect();
+            let _ = write!(
+                json,
+                ",\"aliasing\":{{\"blocks\":[{}],\"requested_size\":{},\"allocated_size\":{},\"saved_size\":{}}}",
+                blocks.join(","),
+                aliasing.statistics.requested_size,
+                aliasing.statistics.allocated_size,
+                aliasing.statistics.get_saved_size()
+            );
+        }
+        json.push('}');
+        json
+    }
+}
+
+/// Queue of a pass changed between two graphs
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct QueueChange {
+    pub pass: String,
+    pub old_queue: QueueType,
+    pub new_queue: QueueType,
+}
+
+/// Differences between two compiled graphs
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct GraphDiff {
+    pub added_passes: Vec<String>,
+    pub removed_passes: Vec<String>,
+    pub added_barriers: Vec<BarrierSummary>,
+    pub removed_barriers: Vec<BarrierSummary>,
+    pub queue_changes: Vec<QueueChange>,
+}
+
+impl GraphDiff {
+    /// Compare `old` to `new` by the names of passes and resources
+    pub fn new(old: &GraphExport, new: &GraphExport) -> Self {
+        let pass_names = |export: &GraphExport| -> Vec<String> {
+            (0..export.compiled.passes.len())
+                .map(|compiled_index| export.get_pass_name(compiled_index).to_string())
+                .collect()
+        };
+        let old_passes = pass_names(old);
+        let new_passes = pass_names(new);
+        let mut diff = Self {
+            added_passes: new_passes
+                .iter()
+                .filter(|pass| !old_passes.contains(pass))
+                .cloned()
+                .collect(),
+            removed_passes: old_passes
+                .iter()
+                .filter(|pass| !new_passes.contains(pass))
+                .cloned()
+                .collect(),
+            ..Default::default()
+        };
+
+        // Barriers may legitimately repeat, so match them one to one
+        let mut removed_barriers = old.get_barriers();
+        for barrier in new.get_barriers() {
+            match removed_barriers
+                .iter()
+                .position(|removed| *removed == barrier)
+            {
+                Some(position) => {
+                    removed_barriers.remove(position);
+                }
+                None => diff.added_barriers.push(barrier),
+            }
+        }
+        diff.removed_barriers = removed_barriers;
+
+        for (new_index, pass) in new_passes.iter().enumerate() {
+            if let Some(old_index) = old_passes.iter().position(|old_pass| old_pass == pass) {
+                let old_queue = old.get_queue(old_index);
+                let new_queue = new.get_queue(new_index);
+                if old_queue != new_queue {
+                    diff.queue_changes.push(QueueChange {
+                        pass: pass.clone(),
+                        old_queue,
+                        new_queue,
+                    });
+                }
+            }
+        }
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added_passes.is_empty()
+            && self.removed_passes.is_empty()
+            && self.added_barriers.is_empty()
+            && self.removed_barriers.is_empty()
+            && self.queue_changes.is_empty()
+    }
+}
+
+impl fmt::Display for GraphDiff {
+    /// Lists every change on its own line, prefixed with `+`, `-` or `~`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for pass in self.added_passes.iter() {
+            writeln!(f, "+ pass {}", pass)?;
+        }
+        for pass in self.removed_passes.iter() {
+            writeln!(f, "- pass {}", pass)?;
+        }
+        for barrier in self.added_barriers.iter() {
+            writeln!(f, "+ barrier {}", barrier)?;
+        }
+        for barrier in self.removed_barriers.iter() {
+            writeln!(f, "- barrier {}", barrier)?;
+        }
+        for change in self.queue_changes.iter() {
+            writeln!(
+                f,
+                "~ queue of {}: {:?} -> {:?}",
+                change.pass, change.old_queue, change.new_queue
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framework::render_graph::{ImageDesc, QueueSetup};
+    use abstraction::AccessType;
+    use ash::vk;
+
+    fn build_graph(graph: &mut RenderGraph, blur: bool) {
+        let color = graph.create_image(
+            "color",
+            ImageDesc::new_2d(vk::Format::R8G8B8A8_UNORM, 64, 64),
+        );
+        graph
+            .add_pass("draw \"scene\"")
+            .write(color, AccessType::ColorAttachmentWrite);
+        if blur {
+            graph
+                .add_pass("blur")
+                .read(color, AccessType::ComputeShaderReadSampledImage)
+                .side_effects();
+        }
+        graph
+            .add_pass("present")
+            .read(color, AccessType::FragmentShaderReadSampledImage)
+            .side_effects();
+    }
+
+    #[test]
+    /// Both formats contain passes, resource versions and barriers
+    fn test_export() {
+        let mut graph = RenderGraph::new();
+        build_graph(&mut graph, false);
+        let compiled = graph.compile().unwrap();
+        let schedule = Schedule::new(
+            graph.get_resources(),
+            graph.get_passes(),
+            &compiled,
+            &QueueSetup::serial(0),
+            None,
+        );
+        let export = GraphExport::new(&graph, &compiled).schedule(&schedule);
+
+        let dot = export.to_dot();
+        assert!(dot.starts_with("digraph render_graph {"));
+        assert!(dot.contains("pass_0 -> resource_0_1;"));
+        assert!(dot.contains("resource_0_1 -> pass_1;"));
+        assert!(dot.contains("draw \\\"scene\\\""));
+
+        let json = export.to_json();
+        assert!(json.contains("\"name\":\"draw \\\"scene\\\"\",\"queue\":\"Graphics\""));
+        assert!(json.contains(
+            "{\"resource\":\"color\",\"prev_accesses\":[\"ColorAttachmentWrite\"],\"next_accesses\":[\"FragmentShaderReadSampledImage\"],\"discard_contents\":false}"
+        ));
+        assert!(json.contains("\"submissions\":[{\"queue\":\"Graphics\""));
+    }
+
+    #[test]
+    /// Adding a pass reading from another stage shows up as an added barrier
+    fn test_diff() {
+        let mut old_graph = RenderGraph::new();
+        build_graph(&mut old_graph, false);
+        let old_compiled = old_graph.compile().unwrap();
+        let mut new_graph = RenderGraph::new();
+        build_graph(&mut new_graph, true);
+        let new_compiled = new_graph.compile().unwrap();
+
+        let old = GraphExport::new(&old_graph, &old_compiled);
+        let new = GraphExport::new(&new_graph, &new_compiled);
+        assert!(GraphDiff::new(&old, &old).is_empty());
+
+        let diff = GraphDiff::new(&old, &new);
+        assert_eq!(diff.added_passes, vec!["blur".to_string()]);
+        assert!(diff.removed_passes.is_empty());
+        // The transition to the sampled layout moves to the blur pass
+        assert_eq!(diff.added_barriers.len(), 2);
+        assert_eq!(diff.removed_barriers.len(), 1);
+        assert!(diff
+            .to_string()
+            .contains("+ barrier before blur on color: [ColorAttachmentWrite] -> [ComputeShaderReadSampledImage]"));
+    }
+}
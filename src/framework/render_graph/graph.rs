@@ -0,0 +1,481 @@
+use super::compiler::{self, CompiledGraph, CompiledPass, GraphBarrier, RenderGraphError};
+use super::pass::{PassBuilder, PassContext, PassId, PassInfo, RecordFn};
+use super::resource::{
+    BufferDesc, BufferId, ImageDesc, ImageId, ResourceDesc, ResourceId, ResourceInfo,
+    ResourceOrigin,
+};
+use super::schedule::{OwnershipTransfer, QueueSetup, QueueType, Schedule};
+use super::transient::{TransientMemoryPool, TransientResources};
+use crate::abstraction::prelude as abstraction;
+use abstraction::TrackedResource;
+use ash::vk;
+use std::collections::HashMap;
+
+/// A queue the submissions of a [Schedule] are made to
+pub struct GraphQueue<'q> {
+    pub queue: abstraction::Queue,
+    /// Pool of [Self::queue]'s family which command buffers are allocated from
+    pub command_pool: &'q mut abstraction::CommandPool,
+    /// Timeline semaphore signalled by every submission to [Self::queue]
+    pub timeline: &'q abstraction::Semaphore,
+    /// Last value [Self::timeline] was signalled with
+    pub timeline_value: u64,
+}
+
+/// Passes and the resources they access for a single frame
+///
+/// `'a` is the lifetime of imported resources and everything borrowed by record callbacks.
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    resources: Vec<ResourceInfo>,
+    passes: Vec<PassInfo>,
+    records: Vec<Option<RecordFn<'a>>>,
+    imported_images: HashMap<ResourceId, &'a abstraction::Image>,
+    imported_buffers: HashMap<ResourceId, &'a abstraction::Buffer>,
+    /// Memory of transient resources, a pool only used by a single execution if not set
+    memory_pool: Option<TransientMemoryPool>,
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take the memory of transient resources from `memory_pool`, which should outlive the
+    /// graph so the memory is reused by the next frame's graph
+    pub fn memory_pool(mut self, memory_pool: TransientMemoryPool) -> Self {
+        self.memory_pool = Some(memory_pool);
+        self
+    }
+
+    /// Get the pool transient resources take their memory from
+    fn get_memory_pool(&self, device: abstraction::Device) -> TransientMemoryPool {
+        self.memory_pool
+            .clone()
+            .unwrap_or_else(|| TransientMemoryPool::new(device))
+    }
+
+    fn add_resource(&mut self, name: &str, desc: ResourceDesc, origin: ResourceOrigin) -> ResourceId {
+        self.resources.push(ResourceInfo {
+            name: name.to_string(),
+            desc,
+            origin,
+            concurrent: false,
+            initial_owner: None,
+        });
+        ResourceId(self.resources.len() - 1)
+    }
+
+    /// Declare an image which is created by the graph
+    pub fn create_image(&mut self, name: &str, desc: ImageDesc) -> ImageId {
+        ImageId(self.add_resource(name, ResourceDesc::Image(desc), ResourceOrigin::Transient))
+    }
+
+    /// Declare a buffer which is created by the graph
+    pub fn create_buffer(&mut self, name: &str, desc: BufferDesc) -> BufferId {
+        BufferId(self.add_resource(name, ResourceDesc::Buffer(desc), ResourceOrigin::Transient))
+    }
+
+    /// Use an existing image in the graph
+    ///
+    /// `initial_access` is the last access of the image before the graph executes. If
+    /// `final_access` is set, the image is transitioned to it after the last pass. Once
+    /// executed, the image's tracked state is updated to the last accesses of the graph.
+    pub fn import_image(
+        &mut self,
+        name: &str,
+        image: &'a abstraction::Image,
+        initial_access: abstraction::AccessType,
+        final_access: Option<abstraction::AccessType>,
+    ) -> ImageId {
+        let resource = self.add_resource(
+            name,
+            ResourceDesc::Image(ImageDesc::from(image)),
+            ResourceOrigin::Imported {
+                initial_access,
+                final_access,
+            },
+        );
+        self.imported_images.insert(resource, image);
+        ImageId(resource)
+    }
+
+    /// Use an existing buffer in the graph
+    ///
+    /// `initial_access` is the last access of the buffer before the graph executes. If
+    /// `final_access` is set, a barrier to it is recorded after the last pass. Once executed,
+    /// the buffer's tracked state is updated to the last accesses of the graph.
+    pub fn import_buffer(
+        &mut self,
+        name: &str,
+        buffer: &'a abstraction::Buffer,
+        initial_access: abstraction::AccessType,
+        final_access: Option<abstraction::AccessType>,
+    ) -> BufferId {
+        let resource = self.add_resource(
+            name,
+            ResourceDesc::Buffer(BufferDesc::new(vk::WHOLE_SIZE)),
+            ResourceOrigin::Imported {
+                initial_access,
+                final_access,
+            },
+        );
+        self.imported_buffers.insert(resource, buffer);
+        BufferId(resource)
+    }
+
+    /// Declare that an imported resource was created with [vk::SharingMode::CONCURRENT]
+    pub fn set_concurrent(&mut self, resource: impl Into<ResourceId>) {
+        let resource = &mut self.resources[resource.into().0];
+        debug_assert!(!resource.is_transient(), "Transient resources are always exclusive");
+        resource.concurrent = true;
+    }
+
+    /// Declare that an exclusive imported resource is owned by the queue family `family_index`
+    /// before the graph executes
+    ///
+    /// The first pass using it on another family acquires it. If `family_index` belongs to one
+    /// of the graph's queues the release is recorded on that queue, otherwise it has to be
+    /// recorded by the caller before the graph executes.
+    pub fn set_initial_owner(&mut self, resource: impl Into<ResourceId>, family_index: u32) {
+        let resource = &mut self.resources[resource.into().0];
+        debug_assert!(
+            !resource.is_transient(),
+            "Transient resources are not owned before the graph"
+        );
+        resource.initial_owner = Some(family_index);
+    }
+
+    /// Add a pass, passes accessing the same resource are ordered as they were added
+    pub fn add_pass(&mut self, name: &str) -> PassBuilder<'_, 'a> {
+        self.passes.push(PassInfo {
+            name: name.to_string(),
+            accesses: Vec::new(),
+            side_effects: false,
+            queue: QueueType::default(),
+        });
+        self.records.push(None);
+        let pass = PassId(self.passes.len() - 1);
+        PassBuilder::new(self, pass)
+    }
+
+    pub fn get_resources(&self) -> &[ResourceInfo] {
+        self.resources.as_slice()
+    }
+
+    pub fn get_resource(&self, resource: impl Into<ResourceId>) -> &ResourceInfo {
+        &self.resources[resource.into().0]
+    }
+
+    pub fn get_passes(&self) -> &[PassInfo] {
+        self.passes.as_slice()
+    }
+
+    pub fn get_pass(&self, pass: PassId) -> &PassInfo {
+        &self.passes[pass.0]
+    }
+
+    pub(crate) fn get_pass_mut(&mut self, pass: PassId) -> &mut PassInfo {
+        &mut self.passes[pass.0]
+    }
+
+    pub(crate) fn set_record(&mut self, pass: PassId, record: RecordFn<'a>) {
+        self.records[pass.0] = Some(record);
+    }
+
+    /// Cull and order the passes and find their barriers without touching the GPU
+    pub fn compile(&self) -> Result<CompiledGraph, RenderGraphError> {
+        compiler::compile(&self.resources, &self.passes)
+    }
+
+    /// Create the transient resources and record every pass of `compiled` into `command_buffer`
+    ///
+    /// Transient resources which are never alive at the same time share memory, the barriers
+    /// this requires are added to `compiled` before recording. The memory is reused across
+    /// frames if the graph has a [Self::memory_pool].
+    ///
+    /// The returned [TransientResources] have to be kept alive until `command_buffer` has
+    /// finished executing.
+    pub fn execute(
+        &mut self,
+        compiled: &CompiledGraph,
+        command_buffer: &abstraction::CommandBuffer<abstraction::Recording>,
+    ) -> Result<TransientResources, vk::Result> {
+        let memory_pool = self.get_memory_pool(command_buffer.get_device());
+        let transient = TransientResources::new(memory_pool, &self.resources, compiled)?;
+        let compiled = transient.get_aliasing_plan().apply(compiled);
+        for compiled_pass in compiled.passes.iter() {
+            self.record_pass(
+                compiled_pass,
+                &[],
+                command_buffer,
+                &transient,
+                vk::PipelineStageFlags2::ALL_COMMANDS,
+            );
+        }
+        command_buffer.flush_barriers(&self.get_barrier_batch(
+            compiled.final_barriers.iter(),
+            &transient,
+            vk::PipelineStageFlags2::ALL_COMMANDS,
+        ));
+        self.update_imported_tracking(&compiled);
+        Ok(transient)
+    }
+
+    /// Record every pass of `compiled` into submissions to several queues, see [Schedule]
+    ///
+    /// Every queue the schedule uses must be present in `queues`. Command buffers are allocated
+    /// from the pool of each queue and the timeline value of each queue is advanced past every
+    /// submission made to it. The returned [TransientResources] have to be kept alive until
+    /// those timeline values are reached.
+    pub fn execute_on_queues(
+        &mut self,
+        compiled: &CompiledGraph,
+        setup: &QueueSetup,
+        queues: &mut HashMap<QueueType, GraphQueue>,
+    ) -> Result<TransientResources, vk::Result> {
+        let device = queues
+            .get(&QueueType::Graphics)
+            .ok_or(vk::Result::ERROR_INITIALIZATION_FAILED)?
+            .queue
+            .get_device();
+        let transient = TransientResources::new(self.get_memory_pool(device), &self.resources, compiled)?;
+        let aliasing_plan = transient.get_aliasing_plan();
+        let compiled = aliasing_plan.apply(compiled);
+        let schedule = Schedule::new(&self.resources, &self.passes, &compiled, setup, Some(aliasing_plan));
+        let base_values: HashMap<QueueType, u64> = queues
+            .iter()
+            .map(|(queue, graph_queue)| (*queue, graph_queue.timeline_value))
+            .collect();
+
+        for (submission_index, submission) in schedule.submissions.iter().enumerate() {
+            let mut wait_infos: Vec<vk::SemaphoreSubmitInfo> = Vec::new();
+            for wait in submission.waits.iter() {
+                let wait_queue = queues
+                    .get(&wait.queue)
+                    .ok_or(vk::Result::ERROR_INITIALIZATION_FAILED)?;
+                // Barriers on the waiting queue have to chain with the wait, so wait on every stage
+                wait_infos.push(
+                    wait_queue
+                        .timeline
+                        .submit_info(base_values[&wait.queue] + wait.value, vk::PipelineStageFlags2::ALL_COMMANDS),
+                );
+            }
+            let graph_queue = queues
+                .get_mut(&submission.queue)
+                .ok_or(vk::Result::ERROR_INITIALIZATION_FAILED)?;
+            let command_buffer = graph_queue
+                .command_pool
+                .allocate()?
+                .begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+
+            // Barriers on shared resources may carry stages of other queues
+            let supported_stages = setup.get_supported_stages(submission.queue);
+            let acquired: Vec<ResourceId> = submission
+                .acquires
+                .iter()
+                .map(|transfer| transfer.barrier.resource)
+                .collect();
+            command_buffer.flush_barriers(&self.get_transfer_batch(
+                &submission.acquires,
+                &transient,
+                supported_stages,
+            ));
+            for (position, compiled_index) in submission.passes.iter().enumerate() {
+                // Acquires replace the barriers of the first pass on the acquired resources
+                let skipped = if position == 0 { acquired.as_slice() } else { &[] };
+                self.record_pass(
+                    &compiled.passes[*compiled_index],
+                    skipped,
+                    &command_buffer,
+                    &transient,
+                    supported_stages,
+                );
+            }
+            if schedule.final_submission == Some(submission_index) {
+                let skipped = if submission.passes.is_empty() { acquired.as_slice() } else { &[] };
+                command_buffer.flush_barriers(&self.get_barrier_batch(
+                    compiled
+                        .final_barriers
+                        .iter()
+                        .filter(|barrier| !skipped.contains(&barrier.resource)),
+                    &transient,
+                    supported_stages,
+                ));
+            }
+            command_buffer.flush_barriers(&self.get_transfer_batch(
+                &submission.releases,
+                &transient,
+                supported_stages,
+            ));
+
+            let signal_info = graph_queue.timeline.submit_info(
+                base_values[&submission.queue] + submission.signal_value,
+                vk::PipelineStageFlags2::ALL_COMMANDS,
+            );
+            command_buffer
+                .end()?
+                .submit(&graph_queue.queue, &wait_infos, &[signal_info], vk::Fence::null())?;
+        }
+        for (queue, graph_queue) in queues.iter_mut() {
+            graph_queue.timeline_value = base_values[queue] + schedule.get_signal_value(*queue);
+        }
+        self.update_imported_tracking(&compiled);
+        Ok(transient)
+    }
+
+    /// Write the last accesses of imported resources back into their trackers, as the graph
+    /// records its own barriers
+    fn update_imported_tracking(&self, compiled: &CompiledGraph) {
+        for (resource_index, resource) in self.resources.iter().enumerate() {
+            let resource_id = ResourceId(resource_index);
+            let accesses: Vec<abstraction::AccessType> = match (
+                compiled.get_resource_usage(resource_id),
+                &resource.origin,
+            ) {
+                (Some(usage), _) => usage.last_accesses.clone(),
+                (
+                    None,
+                    ResourceOrigin::Imported {
+                        final_access: Some(final_access),
+                        ..
+                    },
+                ) => vec![*final_access],
+                // Untouched by the graph
+                _ => continue,
+            };
+            if accesses.is_empty() {
+                continue;
+            }
+            let resource_accesses: Vec<abstraction::ResourceAccess> = accesses
+                .iter()
+                .map(|access| access.get_resource_access())
+                .collect();
+            let state = abstraction::SubresourceState::from_accesses(&resource_accesses);
+            if let Some(image) = self.imported_images.get(&resource_id) {
+                // Layout the compiler picked for the accesses together
+                image.set_state(abstraction::SubresourceState {
+                    layout: abstraction::ImageLayoutPolicy::Optimal.get_layout(&accesses),
+                    ..state
+                });
+            } else if let Some(buffer) = self.imported_buffers.get(&resource_id) {
+                buffer.set_state(state);
+            }
+        }
+    }
+
+    /// Record the barriers of `compiled_pass`, except those on `skipped` resources, and the pass
+    /// on a queue supporting `supported_stages`
+    fn record_pass(
+        &mut self,
+        compiled_pass: &CompiledPass,
+        skipped: &[ResourceId],
+        command_buffer: &abstraction::CommandBuffer<abstraction::Recording>,
+        transient: &TransientResources,
+        supported_stages: vk::PipelineStageFlags2,
+    ) {
+        command_buffer.flush_barriers(&self.get_barrier_batch(
+            compiled_pass
+                .barriers
+                .iter()
+                .filter(|barrier| !skipped.contains(&barrier.resource)),
+            transient,
+            supported_stages,
+        ));
+        if let Some(record) = self.records[compiled_pass.pass.0].as_mut() {
+            record(&PassContext {
+                command_buffer,
+                imported_images: &self.imported_images,
+                imported_buffers: &self.imported_buffers,
+                transient,
+            });
+        }
+    }
+
+    /// Build the Vulkan barriers of `barriers` now that every resource exists, recorded on a
+    /// queue supporting `supported_stages`
+    fn get_barrier_batch<'b>(
+        &self,
+        barriers: impl IntoIterator<Item = &'b GraphBarrier>,
+        transient: &TransientResources,
+        supported_stages: vk::PipelineStageFlags2,
+    ) -> abstraction::BarrierBatch {
+        let mut image_barriers: Vec<abstraction::ImageBarrier> = Vec::new();
+        let mut buffer_barriers: Vec<abstraction::BufferBarrier> = Vec::new();
+        for barrier in barriers {
+            match self.resources[barrier.resource.0].desc {
+                ResourceDesc::Image(_) => {
+                    let image = match self.imported_images.get(&barrier.resource) {
+                        Some(image) => *image,
+                        None => transient.get_image(barrier.resource).unwrap(),
+                    };
+                    let mut image_barrier = abstraction::ImageBarrier::new(
+                        image.get_handle(),
+                        image.get_full_range(),
+                        &barrier.prev_accesses,
+                        &barrier.next_accesses,
+                    );
+                    if barrier.discard_contents {
+                        image_barrier = image_barrier.discard_contents();
+                    }
+                    image_barriers.push(image_barrier.supported_stages(supported_stages));
+                }
+                ResourceDesc::Buffer(_) => {
+                    let buffer = match self.imported_buffers.get(&barrier.resource) {
+                        Some(buffer) => *buffer,
+                        None => transient.get_buffer(barrier.resource).unwrap(),
+                    };
+                    buffer_barriers.push(
+                        abstraction::BufferBarrier::new(
+                            buffer.get_handle(),
+                            &barrier.prev_accesses,
+                            &barrier.next_accesses,
+                        )
+                        .supported_stages(supported_stages),
+                    );
+                }
+            }
+        }
+        abstraction::get_dependency(None, &buffer_barriers, &image_barriers)
+    }
+
+    /// Collect the barriers recording one half of every ownership transfer in `transfers` on a
+    /// queue supporting `supported_stages`
+    fn get_transfer_batch(
+        &self,
+        transfers: &[OwnershipTransfer],
+        transient: &TransientResources,
+        supported_stages: vk::PipelineStageFlags2,
+    ) -> abstraction::BarrierBatch {
+        let mut image_barriers: Vec<abstraction::ImageBarrier> = Vec::new();
+        let mut buffer_barriers: Vec<abstraction::BufferBarrier> = Vec::new();
+        for transfer in transfers {
+            let resource = transfer.barrier.resource;
+            match self.resources[resource.0].desc {
+                ResourceDesc::Image(_) => {
+                    let image = match self.imported_images.get(&resource) {
+                        Some(image) => *image,
+                        None => transient.get_image(resource).unwrap(),
+                    };
+                    image_barriers.push(
+                        transfer
+                            .get_image_barrier(image.get_handle(), image.get_full_range())
+                            .supported_stages(supported_stages),
+                    );
+                }
+                ResourceDesc::Buffer(_) => {
+                    let buffer = match self.imported_buffers.get(&resource) {
+                        Some(buffer) => *buffer,
+                        None => transient.get_buffer(resource).unwrap(),
+                    };
+                    buffer_barriers.push(
+                        transfer
+                            .get_buffer_barrier(buffer.get_handle())
+                            .supported_stages(supported_stages),
+                    );
+                }
+            }
+        }
+        abstraction::get_dependency(None, &buffer_barriers, &image_barriers)
+    }
+}
@@ -0,0 +1,62 @@
+//! A render graph orders the passes of a frame and synchronizes them automatically
+//!
+//! Passes declare which images and buffers they read and write, along with a callback recording
+//! their commands. [RenderGraph::compile] culls passes whose outputs are never used, keeps the
+//! rest in declaration order and inserts synchronization2 barriers and layout transitions
+//! between them. [RenderGraph::execute] then creates the transient resources, aliasing the
+//! memory of resources whose lifetimes do not overlap, and records every pass. A
+//! [TransientMemoryPool] kept across frames lets that memory be reused instead of allocated
+//! again.
+//! [RenderGraph::execute_on_queues] additionally spreads passes across the graphics, async
+//! compute and transfer queues, see [Schedule].
+//!
+//! ```no_run
+//! # use dagal::abstraction::prelude as abstraction;
+//! # use dagal::framework::render_graph::*;
+//! # use ash::vk;
+//! # fn record(swapchain_image: &abstraction::Image, command_buffer: &abstraction::CommandBuffer<abstraction::Recording>) -> Result<(), vk::Result> {
+//! let mut graph = RenderGraph::new();
+//! let scene = graph.create_image("scene", ImageDesc::new_2d(vk::Format::R16G16B16A16_SFLOAT, 1920, 1080));
+//! let swapchain = graph.import_image(
+//!     "swapchain",
+//!     swapchain_image,
+//!     abstraction::AccessType::Nothing,
+//!     Some(abstraction::AccessType::Present),
+//! );
+//! graph
+//!     .add_pass("scene")
+//!     .write(scene, abstraction::AccessType::ColorAttachmentWrite)
+//!     .record(move |context| {
+//!         let rendering_info = abstraction::RenderingInfo::new()
+//!             .color_attachment(abstraction::RenderingAttachment::new(context.get_image(scene)));
+//!         context.get_command_buffer().begin_rendering(&rendering_info);
+//!         context.get_command_buffer().end_rendering();
+//!     });
+//! graph
+//!     .add_pass("tonemap")
+//!     .read(scene, abstraction::AccessType::FragmentShaderReadSampledImage)
+//!     .write(swapchain, abstraction::AccessType::ColorAttachmentWrite)
+//!     .record(|_context| {});
+//! let compiled = graph.compile().unwrap();
+//! // Keep the transient resources alive until the command buffer has executed
+//! let transient = graph.execute(&compiled, command_buffer)?;
+//! # Ok(())
+//! # }
+//! ```
+pub mod aliasing;
+pub mod compiler;
+pub mod export;
+pub mod graph;
+pub mod pass;
+pub mod resource;
+pub mod schedule;
+pub mod transient;
+
+pub use aliasing::*;
+pub use compiler::*;
+pub use export::*;
+pub use graph::*;
+pub use pass::*;
+pub use resource::*;
+pub use schedule::*;
+pub use transient::*;
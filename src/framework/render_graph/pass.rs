@@ -0,0 +1,129 @@
+//! Passes of a [RenderGraph] and the context their record callbacks receive
+use super::resource::{BufferId, ImageId, ResourceId};
+use super::schedule::QueueType;
+use super::transient::TransientResources;
+use super::RenderGraph;
+use crate::abstraction::prelude as abstraction;
+use std::collections::HashMap;
+
+/// Index of a pass in its [RenderGraph]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct PassId(pub(crate) usize);
+
+impl PassId {
+    pub fn get_index(&self) -> usize {
+        self.0
+    }
+}
+
+/// Records the commands of a pass
+pub type RecordFn<'a> = Box<dyn FnMut(&PassContext) + 'a>;
+
+/// A single access of a resource by a pass
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PassAccess {
+    pub resource: ResourceId,
+    pub access: abstraction::AccessType,
+}
+
+/// A pass declared in a [RenderGraph]
+#[derive(Clone, Debug)]
+pub struct PassInfo {
+    pub name: String,
+    /// Every access in the order they were declared, a resource may be accessed several times
+    pub accesses: Vec<PassAccess>,
+    /// Whether the pass has to execute even if none of its outputs are used
+    pub side_effects: bool,
+    /// Queue the pass would like to execute on
+    pub queue: QueueType,
+}
+
+/// Declares the accesses of a pass
+pub struct PassBuilder<'g, 'a> {
+    graph: &'g mut RenderGraph<'a>,
+    pass: PassId,
+}
+
+impl<'g, 'a> PassBuilder<'g, 'a> {
+    pub(crate) fn new(graph: &'g mut RenderGraph<'a>, pass: PassId) -> Self {
+        Self { graph, pass }
+    }
+
+    pub fn get_id(&self) -> PassId {
+        self.pass
+    }
+
+    /// Declare that the pass reads `resource`
+    pub fn read(self, resource: impl Into<ResourceId>, access: abstraction::AccessType) -> Self {
+        debug_assert!(!access.is_write(), "{:?} writes to the resource", access);
+        self.access(resource.into(), access)
+    }
+
+    /// Declare that the pass writes to `resource`
+    pub fn write(self, resource: impl Into<ResourceId>, access: abstraction::AccessType) -> Self {
+        debug_assert!(access.is_write(), "{:?} only reads the resource", access);
+        self.access(resource.into(), access)
+    }
+
+    /// Always execute the pass, even if nothing reads what it writes
+    pub fn side_effects(self) -> Self {
+        self.graph.get_pass_mut(self.pass).side_effects = true;
+        self
+    }
+
+    /// Prefer executing the pass on `queue`, see [super::Schedule]
+    pub fn queue(self, queue: QueueType) -> Self {
+        self.graph.get_pass_mut(self.pass).queue = queue;
+        self
+    }
+
+    /// Set the callback recording the commands of the pass
+    pub fn record<F: FnMut(&PassContext) + 'a>(self, record: F) -> PassId {
+        self.graph.set_record(self.pass, Box::new(record));
+        self.pass
+    }
+
+    fn access(self, resource: ResourceId, access: abstraction::AccessType) -> Self {
+        self.graph
+            .get_pass_mut(self.pass)
+            .accesses
+            .push(PassAccess { resource, access });
+        self
+    }
+}
+
+/// Gives record callbacks access to the command buffer and the resources of the graph
+pub struct PassContext<'c> {
+    pub(crate) command_buffer: &'c abstraction::CommandBuffer<abstraction::Recording>,
+    pub(crate) imported_images: &'c HashMap<ResourceId, &'c abstraction::Image>,
+    pub(crate) imported_buffers: &'c HashMap<ResourceId, &'c abstraction::Buffer>,
+    pub(crate) transient: &'c TransientResources,
+}
+
+impl<'c> PassContext<'c> {
+    pub fn get_command_buffer(&self) -> &abstraction::CommandBuffer<abstraction::Recording> {
+        self.command_buffer
+    }
+
+    /// Get an image of the graph
+    ///
+    /// # Panics
+    /// If the image is transient and was culled along with every pass using it
+    pub fn get_image(&self, image: ImageId) -> &abstraction::Image {
+        match self.imported_images.get(&image.0) {
+            Some(image) => image,
+            None => self.transient.get_image(image.0).unwrap(),
+        }
+    }
+
+    /// Get a buffer of the graph
+    ///
+    /// # Panics
+    /// If the buffer is transient and was culled along with every pass using it
+    pub fn get_buffer(&self, buffer: BufferId) -> &abstraction::Buffer {
+        match self.imported_buffers.get(&buffer.0) {
+            Some(buffer) => buffer,
+            None => self.transient.get_buffer(buffer.0).unwrap(),
+        }
+    }
+}
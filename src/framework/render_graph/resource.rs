@@ -0,0 +1,224 @@
+//! Resources which passes of a [super::RenderGraph] access
+//!
+//! Transient resources are only described when declared and are created by the graph itself,
+//! imported resources are owned by the caller and live past the graph.
+use crate::abstraction::prelude as abstraction;
+use ash::vk;
+
+/// Index of a resource in its [super::RenderGraph]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct ResourceId(pub(crate) usize);
+
+impl ResourceId {
+    pub fn get_index(&self) -> usize {
+        self.0
+    }
+}
+
+/// Image declared in a [super::RenderGraph]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct ImageId(pub(crate) ResourceId);
+
+impl From<ImageId> for ResourceId {
+    fn from(value: ImageId) -> Self {
+        value.0
+    }
+}
+
+/// Buffer declared in a [super::RenderGraph]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct BufferId(pub(crate) ResourceId);
+
+impl From<BufferId> for ResourceId {
+    fn from(value: BufferId) -> Self {
+        value.0
+    }
+}
+
+/// Description of an image, usage flags are inferred from the passes accessing it
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ImageDesc {
+    pub image_type: vk::ImageType,
+    pub format: vk::Format,
+    pub extent: vk::Extent3D,
+    pub mip_levels: u32,
+    pub array_layers: u32,
+    pub samples: vk::SampleCountFlags,
+}
+
+impl ImageDesc {
+    pub fn new_2d(format: vk::Format, width: u32, height: u32) -> Self {
+        Self {
+            image_type: vk::ImageType::TYPE_2D,
+            format,
+            extent: vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: 1,
+            samples: vk::SampleCountFlags::TYPE_1,
+        }
+    }
+
+    pub fn mip_levels(mut self, mip_levels: u32) -> Self {
+        self.mip_levels = mip_levels;
+        self
+    }
+
+    pub fn array_layers(mut self, array_layers: u32) -> Self {
+        self.array_layers = array_layers;
+        self
+    }
+
+    pub fn samples(mut self, samples: vk::SampleCountFlags) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// Get a [vk::ImageSubresourceRange] covering every subresource of the image
+    pub fn get_full_range(&self) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange {
+            aspect_mask: abstraction::format_aspect_mask(self.format),
+            base_mip_level: 0,
+            level_count: self.mip_levels,
+            base_array_layer: 0,
+            layer_count: self.array_layers,
+        }
+    }
+}
+
+impl From<&abstraction::Image> for ImageDesc {
+    fn from(image: &abstraction::Image) -> Self {
+        let extent = image.get_extent();
+        Self {
+            image_type: if extent.depth > 1 {
+                vk::ImageType::TYPE_3D
+            } else {
+                vk::ImageType::TYPE_2D
+            },
+            format: image.get_format(),
+            extent,
+            mip_levels: image.get_mip_levels(),
+            array_layers: image.get_array_layers(),
+            samples: vk::SampleCountFlags::TYPE_1,
+        }
+    }
+}
+
+/// Description of a buffer, usage flags are inferred from the passes accessing it
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct BufferDesc {
+    pub size: vk::DeviceSize,
+}
+
+impl BufferDesc {
+    pub fn new(size: vk::DeviceSize) -> Self {
+        Self { size }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ResourceDesc {
+    Image(ImageDesc),
+    Buffer(BufferDesc),
+}
+
+/// Where a resource comes from
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ResourceOrigin {
+    /// Created by the graph, its contents are undefined before the first pass writing to it
+    Transient,
+    /// Owned outside of the graph
+    Imported {
+        /// Last access of the resource before the graph executes
+        initial_access: abstraction::AccessType,
+        /// Access the resource is transitioned to once every pass has executed
+        final_access: Option<abstraction::AccessType>,
+    },
+}
+
+/// A resource declared in a [super::RenderGraph]
+#[derive(Clone, Debug)]
+pub struct ResourceInfo {
+    pub name: String,
+    pub desc: ResourceDesc,
+    pub origin: ResourceOrigin,
+    /// Whether the resource uses [vk::SharingMode::CONCURRENT], exclusive resources need queue
+    /// family ownership transfers when used by several queue families
+    pub concurrent: bool,
+    /// Queue family owning an exclusive imported resource before the graph executes, [None] if
+    /// it is not owned by any family yet
+    pub initial_owner: Option<u32>,
+}
+
+impl ResourceInfo {
+    pub fn is_transient(&self) -> bool {
+        self.origin == ResourceOrigin::Transient
+    }
+
+    pub fn is_image(&self) -> bool {
+        matches!(self.desc, ResourceDesc::Image(_))
+    }
+}
+
+/// Get the usage an image needs to be created with for `access`
+pub(crate) fn get_image_usage(access: abstraction::AccessType) -> vk::ImageUsageFlags {
+    use abstraction::AccessType::*;
+    match access {
+        VertexShaderReadSampledImage
+        | FragmentShaderReadSampledImage
+        | ComputeShaderReadSampledImage
+        | AnyShaderReadSampledImage => vk::ImageUsageFlags::SAMPLED,
+        VertexShaderReadOther
+        | FragmentShaderReadOther
+        | ComputeShaderReadOther
+        | AnyShaderReadOther
+        | VertexShaderWrite
+        | FragmentShaderWrite
+        | ComputeShaderWrite
+        | AnyShaderWrite => vk::ImageUsageFlags::STORAGE,
+        FragmentShaderReadColorInputAttachment => {
+            vk::ImageUsageFlags::INPUT_ATTACHMENT | vk::ImageUsageFlags::COLOR_ATTACHMENT
+        }
+        FragmentShaderReadDepthStencilInputAttachment => {
+            vk::ImageUsageFlags::INPUT_ATTACHMENT | vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+        }
+        ColorAttachmentRead | ColorAttachmentWrite | ColorAttachmentReadWrite => {
+            vk::ImageUsageFlags::COLOR_ATTACHMENT
+        }
+        DepthStencilAttachmentRead
+        | DepthStencilAttachmentWrite
+        | DepthAttachmentWriteStencilReadOnly
+        | StencilAttachmentWriteDepthReadOnly => vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        TransferRead => vk::ImageUsageFlags::TRANSFER_SRC,
+        TransferWrite => vk::ImageUsageFlags::TRANSFER_DST,
+        _ => vk::ImageUsageFlags::empty(),
+    }
+}
+
+/// Get the usage a buffer needs to be created with for `access`
+pub(crate) fn get_buffer_usage(access: abstraction::AccessType) -> vk::BufferUsageFlags {
+    use abstraction::AccessType::*;
+    match access {
+        IndirectBuffer => vk::BufferUsageFlags::INDIRECT_BUFFER,
+        IndexBuffer => vk::BufferUsageFlags::INDEX_BUFFER,
+        VertexBuffer => vk::BufferUsageFlags::VERTEX_BUFFER,
+        VertexShaderReadUniformBuffer
+        | FragmentShaderReadUniformBuffer
+        | ComputeShaderReadUniformBuffer
+        | AnyShaderReadUniformBuffer => vk::BufferUsageFlags::UNIFORM_BUFFER,
+        VertexShaderReadOther
+        | FragmentShaderReadOther
+        | ComputeShaderReadOther
+        | AnyShaderReadOther
+        | VertexShaderWrite
+        | FragmentShaderWrite
+        | ComputeShaderWrite
+        | AnyShaderWrite => vk::BufferUsageFlags::STORAGE_BUFFER,
+        TransferRead => vk::BufferUsageFlags::TRANSFER_SRC,
+        TransferWrite => vk::BufferUsageFlags::TRANSFER_DST,
+        _ => vk::BufferUsageFlags::empty(),
+    }
+}
@@ -0,0 +1,699 @@
+//! Spreads the passes of a compiled [super::RenderGraph] across several queues
+//!
+//! Passes prefer a [QueueType], which is resolved to the queues which actually exist. Passes
+//! are grouped into submissions in execution order, a new submission is started whenever a pass
+//! depends on work submitted to another queue. Every submission signals the timeline semaphore
+//! of its queue and waits on the timeline values of the submissions it depends on.
+//!
+//! Exclusive resources used by another queue family are released at the end of the submission
+//! which last used them and acquired at the start of the submission using them next. Imported
+//! resources owned by a family before the graph are released on its queue first. When only a
+//! single queue exists every pass ends up in one submission.
+use super::aliasing::AliasingPlan;
+use super::compiler::{self, CompiledGraph, GraphBarrier};
+use super::pass::PassInfo;
+use super::resource::{ResourceId, ResourceInfo};
+use crate::abstraction::prelude as abstraction;
+use ash::vk;
+use std::collections::HashMap;
+
+/// Kind of queue a pass executes on
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub enum QueueType {
+    #[default]
+    Graphics,
+    AsyncCompute,
+    Transfer,
+}
+
+/// A single queue created by the device
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct QueueSlot {
+    pub family_index: u32,
+    pub queue_index: u32,
+}
+
+/// Queues the passes of a graph can be submitted to
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct QueueSetup {
+    pub graphics: QueueSlot,
+    pub async_compute: Option<QueueSlot>,
+    pub transfer: Option<QueueSlot>,
+}
+
+impl QueueSetup {
+    /// Execute every pass on a single graphics queue
+    pub fn serial(graphics_family_index: u32) -> Self {
+        Self {
+            graphics: QueueSlot {
+                family_index: graphics_family_index,
+                queue_index: 0,
+            },
+            async_compute: None,
+            transfer: None,
+        }
+    }
+
+    /// Use the queues `device` was created with
+    ///
+    /// Async compute prefers a dedicated compute family, falling back to a second queue of the
+    /// graphics family. Transfers only use a dedicated transfer family.
+    pub fn from_device(device: &abstraction::Device) -> Option<Self> {
+        let families = device.get_queue_families();
+        let graphics = families
+            .iter()
+            .find(|family| family.get_flags().contains(vk::QueueFlags::GRAPHICS))?;
+        let async_compute = families
+            .iter()
+            .find(|family| {
+                family.get_flags().contains(vk::QueueFlags::COMPUTE)
+                    && !family.get_flags().contains(vk::QueueFlags::GRAPHICS)
+            })
+            .map(|family| QueueSlot {
+                family_index: family.get_index(),
+                queue_index: 0,
+            })
+            .or((graphics.get_count() > 1).then_some(QueueSlot {
+                family_index: graphics.get_index(),
+                queue_index: 1,
+            }));
+        let transfer = families
+            .iter()
+            .find(|family| {
+                family.get_flags().contains(vk::QueueFlags::TRANSFER)
+                    && !family
+                        .get_flags()
+                        .intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+            })
+            .map(|family| QueueSlot {
+                family_index: family.get_index(),
+                queue_index: 0,
+            });
+        Some(Self {
+            graphics: QueueSlot {
+                family_index: graphics.get_index(),
+                queue_index: 0,
+            },
+            async_compute,
+            transfer,
+        })
+    }
+
+    /// Get the queue a pass preferring `queue` executes on
+    pub fn resolve(&self, queue: QueueType) -> QueueType {
+        match queue {
+            QueueType::AsyncCompute if self.async_compute.is_some() => QueueType::AsyncCompute,
+            QueueType::Transfer if self.transfer.is_some() => QueueType::Transfer,
+            QueueType::Transfer if self.async_compute.is_some() => QueueType::AsyncCompute,
+            _ => QueueType::Graphics,
+        }
+    }
+
+    pub fn get_slot(&self, queue: QueueType) -> QueueSlot {
+        match self.resolve(queue) {
+            QueueType::Graphics => self.graphics,
+            QueueType::AsyncCompute => self.async_compute.unwrap(),
+            QueueType::Transfer => self.transfer.unwrap(),
+        }
+    }
+
+    /// Get the stages barriers recorded on the queue passes preferring `queue` execute on can
+    /// wait on
+    ///
+    /// Async compute on a second graphics queue supports every stage, dedicated compute and
+    /// transfer families only their own.
+    pub fn get_supported_stages(&self, queue: QueueType) -> vk::PipelineStageFlags2 {
+        let transfer_stages = vk::PipelineStageFlags2::ALL_TRANSFER
+            | vk::PipelineStageFlags2::COPY
+            | vk::PipelineStageFlags2::BLIT
+            | vk::PipelineStageFlags2::RESOLVE
+            | vk::PipelineStageFlags2::CLEAR
+            | vk::PipelineStageFlags2::HOST;
+        let resolved = self.resolve(queue);
+        if self.get_slot(resolved).family_index == self.graphics.family_index {
+            return vk::PipelineStageFlags2::ALL_COMMANDS;
+        }
+        match resolved {
+            QueueType::AsyncCompute => {
+                transfer_stages
+                    | vk::PipelineStageFlags2::DRAW_INDIRECT
+                    | vk::PipelineStageFlags2::COMPUTE_SHADER
+            }
+            QueueType::Transfer => transfer_stages,
+            QueueType::Graphics => vk::PipelineStageFlags2::ALL_COMMANDS,
+        }
+    }
+
+    /// Retrieve the [abstraction::Queue] passes preferring `queue` are submitted to
+    pub fn get_queue(&self, device: &abstraction::Device, queue: QueueType) -> abstraction::Queue {
+        let slot = self.get_slot(queue);
+        device.get_queue(slot.family_index, slot.queue_index)
+    }
+}
+
+/// Wait on the timeline semaphore of another queue
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct QueueWait {
+    pub queue: QueueType,
+    /// Relative to the timeline value of the queue before the graph executed
+    pub value: u64,
+}
+
+/// Half of a queue family ownership transfer
+///
+/// The release half only carries the stages and accesses of the previous accesses on the source
+/// family, the acquire half only those of the next accesses on the destination family.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OwnershipTransfer {
+    pub barrier: GraphBarrier,
+    pub half: abstraction::QueueTransferHalf,
+    pub src_family_index: u32,
+    pub dst_family_index: u32,
+}
+
+impl OwnershipTransfer {
+    /// Get the barrier recording this half of the transfer on `image`
+    pub fn get_image_barrier(
+        &self,
+        image: vk::Image,
+        subresource_range: vk::ImageSubresourceRange,
+    ) -> abstraction::ImageBarrier<'_> {
+        let mut image_barrier = abstraction::ImageBarrier::new(
+            image,
+            subresource_range,
+            &self.barrier.prev_accesses,
+            &self.barrier.next_accesses,
+        )
+        .queue_family_transfer(self.half, self.src_family_index, self.dst_family_index);
+        if self.barrier.discard_contents {
+            image_barrier = image_barrier.discard_contents();
+        }
+        image_barrier
+    }
+
+    /// Get the barrier recording this half of the transfer on `buffer`
+    pub fn get_buffer_barrier(&self, buffer: vk::Buffer) -> abstraction::BufferBarrier<'_> {
+        abstraction::BufferBarrier::new(
+            buffer,
+            &self.barrier.prev_accesses,
+            &self.barrier.next_accesses,
+        )
+        .queue_family_transfer(self.half, self.src_family_index, self.dst_family_index)
+    }
+}
+
+/// Passes submitted to a queue together
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct QueueSubmission {
+    pub queue: QueueType,
+    pub family_index: u32,
+    /// Indices into [CompiledGraph::passes]
+    pub passes: Vec<usize>,
+    /// Waited on before any pass of the submission executes
+    pub waits: Vec<QueueWait>,
+    /// Relative to the timeline value of the queue before the graph executed
+    pub signal_value: u64,
+    /// Recorded at the start of the submission, replacing the barriers of the first pass on
+    /// the same resources
+    pub acquires: Vec<OwnershipTransfer>,
+    /// Recorded at the end of the submission
+    pub releases: Vec<OwnershipTransfer>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Schedule {
+    /// Submissions in the order they have to be submitted in
+    pub submissions: Vec<QueueSubmission>,
+    /// Submission recording [CompiledGraph::final_barriers]
+    pub final_submission: Option<usize>,
+}
+
+/// Last known use of a resource while scheduling
+#[derive(Clone, Default)]
+struct ResourceTracker {
+    last_write: Option<usize>,
+    /// Submissions reading the resource since [Self::last_write]
+    reads: Vec<usize>,
+    last_submission: Option<usize>,
+    /// Family owning the resource if it is exclusive
+    owner: Option<u32>,
+    last_accesses: Vec<abstraction::AccessType>,
+}
+
+struct ScheduleBuilder<'s> {
+    resources: &'s [ResourceInfo],
+    compiled: &'s CompiledGraph,
+    setup: &'s QueueSetup,
+    aliasing: Option<&'s AliasingPlan>,
+    trackers: Vec<ResourceTracker>,
+    open: HashMap<QueueType, usize>,
+    signal_values: HashMap<QueueType, u64>,
+    schedule: Schedule,
+}
+
+impl<'s> ScheduleBuilder<'s> {
+    /// Schedule `accesses` on `queue`, returning the submission they were added to
+    fn add(
+        &mut self,
+        queue: QueueType,
+        compiled_index: Option<usize>,
+        accesses: &[(ResourceId, Vec<abstraction::AccessType>)],
+        barriers: &[GraphBarrier],
+    ) -> usize {
+        let family_index = self.setup.get_slot(queue).family_index;
+        let mut waits: Vec<usize> = Vec::new();
+        // Transfers along with the submission releasing them, if any
+        let mut transfers: Vec<(Option<usize>, OwnershipTransfer)> = Vec::new();
+        for (resource, accesses) in accesses.iter() {
+            let tracker = &self.trackers[resource.0];
+            let is_write = accesses.iter().any(|access| access.is_write());
+            let mut dependencies: Vec<usize> = tracker.last_write.into_iter().collect();
+            if is_write {
+                dependencies.extend(tracker.reads.iter());
+            }
+            // The first use of aliased memory has to wait on every previous user of it
+            if let (Some(aliasing), Some(compiled_index)) = (self.aliasing, compiled_index) {
+                let usage = self.compiled.get_resource_usage(*resource);
+                if usage.map(|usage| usage.first_pass) == Some(compiled_index) {
+                    for aliased in aliasing.get_aliased_resources(*resource) {
+                        let aliased_tracker = &self.trackers[aliased.0];
+                        dependencies.extend(aliased_tracker.last_write.iter());
+                        dependencies.extend(aliased_tracker.reads.iter());
+                    }
+                }
+            }
+            waits.extend(
+                dependencies
+                    .into_iter()
+                    .filter(|submission| self.schedule.submissions[*submission].queue != queue),
+            );
+
+            if self.resources[resource.0].concurrent {
+                continue;
+            }
+            let barrier = barriers
+                .iter()
+                .find(|barrier| barrier.resource == *resource)
+                .cloned()
+                .unwrap_or_else(|| GraphBarrier {
+                    resource: *resource,
+                    prev_accesses: tracker.last_accesses.clone(),
+                    next_accesses: accesses.clone(),
+                    discard_contents: false,
+                });
+            let last_submission = tracker.last_submission;
+            // Undefined contents do not have to be transferred
+            if let Some(owner) = tracker
+                .owner
+                .filter(|owner| *owner != family_index && !barrier.discard_contents)
+            {
+                // Resources owned before the graph are released on the owning queue if the
+                // graph uses it
+                let release_submission =
+                    last_submission.or_else(|| self.get_release_submission(owner));
+                transfers.push((
+                    release_submission,
+                    OwnershipTransfer {
+                        barrier,
+                        half: abstraction::QueueTransferHalf::Acquire,
+                        src_family_index: owner,
+                        dst_family_index: family_index,
+                    },
+                ));
+                waits.extend(release_submission);
+            }
+        }
+        waits.sort();
+        waits.dedup();
+
+        let submission = match self.open.get(&queue) {
+            Some(submission) if waits.is_empty() => *submission,
+            _ => {
+                // Submissions which are waited on cannot receive any more passes
+                for wait in waits.iter() {
+                    let wait_queue = self.schedule.submissions[*wait].queue;
+                    if self.open.get(&wait_queue) == Some(wait) {
+                        self.open.remove(&wait_queue);
+                    }
+                }
+                self.push_submission(queue, &waits)
+            }
+        };
+        for (release_submission, transfer) in transfers {
+            if let Some(release_submission) = release_submission {
+                self.schedule.submissions[release_submission]
+                    .releases
+                    .push(OwnershipTransfer {
+                        half: abstraction::QueueTransferHalf::Release,
+                        ..transfer.clone()
+                    });
+            }
+            self.schedule.submissions[submission].acquires.push(transfer);
+        }
+        if let Some(compiled_index) = compiled_index {
+            self.schedule.submissions[submission].passes.push(compiled_index);
+        }
+
+        for (resource, accesses) in accesses.iter() {
+            let tracker = &mut self.trackers[resource.0];
+            tracker.last_submission = Some(submission);
+            if accesses.iter().any(|access| access.is_write()) {
+                tracker.last_write = Some(submission);
+                tracker.reads.clear();
+                tracker.last_accesses = accesses.clone();
+            } else {
+                tracker.reads.push(submission);
+                for access in accesses.iter() {
+                    if !tracker.last_accesses.contains(access) {
+                        tracker.last_accesses.push(*access);
+                    }
+                }
+            }
+            if !self.resources[resource.0].concurrent {
+                tracker.owner = Some(family_index);
+            }
+        }
+        submission
+    }
+
+    /// Start a new submission on `queue` waiting on the submissions `waits`
+    fn push_submission(&mut self, queue: QueueType, waits: &[usize]) -> usize {
+        let signal_value = self.signal_values.entry(queue).or_insert(0);
+        *signal_value += 1;
+        self.schedule.submissions.push(QueueSubmission {
+            queue,
+            family_index: self.setup.get_slot(queue).family_index,
+            passes: Vec::new(),
+            waits: waits
+                .iter()
+                .map(|wait| QueueWait {
+                    queue: self.schedule.submissions[*wait].queue,
+                    value: self.schedule.submissions[*wait].signal_value,
+                })
+                .collect(),
+            signal_value: *signal_value,
+            acquires: Vec::new(),
+            releases: Vec::new(),
+        });
+        let submission = self.schedule.submissions.len() - 1;
+        self.open.insert(queue, submission);
+        submission
+    }
+
+    /// Get a submission on a queue of `family_index` releasing resources owned by it before the
+    /// graph, [None] if none of the queues belong to the family
+    fn get_release_submission(&mut self, family_index: u32) -> Option<usize> {
+        let queue = [QueueType::Graphics, QueueType::AsyncCompute, QueueType::Transfer]
+            .into_iter()
+            .find(|queue| {
+                self.setup.resolve(*queue) == *queue
+                    && self.setup.get_slot(*queue).family_index == family_index
+            })?;
+        match self.open.get(&queue) {
+            Some(submission) => Some(*submission),
+            None => Some(self.push_submission(queue, &[])),
+        }
+    }
+}
+
+impl Schedule {
+    /// Assign every pass of `compiled` to a submission
+    ///
+    /// If transient resources alias memory, `aliasing` makes the first use of aliased memory
+    /// wait on its previous users on other queues.
+    pub fn new(
+        resources: &[ResourceInfo],
+        passes: &[PassInfo],
+        compiled: &CompiledGraph,
+        setup: &QueueSetup,
+        aliasing: Option<&AliasingPlan>,
+    ) -> Self {
+        let mut builder = ScheduleBuilder {
+            resources,
+            compiled,
+            setup,
+            aliasing,
+            trackers: resources
+                .iter()
+                .map(|resource| ResourceTracker {
+                    owner: resource.initial_owner.filter(|_| !resource.concurrent),
+                    ..Default::default()
+                })
+                .collect(),
+            open: HashMap::new(),
+            signal_values: HashMap::new(),
+            schedule: Schedule::default(),
+        };
+        for (compiled_index, compiled_pass) in compiled.passes.iter().enumerate() {
+            let pass = &passes[compiled_pass.pass.0];
+            builder.add(
+                setup.resolve(pass.queue),
+                Some(compiled_index),
+                &compiler::group_accesses(pass),
+                &compiled_pass.barriers,
+            );
+        }
+        // Imported resources are handed back on the graphics queue
+        if !compiled.final_barriers.is_empty() {
+            let final_accesses: Vec<(ResourceId, Vec<abstraction::AccessType>)> = compiled
+                .final_barriers
+                .iter()
+                .map(|barrier| (barrier.resource, barrier.next_accesses.clone()))
+                .collect();
+            let submission = builder.add(QueueType::Graphics, None, &final_accesses, &compiled.final_barriers);
+            builder.schedule.final_submission = Some(submission);
+        }
+        builder.schedule
+    }
+
+    /// Get the last timeline value signalled on `queue`, relative to its value before the graph
+    /// executed
+    pub fn get_signal_value(&self, queue: QueueType) -> u64 {
+        self.submissions
+            .iter()
+            .filter(|submission| submission.queue == queue)
+            .map(|submission| submission.signal_value)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Get the queue the compiled pass at `compiled_index` executes on
+    pub fn get_queue(&self, compiled_index: usize) -> Option<QueueType> {
+        self.submissions
+            .iter()
+            .find(|submission| submission.passes.contains(&compiled_index))
+            .map(|submission| submission.queue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framework::render_graph::{ImageDesc, RenderGraph};
+    use abstraction::AccessType;
+
+    fn async_setup() -> QueueSetup {
+        QueueSetup {
+            graphics: QueueSlot {
+                family_index: 0,
+                queue_index: 0,
+            },
+            async_compute: Some(QueueSlot {
+                family_index: 1,
+                queue_index: 0,
+            }),
+            transfer: None,
+        }
+    }
+
+    /// Depth prepass feeding async SSAO, with a shadow pass which can overlap it
+    fn build_graph(graph: &mut RenderGraph) {
+        let depth = graph.create_image("depth", ImageDesc::new_2d(vk::Format::D32_SFLOAT, 64, 64));
+        let ao = graph.create_image("ao", ImageDesc::new_2d(vk::Format::R8_UNORM, 64, 64));
+        let shadow = graph.create_image("shadow", ImageDesc::new_2d(vk::Format::D32_SFLOAT, 64, 64));
+        graph
+            .add_pass("depth")
+            .write(depth, AccessType::DepthStencilAttachmentWrite);
+        graph
+            .add_pass("ssao")
+            .queue(QueueType::AsyncCompute)
+            .read(depth, AccessType::ComputeShaderReadSampledImage)
+            .write(ao, AccessType::ComputeShaderWrite);
+        graph
+            .add_pass("shadow")
+            .write(shadow, AccessType::DepthStencilAttachmentWrite);
+        graph
+            .add_pass("lighting")
+            .read(shadow, AccessType::FragmentShaderReadSampledImage)
+            .read(ao, AccessType::FragmentShaderReadSampledImage)
+            .side_effects();
+    }
+
+    /// Every release has a matching acquire of the same barrier
+    fn assert_transfer_halves(releases: &[OwnershipTransfer], acquires: &[OwnershipTransfer]) {
+        assert_eq!(releases.len(), acquires.len());
+        for (release, acquire) in releases.iter().zip(acquires.iter()) {
+            assert_eq!(release.half, abstraction::QueueTransferHalf::Release);
+            assert_eq!(acquire.half, abstraction::QueueTransferHalf::Acquire);
+            assert_eq!(release.barrier, acquire.barrier);
+            assert_eq!(
+                (release.src_family_index, release.dst_family_index),
+                (acquire.src_family_index, acquire.dst_family_index)
+            );
+        }
+    }
+
+    #[test]
+    /// Without an async compute queue everything is a single graphics submission
+    fn test_serial_fallback() {
+        let mut graph = RenderGraph::new();
+        build_graph(&mut graph);
+        let compiled = graph.compile().unwrap();
+        let schedule = Schedule::new(
+            graph.get_resources(),
+            graph.get_passes(),
+            &compiled,
+            &QueueSetup::serial(0),
+            None,
+        );
+        assert_eq!(schedule.submissions.len(), 1);
+        assert_eq!(schedule.submissions[0].passes, vec![0, 1, 2, 3]);
+        assert!(schedule.submissions[0].waits.is_empty());
+        assert!(schedule.submissions[0].acquires.is_empty());
+    }
+
+    #[test]
+    /// Async passes wait on their inputs and overlap with independent graphics work
+    fn test_async_compute() {
+        let mut graph = RenderGraph::new();
+        build_graph(&mut graph);
+        let compiled = graph.compile().unwrap();
+        let schedule = Schedule::new(graph.get_resources(), graph.get_passes(), &compiled, &async_setup(), None);
+
+        let queues: Vec<QueueType> = schedule.submissions.iter().map(|submission| submission.queue).collect();
+        assert_eq!(
+            queues,
+            vec![
+                QueueType::Graphics,
+                QueueType::AsyncCompute,
+                QueueType::Graphics,
+                QueueType::Graphics
+            ]
+        );
+        let ssao = &schedule.submissions[1];
+        assert_eq!(
+            ssao.waits,
+            vec![QueueWait {
+                queue: QueueType::Graphics,
+                value: 1
+            }]
+        );
+        // The depth buffer moves from the graphics to the compute family
+        assert_eq!(ssao.acquires.len(), 1);
+        assert_eq!((ssao.acquires[0].src_family_index, ssao.acquires[0].dst_family_index), (0, 1));
+        assert_transfer_halves(&schedule.submissions[0].releases, &ssao.acquires);
+        // The compute queue only waits with compute stages, the release only with graphics ones
+        let range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::DEPTH,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let release = schedule.submissions[0].releases[0]
+            .get_image_barrier(vk::Image::null(), range)
+            .get_vk_barrier();
+        let acquire = ssao.acquires[0]
+            .get_image_barrier(vk::Image::null(), range)
+            .get_vk_barrier();
+        assert_eq!(
+            release.src_stage_mask,
+            vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS
+        );
+        assert_eq!(release.dst_stage_mask, vk::PipelineStageFlags2::NONE);
+        assert_eq!(release.dst_access_mask, vk::AccessFlags2::NONE);
+        assert_eq!(acquire.src_stage_mask, vk::PipelineStageFlags2::NONE);
+        assert_eq!(acquire.src_access_mask, vk::AccessFlags2::NONE);
+        assert_eq!(acquire.dst_stage_mask, vk::PipelineStageFlags2::COMPUTE_SHADER);
+        assert_eq!(release.new_layout, acquire.new_layout);
+
+        // The shadow pass does not wait on SSAO so both can run at the same time
+        assert_eq!(schedule.submissions[2].passes, vec![2]);
+        assert!(schedule.submissions[2].waits.is_empty());
+        let lighting = &schedule.submissions[3];
+        assert_eq!(
+            lighting.waits,
+            vec![QueueWait {
+                queue: QueueType::AsyncCompute,
+                value: 1
+            }]
+        );
+        assert_transfer_halves(&schedule.submissions[1].releases, &lighting.acquires);
+        assert_eq!(schedule.get_signal_value(QueueType::Graphics), 3);
+        assert_eq!(schedule.get_queue(1), Some(QueueType::AsyncCompute));
+    }
+
+    #[test]
+    /// Imported resources owned by the compute family are released on the compute queue first
+    fn test_initial_owner() {
+        let usage = vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::VERTEX_BUFFER;
+        let buffer = abstraction::Buffer::from_vk(vk::Buffer::null(), 256, usage);
+        let mut graph = RenderGraph::new();
+        let particles =
+            graph.import_buffer("particles", &buffer, AccessType::ComputeShaderWrite, None);
+        graph.set_initial_owner(particles, 1);
+        graph
+            .add_pass("draw")
+            .read(particles, AccessType::VertexBuffer)
+            .side_effects();
+        let compiled = graph.compile().unwrap();
+        let schedule = Schedule::new(
+            graph.get_resources(),
+            graph.get_passes(),
+            &compiled,
+            &async_setup(),
+            None,
+        );
+
+        assert_eq!(schedule.submissions.len(), 2);
+        let release = &schedule.submissions[0];
+        assert_eq!(release.queue, QueueType::AsyncCompute);
+        assert!(release.passes.is_empty());
+        let draw = &schedule.submissions[1];
+        assert_eq!(
+            draw.waits,
+            vec![QueueWait {
+                queue: QueueType::AsyncCompute,
+                value: 1
+            }]
+        );
+        assert_eq!((draw.acquires[0].src_family_index, draw.acquires[0].dst_family_index), (1, 0));
+        assert_transfer_halves(&release.releases, &draw.acquires);
+
+        // Owners without a queue in the graph are left to the caller to release
+        let schedule = Schedule::new(
+            graph.get_resources(),
+            graph.get_passes(),
+            &compiled,
+            &QueueSetup::serial(0),
+            None,
+        );
+        assert_eq!(schedule.submissions.len(), 1);
+        assert!(schedule.submissions[0].waits.is_empty());
+        assert_eq!(schedule.submissions[0].acquires.len(), 1);
+    }
+
+    #[test]
+    /// Dedicated compute queues leave out graphics stages, shared families support everything
+    fn test_supported_stages() {
+        let stages = async_setup().get_supported_stages(QueueType::AsyncCompute);
+        assert!(stages.contains(vk::PipelineStageFlags2::COMPUTE_SHADER));
+        assert!(!stages.intersects(vk::PipelineStageFlags2::FRAGMENT_SHADER));
+        assert!(!stages.intersects(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT));
+        assert_eq!(
+            QueueSetup::serial(0).get_supported_stages(QueueType::AsyncCompute),
+            vk::PipelineStageFlags2::ALL_COMMANDS
+        );
+    }
+}
@@ -0,0 +1,350 @@
+//! Creates the transient resources of a compiled [super::RenderGraph]
+use super::aliasing::{AliasingPlan, AliasingRequest};
+use super::compiler::CompiledGraph;
+use super::resource::{BufferDesc, ImageDesc, ResourceDesc, ResourceId, ResourceInfo};
+use crate::abstraction::prelude as abstraction;
+use ash::vk;
+use ash::vk::TaggedStructure;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// An image which has been created but has no memory bound to it yet
+struct UnboundImage {
+    resource: ResourceId,
+    handle: vk::Image,
+    desc: ImageDesc,
+    usage: vk::ImageUsageFlags,
+}
+
+/// A device memory allocation which is not bound to anything
+struct PooledMemory {
+    memory: vk::DeviceMemory,
+    size: vk::DeviceSize,
+    memory_type_index: u32,
+}
+
+struct MemoryPoolInner {
+    device: abstraction::Device,
+    free: Mutex<Vec<PooledMemory>>,
+}
+
+impl Drop for MemoryPoolInner {
+    fn drop(&mut self) {
+        // Every [TransientResources] holds on to the pool, so no allocation is in use anymore
+        for pooled in self.free.get_mut().unwrap().drain(..) {
+            unsafe { self.device.handle_as_ref().free_memory(pooled.memory, None) };
+        }
+    }
+}
+
+/// Device memory of transient resources which is reused by later executions of a graph
+///
+/// Memory is handed back to the pool once the [TransientResources] using it are dropped, so
+/// a graph executed every frame only allocates memory while its requirements grow. Cloning the
+/// pool shares the same allocations.
+/// ```ignore
+/// let memory_pool = TransientMemoryPool::new(device.clone());
+/// // Every frame
+/// let mut graph = RenderGraph::new().memory_pool(memory_pool.clone());
+/// ```
+#[derive(Clone)]
+pub struct TransientMemoryPool {
+    inner: Arc<MemoryPoolInner>,
+}
+
+impl TransientMemoryPool {
+    pub fn new(device: abstraction::Device) -> Self {
+        Self {
+            inner: Arc::new(MemoryPoolInner {
+                device,
+                free: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Take the smallest unused allocation of `memory_type_index` holding at least `size` bytes,
+    /// allocating a new one if there is none
+    fn allocate(
+        &self,
+        size: vk::DeviceSize,
+        memory_type_index: u32,
+    ) -> Result<PooledMemory, vk::Result> {
+        let mut free = self.inner.free.lock().unwrap();
+        let best_fit = free
+            .iter()
+            .enumerate()
+            .filter(|(_, pooled)| {
+                pooled.memory_type_index == memory_type_index && pooled.size >= size
+            })
+            .min_by_key(|(_, pooled)| pooled.size)
+            .map(|(index, _)| index);
+        if let Some(index) = best_fit {
+            return Ok(free.swap_remove(index));
+        }
+        let allocate_info = vk::MemoryAllocateInfo {
+            s_type: vk::MemoryAllocateInfo::STRUCTURE_TYPE,
+            allocation_size: size,
+            memory_type_index,
+            ..Default::default()
+        };
+        let memory = unsafe {
+            self.inner
+                .device
+                .handle_as_ref()
+                .allocate_memory(&allocate_info, None)?
+        };
+        Ok(PooledMemory {
+            memory,
+            size,
+            memory_type_index,
+        })
+    }
+
+    fn release(&self, pooled: PooledMemory) {
+        self.inner.free.lock().unwrap().push(pooled);
+    }
+
+    /// Free every allocation which is not in use, such as after the resolution went down
+    pub fn trim(&self) {
+        let mut free = self.inner.free.lock().unwrap();
+        for pooled in free.drain(..) {
+            unsafe { self.inner.device.handle_as_ref().free_memory(pooled.memory, None) };
+        }
+    }
+
+    /// Get the number of bytes allocated which are not in use
+    pub fn get_free_size(&self) -> vk::DeviceSize {
+        self.inner.free.lock().unwrap().iter().map(|pooled| pooled.size).sum()
+    }
+
+    pub fn get_device(&self) -> abstraction::Device {
+        self.inner.device.clone()
+    }
+}
+
+/// Transient images and buffers of a single execution of a graph
+///
+/// Resources whose lifetimes do not overlap are bound to the same memory, see
+/// [super::aliasing]. Has to be kept alive until every command buffer the graph was recorded
+/// into finished executing, its memory then goes back to the [TransientMemoryPool].
+pub struct TransientResources {
+    device: abstraction::Device,
+    memory_pool: TransientMemoryPool,
+    unbound_images: Vec<UnboundImage>,
+    images: HashMap<ResourceId, abstraction::Image>,
+    /// Default views of [Self::images], which [abstraction::Image] does not destroy
+    image_views: Vec<vk::ImageView>,
+    buffers: HashMap<ResourceId, abstraction::Buffer>,
+    /// Memory of every block of [Self::aliasing_plan]
+    memory: Vec<PooledMemory>,
+    aliasing_plan: AliasingPlan,
+}
+
+impl TransientResources {
+    /// Create every transient resource used by `compiled` with memory from `memory_pool`
+    pub fn new(
+        memory_pool: TransientMemoryPool,
+        resources: &[ResourceInfo],
+        compiled: &CompiledGraph,
+    ) -> Result<Self, vk::Result> {
+        let mut transient = Self {
+            device: memory_pool.get_device(),
+            memory_pool,
+            unbound_images: Vec::new(),
+            images: HashMap::new(),
+            image_views: Vec::new(),
+            buffers: HashMap::new(),
+            memory: Vec::new(),
+            aliasing_plan: AliasingPlan::default(),
+        };
+        // Resources created so far are cleaned up by dropping `transient` on failure
+        let mut requests: Vec<AliasingRequest> = Vec::new();
+        for usage in compiled.resources.iter() {
+            let resource = &resources[usage.resource.0];
+            if !resource.is_transient() {
+                continue;
+            }
+            let memory_requirements = match resource.desc {
+                ResourceDesc::Image(desc) => transient.create_image(usage.resource, desc, usage.image_usage)?,
+                ResourceDesc::Buffer(desc) => transient.create_buffer(usage.resource, &desc, usage.buffer_usage)?,
+            };
+            let memory_type_index = transient
+                .device
+                .find_memory_type_index(
+                    memory_requirements.memory_type_bits,
+                    vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                )
+                .ok_or(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY)?;
+            requests.push(AliasingRequest {
+                resource: usage.resource,
+                size: memory_requirements.size,
+                alignment: memory_requirements.alignment,
+                memory_type_index,
+                is_image: resource.is_image(),
+                first_pass: usage.first_pass,
+                last_pass: usage.last_pass,
+            });
+        }
+
+        transient.aliasing_plan = AliasingPlan::new(&requests);
+        for block in transient.aliasing_plan.blocks.clone() {
+            let memory = transient
+                .memory_pool
+                .allocate(block.size, block.memory_type_index)?;
+            transient.memory.push(memory);
+        }
+        for (resource, buffer) in transient.buffers.iter() {
+            let placement = transient.aliasing_plan.get_placement(*resource).unwrap();
+            unsafe {
+                transient.device.handle_as_ref().bind_buffer_memory(
+                    buffer.get_handle(),
+                    transient.memory[placement.block].memory,
+                    placement.offset,
+                )?
+            };
+        }
+        while let Some(image) = transient.unbound_images.pop() {
+            transient.bind_image(image)?;
+        }
+        Ok(transient)
+    }
+
+    pub fn get_image(&self, resource: ResourceId) -> Option<&abstraction::Image> {
+        self.images.get(&resource)
+    }
+
+    pub fn get_buffer(&self, resource: ResourceId) -> Option<&abstraction::Buffer> {
+        self.buffers.get(&resource)
+    }
+
+    pub fn get_aliasing_plan(&self) -> &AliasingPlan {
+        &self.aliasing_plan
+    }
+
+    pub fn get_device(&self) -> abstraction::Device {
+        self.device.clone()
+    }
+
+    fn get_image_ci(desc: &ImageDesc, usage: vk::ImageUsageFlags) -> vk::ImageCreateInfo {
+        vk::ImageCreateInfo {
+            s_type: vk::ImageCreateInfo::STRUCTURE_TYPE,
+            image_type: desc.image_type,
+            format: desc.format,
+            extent: desc.extent,
+            mip_levels: desc.mip_levels,
+            array_layers: desc.array_layers,
+            samples: desc.samples,
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            ..Default::default()
+        }
+    }
+
+    fn create_image(
+        &mut self,
+        resource: ResourceId,
+        desc: ImageDesc,
+        usage: vk::ImageUsageFlags,
+    ) -> Result<vk::MemoryRequirements, vk::Result> {
+        let image_ci = Self::get_image_ci(&desc, usage);
+        let handle = unsafe { self.device.handle_as_ref().create_image(&image_ci, None)? };
+        self.unbound_images.push(UnboundImage {
+            resource,
+            handle,
+            desc,
+            usage,
+        });
+        Ok(unsafe { self.device.handle_as_ref().get_image_memory_requirements(handle) })
+    }
+
+    /// Bind `image` to its placement and create its default view
+    fn bind_image(&mut self, image: UnboundImage) -> Result<(), vk::Result> {
+        let device = self.device.clone();
+        let placement = *self.aliasing_plan.get_placement(image.resource).unwrap();
+        let view_type = match (image.desc.image_type, image.desc.array_layers) {
+            (vk::ImageType::TYPE_3D, _) => vk::ImageViewType::TYPE_3D,
+            (vk::ImageType::TYPE_1D, 1) => vk::ImageViewType::TYPE_1D,
+            (vk::ImageType::TYPE_1D, _) => vk::ImageViewType::TYPE_1D_ARRAY,
+            (_, 1) => vk::ImageViewType::TYPE_2D,
+            (_, _) => vk::ImageViewType::TYPE_2D_ARRAY,
+        };
+        let image_view_ci = vk::ImageViewCreateInfo {
+            s_type: vk::ImageViewCreateInfo::STRUCTURE_TYPE,
+            image: image.handle,
+            view_type,
+            format: image.desc.format,
+            subresource_range: image.desc.get_full_range(),
+            ..Default::default()
+        };
+        let image_view = unsafe {
+            device
+                .handle_as_ref()
+                .bind_image_memory(
+                    image.handle,
+                    self.memory[placement.block].memory,
+                    placement.offset,
+                )
+                .and_then(|_| device.handle_as_ref().create_image_view(&image_view_ci, None))
+        };
+        let image_view = match image_view {
+            Ok(image_view) => image_view,
+            Err(error) => {
+                unsafe { device.handle_as_ref().destroy_image(image.handle, None) };
+                return Err(error);
+            }
+        };
+        self.image_views.push(image_view);
+        self.images.insert(
+            image.resource,
+            abstraction::Image::from_vk(
+                image.handle,
+                device,
+                vk::Sampler::null(),
+                image_view,
+                &Self::get_image_ci(&image.desc, image.usage),
+            ),
+        );
+        Ok(())
+    }
+
+    fn create_buffer(
+        &mut self,
+        resource: ResourceId,
+        desc: &BufferDesc,
+        usage: vk::BufferUsageFlags,
+    ) -> Result<vk::MemoryRequirements, vk::Result> {
+        let buffer_ci = vk::BufferCreateInfo {
+            s_type: vk::BufferCreateInfo::STRUCTURE_TYPE,
+            size: desc.size,
+            usage,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            ..Default::default()
+        };
+        let handle = unsafe { self.device.handle_as_ref().create_buffer(&buffer_ci, None)? };
+        self.buffers.insert(resource, abstraction::Buffer::from_vk(handle, desc.size, usage));
+        Ok(unsafe { self.device.handle_as_ref().get_buffer_memory_requirements(handle) })
+    }
+}
+
+impl Drop for TransientResources {
+    fn drop(&mut self) {
+        // Resources have to be destroyed before the memory bound to them is reused
+        unsafe {
+            for image_view in self.image_views.drain(..) {
+                self.device.handle_as_ref().destroy_image_view(image_view, None);
+            }
+            self.images.clear();
+            for image in self.unbound_images.drain(..) {
+                self.device.handle_as_ref().destroy_image(image.handle, None);
+            }
+            for (_, buffer) in self.buffers.drain() {
+                self.device.handle_as_ref().destroy_buffer(buffer.get_handle(), None);
+            }
+        }
+        for memory in self.memory.drain(..) {
+            self.memory_pool.release(memory);
+        }
+    }
+}
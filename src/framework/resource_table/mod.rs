@@ -0,0 +1,21 @@
+//! A bindless table of every resource shaders access
+//!
+//! [ResourceTable] holds one update-after-bind descriptor set with an array for every kind of
+//! descriptor. Registering a resource writes it into a free slot and returns a [Handle]
+//! whose index stays the same until the resource is unregistered, so shaders can access the
+//! resource through the index alone:
+//! ```glsl
+//! layout(set = 0, binding = 0) uniform texture2D sampled_images[];
+//! layout(set = 0, binding = 1, rgba8) uniform image2D storage_images[];
+//! layout(set = 0, binding = 2) buffer StorageBuffer { uint data[]; } storage_buffers[];
+//! layout(set = 0, binding = 3) uniform sampler samplers[];
+//! ```
+//! Unregistered slots may still be accessed by command buffers in flight, so they are only
+//! reused once the timeline value passed to [ResourceTable::unregister] has been reached.
+//!
+//! [Handle]: crate::abstraction::utility::storage::Handle
+pub mod slots;
+pub mod table;
+
+pub use slots::*;
+pub use table::*;
@@ -0,0 +1,118 @@
+//! Hands out slots of a fixed size array, only reusing freed slots once the GPU is done with them
+use crate::abstraction::prelude as abstraction;
+use abstraction::utility::storage::{Handle, Storage};
+
+/// A slot which was freed but may still be accessed by pending command buffers
+struct RetiringSlot {
+    index: usize,
+    /// Timeline value which is reached once no command buffer accesses the slot anymore
+    retire_value: u64,
+}
+
+/// Slots of a single array of a [super::ResourceTable]
+///
+/// Handles are backed by [Storage], so handles to freed slots are rejected even once the slot
+/// holds another resource.
+pub struct ResourceSlots<T> {
+    storage: Storage<T>,
+    /// Slots which can be reused immediately
+    free: Vec<usize>,
+    retiring: Vec<RetiringSlot>,
+    capacity: usize,
+}
+
+impl<T> ResourceSlots<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            storage: Storage::new(),
+            free: Vec::new(),
+            retiring: Vec::new(),
+            capacity,
+        }
+    }
+
+    /// Store `resource` in a free slot, returns [None] if every slot is in use
+    pub fn allocate(&mut self, resource: T) -> Option<Handle<T>> {
+        if let Some(index) = self.free.pop() {
+            Some(self.storage.insert(index, resource))
+        } else if self.storage.len() < self.capacity {
+            Some(self.storage.push_back(resource))
+        } else {
+            None
+        }
+    }
+
+    /// Free the slot of `handle` once the timeline reaches `retire_value`
+    ///
+    /// Returns false if `handle` is outdated.
+    pub fn free(&mut self, handle: Handle<T>, retire_value: u64) -> bool {
+        if !self.storage.invalidate(handle) {
+            return false;
+        }
+        self.retiring.push(RetiringSlot {
+            index: handle.get_identifier() as usize,
+            retire_value,
+        });
+        true
+    }
+
+    /// Make every slot whose retire value is at most `completed_value` reusable
+    pub fn reclaim(&mut self, completed_value: u64) {
+        let free = &mut self.free;
+        self.retiring.retain(|slot| {
+            if slot.retire_value <= completed_value {
+                free.push(slot.index);
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    pub fn get(&self, handle: Handle<T>) -> Option<&T> {
+        self.storage.get(handle)
+    }
+
+    /// Get the array index of `handle`, which is stable until the slot is freed
+    pub fn get_index(&self, handle: Handle<T>) -> Option<u32> {
+        if self.storage.is_valid(handle) {
+            Some(handle.get_identifier() as u32)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Freed slots are only handed out again once their retire value has been reached
+    fn test_deferred_reuse() {
+        let mut slots = ResourceSlots::new(2);
+        let first = slots.allocate(1).unwrap();
+        let second = slots.allocate(2).unwrap();
+        assert_eq!(slots.get_index(first), Some(0));
+        assert_eq!(slots.get_index(second), Some(1));
+        assert!(slots.allocate(3).is_none());
+
+        assert!(slots.free(first, 5));
+        assert!(!slots.free(first, 5));
+        assert_eq!(slots.get(first), None);
+        slots.reclaim(4);
+        assert!(slots.allocate(3).is_none());
+
+        slots.reclaim(5);
+        let third = slots.allocate(3).unwrap();
+        assert_eq!(slots.get_index(third), Some(0));
+        assert_eq!(slots.get(third), Some(&3));
+        // The old handle to the slot stays outdated
+        assert_eq!(slots.get_index(first), None);
+        assert_eq!(slots.get(second), Some(&2));
+    }
+}
@@ -0,0 +1,382 @@
+use super::slots::ResourceSlots;
+use crate::abstraction::prelude as abstraction;
+use abstraction::utility::storage::Handle;
+use abstraction::Resource;
+use ash::vk;
+use ash::vk::TaggedStructure;
+
+/// Number of descriptors in every array of a [ResourceTable]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ResourceTableCapacity {
+    pub sampled_images: u32,
+    pub storage_images: u32,
+    pub storage_buffers: u32,
+    pub samplers: u32,
+}
+
+impl Default for ResourceTableCapacity {
+    fn default() -> Self {
+        Self {
+            sampled_images: 16384,
+            storage_images: 16384,
+            storage_buffers: 16384,
+            samplers: 1024,
+        }
+    }
+}
+
+/// An image in [ResourceTable::SAMPLED_IMAGE_BINDING], expected to be in
+/// [vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL] when accessed
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SampledImageEntry {
+    pub image_view: vk::ImageView,
+}
+
+/// An image in [ResourceTable::STORAGE_IMAGE_BINDING], expected to be in
+/// [vk::ImageLayout::GENERAL] when accessed
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct StorageImageEntry {
+    pub image_view: vk::ImageView,
+}
+
+/// A buffer in [ResourceTable::STORAGE_BUFFER_BINDING]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct StorageBufferEntry {
+    pub buffer: vk::Buffer,
+}
+
+/// A sampler in [ResourceTable::SAMPLER_BINDING]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SamplerEntry {
+    pub sampler: vk::Sampler,
+}
+
+/// Entries of one of the arrays of a [ResourceTable]
+pub trait TableEntry: Sized {
+    const BINDING: u32;
+    /// Type of the descriptors in [Self::BINDING]
+    const DESCRIPTOR_TYPE: vk::DescriptorType;
+
+    fn get_slots(table: &ResourceTable) -> &ResourceSlots<Self>;
+
+    fn get_slots_mut(table: &mut ResourceTable) -> &mut ResourceSlots<Self>;
+}
+
+impl TableEntry for SampledImageEntry {
+    const BINDING: u32 = ResourceTable::SAMPLED_IMAGE_BINDING;
+    const DESCRIPTOR_TYPE: vk::DescriptorType = vk::DescriptorType::SAMPLED_IMAGE;
+
+    fn get_slots(table: &ResourceTable) -> &ResourceSlots<Self> {
+        &table.sampled_images
+    }
+
+    fn get_slots_mut(table: &mut ResourceTable) -> &mut ResourceSlots<Self> {
+        &mut table.sampled_images
+    }
+}
+
+impl TableEntry for StorageImageEntry {
+    const BINDING: u32 = ResourceTable::STORAGE_IMAGE_BINDING;
+    const DESCRIPTOR_TYPE: vk::DescriptorType = vk::DescriptorType::STORAGE_IMAGE;
+
+    fn get_slots(table: &ResourceTable) -> &ResourceSlots<Self> {
+        &table.storage_images
+    }
+
+    fn get_slots_mut(table: &mut ResourceTable) -> &mut ResourceSlots<Self> {
+        &mut table.storage_images
+    }
+}
+
+impl TableEntry for StorageBufferEntry {
+    const BINDING: u32 = ResourceTable::STORAGE_BUFFER_BINDING;
+    const DESCRIPTOR_TYPE: vk::DescriptorType = vk::DescriptorType::STORAGE_BUFFER;
+
+    fn get_slots(table: &ResourceTable) -> &ResourceSlots<Self> {
+        &table.storage_buffers
+    }
+
+    fn get_slots_mut(table: &mut ResourceTable) -> &mut ResourceSlots<Self> {
+        &mut table.storage_buffers
+    }
+}
+
+impl TableEntry for SamplerEntry {
+    const BINDING: u32 = ResourceTable::SAMPLER_BINDING;
+    const DESCRIPTOR_TYPE: vk::DescriptorType = vk::DescriptorType::SAMPLER;
+
+    fn get_slots(table: &ResourceTable) -> &ResourceSlots<Self> {
+        &table.samplers
+    }
+
+    fn get_slots_mut(table: &mut ResourceTable) -> &mut ResourceSlots<Self> {
+        &mut table.samplers
+    }
+}
+
+/// A single update-after-bind descriptor set holding every resource shaders access by index
+///
+/// The table does not own the resources registered into it, they have to outlive their
+/// registration and every command buffer using their index. Dropping the table waits for the
+/// device to be idle, as its descriptor set may still be bound by commands in flight.
+pub struct ResourceTable {
+    device: abstraction::Device,
+    layout: vk::DescriptorSetLayout,
+    pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+    sampled_images: ResourceSlots<SampledImageEntry>,
+    storage_images: ResourceSlots<StorageImageEntry>,
+    storage_buffers: ResourceSlots<StorageBufferEntry>,
+    samplers: ResourceSlots<SamplerEntry>,
+}
+
+impl ResourceTable {
+    pub const SAMPLED_IMAGE_BINDING: u32 = 0;
+    pub const STORAGE_IMAGE_BINDING: u32 = 1;
+    pub const STORAGE_BUFFER_BINDING: u32 = 2;
+    pub const SAMPLER_BINDING: u32 = 3;
+
+    pub fn new(device: abstraction::Device, capacity: ResourceTableCapacity) -> Result<Self, vk::Result> {
+        let arrays = [
+            (SampledImageEntry::BINDING, SampledImageEntry::DESCRIPTOR_TYPE, capacity.sampled_images),
+            (StorageImageEntry::BINDING, StorageImageEntry::DESCRIPTOR_TYPE, capacity.storage_images),
+            (StorageBufferEntry::BINDING, StorageBufferEntry::DESCRIPTOR_TYPE, capacity.storage_buffers),
+            (SamplerEntry::BINDING, SamplerEntry::DESCRIPTOR_TYPE, capacity.samplers),
+        ];
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = arrays
+            .iter()
+            .map(|(binding, descriptor_type, descriptor_count)| vk::DescriptorSetLayoutBinding {
+                binding: *binding,
+                descriptor_type: *descriptor_type,
+                descriptor_count: *descriptor_count,
+                stage_flags: vk::ShaderStageFlags::ALL,
+                ..Default::default()
+            })
+            .collect();
+        // Slots are written while the set is bound and most of them are never written at all
+        let binding_flags = vec![
+            vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+                | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING
+                | vk::DescriptorBindingFlags::PARTIALLY_BOUND;
+            bindings.len()
+        ];
+        let binding_flags_ci = vk::DescriptorSetLayoutBindingFlagsCreateInfo {
+            s_type: vk::DescriptorSetLayoutBindingFlagsCreateInfo::STRUCTURE_TYPE,
+            binding_count: binding_flags.len() as u32,
+            p_binding_flags: binding_flags.as_ptr(),
+            ..Default::default()
+        };
+        let layout_ci = vk::DescriptorSetLayoutCreateInfo {
+            s_type: vk::DescriptorSetLayoutCreateInfo::STRUCTURE_TYPE,
+            p_next: abstraction::utility::p_next(&binding_flags_ci),
+            flags: vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL,
+            binding_count: bindings.len() as u32,
+            p_bindings: bindings.as_ptr(),
+        };
+        let layout = unsafe {
+            device
+                .handle_as_ref()
+                .create_descriptor_set_layout(&layout_ci, None)?
+        };
+
+        let pool_sizes: Vec<vk::DescriptorPoolSize> = arrays
+            .iter()
+            .map(|(_, ty, descriptor_count)| vk::DescriptorPoolSize {
+                ty: *ty,
+                descriptor_count: *descriptor_count,
+            })
+            .collect();
+        let pool_ci = vk::DescriptorPoolCreateInfo {
+            s_type: vk::DescriptorPoolCreateInfo::STRUCTURE_TYPE,
+            flags: vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND,
+            max_sets: 1,
+            pool_size_count: pool_sizes.len() as u32,
+            p_pool_sizes: pool_sizes.as_ptr(),
+            ..Default::default()
+        };
+        let pool = match unsafe { device.handle_as_ref().create_descriptor_pool(&pool_ci, None) } {
+            Ok(pool) => pool,
+            Err(error) => {
+                unsafe { device.handle_as_ref().destroy_descriptor_set_layout(layout, None) };
+                return Err(error);
+            }
+        };
+
+        let set_ai = vk::DescriptorSetAllocateInfo {
+            s_type: vk::DescriptorSetAllocateInfo::STRUCTURE_TYPE,
+            descriptor_pool: pool,
+            descriptor_set_count: 1,
+            p_set_layouts: &layout,
+            ..Default::default()
+        };
+        let descriptor_set = match unsafe { device.handle_as_ref().allocate_descriptor_sets(&set_ai) } {
+            Ok(descriptor_sets) => descriptor_sets[0],
+            Err(error) => {
+                unsafe {
+                    device.handle_as_ref().destroy_descriptor_pool(pool, None);
+                    device.handle_as_ref().destroy_descriptor_set_layout(layout, None);
+                }
+                return Err(error);
+            }
+        };
+
+        Ok(Self {
+            device,
+            layout,
+            pool,
+            descriptor_set,
+            sampled_images: ResourceSlots::new(capacity.sampled_images as usize),
+            storage_images: ResourceSlots::new(capacity.storage_images as usize),
+            storage_buffers: ResourceSlots::new(capacity.storage_buffers as usize),
+            samplers: ResourceSlots::new(capacity.samplers as usize),
+        })
+    }
+
+    /// Register the default view of `image` to be sampled
+    pub fn register_sampled_image(
+        &mut self,
+        image: &abstraction::Image,
+    ) -> Result<Handle<SampledImageEntry>, vk::Result> {
+        let image_info = vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: image.get_image_view(),
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        };
+        self.register(
+            SampledImageEntry {
+                image_view: image_info.image_view,
+            },
+            abstraction::DescriptorInfo::Image(image_info),
+        )
+    }
+
+    /// Register the default view of `image` as a storage image
+    pub fn register_storage_image(
+        &mut self,
+        image: &abstraction::Image,
+    ) -> Result<Handle<StorageImageEntry>, vk::Result> {
+        let image_info = vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: image.get_image_view(),
+            image_layout: vk::ImageLayout::GENERAL,
+        };
+        self.register(
+            StorageImageEntry {
+                image_view: image_info.image_view,
+            },
+            abstraction::DescriptorInfo::Image(image_info),
+        )
+    }
+
+    /// Register the whole of `buffer` as a storage buffer
+    pub fn register_storage_buffer(
+        &mut self,
+        buffer: &abstraction::Buffer,
+    ) -> Result<Handle<StorageBufferEntry>, vk::Result> {
+        self.register(
+            StorageBufferEntry {
+                buffer: buffer.get_handle(),
+            },
+            buffer.get_descriptor(),
+        )
+    }
+
+    pub fn register_sampler(&mut self, sampler: vk::Sampler) -> Result<Handle<SamplerEntry>, vk::Result> {
+        self.register(
+            SamplerEntry { sampler },
+            abstraction::DescriptorInfo::Image(vk::DescriptorImageInfo {
+                sampler,
+                ..Default::default()
+            }),
+        )
+    }
+
+    /// Allocate a slot for `entry` and write `descriptor` into it
+    ///
+    /// Returns [vk::Result::ERROR_TOO_MANY_OBJECTS] if every slot of the array is in use.
+    fn register<T: TableEntry>(
+        &mut self,
+        entry: T,
+        descriptor: abstraction::DescriptorInfo,
+    ) -> Result<Handle<T>, vk::Result> {
+        let handle = T::get_slots_mut(self)
+            .allocate(entry)
+            .ok_or(vk::Result::ERROR_TOO_MANY_OBJECTS)?;
+        let mut write = vk::WriteDescriptorSet {
+            s_type: vk::WriteDescriptorSet::STRUCTURE_TYPE,
+            dst_set: self.descriptor_set,
+            dst_binding: T::BINDING,
+            dst_array_element: handle.get_identifier() as u32,
+            descriptor_count: 1,
+            descriptor_type: T::DESCRIPTOR_TYPE,
+            ..Default::default()
+        };
+        match &descriptor {
+            abstraction::DescriptorInfo::Image(image_info) => write.p_image_info = image_info,
+            abstraction::DescriptorInfo::Buffer(buffer_info) => write.p_buffer_info = buffer_info,
+        }
+        unsafe { self.device.handle_as_ref().update_descriptor_sets(&[write], &[]) };
+        Ok(handle)
+    }
+
+    /// Free the slot of `handle` once the timeline reaches `retire_value`
+    ///
+    /// `retire_value` should be the value signalled by the last submission which may access
+    /// the slot. Returns false if `handle` was already unregistered.
+    pub fn unregister<T: TableEntry>(&mut self, handle: Handle<T>, retire_value: u64) -> bool {
+        T::get_slots_mut(self).free(handle, retire_value)
+    }
+
+    /// Make every slot whose retire value is at most `completed_value` reusable
+    pub fn reclaim(&mut self, completed_value: u64) {
+        self.sampled_images.reclaim(completed_value);
+        self.storage_images.reclaim(completed_value);
+        self.storage_buffers.reclaim(completed_value);
+        self.samplers.reclaim(completed_value);
+    }
+
+    /// [Self::reclaim] every slot retired by the current value of `timeline`
+    pub fn reclaim_timeline(&mut self, timeline: &abstraction::Semaphore) -> Result<(), vk::Result> {
+        self.reclaim(timeline.get_value()?);
+        Ok(())
+    }
+
+    /// Get the index shaders use to access the resource of `handle`
+    ///
+    /// Returns [None] if `handle` has been unregistered.
+    pub fn get_index<T: TableEntry>(&self, handle: Handle<T>) -> Option<u32> {
+        T::get_slots(self).get_index(handle)
+    }
+
+    pub fn get_entry<T: TableEntry>(&self, handle: Handle<T>) -> Option<&T> {
+        T::get_slots(self).get(handle)
+    }
+
+    pub fn get_layout(&self) -> vk::DescriptorSetLayout {
+        self.layout
+    }
+
+    pub fn get_descriptor_set(&self) -> vk::DescriptorSet {
+        self.descriptor_set
+    }
+
+    pub fn get_device(&self) -> abstraction::Device {
+        self.device.clone()
+    }
+}
+
+impl Drop for ResourceTable {
+    fn drop(&mut self) {
+        unsafe {
+            // Submitted commands may still access the descriptor set
+            let _ = self.device.handle_as_ref().device_wait_idle();
+            // Frees the descriptor set as well
+            self.device
+                .handle_as_ref()
+                .destroy_descriptor_pool(self.pool, None);
+            self.device
+                .handle_as_ref()
+                .destroy_descriptor_set_layout(self.layout, None);
+        }
+    }
+}
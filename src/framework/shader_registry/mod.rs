@@ -0,0 +1,14 @@
+//! Hot reloading of shaders and the pipelines built from them
+//!
+//! [ShaderRegistry] owns shader modules loaded from files along with every pipeline built from
+//! them. Polling the registry checks the files for changes, recompiles changed shaders through
+//! a [ShaderCompiler] and rebuilds the pipelines using them. Replaced pipelines are handed to
+//! their [crate::abstraction::pipeline::PipelineDeletionQueue], so command buffers in flight
+//! can keep using them. Rebuilt pipelines keep their set layouts and push constant ranges, so a
+//! shader which no longer fits them fails to reload just like one which fails to compile: it
+//! keeps its previous module and pipelines, and the failure is reported as a [ShaderDiagnostic].
+pub mod registry;
+pub mod watcher;
+
+pub use registry::*;
+pub use watcher::*;
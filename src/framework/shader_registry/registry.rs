@@ -0,0 +1,556 @@
+use super::watcher::FileWatcher;
+use crate::abstraction::prelude as abstraction;
+use crate::abstraction::utility::shader::{self, ShaderModule};
+use ash::vk;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Compiles shader files into SPIR-V
+///
+/// Implemented for closures, so any external compiler can be hooked up.
+pub trait ShaderCompiler {
+    /// Compile the shader at `path`, returning the diagnostics of the compiler on failure
+    fn compile(&self, path: &Path) -> Result<Vec<u32>, String>;
+}
+
+impl<F: Fn(&Path) -> Result<Vec<u32>, String>> ShaderCompiler for F {
+    fn compile(&self, path: &Path) -> Result<Vec<u32>, String> {
+        self(path)
+    }
+}
+
+/// Loads precompiled SPIR-V files as they are
+pub struct SpirvLoader;
+
+impl ShaderCompiler for SpirvLoader {
+    fn compile(&self, path: &Path) -> Result<Vec<u32>, String> {
+        let bytes = std::fs::read(path).map_err(|error| error.to_string())?;
+        shader::spirv_from_bytes(&bytes).map_err(|error| error.to_string())
+    }
+}
+
+/// Index of a shader in its [ShaderRegistry]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct ShaderId(pub(crate) usize);
+
+impl ShaderId {
+    pub fn get_index(&self) -> usize {
+        self.0
+    }
+}
+
+/// Index of a pipeline in its [ShaderRegistry]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct PipelineId(pub(crate) usize);
+
+impl PipelineId {
+    pub fn get_index(&self) -> usize {
+        self.0
+    }
+}
+
+/// Why a shader could not be loaded or reloaded
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ShaderDiagnostic {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for ShaderDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl std::error::Error for ShaderDiagnostic {}
+
+/// Check that the bindings and push constants a shader uses fit the layout of its pipeline
+fn check_layout(
+    bindings: &[abstraction::ReflectedBinding],
+    push_constant_range: Option<vk::PushConstantRange>,
+    set_layouts: &[&[abstraction::DescriptorSetLayoutBinding]],
+    push_constant_ranges: &[vk::PushConstantRange],
+) -> Result<(), String> {
+    for binding in bindings {
+        let declared = set_layouts
+            .get(binding.set as usize)
+            .and_then(|set_layout| {
+                set_layout
+                    .iter()
+                    .find(|declared| declared.binding == binding.binding)
+            })
+            .ok_or_else(|| {
+                format!(
+                    "set {} binding {} ({}) is not part of the pipeline layout",
+                    binding.set, binding.binding, binding.name
+                )
+            })?;
+        // Runtime arrays have a count of 0 and fit any count
+        if declared.descriptor_type != binding.descriptor_type
+            || declared.descriptor_count < binding.descriptor_count
+            || !declared.stage_flags.contains(binding.stage_flags)
+        {
+            return Err(format!(
+                "set {} binding {} ({}) is used as {} x {:?} in {:?} but the pipeline layout has \
+                 {} x {:?} in {:?}",
+                binding.set,
+                binding.binding,
+                binding.name,
+                binding.descriptor_count,
+                binding.descriptor_type,
+                binding.stage_flags,
+                declared.descriptor_count,
+                declared.descriptor_type,
+                declared.stage_flags
+            ));
+        }
+    }
+    if let Some(used) = push_constant_range {
+        let covered = push_constant_ranges.iter().any(|range| {
+            range.stage_flags.contains(used.stage_flags)
+                && range.offset <= used.offset
+                && used.offset + used.size <= range.offset + range.size
+        });
+        if !covered {
+            return Err(format!(
+                "push constants {}..{} in {:?} are not part of the pipeline layout",
+                used.offset,
+                used.offset + used.size,
+                used.stage_flags
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Outcome of reloading a single shader
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ReloadEvent {
+    /// The shader was reloaded and every pipeline using it was rebuilt
+    Reloaded {
+        shader: ShaderId,
+        pipelines: Vec<PipelineId>,
+    },
+    /// The shader or one of its pipelines failed to build, the previous ones are kept
+    Failed {
+        shader: ShaderId,
+        diagnostic: ShaderDiagnostic,
+    },
+}
+
+enum RegisteredPipelineKind {
+    Graphics(abstraction::GraphicsPipeline),
+    Compute(abstraction::ComputePipeline),
+}
+
+struct RegisteredPipeline {
+    /// Every shader the pipeline is built from along with its entry point
+    shaders: Vec<(ShaderId, String)>,
+    pipeline: RegisteredPipelineKind,
+}
+
+struct RegisteredShader {
+    path: PathBuf,
+    /// Files the shader includes, changes to them reload the shader as well
+    dependencies: Vec<PathBuf>,
+    module: ShaderModule,
+}
+
+/// Owns shaders loaded from files and rebuilds the pipelines using them once the files change
+pub struct ShaderRegistry {
+    device: abstraction::Device,
+    compiler: Box<dyn ShaderCompiler>,
+    watcher: FileWatcher,
+    shaders: Vec<RegisteredShader>,
+    pipelines: Vec<Option<RegisteredPipeline>>,
+    /// Diagnostics of every shader whose last reload failed
+    diagnostics: HashMap<ShaderId, ShaderDiagnostic>,
+}
+
+impl ShaderRegistry {
+    pub fn new<C: ShaderCompiler + 'static>(device: abstraction::Device, compiler: C) -> Self {
+        Self {
+            device,
+            compiler: Box::new(compiler),
+            watcher: FileWatcher::new(),
+            shaders: Vec::new(),
+            pipelines: Vec::new(),
+            diagnostics: HashMap::new(),
+        }
+    }
+
+    /// Compile `path` into a new module
+    fn compile(&self, path: &Path) -> Result<ShaderModule, ShaderDiagnostic> {
+        self.compiler
+            .compile(path)
+            .and_then(|code| {
+                ShaderModule::from_spirv(self.device.clone(), &code)
+                    .map_err(|error| error.to_string())
+            })
+            .map_err(|message| ShaderDiagnostic {
+                path: path.to_path_buf(),
+                message,
+            })
+    }
+
+    /// Load and start watching the shader at `path`
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<ShaderId, ShaderDiagnostic> {
+        let path = path.as_ref().to_path_buf();
+        let module = self.compile(&path)?;
+        self.watcher.watch(&path);
+        self.shaders.push(RegisteredShader {
+            path,
+            dependencies: Vec::new(),
+            module,
+        });
+        Ok(ShaderId(self.shaders.len() - 1))
+    }
+
+    /// Reload `shader` whenever `path` changes as well, such as files it includes
+    pub fn watch_dependency<P: AsRef<Path>>(&mut self, shader: ShaderId, path: P) {
+        let path = path.as_ref().to_path_buf();
+        self.watcher.watch(&path);
+        self.shaders[shader.0].dependencies.push(path);
+    }
+
+    pub fn get_module(&self, shader: ShaderId) -> &ShaderModule {
+        &self.shaders[shader.0].module
+    }
+
+    /// Build a graphics pipeline from `builder` using `shaders`, each along with its entry point
+    ///
+    /// The pipeline is rebuilt whenever one of the shaders is reloaded. Failing to build it is
+    /// reported against the first shader, missing entry points against their own shader.
+    pub fn add_graphics_pipeline(
+        &mut self,
+        builder: abstraction::GraphicsPipelineBuilder,
+        shaders: &[(ShaderId, &str)],
+    ) -> Result<PipelineId, ShaderDiagnostic> {
+        let diagnostic = |shader: ShaderId, message: String| ShaderDiagnostic {
+            path: self.shaders[shader.0].path.clone(),
+            message,
+        };
+        let mut builder = builder;
+        for (shader, entry_point) in shaders.iter() {
+            builder = builder
+                .shader_module(self.get_module(*shader), entry_point)
+                .map_err(|error| diagnostic(*shader, error.to_string()))?;
+        }
+        let pipeline = builder.build().map_err(|result| {
+            let first = shaders.first().map(|(shader, _)| *shader);
+            ShaderDiagnostic {
+                path: first
+                    .map(|shader| self.shaders[shader.0].path.clone())
+                    .unwrap_or_default(),
+                message: format!("failed to build pipeline: {}", result),
+            }
+        })?;
+        let shaders: Vec<(ShaderId, String)> = shaders
+            .iter()
+            .map(|(shader, entry_point)| (*shader, entry_point.to_string()))
+            .collect();
+        Ok(self.insert_pipeline(shaders, RegisteredPipelineKind::Graphics(pipeline)))
+    }
+
+    /// Build a compute pipeline from `builder` using `entry_point` of `shader`
+    ///
+    /// The pipeline is rebuilt whenever the shader is reloaded.
+    pub fn add_compute_pipeline(
+        &mut self,
+        builder: abstraction::ComputePipelineBuilder,
+        shader: ShaderId,
+        entry_point: &str,
+    ) -> Result<PipelineId, ShaderDiagnostic> {
+        let diagnostic = |message: String| ShaderDiagnostic {
+            path: self.shaders[shader.0].path.clone(),
+            message,
+        };
+        let pipeline = builder
+            .shader_module(self.get_module(shader), entry_point)
+            .map_err(|error| diagnostic(error.to_string()))?
+            .build()
+            .map_err(|result| diagnostic(result.to_string()))?;
+        Ok(self.insert_pipeline(
+            vec![(shader, entry_point.to_string())],
+            RegisteredPipelineKind::Compute(pipeline),
+        ))
+    }
+
+    fn insert_pipeline(
+        &mut self,
+        shaders: Vec<(ShaderId, String)>,
+        pipeline: RegisteredPipelineKind,
+    ) -> PipelineId {
+        self.pipelines
+            .push(Some(RegisteredPipeline { shaders, pipeline }));
+        PipelineId(self.pipelines.len() - 1)
+    }
+
+    /// Get the current version of a graphics pipeline, which changes whenever it is rebuilt
+    pub fn get_graphics_pipeline(
+        &self,
+        pipeline: PipelineId,
+    ) -> Option<&abstraction::GraphicsPipeline> {
+        match self.pipelines.get(pipeline.0)? {
+            Some(RegisteredPipeline {
+                pipeline: RegisteredPipelineKind::Graphics(pipeline),
+                ..
+            }) => Some(pipeline),
+            _ => None,
+        }
+    }
+
+    /// Get the current version of a compute pipeline, which changes whenever it is rebuilt
+    pub fn get_compute_pipeline(
+        &self,
+        pipeline: PipelineId,
+    ) -> Option<&abstraction::ComputePipeline> {
+        match self.pipelines.get(pipeline.0)? {
+            Some(RegisteredPipeline {
+                pipeline: RegisteredPipelineKind::Compute(pipeline),
+                ..
+            }) => Some(pipeline),
+            _ => None,
+        }
+    }
+
+    /// Stop rebuilding a pipeline and hand it over to its deletion queue
+    pub fn remove_pipeline(&mut self, pipeline: PipelineId) {
+        if let Some(slot) = self.pipelines.get_mut(pipeline.0) {
+            *slot = None;
+        }
+    }
+
+    /// Get the diagnostics of every shader whose last reload failed
+    pub fn get_diagnostics(&self) -> impl Iterator<Item = (ShaderId, &ShaderDiagnostic)> {
+        self.diagnostics
+            .iter()
+            .map(|(shader, diagnostic)| (*shader, diagnostic))
+    }
+
+    /// Reload every shader whose file, or one of its dependencies, changed since the last poll
+    pub fn poll(&mut self) -> Vec<ReloadEvent> {
+        let changed = self.watcher.poll();
+        if changed.is_empty() {
+            return Vec::new();
+        }
+        let shaders: Vec<ShaderId> = self
+            .shaders
+            .iter()
+            .enumerate()
+            .filter(|(_, registered)| {
+                changed.contains(&registered.path)
+                    || registered
+                        .dependencies
+                        .iter()
+                        .any(|dependency| changed.contains(dependency))
+            })
+            .map(|(index, _)| ShaderId(index))
+            .collect();
+        shaders
+            .into_iter()
+            .map(|shader| self.reload(shader))
+            .collect()
+    }
+
+    /// Recompile `shader` and rebuild every pipeline using it
+    ///
+    /// Nothing is replaced unless the shader and every pipeline built successfully.
+    pub fn reload(&mut self, shader: ShaderId) -> ReloadEvent {
+        let path = self.shaders[shader.0].path.clone();
+        let result = self.compile(&path).and_then(|module| {
+            let pipelines = self.rebuild_pipelines(shader, &module)?;
+            Ok((module, pipelines))
+        });
+        let (module, pipelines) = match result {
+            Ok(result) => result,
+            Err(diagnostic) => {
+                self.diagnostics.insert(shader, diagnostic.clone());
+                return ReloadEvent::Failed { shader, diagnostic };
+            }
+        };
+        let rebuilt: Vec<PipelineId> = pipelines.iter().map(|(pipeline, _)| *pipeline).collect();
+        // Replaced pipelines are handed over to their deletion queue once dropped
+        for (pipeline, rebuilt) in pipelines {
+            if let Some(registered) = self.pipelines[pipeline.0].as_mut() {
+                registered.pipeline = rebuilt;
+            }
+        }
+        // Pipelines no longer reference the previous module, so it can be destroyed right away
+        self.shaders[shader.0].module = module;
+        self.diagnostics.remove(&shader);
+        ReloadEvent::Reloaded {
+            shader,
+            pipelines: rebuilt,
+        }
+    }
+
+    /// Build every pipeline using `shader` with `module` in its place
+    fn rebuild_pipelines(
+        &self,
+        shader: ShaderId,
+        module: &ShaderModule,
+    ) -> Result<Vec<(PipelineId, RegisteredPipelineKind)>, ShaderDiagnostic> {
+        let diagnostic = |message: String| ShaderDiagnostic {
+            path: self.shaders[shader.0].path.clone(),
+            message,
+        };
+        let mut rebuilt = Vec::new();
+        for (index, registered) in self.pipelines.iter().enumerate() {
+            let Some(registered) = registered else {
+                continue;
+            };
+            if !registered.shaders.iter().any(|(used, _)| *used == shader) {
+                continue;
+            }
+            let get_module = |used: ShaderId| {
+                if used == shader {
+                    module
+                } else {
+                    &self.shaders[used.0].module
+                }
+            };
+            for (used, entry_point) in registered.shaders.iter() {
+                if get_module(*used).get_entry_point(entry_point).is_none() {
+                    return Err(diagnostic(format!(
+                        "entry point {} no longer exists",
+                        entry_point
+                    )));
+                }
+            }
+            // Set layouts and push constant ranges carry over, so the new module has to fit them
+            let (set_layouts, push_constant_ranges) = match &registered.pipeline {
+                RegisteredPipelineKind::Graphics(pipeline) => (
+                    pipeline.get_builder().get_set_layouts(),
+                    pipeline.get_builder().get_push_constant_ranges(),
+                ),
+                RegisteredPipelineKind::Compute(pipeline) => (
+                    pipeline.get_builder().get_set_layouts(),
+                    pipeline.get_builder().get_push_constant_ranges(),
+                ),
+            };
+            let set_layouts: Vec<&[abstraction::DescriptorSetLayoutBinding]> = set_layouts
+                .iter()
+                .map(|set_layout| set_layout.get_bindings())
+                .collect();
+            for (_, entry_point) in registered
+                .shaders
+                .iter()
+                .filter(|(used, _)| *used == shader)
+            {
+                let reflection = abstraction::ShaderReflection::from_module(module, entry_point)
+                    .map_err(|error| diagnostic(error.to_string()))?;
+                check_layout(
+                    reflection.get_bindings(),
+                    reflection.get_push_constant_range(),
+                    &set_layouts,
+                    push_constant_ranges,
+                )
+                .map_err(|message| {
+                    diagnostic(format!("pipeline {} cannot be rebuilt: {}", index, message))
+                })?;
+            }
+            // Specialization constants of every stage carry over to the rebuilt pipeline
+            let pipeline = match &registered.pipeline {
+                RegisteredPipelineKind::Graphics(pipeline) => {
+                    let mut builder = pipeline.get_builder().clone();
+                    for (used, entry_point) in registered.shaders.iter() {
+                        let module = get_module(*used);
+                        let stage = module.get_entry_point(entry_point).unwrap().stage;
+                        let specialization = builder
+                            .get_stages()
+                            .iter()
+                            .find(|existing| existing.get_stage() == stage)
+                            .map(|existing| existing.get_specialization().clone())
+                            .unwrap_or_default();
+                        builder = builder
+                            .shader_module(module, entry_point)
+                            .map_err(|error| diagnostic(error.to_string()))?
+                            .specialization(stage, specialization);
+                    }
+                    builder.build().map(RegisteredPipelineKind::Graphics)
+                }
+                RegisteredPipelineKind::Compute(pipeline) => {
+                    let builder = pipeline.get_builder().clone();
+                    let specialization = builder
+                        .get_stage()
+                        .map(|existing| existing.get_specialization().clone())
+                        .unwrap_or_default();
+                    let (used, entry_point) = &registered.shaders[0];
+                    let builder = builder
+                        .shader_module(get_module(*used), entry_point)
+                        .map_err(|error| diagnostic(error.to_string()))?;
+                    builder
+                        .specialization(specialization)
+                        .build()
+                        .map(RegisteredPipelineKind::Compute)
+                }
+            }
+            .map_err(|result| {
+                diagnostic(format!("failed to rebuild pipeline {}: {}", index, result))
+            })?;
+            rebuilt.push((PipelineId(index), pipeline));
+        }
+        Ok(rebuilt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(
+        descriptor_type: vk::DescriptorType,
+        descriptor_count: u32,
+    ) -> abstraction::ReflectedBinding {
+        abstraction::ReflectedBinding {
+            set: 1,
+            binding: 2,
+            descriptor_type,
+            descriptor_count,
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            name: "data".to_string(),
+        }
+    }
+
+    #[test]
+    /// Reloaded shaders have to use bindings and push constants the pipeline layout declares
+    fn test_check_layout() {
+        let declared = abstraction::DescriptorSetLayoutBinding {
+            binding: 2,
+            descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+            descriptor_count: 4,
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            binding_flags: vk::DescriptorBindingFlags::empty(),
+        };
+        let set_layouts: [&[abstraction::DescriptorSetLayoutBinding]; 2] = [&[], &[declared]];
+        let ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            offset: 0,
+            size: 16,
+        }];
+        let push_constants = |offset, size| {
+            Some(vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+                offset,
+                size,
+            })
+        };
+        let storage = vk::DescriptorType::STORAGE_BUFFER;
+        assert!(check_layout(
+            &[binding(storage, 4)],
+            push_constants(4, 8),
+            &set_layouts,
+            &ranges
+        )
+        .is_ok());
+        assert!(check_layout(&[binding(storage, 0)], None, &set_layouts, &ranges).is_ok());
+        // Larger arrays, other types and missing sets no longer fit
+        assert!(check_layout(&[binding(storage, 8)], None, &set_layouts, &ranges).is_err());
+        let uniform = vk::DescriptorType::UNIFORM_BUFFER;
+        assert!(check_layout(&[binding(uniform, 1)], None, &set_layouts, &ranges).is_err());
+        assert!(check_layout(&[binding(storage, 1)], None, &set_layouts[..1], &ranges).is_err());
+        assert!(check_layout(&[], push_constants(8, 16), &set_layouts, &ranges).is_err());
+    }
+}
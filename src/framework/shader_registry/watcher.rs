@@ -0,0 +1,80 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Get the last modification time of `path`, [None] if it cannot be read
+fn get_modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Detects changes to files by polling their modification time
+#[derive(Clone, Debug, Default)]
+pub struct FileWatcher {
+    /// Every watched file along with its modification time when last polled
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl FileWatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start watching `path`, changes made before this call are not reported
+    pub fn watch<P: AsRef<Path>>(&mut self, path: P) {
+        let path = path.as_ref().to_path_buf();
+        if self.files.iter().any(|(watched, _)| *watched == path) {
+            return;
+        }
+        let modified = get_modified(&path);
+        self.files.push((path, modified));
+    }
+
+    pub fn unwatch<P: AsRef<Path>>(&mut self, path: P) {
+        self.files.retain(|(watched, _)| watched != path.as_ref());
+    }
+
+    /// Get every file which was modified, created or deleted since the last poll
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for (path, modified) in self.files.iter_mut() {
+            let current = get_modified(path);
+            if current != *modified {
+                *modified = current;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+
+    pub fn get_files(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|(path, _)| path.as_path())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    /// Only files modified since the last poll are reported
+    fn test_poll() {
+        let path = std::env::temp_dir().join(format!("dagal_watcher_{}.spv", std::process::id()));
+        std::fs::write(&path, [0u8; 4]).unwrap();
+        let mut watcher = FileWatcher::new();
+        watcher.watch(&path);
+        watcher.watch(&path);
+        assert_eq!(watcher.get_files().count(), 1);
+        assert!(watcher.poll().is_empty());
+
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(watcher.poll(), vec![path.clone()]);
+        assert!(watcher.poll().is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(watcher.poll(), vec![path.clone()]);
+    }
+}